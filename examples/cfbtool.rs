@@ -22,6 +22,9 @@ enum Command {
     /// Changes storage CLSIDs
     Chcls { clsid: Uuid, path: Vec<String> },
 
+    /// Shows the differences between two compound files
+    Diff { old: PathBuf, new: PathBuf },
+
     /// Lists storage contents
     Ls {
         #[clap(short, long)]
@@ -220,6 +223,11 @@ fn main() {
                 comp.flush().unwrap();
             }
         }
+        Command::Diff { old, new } => {
+            let old = cfb::open(&old).unwrap();
+            let new = cfb::open(&new).unwrap();
+            print!("{}", cfb::diff(&old, &new).unwrap());
+        }
         Command::Ls { long, all, path } => {
             for path in path {
                 let (comp_path, inner_path) = split(&path);
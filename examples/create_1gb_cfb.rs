@@ -0,0 +1,68 @@
+//! Benchmarks writing a single very large stream to a new compound file.
+//!
+//! Usage: `cargo run --release --example create_1gb_cfb [PATH] [SIZE_MIB]`
+//!
+//! If no path is given, the file is written to the temp directory and removed
+//! afterwards.
+//!
+//! The time taken to write each successive 64 MiB chunk is printed as the
+//! stream grows.  Since sector allocation and chain extension take constant
+//! time, each chunk should take about as long as the first, no matter how
+//! large the stream already is.
+
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::time::Instant;
+
+const CHUNK_LEN: usize = 64 * 1024 * 1024;
+
+fn main() -> io::Result<()> {
+    let mut args = env::args().skip(1);
+    let (path, is_temp) = match args.next() {
+        Some(arg) => (PathBuf::from(arg), false),
+        None => (env::temp_dir().join("create_1gb_cfb.cfb"), true),
+    };
+    let size_mib: usize = match args.next() {
+        Some(arg) => arg.parse().map_err(|_| {
+            io::Error::new(io::ErrorKind::InvalidInput, "invalid size")
+        })?,
+        None => 1024,
+    };
+    let total_len = size_mib * 1024 * 1024;
+
+    let mut comp = cfb::create(&path)?;
+    let mut stream = comp.create_stream("/data")?;
+    let block: Vec<u8> = (0..CHUNK_LEN).map(|i| (i % 251) as u8).collect();
+    let start = Instant::now();
+    let mut written = 0;
+    while written < total_len {
+        let len = CHUNK_LEN.min(total_len - written);
+        let chunk_start = Instant::now();
+        // Write in modest pieces, as a typical exporter would.
+        for piece in block[..len].chunks(64 * 1024) {
+            stream.write_all(piece)?;
+        }
+        written += len;
+        println!(
+            "{:>6} MiB written; last chunk took {:>8.3} s",
+            written / (1024 * 1024),
+            chunk_start.elapsed().as_secs_f64()
+        );
+    }
+    stream.flush()?;
+    drop(stream);
+    comp.flush()?;
+    println!(
+        "Wrote {} MiB to {} in {:.3} s",
+        size_mib,
+        path.display(),
+        start.elapsed().as_secs_f64()
+    );
+    drop(comp);
+    if is_temp {
+        fs::remove_file(&path)?;
+    }
+    Ok(())
+}
@@ -1,10 +1,14 @@
 use crate::internal::{
-    consts, Chain, Sector, SectorInit, Sectors, Validation, Version,
+    consts, AllocationPolicy, Chain, Sector, SectorInit, Sectors, Validation,
+    Version,
 };
 use crate::WriteLeNumber;
-use fnv::FnvHashSet;
-use std::io::{self, Seek, Write};
+use fnv::{FnvHashMap, FnvHashSet};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, Read, Seek, Write};
 use std::mem::size_of;
+#[cfg(test)]
+use std::sync::atomic::{AtomicU64, Ordering as AtomicOrdering};
 
 //===========================================================================//
 
@@ -17,6 +21,32 @@ macro_rules! malformed {
 
 //===========================================================================//
 
+/// The maximum number of chains whose sector lists are cached at once.
+const MAX_CACHED_CHAINS: usize = 64;
+
+/// The number of sectors in the first run reserved for a chain under the
+/// `Contiguous` allocation policy.
+const MIN_RUN_LEN: u32 = 16;
+
+/// The maximum number of sectors in a run reserved automatically under the
+/// `Contiguous` allocation policy.
+const MAX_RUN_LEN: u32 = 4096;
+
+//===========================================================================//
+
+/// A run of free sectors set aside for the chain ending at a given sector.
+#[derive(Clone, Copy)]
+struct Reservation {
+    /// The first reserved sector, which is the next one the chain will use.
+    start: u32,
+    /// One past the last reserved sector.
+    end: u32,
+    /// The length of the run when it was reserved.
+    run_len: u32,
+}
+
+//===========================================================================//
+
 /// A wrapper around the sectors of a compound file, providing sector
 /// allocation via the FAT and DIFAT.
 pub struct Allocator<F> {
@@ -24,6 +54,32 @@ pub struct Allocator<F> {
     difat_sector_ids: Vec<u32>,
     difat: Vec<u32>,
     fat: Vec<u32>,
+    secure_delete: bool,
+    /// No sector before this index in the FAT is free, so searches for a free
+    /// sector can start here.
+    free_hint: usize,
+    /// The sectors that are marked free in the FAT (whether or not they are
+    /// reserved), so that searching for a run of free sectors doesn't have
+    /// to step over every sector that is in use.
+    free_sectors: BTreeSet<u32>,
+    /// The sector numbers of recently-used chains, keyed by starting sector.
+    /// Any operation that frees sectors clears this cache.
+    chains: FnvHashMap<u32, Vec<u32>>,
+    policy: AllocationPolicy,
+    /// Runs of free sectors set aside for growing chains, keyed by the
+    /// current last sector of each chain.
+    reservations: FnvHashMap<u32, Reservation>,
+    /// The non-empty runs in `reservations`, as a map from the start of each
+    /// run to its end, so that checking whether a given sector is reserved
+    /// doesn't have to look at every reservation.
+    reserved_runs: BTreeMap<u32, u32>,
+    /// Incremented whenever a sector is freed, since any chain that included
+    /// that sector has changed.
+    generation: u64,
+    /// The number of FAT entries examined so far, so that tests can check
+    /// that allocation doesn't slow down as chains get longer.
+    #[cfg(test)]
+    fat_visits: AtomicU64,
 }
 
 impl<F> Allocator<F> {
@@ -34,8 +90,26 @@ impl<F> Allocator<F> {
         fat: Vec<u32>,
         validation: Validation,
     ) -> io::Result<Allocator<F>> {
-        let mut alloc = Allocator { sectors, difat_sector_ids, difat, fat };
+        let mut alloc = Allocator {
+            sectors,
+            difat_sector_ids,
+            difat,
+            fat,
+            secure_delete: false,
+            free_hint: 0,
+            free_sectors: BTreeSet::new(),
+            chains: FnvHashMap::default(),
+            policy: AllocationPolicy::default(),
+            reservations: FnvHashMap::default(),
+            reserved_runs: BTreeMap::new(),
+            generation: 0,
+            #[cfg(test)]
+            fat_visits: AtomicU64::new(0),
+        };
         alloc.validate(validation)?;
+        alloc.free_sectors = (0..alloc.fat.len() as u32)
+            .filter(|&id| alloc.fat[id as usize] == consts::FREE_SECTOR)
+            .collect();
         Ok(alloc)
     }
 
@@ -51,7 +125,174 @@ impl<F> Allocator<F> {
         self.sectors.sector_len()
     }
 
+    pub fn num_sectors(&self) -> u32 {
+        self.sectors.num_sectors()
+    }
+
+    /// Returns true if freed sectors are overwritten with zeros.
+    pub fn secure_delete(&self) -> bool {
+        self.secure_delete
+    }
+
+    pub fn set_secure_delete(&mut self, secure_delete: bool) {
+        self.secure_delete = secure_delete;
+    }
+
+    pub fn allocation_policy(&self) -> AllocationPolicy {
+        self.policy
+    }
+
+    pub fn set_allocation_policy(&mut self, policy: AllocationPolicy) {
+        self.policy = policy;
+    }
+
+    /// Returns a number that changes whenever any sector is freed.  Chains
+    /// only otherwise change by growing, so a list of a chain's sectors
+    /// remains valid (though possibly incomplete) as long as this number is
+    /// the same.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Returns the number of FAT entries that allocation and chain traversal
+    /// have examined so far.
+    #[cfg(test)]
+    pub fn fat_visits(&self) -> u64 {
+        self.fat_visits.load(AtomicOrdering::Relaxed)
+    }
+
+    /// Records that a FAT entry was examined.  This does nothing outside of
+    /// tests.
+    fn visit_fat(&self) {
+        #[cfg(test)]
+        self.fat_visits.fetch_add(1, AtomicOrdering::Relaxed);
+    }
+
+    /// Returns the in-memory copy of the FAT, indexed by sector number.
+    pub fn fat(&self) -> &[u32] {
+        &self.fat
+    }
+
+    /// Returns the sector numbers of the FAT sectors, in DIFAT order.
+    pub fn difat(&self) -> &[u32] {
+        &self.difat
+    }
+
+    /// Returns the sector numbers of the DIFAT chain.
+    pub fn difat_sector_ids(&self) -> &[u32] {
+        &self.difat_sector_ids
+    }
+
+    /// Returns the sector numbers of the chain starting at the given sector,
+    /// without opening the chain for I/O.
+    pub fn chain_sector_ids(
+        &self,
+        start_sector_id: u32,
+    ) -> io::Result<Vec<u32>> {
+        let mut sector_ids = Vec::<u32>::new();
+        let mut current_sector_id = start_sector_id;
+        while current_sector_id != consts::END_OF_CHAIN {
+            if sector_ids.len() >= self.fat.len() {
+                invalid_data!(
+                    "Chain starting at sector {} contains a loop",
+                    start_sector_id
+                );
+            }
+            sector_ids.push(current_sector_id);
+            current_sector_id = self.next(current_sector_id)?;
+        }
+        Ok(sector_ids)
+    }
+
+    /// Returns the sector number at the given index within the chain starting
+    /// at the given sector, caching the chain's sector list so that later
+    /// lookups don't need to walk the FAT again.
+    pub fn chain_sector_id(
+        &mut self,
+        start_sector_id: u32,
+        index: usize,
+    ) -> io::Result<u32> {
+        if !self.chains.contains_key(&start_sector_id) {
+            let sector_ids = self.chain_sector_ids(start_sector_id)?;
+            self.cache_chain(sector_ids);
+        }
+        match self.chains.get(&start_sector_id).and_then(|ids| ids.get(index))
+        {
+            Some(&sector_id) => Ok(sector_id),
+            None => invalid_data!(
+                "Chain starting at sector {} has no sector at index {}",
+                start_sector_id,
+                index
+            ),
+        }
+    }
+
+    /// Removes the sector list of the chain starting at the given sector from
+    /// the cache, if it's there, and returns it.
+    pub fn take_cached_chain(
+        &mut self,
+        start_sector_id: u32,
+    ) -> Option<Vec<u32>> {
+        self.chains.remove(&start_sector_id)
+    }
+
+    /// Adds the sector list of a chain to the cache, keyed by its first
+    /// sector.
+    pub fn cache_chain(&mut self, sector_ids: Vec<u32>) {
+        if let Some(&start_sector_id) = sector_ids.first() {
+            if self.chains.len() >= MAX_CACHED_CHAINS {
+                self.chains.clear();
+            }
+            self.chains.insert(start_sector_id, sector_ids);
+        }
+    }
+
+    /// Returns true if the given sector is free and not reserved for any
+    /// chain.
+    fn is_available(&self, sector_id: u32) -> bool {
+        self.visit_fat();
+        (sector_id as usize) < self.fat.len()
+            && self.fat[sector_id as usize] == consts::FREE_SECTOR
+            && self.reserved_run_end(sector_id).is_none()
+    }
+
+    /// If the given sector is part of a reserved run, returns the end of that
+    /// run.
+    fn reserved_run_end(&self, sector_id: u32) -> Option<u32> {
+        self.reserved_runs
+            .range(..=sector_id)
+            .next_back()
+            .map(|(_, &end)| end)
+            .filter(|&end| sector_id < end)
+    }
+
+    /// Sets aside a run of sectors for the chain ending at the given sector,
+    /// replacing any existing reservation for that chain.
+    fn insert_reservation(
+        &mut self,
+        last_sector_id: u32,
+        reservation: Reservation,
+    ) {
+        self.remove_reservation(last_sector_id);
+        if reservation.start < reservation.end {
+            self.reserved_runs.insert(reservation.start, reservation.end);
+        }
+        self.reservations.insert(last_sector_id, reservation);
+    }
+
+    fn remove_reservation(
+        &mut self,
+        last_sector_id: u32,
+    ) -> Option<Reservation> {
+        let reservation = self.reservations.remove(&last_sector_id)?;
+        if reservation.start < reservation.end {
+            self.reserved_runs.remove(&reservation.start);
+        }
+        Some(reservation)
+    }
+
     pub fn next(&self, sector_id: u32) -> io::Result<u32> {
+        self.visit_fat();
         let index = sector_id as usize;
         if index >= self.fat.len() {
             invalid_data!(
@@ -176,6 +417,26 @@ impl<F: Seek> Allocator<F> {
         self.sectors.seek_within_sector(sector_id, offset_within_sector)
     }
 
+    pub fn seek_within_sectors(
+        &mut self,
+        sector_id: u32,
+        num_sectors: u32,
+        offset_within_run: u64,
+    ) -> io::Result<Sector<'_, F>> {
+        self.sectors.seek_within_sectors(
+            sector_id,
+            num_sectors,
+            offset_within_run,
+        )
+    }
+
+    pub fn read_at(&mut self, offset: u64, len: u64) -> io::Result<Vec<u8>>
+    where
+        F: Read,
+    {
+        self.sectors.read_at(offset, len)
+    }
+
     pub fn seek_within_subsector(
         &mut self,
         sector_id: u32,
@@ -198,7 +459,7 @@ impl<F: Write + Seek> Allocator<F> {
     /// Allocates a new chain with one sector, and returns the starting sector
     /// number.
     pub fn begin_chain(&mut self, init: SectorInit) -> io::Result<u32> {
-        self.allocate_sector(init)
+        self.allocate_sector_after(None, init)
     }
 
     /// Given the starting sector (or any internal sector) of a chain, extends
@@ -210,38 +471,211 @@ impl<F: Write + Seek> Allocator<F> {
         init: SectorInit,
     ) -> io::Result<u32> {
         debug_assert_ne!(start_sector_id, consts::END_OF_CHAIN);
-        let mut last_sector_id = start_sector_id;
+        let mut last_sector_id = match self.chains.get(&start_sector_id) {
+            Some(sector_ids) => *sector_ids.last().unwrap(),
+            None => start_sector_id,
+        };
         loop {
+            self.visit_fat();
             let next = self.fat[last_sector_id as usize];
             if next == consts::END_OF_CHAIN {
                 break;
             }
             last_sector_id = next;
         }
-        let new_sector_id = self.allocate_sector(init)?;
+        let new_sector_id =
+            self.allocate_sector_after(Some(last_sector_id), init)?;
         self.set_fat(last_sector_id, new_sector_id)?;
+        // Keep any cached chains that end at the old last sector up to date.
+        for sector_ids in self.chains.values_mut() {
+            if sector_ids.last() == Some(&last_sector_id) {
+                sector_ids.push(new_sector_id);
+            }
+        }
         Ok(new_sector_id)
     }
 
+    /// Reserves enough free sectors to extend the chain ending at the given
+    /// sector by `num_sectors` sectors, adjacent to each other (and to the
+    /// end of the chain) where possible.  The reserved sectors are used the
+    /// next time the chain is extended, regardless of allocation policy.
+    pub fn reserve(
+        &mut self,
+        last_sector_id: u32,
+        num_sectors: u32,
+    ) -> io::Result<()> {
+        if num_sectors == 0 {
+            return Ok(());
+        }
+        let reservation = match self.remove_reservation(last_sector_id) {
+            Some(reservation) if reservation.start < reservation.end => {
+                let end = self.grow_run(
+                    reservation.start,
+                    reservation.end,
+                    reservation.start.saturating_add(num_sectors),
+                )?;
+                Reservation {
+                    end,
+                    run_len: reservation.run_len.max(num_sectors),
+                    ..reservation
+                }
+            }
+            _ => {
+                let (start, end) =
+                    self.find_run(Some(last_sector_id), num_sectors)?;
+                Reservation { start, end, run_len: num_sectors }
+            }
+        };
+        self.insert_reservation(last_sector_id, reservation);
+        Ok(())
+    }
+
+    /// Allocates a new sector for the chain ending at the given sector (or
+    /// for a new chain, if `None`), taking it from the chain's reserved run
+    /// if it has one, and otherwise choosing it according to the allocation
+    /// policy.  The new sector's FAT entry is set to `END_OF_CHAIN`.
+    fn allocate_sector_after(
+        &mut self,
+        last_sector_id: Option<u32>,
+        init: SectorInit,
+    ) -> io::Result<u32> {
+        let reservation =
+            last_sector_id.and_then(|id| self.remove_reservation(id));
+        let (start, end, run_len) = match reservation {
+            Some(reservation) if reservation.start < reservation.end => {
+                (reservation.start, reservation.end, reservation.run_len)
+            }
+            _ => match self.policy {
+                AllocationPolicy::FirstFit => {
+                    return self.allocate_sector(init);
+                }
+                AllocationPolicy::Contiguous => {
+                    let run_len = reservation.map_or(MIN_RUN_LEN, |r| {
+                        r.run_len.saturating_mul(2).min(MAX_RUN_LEN)
+                    });
+                    let (start, end) =
+                        self.find_run(last_sector_id, run_len)?;
+                    (start, end, run_len)
+                }
+            },
+        };
+        self.set_fat(start, consts::END_OF_CHAIN)?;
+        self.sectors.init_sector(start, init)?;
+        let reservation = Reservation { start: start + 1, end, run_len };
+        self.insert_reservation(start, reservation);
+        Ok(start)
+    }
+
+    /// Finds (or creates, at the end of the file) a run of up to `run_len`
+    /// available sectors, preferring one that starts right after the given
+    /// sector, and returns the start and end of the run.  The run will hold
+    /// at least one sector.
+    fn find_run(
+        &mut self,
+        last_sector_id: Option<u32>,
+        run_len: u32,
+    ) -> io::Result<(u32, u32)> {
+        debug_assert!(run_len > 0);
+        if let Some(last_sector_id) = last_sector_id {
+            let start = last_sector_id + 1;
+            let end = self.grow_run(start, start, start + run_len)?;
+            if end > start {
+                return Ok((start, end));
+            }
+        }
+        // Look for an existing run of available sectors that is long enough
+        // (or that reaches the end of the file, and so can be lengthened).
+        // Only free sectors need looking at, and reserved runs can be
+        // skipped over whole.
+        let mut run: Option<(u32, u32)> = None;
+        let mut next_id = self.free_hint as u32;
+        while let Some(&sector_id) = self.free_sectors.range(next_id..).next()
+        {
+            self.visit_fat();
+            if let Some(end) = self.reserved_run_end(sector_id) {
+                run = None;
+                next_id = end;
+                continue;
+            }
+            let start = match run {
+                Some((start, end)) if end == sector_id => start,
+                _ => sector_id,
+            };
+            if sector_id + 1 - start >= run_len {
+                return Ok((start, sector_id + 1));
+            }
+            run = Some((start, sector_id + 1));
+            next_id = sector_id + 1;
+        }
+        if let Some((start, end)) = run {
+            if end as usize == self.fat.len() {
+                return Ok((
+                    start,
+                    self.grow_run(start, end, start + run_len)?,
+                ));
+            }
+        }
+        // Otherwise, start a new run at the end of the file.  Appending a
+        // sector may first require a new FAT sector, in which case the run
+        // starts after that instead.
+        loop {
+            let start = self.fat.len() as u32;
+            let end = self.grow_run(start, start, start + run_len)?;
+            if end > start {
+                return Ok((start, end));
+            }
+        }
+    }
+
+    /// Lengthens the run of available sectors from `start` to `end` towards
+    /// `want_end`, adding free sectors to the end of the file as needed, and
+    /// returns the new end of the run.  The run stops short if it reaches a
+    /// sector that is in use.
+    fn grow_run(
+        &mut self,
+        start: u32,
+        mut end: u32,
+        want_end: u32,
+    ) -> io::Result<u32> {
+        debug_assert!(start <= end);
+        while end < want_end {
+            if (end as usize) < self.fat.len() {
+                if !self.is_available(end) {
+                    break;
+                }
+            } else if self.append_free_sector()? != end {
+                break;
+            }
+            end += 1;
+        }
+        Ok(end)
+    }
+
+    /// Adds a new free sector to the end of the file (first adding a new FAT
+    /// sector if needed), and returns its sector number.
+    fn append_free_sector(&mut self) -> io::Result<u32> {
+        self.make_room_in_fat()?;
+        let new_sector = self.fat.len() as u32;
+        self.set_fat(new_sector, consts::FREE_SECTOR)?;
+        self.sectors.init_sector(new_sector, SectorInit::Zero)?;
+        Ok(new_sector)
+    }
+
     /// Allocates a new entry in the FAT, sets its value to `END_OF_CHAIN`, and
     /// returns the new sector number.
     fn allocate_sector(&mut self, init: SectorInit) -> io::Result<u32> {
         // If there's an existing free sector, use that.
-        for sector_id in 0..self.fat.len() {
-            if self.fat[sector_id] == consts::FREE_SECTOR {
-                let sector_id = sector_id as u32;
+        while self.free_hint < self.fat.len() {
+            let sector_id = self.free_hint as u32;
+            self.free_hint += 1;
+            if self.is_available(sector_id) {
                 self.set_fat(sector_id, consts::END_OF_CHAIN)?;
                 self.sectors.init_sector(sector_id, init)?;
                 return Ok(sector_id);
             }
         }
-        // Otherwise, we need a new sector; if there's not room in the FAT to
-        // add it, then first we need to allocate a new FAT sector.
-        let fat_entries_per_sector =
-            self.sectors.sector_len() / size_of::<u32>();
-        if self.fat.len() % fat_entries_per_sector == 0 {
-            self.append_fat_sector()?;
-        }
+        // Otherwise, we need a new sector at the end of the file.
+        self.make_room_in_fat()?;
         // Add a new sector to the end of the file and return it.
         let new_sector = self.fat.len() as u32;
         self.set_fat(new_sector, consts::END_OF_CHAIN)?;
@@ -249,6 +683,17 @@ impl<F: Write + Seek> Allocator<F> {
         Ok(new_sector)
     }
 
+    /// If there's not room in the FAT to add another sector to the end of the
+    /// file, allocates a new FAT sector.
+    fn make_room_in_fat(&mut self) -> io::Result<()> {
+        let fat_entries_per_sector =
+            self.sectors.sector_len() / size_of::<u32>();
+        if self.fat.len() % fat_entries_per_sector == 0 {
+            self.append_fat_sector()?;
+        }
+        Ok(())
+    }
+
     /// Adds a new sector to the FAT chain at the end of the file, and updates
     /// the FAT and DIFAT accordingly.
     fn append_fat_sector(&mut self) -> io::Result<()> {
@@ -333,9 +778,21 @@ impl<F: Write + Seek> Allocator<F> {
         Ok(())
     }
 
-    /// Deallocates the specified sector.
+    /// Deallocates the specified sector (zeroing its contents first, if secure
+    /// deletion is enabled).
     fn free_sector(&mut self, sector_id: u32) -> io::Result<()> {
+        if self.secure_delete {
+            self.sectors.init_sector(sector_id, SectorInit::Zero)?;
+        }
         self.set_fat(sector_id, consts::FREE_SECTOR)?;
+        self.free_hint = self.free_hint.min(sector_id as usize);
+        // If the sector was the end of a chain with reserved sectors, release
+        // them.
+        if let Some(reservation) = self.remove_reservation(sector_id) {
+            self.free_hint = self.free_hint.min(reservation.start as usize);
+        }
+        self.chains.clear();
+        self.generation += 1;
         // TODO: Truncate FAT if last FAT sector is now all free.
         Ok(())
     }
@@ -358,9 +815,18 @@ impl<F: Write + Seek> Allocator<F> {
         } else {
             self.fat[index] = value;
         }
+        if value == consts::FREE_SECTOR {
+            self.free_sectors.insert(index as u32);
+        } else {
+            self.free_sectors.remove(&(index as u32));
+        }
         Ok(())
     }
 
+    pub fn write_at(&mut self, offset: u64, data: &[u8]) -> io::Result<()> {
+        self.sectors.write_at(offset, data)
+    }
+
     /// Flushes all changes to the underlying file.
     pub fn flush(&mut self) -> io::Result<()> {
         self.sectors.flush()
@@ -372,7 +838,10 @@ impl<F: Write + Seek> Allocator<F> {
 #[cfg(test)]
 mod tests {
     use super::Allocator;
-    use crate::internal::{consts, Sectors, Validation, Version};
+    use crate::internal::{
+        consts, AllocationPolicy, SectorInit, Sectors, Validation, Version,
+    };
+    use std::collections::BTreeMap;
     use std::io::Cursor;
 
     fn make_sectors(
@@ -525,6 +994,108 @@ mod tests {
         let fat = vec![consts::FAT_SECTOR, consts::INVALID_SECTOR];
         make_allocator(difat, fat, Validation::Permissive);
     }
+
+    #[test]
+    fn reuse_lowest_free_sector() {
+        let difat = vec![0];
+        let fat = vec![consts::FAT_SECTOR];
+        let mut allocator = make_allocator(difat, fat, Validation::Strict);
+        let start = allocator.begin_chain(SectorInit::Zero).unwrap();
+        for _ in 0..5 {
+            allocator.extend_chain(start, SectorInit::Zero).unwrap();
+        }
+        assert_eq!(
+            allocator.chain_sector_ids(start).unwrap(),
+            vec![1, 2, 3, 4, 5, 6]
+        );
+        allocator.free_chain_after(2).unwrap();
+        assert_eq!(allocator.begin_chain(SectorInit::Zero).unwrap(), 3);
+        assert_eq!(
+            allocator.extend_chain(start, SectorInit::Zero).unwrap(),
+            4
+        );
+        assert_eq!(allocator.extend_chain(3, SectorInit::Zero).unwrap(), 5);
+        assert_eq!(
+            allocator.extend_chain(start, SectorInit::Zero).unwrap(),
+            6
+        );
+        assert_eq!(
+            allocator.extend_chain(start, SectorInit::Zero).unwrap(),
+            7
+        );
+        assert_eq!(
+            allocator.chain_sector_ids(start).unwrap(),
+            vec![1, 2, 4, 6, 7]
+        );
+        assert_eq!(allocator.chain_sector_ids(3).unwrap(), vec![3, 5]);
+    }
+
+    #[test]
+    fn cached_chains_stay_up_to_date() {
+        let difat = vec![0];
+        let fat = vec![consts::FAT_SECTOR];
+        let mut allocator = make_allocator(difat, fat, Validation::Strict);
+        let start1 = allocator.begin_chain(SectorInit::Zero).unwrap();
+        let start2 = allocator.begin_chain(SectorInit::Zero).unwrap();
+        assert_eq!(allocator.chain_sector_id(start1, 0).unwrap(), start1);
+        assert_eq!(allocator.chain_sector_id(start2, 0).unwrap(), start2);
+        for _ in 0..3 {
+            allocator.extend_chain(start1, SectorInit::Zero).unwrap();
+            allocator.extend_chain(start2, SectorInit::Zero).unwrap();
+        }
+        for start in [start1, start2] {
+            let sector_ids = allocator.chain_sector_ids(start).unwrap();
+            for (index, &sector_id) in sector_ids.iter().enumerate() {
+                assert_eq!(
+                    allocator.chain_sector_id(start, index).unwrap(),
+                    sector_id
+                );
+            }
+            assert!(allocator.chain_sector_id(start, 4).is_err());
+        }
+        allocator.free_chain_after(start1).unwrap();
+        assert!(allocator.chain_sector_id(start1, 1).is_err());
+        let chain = allocator.open_chain(start2, SectorInit::Zero).unwrap();
+        assert_eq!(chain.num_sectors(), 4);
+    }
+
+    #[test]
+    fn reserved_runs_stay_up_to_date() {
+        let difat = vec![0];
+        let fat = vec![consts::FAT_SECTOR];
+        let mut allocator = make_allocator(difat, fat, Validation::Strict);
+        allocator.set_allocation_policy(AllocationPolicy::Contiguous);
+        let check = |allocator: &Allocator<Cursor<Vec<u8>>>| {
+            let expected: BTreeMap<u32, u32> = allocator
+                .reservations
+                .values()
+                .filter(|reservation| reservation.start < reservation.end)
+                .map(|reservation| (reservation.start, reservation.end))
+                .collect();
+            assert_eq!(allocator.reserved_runs, expected);
+            for (&start, &end) in expected.iter() {
+                assert!((start..end).all(|id| !allocator.is_available(id)));
+            }
+        };
+        let start1 = allocator.begin_chain(SectorInit::Zero).unwrap();
+        let start2 = allocator.begin_chain(SectorInit::Zero).unwrap();
+        allocator.reserve(start1, 20).unwrap();
+        check(&allocator);
+        let mut last2 = start2;
+        for _ in 0..12 {
+            allocator.extend_chain(start1, SectorInit::Zero).unwrap();
+            last2 = allocator.extend_chain(start2, SectorInit::Zero).unwrap();
+            check(&allocator);
+        }
+        allocator.reserve(last2, 8).unwrap();
+        check(&allocator);
+        allocator.free_chain_after(start1).unwrap();
+        check(&allocator);
+        allocator.free_chain(start2).unwrap();
+        check(&allocator);
+        let chain1 = allocator.chain_sector_ids(start1).unwrap();
+        assert_eq!(chain1, vec![start1]);
+    }
 }
 
 //===========================================================================//
@@ -0,0 +1,929 @@
+use crate::{CompoundFile, Entry, Stream, Version, WalkOptions};
+use fnv::FnvHashMap;
+use std::collections::{hash_map, BTreeSet, VecDeque};
+use std::error::Error;
+use std::fmt;
+use std::future::poll_fn;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{ready, Context, Poll};
+use tokio::io::{AsyncRead, AsyncSeek, AsyncSeekExt, AsyncWrite, ReadBuf};
+
+//===========================================================================//
+
+/// The size of the chunks in which the underlying file is read and written.
+const PAGE_LEN: usize = 64 * 1024;
+
+/// The maximum number of pages to keep cached while the file is only being
+/// read (16 MiB worth).
+const MAX_CLEAN_PAGES: usize = 256;
+
+//===========================================================================//
+
+/// The error payload used to signal that an operation needs a page of the
+/// underlying file that hasn't been read yet.
+#[derive(Debug)]
+struct MissingPage(u64);
+
+impl fmt::Display for MissingPage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Page {} of the file has not been read yet", self.0)
+    }
+}
+
+impl Error for MissingPage {}
+
+fn missing_page_error(index: u64) -> io::Error {
+    io::Error::new(io::ErrorKind::WouldBlock, MissingPage(index))
+}
+
+/// If the error is because of a missing page, returns the page index.
+fn missing_page(error: &io::Error) -> Option<u64> {
+    let inner = error.get_ref()?;
+    inner.downcast_ref::<MissingPage>().map(|missing| missing.0)
+}
+
+//===========================================================================//
+
+/// An in-memory copy of the parts of the underlying file that have been read
+/// so far, along with any changes that haven't been written back yet.
+struct PageCache {
+    /// The length of the underlying file as of the last time it was written
+    /// back.  Pages past this point don't need to be read.
+    disk_len: u64,
+    /// The current length of the file, including unwritten changes.
+    len: u64,
+    pages: FnvHashMap<u64, Box<[u8]>>,
+    dirty: BTreeSet<u64>,
+    /// All pages before this one are known to be present.
+    first_maybe_missing: u64,
+    /// The pages read from the underlying file, oldest first, for evicting
+    /// them once there are too many.
+    fetch_order: VecDeque<u64>,
+    /// The most pages to keep once `trim()` is called.
+    max_clean_pages: usize,
+    /// Set once the whole file has been read in to prepare for changing it,
+    /// after which no pages are ever evicted.
+    keep_all: bool,
+    /// While this is set, reads of pages that haven't been read yet return
+    /// zeros instead of failing, and the pages are recorded here.
+    missed: Option<BTreeSet<u64>>,
+}
+
+impl PageCache {
+    fn new(len: u64) -> PageCache {
+        PageCache {
+            disk_len: len,
+            len,
+            pages: FnvHashMap::default(),
+            dirty: BTreeSet::new(),
+            first_maybe_missing: 0,
+            fetch_order: VecDeque::new(),
+            max_clean_pages: MAX_CLEAN_PAGES,
+            keep_all: false,
+            missed: None,
+        }
+    }
+
+    fn page_start(index: u64) -> u64 {
+        index * PAGE_LEN as u64
+    }
+
+    fn is_present(&self, index: u64) -> bool {
+        self.pages.contains_key(&index)
+            || PageCache::page_start(index) >= self.disk_len
+    }
+
+    /// Returns the first page that still needs to be read from the
+    /// underlying file, if any.
+    fn next_missing(&mut self) -> Option<u64> {
+        let num_pages = self.disk_len.div_ceil(PAGE_LEN as u64);
+        while self.first_maybe_missing < num_pages {
+            if !self.pages.contains_key(&self.first_maybe_missing) {
+                return Some(self.first_maybe_missing);
+            }
+            self.first_maybe_missing += 1;
+        }
+        None
+    }
+
+    /// Returns the range of the underlying file to read to fill in the given
+    /// page.
+    fn page_disk_range(&self, index: u64) -> (u64, usize) {
+        let start = PageCache::page_start(index);
+        let len = (self.disk_len - start).min(PAGE_LEN as u64) as usize;
+        (start, len)
+    }
+
+    fn insert_page(&mut self, index: u64, mut data: Vec<u8>) {
+        data.resize(PAGE_LEN, 0);
+        if let hash_map::Entry::Vacant(entry) = self.pages.entry(index) {
+            entry.insert(data.into_boxed_slice());
+            self.fetch_order.push_back(index);
+        }
+    }
+
+    /// Evicts the oldest clean pages until at most `max_clean_pages` pages
+    /// remain, unless the whole file is being kept.  This must only be
+    /// called between operations, since an operation that is retried needs
+    /// all the pages fetched for it to still be there the next time.
+    fn trim(&mut self) {
+        if self.keep_all {
+            return;
+        }
+        while self.pages.len() > self.max_clean_pages {
+            let index = match self.fetch_order.pop_front() {
+                Some(index) => index,
+                None => break,
+            };
+            if !self.dirty.contains(&index) {
+                self.pages.remove(&index);
+                self.first_maybe_missing = self.first_maybe_missing.min(index);
+            }
+        }
+    }
+
+    fn read(&mut self, position: u64, buf: &mut [u8]) -> io::Result<usize> {
+        if position >= self.len || buf.is_empty() {
+            return Ok(0);
+        }
+        let index = position / PAGE_LEN as u64;
+        let offset = (position - PageCache::page_start(index)) as usize;
+        let num_bytes = buf
+            .len()
+            .min(PAGE_LEN - offset)
+            .min((self.len - position) as usize);
+        match self.pages.get(&index) {
+            Some(page) => buf[..num_bytes]
+                .copy_from_slice(&page[offset..offset + num_bytes]),
+            None if self.is_present(index) => buf[..num_bytes].fill(0),
+            None => match self.missed.as_mut() {
+                Some(missed) => {
+                    missed.insert(index);
+                    buf[..num_bytes].fill(0);
+                }
+                None => return Err(missing_page_error(index)),
+            },
+        }
+        Ok(num_bytes)
+    }
+
+    /// Writes data at the given position.  If any page that's only partly
+    /// overwritten still needs to be read, returns an error without changing
+    /// anything.
+    fn write(&mut self, position: u64, buf: &[u8]) -> io::Result<()> {
+        if buf.is_empty() {
+            return Ok(());
+        }
+        let end = position + buf.len() as u64;
+        let first_index = position / PAGE_LEN as u64;
+        let last_index = (end - 1) / PAGE_LEN as u64;
+        for index in first_index..=last_index {
+            if !self.is_present(index) {
+                let (page_start, page_len) = self.page_disk_range(index);
+                if position > page_start || end < page_start + page_len as u64
+                {
+                    return Err(missing_page_error(index));
+                }
+            }
+        }
+        let mut written = 0;
+        while written < buf.len() {
+            let position = position + written as u64;
+            let index = position / PAGE_LEN as u64;
+            let offset = (position - PageCache::page_start(index)) as usize;
+            let num_bytes = (PAGE_LEN - offset).min(buf.len() - written);
+            let page = self
+                .pages
+                .entry(index)
+                .or_insert_with(|| vec![0; PAGE_LEN].into_boxed_slice());
+            page[offset..offset + num_bytes]
+                .copy_from_slice(&buf[written..written + num_bytes]);
+            self.dirty.insert(index);
+            written += num_bytes;
+        }
+        self.len = self.len.max(end);
+        Ok(())
+    }
+}
+
+//===========================================================================//
+
+/// A synchronous view of a `PageCache`, which the (synchronous) compound file
+/// code uses as its underlying file.  Reads of pages that haven't been
+/// fetched yet fail with a `MissingPage` error, after which the operation is
+/// retried once the page has been fetched.
+#[derive(Clone)]
+struct CachedFile {
+    cache: Arc<Mutex<PageCache>>,
+    position: u64,
+}
+
+impl Read for CachedFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let num_bytes = self.cache.lock().unwrap().read(self.position, buf)?;
+        self.position += num_bytes as u64;
+        Ok(num_bytes)
+    }
+}
+
+impl Write for CachedFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.cache.lock().unwrap().write(self.position, buf)?;
+        self.position += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for CachedFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(delta) => {
+                self.cache.lock().unwrap().len.checked_add_signed(delta)
+            }
+            SeekFrom::Current(delta) => {
+                self.position.checked_add_signed(delta)
+            }
+        };
+        match position {
+            Some(position) => {
+                self.position = position;
+                Ok(position)
+            }
+            None => invalid_input!("Invalid seek to {:?}", pos),
+        }
+    }
+}
+
+//===========================================================================//
+
+/// An I/O operation on the underlying file that is in progress.
+enum PagerState {
+    Idle,
+    Seeking { index: u64, for_write: bool, started: bool },
+    Reading { index: u64, data: Vec<u8>, filled: usize },
+    Writing { index: u64, data: Vec<u8>, written: usize },
+}
+
+/// Moves pages between the underlying asynchronous file and the page cache.
+///
+/// Reads and write-backs never overlap within a single call, so if one kind
+/// of operation finds the other still in progress, that can only be because
+/// the future driving it was dropped, and it is simply abandoned.  (Pages are
+/// only marked clean once they've been written out in full, so an abandoned
+/// write-back is redone by the next one.)
+struct Pager<F> {
+    inner: F,
+    cache: Arc<Mutex<PageCache>>,
+    state: PagerState,
+}
+
+impl<F> Pager<F> {
+    fn new(inner: F, len: u64) -> Pager<F> {
+        Pager {
+            inner,
+            cache: Arc::new(Mutex::new(PageCache::new(len))),
+            state: PagerState::Idle,
+        }
+    }
+
+    fn cached_file(&self) -> CachedFile {
+        CachedFile { cache: self.cache.clone(), position: 0 }
+    }
+
+    /// Clears out the current operation if it failed, so that the next call
+    /// starts over.
+    fn reset_on_error(
+        &mut self,
+        poll: Poll<io::Result<()>>,
+    ) -> Poll<io::Result<()>> {
+        if let Poll::Ready(Err(_)) = poll {
+            self.state = PagerState::Idle;
+        }
+        poll
+    }
+}
+
+impl<F: AsyncSeek + Unpin> Pager<F> {
+    fn poll_seek(
+        inner: &mut F,
+        cx: &mut Context<'_>,
+        started: &mut bool,
+        index: u64,
+    ) -> Poll<io::Result<()>> {
+        let mut inner = Pin::new(inner);
+        if !*started {
+            // Any earlier seek has to be finished before starting a new one.
+            ready!(inner.as_mut().poll_complete(cx))?;
+            let position = PageCache::page_start(index);
+            inner.as_mut().start_seek(SeekFrom::Start(position))?;
+            *started = true;
+        }
+        ready!(inner.poll_complete(cx))?;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<F: AsyncRead + AsyncSeek + Unpin> Pager<F> {
+    /// Reads in the given page, if it isn't already present.
+    fn poll_fetch(
+        &mut self,
+        cx: &mut Context<'_>,
+        index: u64,
+    ) -> Poll<io::Result<()>> {
+        let poll = self.poll_fetch_inner(cx, index);
+        self.reset_on_error(poll)
+    }
+
+    fn poll_fetch_inner(
+        &mut self,
+        cx: &mut Context<'_>,
+        index: u64,
+    ) -> Poll<io::Result<()>> {
+        loop {
+            match &mut self.state {
+                PagerState::Idle => {
+                    if self.cache.lock().unwrap().is_present(index) {
+                        return Poll::Ready(Ok(()));
+                    }
+                    self.state = PagerState::Seeking {
+                        index,
+                        for_write: false,
+                        started: false,
+                    };
+                }
+                PagerState::Seeking { index, for_write: false, started } => {
+                    let index = *index;
+                    ready!(Pager::poll_seek(
+                        &mut self.inner,
+                        cx,
+                        started,
+                        index
+                    ))?;
+                    let (_, len) =
+                        self.cache.lock().unwrap().page_disk_range(index);
+                    let data = vec![0; len];
+                    self.state =
+                        PagerState::Reading { index, data, filled: 0 };
+                }
+                PagerState::Reading { index, data, filled } => {
+                    while *filled < data.len() {
+                        let mut buf = ReadBuf::new(&mut data[*filled..]);
+                        ready!(
+                            Pin::new(&mut self.inner).poll_read(cx, &mut buf)
+                        )?;
+                        if buf.filled().is_empty() {
+                            return Poll::Ready(Err(io::Error::new(
+                                io::ErrorKind::UnexpectedEof,
+                                "File ended unexpectedly",
+                            )));
+                        }
+                        *filled += buf.filled().len();
+                    }
+                    let data = std::mem::take(data);
+                    self.cache.lock().unwrap().insert_page(*index, data);
+                    self.state = PagerState::Idle;
+                }
+                _ => self.state = PagerState::Idle,
+            }
+        }
+    }
+
+    /// Reads in every page of the file that hasn't been read yet.  From then
+    /// on, the whole file stays cached.
+    fn poll_fetch_all(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<io::Result<()>> {
+        self.cache.lock().unwrap().keep_all = true;
+        loop {
+            let next_missing = self.cache.lock().unwrap().next_missing();
+            match next_missing {
+                Some(index) => ready!(self.poll_fetch(cx, index))?,
+                None => return Poll::Ready(Ok(())),
+            }
+        }
+    }
+
+    /// Runs a synchronous operation on the page cache, fetching pages and
+    /// retrying the operation for as long as it needs pages that haven't
+    /// been read yet.
+    ///
+    /// Rather than stopping at the first missing page, the operation reads
+    /// zeros in its place and carries on, so that each attempt turns up as
+    /// many of the missing pages as it can; these are then all fetched
+    /// together before the next attempt.  (Otherwise, parsing a file would
+    /// start over once for every page it touches.)  The result of any
+    /// attempt that saw missing pages is thrown away, so the operation must
+    /// not have any lasting effects, and must cope with arbitrary data in
+    /// place of the missing pages (as parsing code must anyway).
+    async fn run<T, O>(&mut self, mut operation: O) -> io::Result<T>
+    where
+        O: FnMut() -> io::Result<T>,
+    {
+        self.cache.lock().unwrap().trim();
+        loop {
+            self.cache.lock().unwrap().missed = Some(BTreeSet::new());
+            let result = operation();
+            let mut missed =
+                self.cache.lock().unwrap().missed.take().unwrap_or_default();
+            if let Err(ref error) = result {
+                missed.extend(missing_page(error));
+            }
+            if missed.is_empty() {
+                return result;
+            }
+            drop(result);
+            for index in missed {
+                poll_fn(|cx| self.poll_fetch(cx, index)).await?;
+            }
+        }
+    }
+
+    async fn fetch_all(&mut self) -> io::Result<()> {
+        poll_fn(|cx| self.poll_fetch_all(cx)).await
+    }
+}
+
+impl<F: AsyncRead + AsyncWrite + AsyncSeek + Unpin> Pager<F> {
+    /// Writes every changed page back to the underlying file, and flushes
+    /// it.
+    fn poll_write_back(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<io::Result<()>> {
+        let poll = self.poll_write_back_inner(cx);
+        self.reset_on_error(poll)
+    }
+
+    fn poll_write_back_inner(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<io::Result<()>> {
+        loop {
+            match &mut self.state {
+                PagerState::Idle => {
+                    let next_dirty =
+                        self.cache.lock().unwrap().dirty.first().copied();
+                    match next_dirty {
+                        Some(index) => {
+                            self.state = PagerState::Seeking {
+                                index,
+                                for_write: true,
+                                started: false,
+                            };
+                        }
+                        None => break,
+                    }
+                }
+                PagerState::Seeking { index, for_write: true, started } => {
+                    let index = *index;
+                    ready!(Pager::poll_seek(
+                        &mut self.inner,
+                        cx,
+                        started,
+                        index
+                    ))?;
+                    let cache = self.cache.lock().unwrap();
+                    let len = (cache.len - PageCache::page_start(index))
+                        .min(PAGE_LEN as u64)
+                        as usize;
+                    let data = cache.pages[&index][..len].to_vec();
+                    drop(cache);
+                    self.state =
+                        PagerState::Writing { index, data, written: 0 };
+                }
+                PagerState::Writing { index, data, written } => {
+                    while *written < data.len() {
+                        let num_bytes = ready!(Pin::new(&mut self.inner)
+                            .poll_write(cx, &data[*written..]))?;
+                        if num_bytes == 0 {
+                            return Poll::Ready(Err(io::Error::new(
+                                io::ErrorKind::WriteZero,
+                                "Failed to write to underlying file",
+                            )));
+                        }
+                        *written += num_bytes;
+                    }
+                    self.cache.lock().unwrap().dirty.remove(index);
+                    self.state = PagerState::Idle;
+                }
+                _ => self.state = PagerState::Idle,
+            }
+        }
+        ready!(Pin::new(&mut self.inner).poll_flush(cx))?;
+        let mut cache = self.cache.lock().unwrap();
+        cache.disk_len = cache.len;
+        Poll::Ready(Ok(()))
+    }
+}
+
+//===========================================================================//
+
+/// A compound file backed by an asynchronous reader/writer, such as a
+/// `tokio::fs::File`.  This type is only available with the `async` cargo
+/// feature.
+///
+/// The file is parsed and updated by the same code as `CompoundFile`, running
+/// against an in-memory cache of the underlying file.  Parts of the file are
+/// read into the cache as they are needed, except that the whole file is read
+/// in before the first change is made to it.  Until then, the cache holds at
+/// most 16 MiB of the file's contents (plus whatever parts of the directory
+/// and allocation tables are needed to open it), dropping the parts that
+/// were read longest ago first; from then on, the whole file stays in
+/// memory.  Changes are only written back to the underlying file when
+/// `flush()` is called on the compound file, or on one of its streams.
+pub struct AsyncCompoundFile<F> {
+    comp: CompoundFile<CachedFile>,
+    pager: Pager<F>,
+}
+
+impl<F> AsyncCompoundFile<F> {
+    /// Returns the CFB format version used for this compound file.
+    pub fn version(&self) -> Version {
+        self.comp.version()
+    }
+
+    /// Returns information about the root storage.  This is guaranteed to
+    /// exist, and to be a storage.
+    pub fn root_entry(&self) -> Entry {
+        self.comp.root_entry()
+    }
+
+    /// Given a path within the compound file, get information about that
+    /// stream or storage object.
+    pub fn entry<P: AsRef<Path>>(&self, path: P) -> io::Result<Entry> {
+        self.comp.entry(path)
+    }
+
+    /// Returns an iterator over the entries within a storage object.  See
+    /// `CompoundFile::read_storage()`.
+    pub fn read_storage<P: AsRef<Path>>(
+        &self,
+        path: P,
+    ) -> io::Result<impl Iterator<Item = Entry> + '_> {
+        self.comp.read_storage(path)
+    }
+
+    /// Returns an iterator over all entries within the compound file, in
+    /// preorder.  See `CompoundFile::walk()`.
+    pub fn walk(&self) -> impl Iterator<Item = Entry> + '_ {
+        self.comp.walk()
+    }
+
+    /// Returns an iterator over all entries under a storage subtree, in
+    /// preorder.  See `CompoundFile::walk_storage()`.
+    pub fn walk_storage<P: AsRef<Path>>(
+        &self,
+        path: P,
+    ) -> io::Result<impl Iterator<Item = Entry> + '_> {
+        self.comp.walk_storage(path)
+    }
+
+    /// Returns an iterator over the entries under a storage subtree, using
+    /// the given options.  See `CompoundFile::walk_storage_with()`.
+    pub fn walk_storage_with<P: AsRef<Path>>(
+        &self,
+        path: P,
+        options: &WalkOptions,
+    ) -> io::Result<impl Iterator<Item = Entry> + '_> {
+        self.comp.walk_storage_with(path, options)
+    }
+
+    /// Returns true if there is an existing stream or storage at the given
+    /// path, or false if there is nothing at that path.
+    pub fn exists<P: AsRef<Path>>(&self, path: P) -> bool {
+        self.comp.exists(path)
+    }
+
+    /// Returns true if there is an existing stream at the given path, or
+    /// false if there is a storage or nothing at that path.
+    pub fn is_stream<P: AsRef<Path>>(&self, path: P) -> bool {
+        self.comp.is_stream(path)
+    }
+
+    /// Returns true if there is an existing storage at the given path, or
+    /// false if there is a stream or nothing at that path.
+    pub fn is_storage<P: AsRef<Path>>(&self, path: P) -> bool {
+        self.comp.is_storage(path)
+    }
+
+    /// Opens an existing stream in the compound file for reading and/or
+    /// writing (depending on what the underlying file supports).
+    pub fn open_stream<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+    ) -> io::Result<AsyncStream<'_, F>> {
+        let stream = self.comp.open_stream(path)?;
+        Ok(AsyncStream::new(stream, &mut self.pager))
+    }
+
+    /// Consumes the `AsyncCompoundFile`, returning the underlying
+    /// reader/writer.  Any changes that haven't been flushed are lost.
+    pub fn into_inner(self) -> F {
+        self.pager.inner
+    }
+}
+
+impl<F: AsyncRead + AsyncSeek + Unpin> AsyncCompoundFile<F> {
+    /// Opens an existing compound file, using the underlying reader.  If the
+    /// underlying reader also supports the `AsyncWrite` trait, then the
+    /// `AsyncCompoundFile` object will be writable as well.  See
+    /// `CompoundFile::open()`.
+    pub async fn open(inner: F) -> io::Result<AsyncCompoundFile<F>> {
+        AsyncCompoundFile::open_with(inner, CompoundFile::open).await
+    }
+
+    /// Like `open()`, but is stricter when parsing and will return an error
+    /// if the file violates the CFB spec in any way (which many CFB files in
+    /// the wild do).  See `CompoundFile::open_strict()`.
+    pub async fn open_strict(inner: F) -> io::Result<AsyncCompoundFile<F>> {
+        AsyncCompoundFile::open_with(inner, CompoundFile::open_strict).await
+    }
+
+    async fn open_with(
+        mut inner: F,
+        open: fn(CachedFile) -> io::Result<CompoundFile<CachedFile>>,
+    ) -> io::Result<AsyncCompoundFile<F>> {
+        let len = inner.seek(SeekFrom::End(0)).await?;
+        let mut pager = Pager::new(inner, len);
+        let file = pager.cached_file();
+        let comp = pager.run(|| open(file.clone())).await?;
+        Ok(AsyncCompoundFile { comp, pager })
+    }
+}
+
+impl<F: AsyncRead + AsyncWrite + AsyncSeek + Unpin> AsyncCompoundFile<F> {
+    /// Creates a new compound file with no contents, using the underlying
+    /// reader/writer.  The reader/writer should be initially empty.
+    pub async fn create(inner: F) -> io::Result<AsyncCompoundFile<F>> {
+        AsyncCompoundFile::create_with_version(Version::V4, inner).await
+    }
+
+    /// Creates a new compound file of the given version with no contents,
+    /// using the underlying writer.  The writer should be initially empty.
+    /// The new file is only written out by the first call to `flush()`.
+    pub async fn create_with_version(
+        version: Version,
+        inner: F,
+    ) -> io::Result<AsyncCompoundFile<F>> {
+        let pager = Pager::new(inner, 0);
+        let comp =
+            CompoundFile::create_with_version(version, pager.cached_file())?;
+        Ok(AsyncCompoundFile { comp, pager })
+    }
+
+    /// Creates a new, empty storage object (i.e. "directory") at the provided
+    /// path.  The parent storage object must already exist.
+    pub async fn create_storage<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+    ) -> io::Result<()> {
+        self.pager.fetch_all().await?;
+        self.comp.create_storage(path)
+    }
+
+    /// Recursively creates a storage and all of its parent storages if they
+    /// are missing.
+    pub async fn create_storage_all<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+    ) -> io::Result<()> {
+        self.pager.fetch_all().await?;
+        self.comp.create_storage_all(path)
+    }
+
+    /// Removes the storage object at the provided path.  The storage object
+    /// must exist and have no children.
+    pub async fn remove_storage<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+    ) -> io::Result<()> {
+        self.pager.fetch_all().await?;
+        self.comp.remove_storage(path)
+    }
+
+    /// Recursively removes a storage and all of its children.  If called on
+    /// the root storage, recursively removes all of its children but not the
+    /// root storage itself (which cannot be removed).
+    pub async fn remove_storage_all<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+    ) -> io::Result<()> {
+        self.pager.fetch_all().await?;
+        self.comp.remove_storage_all(path)
+    }
+
+    /// Creates and returns a new, empty stream object at the provided path.
+    /// If a stream already exists at that path, it will be replaced by the
+    /// new stream.  The parent storage object must already exist.
+    pub async fn create_stream<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+    ) -> io::Result<AsyncStream<'_, F>> {
+        self.pager.fetch_all().await?;
+        let stream = self.comp.create_stream(path)?;
+        Ok(AsyncStream::new(stream, &mut self.pager))
+    }
+
+    /// Removes the stream object at the provided path.
+    pub async fn remove_stream<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+    ) -> io::Result<()> {
+        self.pager.fetch_all().await?;
+        self.comp.remove_stream(path)
+    }
+
+    /// Flushes all changes to the underlying file.
+    pub async fn flush(&mut self) -> io::Result<()> {
+        self.pager.fetch_all().await?;
+        self.comp.flush()?;
+        poll_fn(|cx| self.pager.poll_write_back(cx)).await
+    }
+}
+
+//===========================================================================//
+
+/// A stream entry in an `AsyncCompoundFile`.  This type is only available
+/// with the `async` cargo feature.
+///
+/// Like a `Stream`, an `AsyncStream` buffers its data; buffered changes are
+/// moved into the compound file when the stream is dropped, but are only
+/// written to the underlying file once the stream (or the compound file) is
+/// flushed.
+pub struct AsyncStream<'a, F> {
+    stream: Stream<CachedFile>,
+    pager: &'a mut Pager<F>,
+    seek_result: Option<io::Result<u64>>,
+}
+
+impl<'a, F> AsyncStream<'a, F> {
+    fn new(
+        stream: Stream<CachedFile>,
+        pager: &'a mut Pager<F>,
+    ) -> AsyncStream<'a, F> {
+        AsyncStream { stream, pager, seek_result: None }
+    }
+
+    /// Returns the current length of the stream, in bytes.
+    pub fn len(&self) -> u64 {
+        self.stream.len()
+    }
+
+    /// Returns true if the stream is empty.
+    pub fn is_empty(&self) -> bool {
+        self.stream.is_empty()
+    }
+}
+
+impl<F: AsyncRead + AsyncSeek + Unpin> AsyncRead for AsyncStream<'_, F> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        this.pager.cache.lock().unwrap().trim();
+        loop {
+            match this.stream.read(buf.initialize_unfilled()) {
+                Ok(num_bytes) => {
+                    buf.advance(num_bytes);
+                    return Poll::Ready(Ok(()));
+                }
+                Err(error) => match missing_page(&error) {
+                    Some(index) => ready!(this.pager.poll_fetch(cx, index))?,
+                    None => return Poll::Ready(Err(error)),
+                },
+            }
+        }
+    }
+}
+
+impl<F: AsyncRead + AsyncWrite + AsyncSeek + Unpin> AsyncWrite
+    for AsyncStream<'_, F>
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        ready!(this.pager.poll_fetch_all(cx))?;
+        Poll::Ready(this.stream.write(buf))
+    }
+
+    fn poll_flush(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.pager.poll_fetch_all(cx))?;
+        this.stream.flush()?;
+        this.pager.poll_write_back(cx)
+    }
+
+    fn poll_shutdown(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<io::Result<()>> {
+        self.poll_flush(cx)
+    }
+}
+
+impl<F> AsyncSeek for AsyncStream<'_, F> {
+    fn start_seek(self: Pin<&mut Self>, position: SeekFrom) -> io::Result<()> {
+        let this = self.get_mut();
+        this.seek_result = Some(this.stream.seek(position));
+        Ok(())
+    }
+
+    fn poll_complete(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<io::Result<u64>> {
+        let this = self.get_mut();
+        Poll::Ready(match this.seek_result.take() {
+            Some(result) => result,
+            None => this.stream.stream_position(),
+        })
+    }
+}
+
+//===========================================================================//
+
+#[cfg(test)]
+mod tests {
+    use super::{Pager, PAGE_LEN};
+    use crate::{AsyncCompoundFile, CompoundFile};
+    use std::io::{Cursor, Write};
+    use tokio::io::AsyncReadExt;
+
+    /// Creates a compound file whose directory sectors are spread out over
+    /// many pages, interleaved with stream data.
+    fn make_cfb(num_streams: usize) -> Vec<u8> {
+        let mut comp = CompoundFile::create(Cursor::new(Vec::new())).unwrap();
+        for index in 0..num_streams {
+            let path = format!("/stream{}", index);
+            let data = vec![index as u8; 4096];
+            comp.create_stream(&path).unwrap().write_all(&data).unwrap();
+        }
+        comp.into_inner().into_inner()
+    }
+
+    #[tokio::test]
+    async fn open_fetches_missing_pages_in_batches() {
+        let data = make_cfb(400);
+        let num_pages = data.len().div_ceil(PAGE_LEN);
+        assert!(num_pages > 20);
+        let len = data.len() as u64;
+        let mut pager = Pager::new(Cursor::new(data), len);
+        let file = pager.cached_file();
+        let mut attempts = 0;
+        let comp = pager
+            .run(|| {
+                attempts += 1;
+                CompoundFile::open_strict(file.clone())
+            })
+            .await
+            .unwrap();
+        assert_eq!(comp.walk().count(), 401);
+        assert!(attempts <= 4, "took {} attempts", attempts);
+    }
+
+    #[tokio::test]
+    async fn clean_pages_are_evicted() {
+        let data = make_cfb(400);
+        let mut comp =
+            AsyncCompoundFile::open(Cursor::new(data.clone())).await.unwrap();
+        comp.pager.cache.lock().unwrap().max_clean_pages = 4;
+        for index in 0..400 {
+            let path = format!("/stream{}", index);
+            let mut stream = comp.open_stream(&path).unwrap();
+            let mut buffer = Vec::new();
+            stream.read_to_end(&mut buffer).await.unwrap();
+            assert_eq!(buffer, vec![index as u8; 4096]);
+            assert!(comp.pager.cache.lock().unwrap().pages.len() <= 5);
+        }
+
+        // Once the file is being changed, everything stays cached.
+        comp.create_stream("/new").await.unwrap();
+        let num_pages = data.len().div_ceil(PAGE_LEN);
+        for index in 0..400 {
+            let path = format!("/stream{}", index);
+            let mut stream = comp.open_stream(&path).unwrap();
+            let mut buffer = Vec::new();
+            stream.read_to_end(&mut buffer).await.unwrap();
+        }
+        assert!(comp.pager.cache.lock().unwrap().pages.len() >= num_pages);
+    }
+}
+
+//===========================================================================//
@@ -0,0 +1,302 @@
+use crate::internal::{path, Timestamp, Version};
+use crate::CompoundFile;
+use std::collections::BTreeMap;
+use std::io::{self, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+//===========================================================================//
+
+/// A description of a single storage or stream within a `CfbBuilder`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct EntrySpec {
+    data: Option<Vec<u8>>,
+    clsid: Uuid,
+    state_bits: u32,
+    created: Option<Timestamp>,
+    modified: Option<Timestamp>,
+}
+
+impl EntrySpec {
+    /// Returns a description of a storage with default attributes.
+    pub fn storage() -> EntrySpec {
+        EntrySpec {
+            data: None,
+            clsid: Uuid::nil(),
+            state_bits: 0,
+            created: None,
+            modified: None,
+        }
+    }
+
+    /// Returns a description of a stream with the given contents and default
+    /// attributes.
+    pub fn stream<D: Into<Vec<u8>>>(data: D) -> EntrySpec {
+        EntrySpec { data: Some(data.into()), ..EntrySpec::storage() }
+    }
+
+    /// Returns true if this describes a stream.
+    pub fn is_stream(&self) -> bool {
+        self.data.is_some()
+    }
+
+    /// Returns true if this describes a storage.
+    pub fn is_storage(&self) -> bool {
+        self.data.is_none()
+    }
+
+    /// Returns the stream's contents, or `None` for a storage.
+    pub fn data(&self) -> Option<&[u8]> {
+        self.data.as_deref()
+    }
+
+    /// Returns the CLSID (that is, the object class GUID) for this entry.
+    pub fn clsid(&self) -> &Uuid {
+        &self.clsid
+    }
+
+    /// Sets the CLSID for this entry.  Only storages may have a non-nil
+    /// CLSID.
+    pub fn with_clsid(mut self, clsid: Uuid) -> EntrySpec {
+        self.clsid = clsid;
+        self
+    }
+
+    /// Returns the user-defined bitflags set for this entry.
+    pub fn state_bits(&self) -> u32 {
+        self.state_bits
+    }
+
+    /// Sets the user-defined bitflags for this entry.
+    pub fn with_state_bits(mut self, bits: u32) -> EntrySpec {
+        self.state_bits = bits;
+        self
+    }
+
+    /// Returns the creation time for this entry, if one has been set.
+    pub fn created(&self) -> Option<Timestamp> {
+        self.created
+    }
+
+    /// Sets the creation time for this entry.  If none is set, the entry's
+    /// creation time is whatever `CompoundFile` gives new entries by default.
+    /// Streams always have a creation time of zero, as the CFB spec
+    /// requires, so this has no effect on them.
+    pub fn with_created(mut self, timestamp: Timestamp) -> EntrySpec {
+        self.created = Some(timestamp);
+        self
+    }
+
+    /// Returns the modification time for this entry, if one has been set.
+    pub fn modified(&self) -> Option<Timestamp> {
+        self.modified
+    }
+
+    /// Sets the modification time for this entry.  As with `with_created`,
+    /// this has no effect on streams.
+    pub fn with_modified(mut self, timestamp: Timestamp) -> EntrySpec {
+        self.modified = Some(timestamp);
+        self
+    }
+}
+
+//===========================================================================//
+
+/// A declarative description of the contents of a compound file, which can
+/// be created in one call with `build()`.
+///
+/// This is mainly useful for creating test fixtures, and (via
+/// `from_compound_file()`) for comparing the contents of a compound file
+/// against an expected snapshot.
+///
+/// ```
+/// use cfb::{CfbBuilder, EntrySpec};
+/// use std::io::Cursor;
+/// let builder = CfbBuilder::new()
+///     .with_storage("/foo")
+///     .with_stream("/foo/bar", "hello")
+///     .with_entry("/baz", EntrySpec::storage().with_state_bits(7));
+/// let mut comp = builder.build(Cursor::new(Vec::new())).unwrap();
+/// assert_eq!(comp.entry("/baz").unwrap().state_bits(), 7);
+/// let snapshot = CfbBuilder::from_compound_file(&mut comp).unwrap();
+/// let spec = snapshot.entry("/foo/bar").unwrap();
+/// assert_eq!(spec.data(), Some(&b"hello"[..]));
+/// ```
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CfbBuilder {
+    version: Version,
+    /// Ordered so that every storage comes before its children.
+    entries: BTreeMap<PathBuf, EntrySpec>,
+    fixed_timestamp: Option<Timestamp>,
+}
+
+impl CfbBuilder {
+    /// Returns a description of an empty version 4 compound file.
+    pub fn new() -> CfbBuilder {
+        CfbBuilder {
+            version: Version::V4,
+            entries: BTreeMap::new(),
+            fixed_timestamp: None,
+        }
+    }
+
+    /// Returns a description of the contents of an existing compound file,
+    /// including all stream data and every entry's attributes.
+    pub fn from_compound_file<F: Read + Seek>(
+        comp: &mut CompoundFile<F>,
+    ) -> io::Result<CfbBuilder> {
+        let mut builder = CfbBuilder::new().with_version(comp.version());
+        let entries: Vec<_> = comp.walk_storage("/")?.collect();
+        for entry in entries {
+            let mut spec = if entry.is_stream() {
+                let mut data = Vec::with_capacity(entry.len() as usize);
+                comp.open_stream(entry.path())?.read_to_end(&mut data)?;
+                EntrySpec::stream(data)
+            } else {
+                EntrySpec::storage()
+            };
+            spec.clsid = *entry.clsid();
+            spec.state_bits = entry.state_bits();
+            if entry.is_root() {
+                // The root entry's timestamps are zero by default, and it's
+                // omitted entirely if it has no other attributes either.
+                let nonzero = |timestamp| {
+                    Some(timestamp).filter(|&ts| ts != Timestamp::zero())
+                };
+                spec.created = nonzero(entry.created_timestamp());
+                spec.modified = nonzero(entry.modified_timestamp());
+                if spec == EntrySpec::storage() {
+                    continue;
+                }
+            } else if entry.is_storage() {
+                spec.created = Some(entry.created_timestamp());
+                spec.modified = Some(entry.modified_timestamp());
+            }
+            builder.entries.insert(entry.path().to_path_buf(), spec);
+        }
+        Ok(builder)
+    }
+
+    /// Returns the CFB format version to create.
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    /// Sets the CFB format version to create (version 4 by default).
+    pub fn with_version(mut self, version: Version) -> CfbBuilder {
+        self.version = version;
+        self
+    }
+
+    /// Returns the timestamp given to storages without explicit timestamps,
+    /// if one has been set.
+    pub fn fixed_timestamp(&self) -> Option<Timestamp> {
+        self.fixed_timestamp
+    }
+
+    /// Sets the creation and modified time given to storages without
+    /// explicit timestamps (which otherwise get the time at which they're
+    /// built), and makes the built `CompoundFile` use it in place of the
+    /// current time (see `CompoundFile::set_fixed_timestamp`).
+    pub fn with_fixed_timestamp(mut self, timestamp: Timestamp) -> CfbBuilder {
+        self.fixed_timestamp = Some(timestamp);
+        self
+    }
+
+    /// Returns an iterator over the described entries, with each storage
+    /// coming before its children.
+    pub fn entries(&self) -> impl Iterator<Item = (&Path, &EntrySpec)> {
+        self.entries.iter().map(|(path, spec)| (path.as_path(), spec))
+    }
+
+    /// Returns the description of the entry at the given path, if any.
+    pub fn entry<P: AsRef<Path>>(&self, path: P) -> Option<&EntrySpec> {
+        self.entries.get(path.as_ref())
+    }
+
+    /// Adds (or replaces) the entry at the given absolute path.  Any missing
+    /// parent storages are created with default attributes.  The root
+    /// storage can be given attributes by describing it at the path `/`.
+    pub fn with_entry<P: Into<PathBuf>>(
+        mut self,
+        path: P,
+        spec: EntrySpec,
+    ) -> CfbBuilder {
+        self.entries.insert(path.into(), spec);
+        self
+    }
+
+    /// Adds a storage with default attributes at the given path.
+    pub fn with_storage<P: Into<PathBuf>>(self, path: P) -> CfbBuilder {
+        self.with_entry(path, EntrySpec::storage())
+    }
+
+    /// Adds a stream with the given contents and default attributes at the
+    /// given path.
+    pub fn with_stream<P: Into<PathBuf>, D: Into<Vec<u8>>>(
+        self,
+        path: P,
+        data: D,
+    ) -> CfbBuilder {
+        self.with_entry(path, EntrySpec::stream(data))
+    }
+
+    /// Creates the described compound file using the underlying
+    /// reader/writer, which should be initially empty, and flushes it.
+    /// Returns an error if any of the paths are invalid, or if two of them
+    /// name the same entry (since names are case-insensitive).
+    pub fn build<F: Read + Write + Seek>(
+        &self,
+        inner: F,
+    ) -> io::Result<CompoundFile<F>> {
+        let mut comp = CompoundFile::create_with_version(self.version, inner)?;
+        comp.set_fixed_timestamp(self.fixed_timestamp);
+        for (path, spec) in self.entries.iter() {
+            let names = path::name_chain_from_path(path)?;
+            for name in names.iter() {
+                path::validate_name(name)?;
+            }
+            if let Some((_, parents)) = names.split_last() {
+                comp.create_storage_all(path::path_from_name_chain(parents))?;
+            }
+            match spec.data {
+                Some(_) if names.is_empty() => {
+                    invalid_input!("The root entry must be a storage");
+                }
+                Some(ref data) => {
+                    comp.create_new_stream(path)?.write_all(data)?;
+                }
+                None if names.is_empty() => {}
+                None => comp.create_storage(path)?,
+            }
+            if !spec.clsid.is_nil() {
+                comp.set_storage_clsid(path, spec.clsid)?;
+            }
+            comp.set_state_bits(path, spec.state_bits)?;
+        }
+        // Set timestamps last, so that creating children doesn't change them.
+        for (path, spec) in self.entries.iter() {
+            if spec.is_stream() {
+                continue;
+            }
+            comp.set_entry_with_path(path, |dir_entry| {
+                if let Some(created) = spec.created {
+                    dir_entry.creation_time = created;
+                }
+                if let Some(modified) = spec.modified {
+                    dir_entry.modified_time = modified;
+                }
+            })?;
+        }
+        comp.flush()?;
+        Ok(comp)
+    }
+}
+
+impl Default for CfbBuilder {
+    fn default() -> CfbBuilder {
+        CfbBuilder::new()
+    }
+}
+
+//===========================================================================//
@@ -1,6 +1,7 @@
-use crate::internal::{consts, Allocator, Sector, SectorInit};
+use crate::internal::{consts, Allocator, SectorInit};
 use std::cmp;
 use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::mem;
 
 //===========================================================================//
 
@@ -17,6 +18,15 @@ impl<'a, F> Chain<'a, F> {
         start_sector_id: u32,
         init: SectorInit,
     ) -> io::Result<Chain<'a, F>> {
+        if let Some(sector_ids) = allocator.take_cached_chain(start_sector_id)
+        {
+            return Ok(Chain {
+                allocator,
+                init,
+                sector_ids,
+                offset_from_start: 0,
+            });
+        }
         let mut sector_ids = Vec::<u32>::new();
         let mut current_sector_id = start_sector_id;
         let first_sector_id = start_sector_id;
@@ -44,39 +54,89 @@ impl<'a, F> Chain<'a, F> {
     pub fn len(&self) -> u64 {
         (self.allocator.sector_len() as u64) * (self.sector_ids.len() as u64)
     }
-}
 
-impl<'a, F: Seek> Chain<'a, F> {
-    pub fn into_subsector(
-        self,
-        subsector_index: u32,
-        subsector_len: usize,
-        offset_within_subsector: u64,
-    ) -> io::Result<Sector<'a, F>> {
-        debug_assert!(offset_within_subsector <= subsector_len as u64);
-        debug_assert_eq!(self.allocator.sector_len() % subsector_len, 0);
-        let subsectors_per_sector =
-            self.allocator.sector_len() / subsector_len;
-        let sector_index_within_chain =
-            subsector_index as usize / subsectors_per_sector;
-        let subsector_index_within_sector =
-            subsector_index % (subsectors_per_sector as u32);
-        let sector_id = *self
-            .sector_ids
-            .get(sector_index_within_chain)
-            .ok_or_else(|| {
-                io::Error::new(io::ErrorKind::InvalidData, "invalid sector id")
-            })?;
-        self.allocator.seek_within_subsector(
-            sector_id,
-            subsector_index_within_sector,
-            subsector_len,
-            offset_within_subsector,
-        )
+    /// Returns the number of sectors (at most `max_sectors`) in the run of
+    /// adjacent sectors that starts at the given index within the chain.
+    fn run_len(&self, index: usize, max_sectors: usize) -> u32 {
+        let first_sector_id = self.sector_ids[index] as u64;
+        self.sector_ids[index..]
+            .iter()
+            .take(max_sectors)
+            .enumerate()
+            .take_while(|&(offset, &sector_id)| {
+                sector_id as u64 == first_sector_id + offset as u64
+            })
+            .count() as u32
     }
 }
 
 impl<'a, F: Write + Seek> Chain<'a, F> {
+    /// Extends the chain as needed to hold all of the data, so that it can
+    /// be written a run of adjacent sectors at a time, and then writes it.
+    fn extend_and_write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let end = self.offset_from_start + buf.len() as u64;
+        while self.len() < end {
+            let new_sector_id =
+                if let Some(&last_sector_id) = self.sector_ids.last() {
+                    self.allocator.extend_chain(last_sector_id, self.init)?
+                } else {
+                    self.allocator.begin_chain(self.init)?
+                };
+            self.sector_ids.push(new_sector_id);
+        }
+        let total_len = self.len();
+        let sector_len = self.allocator.sector_len() as u64;
+        let mut bytes_written = 0;
+        while bytes_written < buf.len() {
+            let current_sector_index =
+                (self.offset_from_start / sector_len) as usize;
+            debug_assert!(current_sector_index < self.sector_ids.len());
+            let current_sector_id = self.sector_ids[current_sector_index];
+            let offset_within_sector = self.offset_from_start % sector_len;
+            let remaining = &buf[bytes_written..];
+            let max_sectors = (offset_within_sector + remaining.len() as u64)
+                .div_ceil(sector_len);
+            let num_sectors =
+                self.run_len(current_sector_index, max_sectors as usize);
+            let result = self
+                .allocator
+                .seek_within_sectors(
+                    current_sector_id,
+                    num_sectors,
+                    offset_within_sector,
+                )
+                .and_then(|mut sector| sector.write(remaining));
+            match result {
+                Ok(0) => break,
+                Ok(num_bytes) => {
+                    bytes_written += num_bytes;
+                    self.offset_from_start += num_bytes as u64;
+                }
+                // Report the data that did get written; the error will come
+                // up again on the next call.
+                Err(_) if bytes_written > 0 => break,
+                Err(error) => return Err(error),
+            }
+        }
+        debug_assert!(self.offset_from_start <= total_len);
+        Ok(bytes_written)
+    }
+
+    /// Frees any sectors in the chain past the first `num_sectors`.
+    fn truncate(&mut self, num_sectors: usize) -> io::Result<()> {
+        if num_sectors >= self.sector_ids.len() {
+            return Ok(());
+        }
+        if num_sectors == 0 {
+            self.allocator.free_chain(self.sector_ids[0])?;
+        } else {
+            self.allocator
+                .free_chain_after(self.sector_ids[num_sectors - 1])?;
+        }
+        self.sector_ids.truncate(num_sectors);
+        Ok(())
+    }
+
     /// Resizes the chain to the minimum number of sectors large enough to old
     /// `new_len` bytes, allocating or freeing sectors as needed.
     pub fn set_len(&mut self, new_len: u64) -> io::Result<()> {
@@ -87,12 +147,24 @@ impl<'a, F: Write + Seek> Chain<'a, F> {
             if let Some(&start_sector) = self.sector_ids.first() {
                 self.allocator.free_chain(start_sector)?;
             }
+            self.sector_ids.clear();
         } else if new_num_sectors <= self.sector_ids.len() {
             if new_num_sectors < self.sector_ids.len() {
                 self.allocator
                     .free_chain_after(self.sector_ids[new_num_sectors - 1])?;
+                self.sector_ids.truncate(new_num_sectors);
+            }
+            // Zero the remainder of the final sector, so that no stale data
+            // lingers past the end of the chain (and so that any later growth
+            // of the chain reads back as zeros).
+            let offset_within_sector = new_len % sector_len;
+            if offset_within_sector != 0 {
+                let last_sector_id = self.sector_ids[new_num_sectors - 1];
+                let remainder = (sector_len - offset_within_sector) as usize;
+                self.allocator
+                    .seek_within_sector(last_sector_id, offset_within_sector)?
+                    .write_all(&vec![0; remainder])?;
             }
-            // TODO: init remainder of final sector
         } else {
             for _ in self.sector_ids.len()..new_num_sectors {
                 let new_sector_id = if let Some(&last_sector_id) =
@@ -108,8 +180,19 @@ impl<'a, F: Write + Seek> Chain<'a, F> {
         Ok(())
     }
 
-    pub fn free(self) -> io::Result<()> {
-        self.allocator.free_chain(self.start_sector_id())
+    pub fn free(mut self) -> io::Result<()> {
+        let start_sector_id = self.start_sector_id();
+        self.sector_ids.clear();
+        self.allocator.free_chain(start_sector_id)
+    }
+}
+
+impl<'a, F> Drop for Chain<'a, F> {
+    fn drop(&mut self) {
+        // Hand the sector list back to the allocator, so that reopening this
+        // chain doesn't require walking the FAT again.
+        let sector_ids = mem::take(&mut self.sector_ids);
+        self.allocator.cache_chain(sector_ids);
     }
 }
 
@@ -148,9 +231,15 @@ impl<'a, F: Read + Seek> Read for Chain<'a, F> {
         debug_assert!(current_sector_index < self.sector_ids.len());
         let current_sector_id = self.sector_ids[current_sector_index];
         let offset_within_sector = self.offset_from_start % sector_len;
-        let mut sector = self
-            .allocator
-            .seek_within_sector(current_sector_id, offset_within_sector)?;
+        let max_sectors =
+            (offset_within_sector + max_len as u64).div_ceil(sector_len);
+        let num_sectors =
+            self.run_len(current_sector_index, max_sectors as usize);
+        let mut sector = self.allocator.seek_within_sectors(
+            current_sector_id,
+            num_sectors,
+            offset_within_sector,
+        )?;
         let bytes_read = sector.read(&mut buf[0..max_len])?;
         self.offset_from_start += bytes_read as u64;
         debug_assert!(self.offset_from_start <= total_len);
@@ -163,31 +252,15 @@ impl<'a, F: Write + Seek> Write for Chain<'a, F> {
         if buf.is_empty() {
             return Ok(0);
         }
-        let mut total_len = self.len();
+        let old_num_sectors = self.sector_ids.len();
+        let result = self.extend_and_write(buf);
+        // If the data stopped short (say, because of an I/O error), free any
+        // new sectors that it didn't reach, so that the chain doesn't end up
+        // longer than its stream.
         let sector_len = self.allocator.sector_len() as u64;
-        if self.offset_from_start == total_len {
-            let new_sector_id =
-                if let Some(&last_sector_id) = self.sector_ids.last() {
-                    self.allocator.extend_chain(last_sector_id, self.init)?
-                } else {
-                    self.allocator.begin_chain(self.init)?
-                };
-            self.sector_ids.push(new_sector_id);
-            total_len += sector_len;
-            debug_assert_eq!(total_len, self.len());
-        }
-        let current_sector_index =
-            (self.offset_from_start / sector_len) as usize;
-        debug_assert!(current_sector_index < self.sector_ids.len());
-        let current_sector_id = self.sector_ids[current_sector_index];
-        let offset_within_sector = self.offset_from_start % sector_len;
-        let mut sector = self
-            .allocator
-            .seek_within_sector(current_sector_id, offset_within_sector)?;
-        let bytes_written = sector.write(buf)?;
-        self.offset_from_start += bytes_written as u64;
-        debug_assert!(self.offset_from_start <= total_len);
-        Ok(bytes_written)
+        let num_sectors = self.offset_from_start.div_ceil(sector_len) as usize;
+        self.truncate(num_sectors.max(old_num_sectors))?;
+        result
     }
 
     fn flush(&mut self) -> io::Result<()> {
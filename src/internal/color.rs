@@ -6,19 +6,21 @@ use crate::internal::consts;
 /// red-black tree).
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum Color {
+    /// A red node.
     Red,
+    /// A black node.
     Black,
 }
 
 impl Color {
-    pub fn as_byte(&self) -> u8 {
+    pub(crate) fn as_byte(&self) -> u8 {
         match self {
             Color::Red => consts::COLOR_RED,
             Color::Black => consts::COLOR_BLACK,
         }
     }
 
-    pub fn from_byte(byte: u8) -> Option<Color> {
+    pub(crate) fn from_byte(byte: u8) -> Option<Color> {
         if byte == consts::COLOR_RED {
             Some(Color::Red)
         } else if byte == consts::COLOR_BLACK {
@@ -0,0 +1,600 @@
+use crate::internal::{hash, path, Entry, HashOptions, StreamOptions};
+use crate::CompoundFile;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Read, Seek};
+use std::ops::Range;
+use std::path::{Component, Path};
+
+//===========================================================================//
+
+/// The most bytes of stream data to compare at a time.
+const CHUNK_LEN: usize = 64 * 1024;
+
+/// Ranges of changed bytes separated by fewer than this many unchanged bytes
+/// are reported as a single range.
+const MERGE_GAP: u64 = 8;
+
+/// The most changed byte ranges that `Display` prints for a single stream.
+const MAX_DISPLAYED_RANGES: usize = 5;
+
+//===========================================================================//
+
+/// A piece of metadata that can differ between two versions of an entry.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum MetadataField {
+    /// The entry's CLSID.
+    Clsid,
+    /// The entry's user-defined bitflags.
+    StateBits,
+    /// The entry's creation time.
+    Created,
+    /// The entry's last modification time.
+    Modified,
+}
+
+/// A single difference between two compound files, as reported by
+/// `cfb::diff`.
+#[derive(Clone, Debug)]
+pub enum EntryChange {
+    /// An entry that exists only in the new file.
+    Added(Entry),
+    /// An entry that exists only in the old file.
+    Removed(Entry),
+    /// An entry that was moved or renamed, without changing its contents.
+    /// For a storage, the entries within it aren't reported separately.
+    Renamed {
+        /// The entry in the old file.
+        old: Entry,
+        /// The entry in the new file.
+        new: Entry,
+    },
+    /// A stream whose contents differ.
+    ContentChanged {
+        /// The stream in the old file.
+        old: Entry,
+        /// The stream in the new file.
+        new: Entry,
+        /// The byte ranges that differ, in order, with nearby ranges merged
+        /// together.  If the stream's length changed, the last range covers
+        /// the bytes past the end of the shorter version.
+        ranges: Vec<Range<u64>>,
+    },
+    /// An entry whose CLSID, state bits and/or timestamps differ.
+    MetadataChanged {
+        /// The entry in the old file.
+        old: Entry,
+        /// The entry in the new file.
+        new: Entry,
+        /// The fields that differ.
+        fields: Vec<MetadataField>,
+    },
+}
+
+impl EntryChange {
+    /// Returns the path of the changed entry.  For a renamed entry, this is
+    /// its path in the new file.
+    pub fn path(&self) -> &Path {
+        match self {
+            EntryChange::Added(entry) | EntryChange::Removed(entry) => {
+                entry.path()
+            }
+            EntryChange::Renamed { new, .. }
+            | EntryChange::ContentChanged { new, .. }
+            | EntryChange::MetadataChanged { new, .. } => new.path(),
+        }
+    }
+}
+
+impl fmt::Display for EntryChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EntryChange::Added(entry) => {
+                write!(f, "+ {}", entry.path().display())
+            }
+            EntryChange::Removed(entry) => {
+                write!(f, "- {}", entry.path().display())
+            }
+            EntryChange::Renamed { old, new } => write!(
+                f,
+                "R {} -> {}",
+                old.path().display(),
+                new.path().display()
+            ),
+            EntryChange::ContentChanged { old, new, ranges } => {
+                let num_bytes: u64 =
+                    ranges.iter().map(|range| range.end - range.start).sum();
+                write!(
+                    f,
+                    "M {}: {} bytes differ in {} range{}",
+                    new.path().display(),
+                    num_bytes,
+                    ranges.len(),
+                    if ranges.len() == 1 { "" } else { "s" }
+                )?;
+                for (index, range) in ranges.iter().enumerate() {
+                    if index == MAX_DISPLAYED_RANGES {
+                        write!(f, ", ...")?;
+                        break;
+                    }
+                    let separator = if index == 0 { " (" } else { ", " };
+                    write!(
+                        f,
+                        "{}0x{:x}..0x{:x}",
+                        separator, range.start, range.end
+                    )?;
+                }
+                if !ranges.is_empty() {
+                    write!(f, ")")?;
+                }
+                if old.len() != new.len() {
+                    write!(f, "; length {} -> {}", old.len(), new.len())?;
+                }
+                Ok(())
+            }
+            EntryChange::MetadataChanged { old, new, fields } => {
+                write!(f, "A {}:", new.path().display())?;
+                for (index, field) in fields.iter().enumerate() {
+                    let separator = if index == 0 { " " } else { "; " };
+                    match field {
+                        MetadataField::Clsid => write!(
+                            f,
+                            "{}clsid {} -> {}",
+                            separator,
+                            old.clsid().hyphenated(),
+                            new.clsid().hyphenated()
+                        )?,
+                        MetadataField::StateBits => write!(
+                            f,
+                            "{}state bits {:08x} -> {:08x}",
+                            separator,
+                            old.state_bits(),
+                            new.state_bits()
+                        )?,
+                        MetadataField::Created => write!(
+                            f,
+                            "{}created {} -> {}",
+                            separator,
+                            old.created_timestamp().value(),
+                            new.created_timestamp().value()
+                        )?,
+                        MetadataField::Modified => write!(
+                            f,
+                            "{}modified {} -> {}",
+                            separator,
+                            old.modified_timestamp().value(),
+                            new.modified_timestamp().value()
+                        )?,
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+//===========================================================================//
+
+/// The differences between two compound files, as reported by `cfb::diff`.
+/// Its `Display` implementation prints one change per line.
+#[derive(Clone, Debug)]
+pub struct CfbDiff {
+    changes: Vec<EntryChange>,
+}
+
+impl CfbDiff {
+    /// Returns the individual changes, ordered by path.
+    pub fn changes(&self) -> &[EntryChange] {
+        &self.changes
+    }
+
+    /// Returns true if the two files have the same logical contents.
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+impl fmt::Display for CfbDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for change in self.changes.iter() {
+            writeln!(f, "{}", change)?;
+        }
+        Ok(())
+    }
+}
+
+//===========================================================================//
+
+fn path_names(path: &Path) -> Vec<&str> {
+    path.components()
+        .filter_map(|component| match component {
+            Component::Normal(name) => name.to_str(),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Compares two paths within a compound file, component by component, using
+/// CFB name ordering.
+pub fn compare_paths(path1: &Path, path2: &Path) -> Ordering {
+    let (names1, names2) = (path_names(path1), path_names(path2));
+    for (name1, name2) in names1.iter().zip(names2.iter()) {
+        match path::compare_names(name1, name2) {
+            Ordering::Equal => {}
+            ordering => return ordering,
+        }
+    }
+    names1.len().cmp(&names2.len())
+}
+
+pub fn sorted_entries<F>(comp: &CompoundFile<F>) -> io::Result<Vec<Entry>> {
+    // Unlike walk(), walk_storage() reports any error from loading a lazily
+    // opened directory, rather than panicking.
+    let mut entries: Vec<Entry> = comp.walk_storage("/")?.collect();
+    entries.sort_by(|a, b| compare_paths(a.path(), b.path()));
+    Ok(entries)
+}
+
+/// Returns true if `path` is strictly within one of the given storages,
+/// which must be sorted by path.
+fn is_within(path: &Path, storages: &[&Entry]) -> bool {
+    path.ancestors().skip(1).any(|ancestor| {
+        storages
+            .binary_search_by(|entry| compare_paths(entry.path(), ancestor))
+            .is_ok()
+    })
+}
+
+/// Returns true if the entry is a stream with no data or a storage with no
+/// children.
+fn is_empty<F: Read + Seek>(
+    comp: &CompoundFile<F>,
+    entry: &Entry,
+) -> io::Result<bool> {
+    if entry.is_stream() {
+        Ok(entry.len() == 0)
+    } else {
+        Ok(comp.read_storage(entry.path())?.next().is_none())
+    }
+}
+
+fn changed_metadata(old: &Entry, new: &Entry) -> Vec<MetadataField> {
+    let mut fields = Vec::new();
+    if old.clsid() != new.clsid() {
+        fields.push(MetadataField::Clsid);
+    }
+    if old.state_bits() != new.state_bits() {
+        fields.push(MetadataField::StateBits);
+    }
+    if old.created_timestamp() != new.created_timestamp() {
+        fields.push(MetadataField::Created);
+    }
+    if old.modified_timestamp() != new.modified_timestamp() {
+        fields.push(MetadataField::Modified);
+    }
+    fields
+}
+
+/// Returns the ranges of bytes that differ between two streams.
+fn changed_ranges<F: Read + Seek, G: Read + Seek>(
+    old_comp: &CompoundFile<F>,
+    old: &Entry,
+    new_comp: &CompoundFile<G>,
+    new: &Entry,
+) -> io::Result<Vec<Range<u64>>> {
+    let options = StreamOptions::new().with_buffer_size(0);
+    let mut old_stream =
+        old_comp.open_stream_with_path(old.path(), &options)?;
+    let mut new_stream =
+        new_comp.open_stream_with_path(new.path(), &options)?;
+    let common_len = old.len().min(new.len());
+    let mut ranges: Vec<Range<u64>> = Vec::new();
+    let mut push_range = |range: Range<u64>| match ranges.last_mut() {
+        Some(last) if range.start - last.end < MERGE_GAP => {
+            last.end = range.end
+        }
+        _ => ranges.push(range),
+    };
+    let mut old_buffer = vec![0u8; CHUNK_LEN];
+    let mut new_buffer = vec![0u8; CHUNK_LEN];
+    let mut offset = 0;
+    while offset < common_len {
+        let len = (common_len - offset).min(CHUNK_LEN as u64) as usize;
+        old_stream.read_exact(&mut old_buffer[..len])?;
+        new_stream.read_exact(&mut new_buffer[..len])?;
+        let mut index = 0;
+        while index < len {
+            if old_buffer[index] == new_buffer[index] {
+                index += 1;
+                continue;
+            }
+            let start = index;
+            while index < len && old_buffer[index] != new_buffer[index] {
+                index += 1;
+            }
+            push_range(offset + start as u64..offset + index as u64);
+        }
+        offset += len as u64;
+    }
+    if old.len() != new.len() {
+        push_range(common_len..old.len().max(new.len()));
+    }
+    Ok(ranges)
+}
+
+/// Returns true if two streams have the same length and data.
+pub fn streams_equal<F: Read + Seek, G: Read + Seek>(
+    old_comp: &CompoundFile<F>,
+    old: &Entry,
+    new_comp: &CompoundFile<G>,
+    new: &Entry,
+) -> io::Result<bool> {
+    if old.len() != new.len() {
+        return Ok(false);
+    }
+    let options = StreamOptions::new().with_buffer_size(0);
+    let mut old_stream =
+        old_comp.open_stream_with_path(old.path(), &options)?;
+    let mut new_stream =
+        new_comp.open_stream_with_path(new.path(), &options)?;
+    let mut old_buffer = vec![0u8; CHUNK_LEN];
+    let mut new_buffer = vec![0u8; CHUNK_LEN];
+    let mut offset = 0;
+    while offset < old.len() {
+        let len = (old.len() - offset).min(CHUNK_LEN as u64) as usize;
+        old_stream.read_exact(&mut old_buffer[..len])?;
+        new_stream.read_exact(&mut new_buffer[..len])?;
+        if old_buffer[..len] != new_buffer[..len] {
+            return Ok(false);
+        }
+        offset += len as u64;
+    }
+    Ok(true)
+}
+
+/// Returns true if two entries have the same contents, in the sense of
+/// `CompoundFile::hash_entry`: streams with the same data, or storages whose
+/// children have the same names and (recursively) the same contents.
+fn contents_equal<F: Read + Seek, G: Read + Seek>(
+    old_comp: &CompoundFile<F>,
+    old: &Entry,
+    new_comp: &CompoundFile<G>,
+    new: &Entry,
+) -> io::Result<bool> {
+    if old.is_stream() != new.is_stream() {
+        return Ok(false);
+    }
+    if old.is_stream() {
+        return streams_equal(old_comp, old, new_comp, new);
+    }
+    let old_children: Vec<Entry> =
+        old_comp.read_storage(old.path())?.collect();
+    let new_children: Vec<Entry> =
+        new_comp.read_storage(new.path())?.collect();
+    if old_children.len() != new_children.len() {
+        return Ok(false);
+    }
+    for (old_child, new_child) in old_children.iter().zip(&new_children) {
+        if old_child.name() != new_child.name()
+            || !contents_equal(old_comp, old_child, new_comp, new_child)?
+        {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Returns the structural differences between two compound files: entries
+/// that were added, removed or renamed, streams whose contents changed, and
+/// entries whose metadata changed.  Entries are matched up by path (using
+/// case-insensitive CFB name comparison); an entry that was removed from one
+/// place and added in another with the same contents (found by comparing
+/// `CompoundFile::hash_entry` digests, then confirmed by comparing the data)
+/// is reported as renamed, unless it's empty and other empty entries were
+/// also removed or added.
+pub fn diff<F: Read + Seek, G: Read + Seek>(
+    old_comp: &CompoundFile<F>,
+    new_comp: &CompoundFile<G>,
+) -> io::Result<CfbDiff> {
+    let old_entries = sorted_entries(old_comp)?;
+    let new_entries = sorted_entries(new_comp)?;
+    let mut changes = Vec::new();
+    let mut removed: Vec<&Entry> = Vec::new();
+    let mut added: Vec<&Entry> = Vec::new();
+    let (mut old_index, mut new_index) = (0, 0);
+    while old_index < old_entries.len() || new_index < new_entries.len() {
+        let ordering =
+            match (old_entries.get(old_index), new_entries.get(new_index)) {
+                (Some(old), Some(new)) => {
+                    compare_paths(old.path(), new.path())
+                }
+                (Some(_), None) => Ordering::Less,
+                _ => Ordering::Greater,
+            };
+        match ordering {
+            Ordering::Less => {
+                removed.push(&old_entries[old_index]);
+                old_index += 1;
+            }
+            Ordering::Greater => {
+                added.push(&new_entries[new_index]);
+                new_index += 1;
+            }
+            Ordering::Equal => {
+                let old = &old_entries[old_index];
+                let new = &new_entries[new_index];
+                old_index += 1;
+                new_index += 1;
+                if old.is_stream() != new.is_stream() {
+                    removed.push(old);
+                    added.push(new);
+                    continue;
+                }
+                if old.is_stream() {
+                    let ranges = changed_ranges(old_comp, old, new_comp, new)?;
+                    if !ranges.is_empty() {
+                        changes.push(EntryChange::ContentChanged {
+                            old: old.clone(),
+                            new: new.clone(),
+                            ranges,
+                        });
+                    }
+                }
+                let fields = changed_metadata(old, new);
+                if !fields.is_empty() {
+                    changes.push(EntryChange::MetadataChanged {
+                        old: old.clone(),
+                        new: new.clone(),
+                        fields,
+                    });
+                }
+            }
+        }
+    }
+
+    // Match up removed and added entries with the same contents as renames.
+    // Only the outermost removed and added entries are candidates, since
+    // moving a storage moves everything within it.
+    let removed_storages: Vec<&Entry> =
+        removed.iter().copied().filter(|entry| entry.is_storage()).collect();
+    let added_storages: Vec<&Entry> =
+        added.iter().copied().filter(|entry| entry.is_storage()).collect();
+    let outer_removed: Vec<&Entry> = removed
+        .iter()
+        .copied()
+        .filter(|entry| !is_within(entry.path(), &removed_storages))
+        .collect();
+    let outer_added: Vec<&Entry> = added
+        .iter()
+        .copied()
+        .filter(|entry| !is_within(entry.path(), &added_storages))
+        .collect();
+    let mut renamed: Vec<(&Entry, &Entry)> = Vec::new();
+    if !outer_removed.is_empty() && !outer_added.is_empty() {
+        let options = HashOptions::new();
+        let mut candidates: HashMap<Vec<u8>, Vec<&Entry>> = HashMap::new();
+        for &entry in outer_added.iter().rev() {
+            let digest = hash::hash_entry(new_comp, entry.path(), &options)?;
+            candidates.entry(digest).or_default().push(entry);
+        }
+        let mut removed_digests: Vec<(&Entry, Vec<u8>)> = Vec::new();
+        let mut num_removed: HashMap<Vec<u8>, usize> = HashMap::new();
+        for &entry in outer_removed.iter() {
+            let digest = hash::hash_entry(old_comp, entry.path(), &options)?;
+            *num_removed.entry(digest.clone()).or_default() += 1;
+            removed_digests.push((entry, digest));
+        }
+        for (entry, digest) in removed_digests {
+            // Every empty stream (or empty storage) has the same contents, so
+            // an empty entry only counts as renamed if it's the only one
+            // removed and the only one added with its digest.
+            let is_unique = num_removed[&digest] == 1
+                && candidates.get(&digest).is_some_and(|list| list.len() == 1);
+            if !is_unique && is_empty(old_comp, entry)? {
+                continue;
+            }
+            // Matching digests could be a hash collision, so confirm each
+            // candidate by comparing its contents.
+            let list = match candidates.get_mut(&digest) {
+                Some(list) => list,
+                None => continue,
+            };
+            let mut matched = None;
+            for index in (0..list.len()).rev() {
+                if contents_equal(old_comp, entry, new_comp, list[index])? {
+                    matched = Some(index);
+                    break;
+                }
+            }
+            if let Some(index) = matched {
+                renamed.push((entry, list.remove(index)));
+            }
+        }
+    }
+    let mut renamed_old: Vec<&Entry> =
+        renamed.iter().map(|&(old, _)| old).collect();
+    let mut renamed_new: Vec<&Entry> =
+        renamed.iter().map(|&(_, new)| new).collect();
+    renamed_old.sort_by(|a, b| compare_paths(a.path(), b.path()));
+    renamed_new.sort_by(|a, b| compare_paths(a.path(), b.path()));
+    let is_renamed = |entry: &Entry, renamed: &[&Entry]| {
+        renamed
+            .binary_search_by(|other| {
+                compare_paths(other.path(), entry.path())
+            })
+            .is_ok()
+            || is_within(entry.path(), renamed)
+    };
+    for entry in removed {
+        if !is_renamed(entry, &renamed_old) {
+            changes.push(EntryChange::Removed(entry.clone()));
+        }
+    }
+    for entry in added {
+        if !is_renamed(entry, &renamed_new) {
+            changes.push(EntryChange::Added(entry.clone()));
+        }
+    }
+    for (old, new) in renamed {
+        changes
+            .push(EntryChange::Renamed { old: old.clone(), new: new.clone() });
+    }
+    changes.sort_by(|a, b| compare_paths(a.path(), b.path()));
+    Ok(CfbDiff { changes })
+}
+
+//===========================================================================//
+
+#[cfg(test)]
+mod tests {
+    use super::{compare_paths, contents_equal};
+    use crate::CompoundFile;
+    use std::cmp::Ordering;
+    use std::io::{Cursor, Write};
+    use std::path::Path;
+
+    #[test]
+    fn path_ordering() {
+        let cmp = |a: &str, b: &str| compare_paths(Path::new(a), Path::new(b));
+        assert_eq!(cmp("/", "/"), Ordering::Equal);
+        assert_eq!(cmp("/", "/a"), Ordering::Less);
+        assert_eq!(cmp("/foo", "/FOO"), Ordering::Equal);
+        assert_eq!(cmp("/foo/bar", "/FOO/BAR"), Ordering::Equal);
+        assert_eq!(cmp("/zz", "/aaa"), Ordering::Less);
+        assert_eq!(cmp("/zz/aaa", "/aaa"), Ordering::Less);
+        assert_eq!(cmp("/b", "/b/a"), Ordering::Less);
+    }
+
+    #[test]
+    fn rename_candidates_are_confirmed_by_contents() {
+        let mut comp = CompoundFile::create(Cursor::new(Vec::new())).unwrap();
+        for (path, data) in [
+            ("/a/x", "data"),
+            ("/b/x", "data"),
+            ("/c/x", "date"),
+            ("/d/y", "data"),
+        ] {
+            let storage = &path[..2];
+            if !comp.exists(storage) {
+                comp.create_storage(storage).unwrap();
+            }
+            comp.create_stream(path)
+                .unwrap()
+                .write_all(data.as_bytes())
+                .unwrap();
+        }
+        let equal = |a: &str, b: &str| {
+            let a = comp.entry(a).unwrap();
+            let b = comp.entry(b).unwrap();
+            contents_equal(&comp, &a, &comp, &b).unwrap()
+        };
+        assert!(equal("/a", "/b"));
+        assert!(equal("/a/x", "/d/y"));
+        assert!(!equal("/a", "/c"));
+        assert!(!equal("/a", "/d"));
+        assert!(!equal("/a", "/a/x"));
+    }
+}
+
+//===========================================================================//
@@ -1,11 +1,11 @@
 use crate::internal::{
-    self, consts, Allocator, Chain, Color, DirEntry, ObjType, Sector,
-    SectorInit, Timestamp, Validation, Version,
+    self, consts, AllocationPolicy, Allocator, Chain, Color, DirEntry,
+    ObjType, Sector, SectorInit, Timestamp, Validation, Version,
 };
 use crate::WriteLeNumber;
 use fnv::FnvHashSet;
 use std::cmp::Ordering;
-use std::io::{self, Seek, SeekFrom, Write};
+use std::io::{self, Read, Seek, SeekFrom, Write};
 
 //===========================================================================//
 
@@ -24,6 +24,36 @@ pub struct Directory<F> {
     allocator: Allocator<F>,
     dir_entries: Vec<DirEntry>,
     dir_start_sector: u32,
+    /// For a directory opened in lazy mode, the parts of it that haven't been
+    /// loaded yet.  Entries in sectors that haven't been loaded are stored as
+    /// unallocated placeholders.
+    lazy: Option<LazyDirectory<F>>,
+    /// If set, used in place of the current time for new timestamps.
+    fixed_timestamp: Option<Timestamp>,
+    /// Incremented whenever an entry is added to or removed from the storage
+    /// tree.
+    structure_generation: u64,
+    /// The parent storage of each reachable entry (or NO_STREAM, for the root
+    /// and unreachable entries), along with the structure generation it was
+    /// computed for.
+    parent_ids: Option<(u64, Vec<u32>)>,
+}
+
+/// The loading state of a directory that is read from the file on demand.
+struct LazyDirectory<F> {
+    validation: Validation,
+    /// Reads the directory entries in the given sector.
+    read_dir_sector:
+        fn(&mut Allocator<F>, u32, Validation) -> io::Result<Vec<DirEntry>>,
+    /// The sectors of the directory chain, in order.
+    dir_sector_ids: Vec<u32>,
+    /// Which of the directory sectors have been read into `dir_entries`.
+    loaded_sectors: Vec<bool>,
+    /// The storages (including the root) whose child entries have all been
+    /// loaded and validated.
+    loaded_storages: FnvHashSet<u32>,
+    /// All entries that have been validated so far, to detect loops.
+    visited: FnvHashSet<u32>,
 }
 
 impl<F> Directory<F> {
@@ -33,7 +63,15 @@ impl<F> Directory<F> {
         dir_start_sector: u32,
         validation: Validation,
     ) -> io::Result<Directory<F>> {
-        let directory = Directory { allocator, dir_entries, dir_start_sector };
+        let directory = Directory {
+            allocator,
+            dir_entries,
+            dir_start_sector,
+            lazy: None,
+            fixed_timestamp: None,
+            structure_generation: 0,
+            parent_ids: None,
+        };
         directory.validate(validation)?;
         Ok(directory)
     }
@@ -50,6 +88,127 @@ impl<F> Directory<F> {
         self.allocator.sector_len()
     }
 
+    pub fn num_sectors(&self) -> u32 {
+        self.allocator.num_sectors()
+    }
+
+    pub fn secure_delete(&self) -> bool {
+        self.allocator.secure_delete()
+    }
+
+    pub fn set_secure_delete(&mut self, secure_delete: bool) {
+        self.allocator.set_secure_delete(secure_delete);
+    }
+
+    pub fn allocation_policy(&self) -> AllocationPolicy {
+        self.allocator.allocation_policy()
+    }
+
+    pub fn set_allocation_policy(&mut self, policy: AllocationPolicy) {
+        self.allocator.set_allocation_policy(policy);
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.allocator.generation()
+    }
+
+    #[cfg(test)]
+    pub fn fat_visits(&self) -> u64 {
+        self.allocator.fat_visits()
+    }
+
+    /// Returns a number that changes whenever an entry is added to or removed
+    /// from the storage tree (but not when an entry's contents or attributes
+    /// change), so that a stream ID or a position within the tree remains
+    /// valid as long as this number is the same.
+    pub fn structure_generation(&self) -> u64 {
+        self.structure_generation
+    }
+
+    /// Returns the stream ID of the storage containing the given entry, or
+    /// `None` if the entry is the root or isn't reachable from it.  In lazy
+    /// mode, this first loads the rest of the directory.
+    pub fn parent_id(&mut self, stream_id: u32) -> io::Result<Option<u32>> {
+        self.load_all()?;
+        let generation = self.structure_generation;
+        let parent_ids = match self.parent_ids {
+            Some((computed, ref parent_ids)) if computed == generation => {
+                parent_ids
+            }
+            _ => {
+                let parent_ids = self.compute_parent_ids();
+                &self.parent_ids.insert((generation, parent_ids)).1
+            }
+        };
+        match parent_ids.get(stream_id as usize) {
+            Some(&consts::NO_STREAM) | None => Ok(None),
+            Some(&parent_id) => Ok(Some(parent_id)),
+        }
+    }
+
+    fn compute_parent_ids(&self) -> Vec<u32> {
+        let mut parent_ids = vec![consts::NO_STREAM; self.dir_entries.len()];
+        let mut storages = vec![consts::ROOT_STREAM_ID];
+        while let Some(storage_id) = storages.pop() {
+            let mut siblings = vec![self.dir_entry(storage_id).child];
+            while let Some(stream_id) = siblings.pop() {
+                // The directory has been validated, but guard against loops
+                // anyway, since an endless loop here would be much worse
+                // than a wrong answer.
+                if stream_id == consts::NO_STREAM
+                    || stream_id == consts::ROOT_STREAM_ID
+                    || parent_ids[stream_id as usize] != consts::NO_STREAM
+                {
+                    continue;
+                }
+                parent_ids[stream_id as usize] = storage_id;
+                let dir_entry = self.dir_entry(stream_id);
+                siblings.push(dir_entry.left_sibling);
+                siblings.push(dir_entry.right_sibling);
+                if dir_entry.obj_type == ObjType::Storage {
+                    storages.push(stream_id);
+                }
+            }
+        }
+        parent_ids
+    }
+
+    pub fn fixed_timestamp(&self) -> Option<Timestamp> {
+        self.fixed_timestamp
+    }
+
+    pub fn set_fixed_timestamp(&mut self, timestamp: Option<Timestamp>) {
+        self.fixed_timestamp = timestamp;
+    }
+
+    /// Returns the timestamp to use for the current time.
+    pub fn now(&self) -> Timestamp {
+        self.fixed_timestamp.unwrap_or_else(Timestamp::now)
+    }
+
+    pub fn fat(&self) -> &[u32] {
+        self.allocator.fat()
+    }
+
+    pub fn difat(&self) -> &[u32] {
+        self.allocator.difat()
+    }
+
+    pub fn difat_sector_ids(&self) -> &[u32] {
+        self.allocator.difat_sector_ids()
+    }
+
+    pub fn chain_sector_ids(
+        &self,
+        start_sector_id: u32,
+    ) -> io::Result<Vec<u32>> {
+        self.allocator.chain_sector_ids(start_sector_id)
+    }
+
+    pub fn dir_start_sector(&self) -> u32 {
+        self.dir_start_sector
+    }
+
     pub fn into_inner(self) -> F {
         self.allocator.into_inner()
     }
@@ -89,11 +248,46 @@ impl<F> Directory<F> {
         &self.dir_entries[stream_id as usize]
     }
 
+    /// Returns all directory entries, including unallocated ones, indexed by
+    /// stream ID.  Returns an error if the directory was opened in lazy mode
+    /// and hasn't been fully loaded yet.
+    pub fn dir_entries(&self) -> io::Result<&[DirEntry]> {
+        if self.is_lazy() {
+            invalid_input!("The directory hasn't been fully loaded");
+        }
+        Ok(&self.dir_entries)
+    }
+
     fn dir_entry_mut(&mut self, stream_id: u32) -> &mut DirEntry {
         &mut self.dir_entries[stream_id as usize]
     }
 
     fn validate(&self, validation: Validation) -> io::Result<()> {
+        self.validate_root()?;
+        let mut visited = FnvHashSet::default();
+        let mut stack = vec![(consts::ROOT_STREAM_ID, false)];
+        while let Some((stream_id, parent_is_red)) = stack.pop() {
+            if visited.contains(&stream_id) {
+                malformed!("loop in tree");
+            }
+            visited.insert(stream_id);
+            self.validate_node(stream_id, parent_is_red, validation)?;
+            let dir_entry = self.dir_entry(stream_id);
+            let node_is_red = dir_entry.color == Color::Red;
+            if dir_entry.left_sibling != consts::NO_STREAM {
+                stack.push((dir_entry.left_sibling, node_is_red));
+            }
+            if dir_entry.right_sibling != consts::NO_STREAM {
+                stack.push((dir_entry.right_sibling, node_is_red));
+            }
+            if dir_entry.child != consts::NO_STREAM {
+                stack.push((dir_entry.child, false));
+            }
+        }
+        Ok(())
+    }
+
+    fn validate_root(&self) -> io::Result<()> {
         if self.dir_entries.is_empty() {
             malformed!("root entry is missing");
         }
@@ -105,95 +299,278 @@ impl<F> Directory<F> {
                 consts::MINI_SECTOR_LEN
             );
         }
-        let mut visited = FnvHashSet::default();
-        let mut stack = vec![(consts::ROOT_STREAM_ID, false)];
-        while let Some((stream_id, parent_is_red)) = stack.pop() {
-            if visited.contains(&stream_id) {
-                malformed!("loop in tree");
+        Ok(())
+    }
+
+    /// Checks a single node of the tree: its object type and color, and that
+    /// its sibling and child indices are in range and its siblings' names are
+    /// correctly ordered relative to its own.  The node's siblings must
+    /// already be loaded.
+    fn validate_node(
+        &self,
+        stream_id: u32,
+        parent_is_red: bool,
+        validation: Validation,
+    ) -> io::Result<()> {
+        let dir_entry = self.dir_entry(stream_id);
+        if stream_id == consts::ROOT_STREAM_ID {
+            if dir_entry.obj_type != ObjType::Root {
+                malformed!(
+                    "root entry has object type {:?}",
+                    dir_entry.obj_type
+                );
             }
-            visited.insert(stream_id);
-            let dir_entry = self.dir_entry(stream_id);
-            if stream_id == consts::ROOT_STREAM_ID {
-                if dir_entry.obj_type != ObjType::Root {
-                    malformed!(
-                        "root entry has object type {:?}",
-                        dir_entry.obj_type
-                    );
-                }
-            } else if dir_entry.obj_type != ObjType::Storage
-                && dir_entry.obj_type != ObjType::Stream
+        } else if dir_entry.obj_type != ObjType::Storage
+            && dir_entry.obj_type != ObjType::Stream
+        {
+            malformed!(
+                "non-root entry with object type {:?}",
+                dir_entry.obj_type
+            );
+        }
+        let node_is_red = dir_entry.color == Color::Red;
+        // The MS-CFB spec section 2.6.4 says that two consecutive nodes in
+        // the red-black tree for siblings within a storage object MUST NOT
+        // both be red, but apparently some implementations don't obey this
+        // (see https://github.com/mdsteele/rust-cfb/issues/10).  We still
+        // want to be able to read these files, so we only consider this an
+        // error under Strict validation.
+        if parent_is_red && node_is_red && validation.is_strict() {
+            malformed!("RB tree has adjacent red nodes");
+        }
+        let left_sibling = dir_entry.left_sibling;
+        if left_sibling != consts::NO_STREAM {
+            if left_sibling as usize >= self.dir_entries.len() {
+                malformed!(
+                    "left sibling index is {}, but directory entry count is {}",
+                    left_sibling,
+                    self.dir_entries.len()
+                );
+            }
+            let entry = &self.dir_entry(left_sibling);
+            if internal::path::compare_names(&entry.name, &dir_entry.name)
+                != Ordering::Less
             {
                 malformed!(
-                    "non-root entry with object type {:?}",
-                    dir_entry.obj_type
+                    "name ordering, {:?} vs {:?}",
+                    dir_entry.name,
+                    entry.name
                 );
             }
-            let node_is_red = dir_entry.color == Color::Red;
-            // The MS-CFB spec section 2.6.4 says that two consecutive nodes in
-            // the red-black tree for siblings within a storage object MUST NOT
-            // both be red, but apparently some implementations don't obey this
-            // (see https://github.com/mdsteele/rust-cfb/issues/10).  We still
-            // want to be able to read these files, so we only consider this an
-            // error under Strict validation.
-            if parent_is_red && node_is_red && validation.is_strict() {
-                malformed!("RB tree has adjacent red nodes");
+        }
+        let right_sibling = dir_entry.right_sibling;
+        if right_sibling != consts::NO_STREAM {
+            if right_sibling as usize >= self.dir_entries.len() {
+                malformed!(
+                    "right sibling index is {}, but directory entry count is \
+                     {}",
+                    right_sibling,
+                    self.dir_entries.len()
+                );
             }
-            let left_sibling = dir_entry.left_sibling;
-            if left_sibling != consts::NO_STREAM {
-                if left_sibling as usize >= self.dir_entries.len() {
-                    malformed!(
-                        "left sibling index is {}, but directory entry count \
-                         is {}",
-                        left_sibling,
-                        self.dir_entries.len()
-                    );
+            let entry = &self.dir_entry(right_sibling);
+            if internal::path::compare_names(&dir_entry.name, &entry.name)
+                != Ordering::Less
+            {
+                malformed!(
+                    "name ordering, {:?} vs {:?}",
+                    dir_entry.name,
+                    entry.name
+                );
+            }
+        }
+        let child = dir_entry.child;
+        if child != consts::NO_STREAM
+            && child as usize >= self.dir_entries.len()
+        {
+            malformed!(
+                "child index is {}, but directory entry count is {}",
+                child,
+                self.dir_entries.len()
+            );
+        }
+        Ok(())
+    }
+
+    /// Returns true if some of the directory hasn't been loaded yet.
+    pub fn is_lazy(&self) -> bool {
+        self.lazy.is_some()
+    }
+
+    /// Reads in the directory sector containing the given entry, if it
+    /// hasn't been read yet.  Does nothing if the stream ID is out of range.
+    fn load_dir_entry(&mut self, stream_id: u32) -> io::Result<()> {
+        let dir_entries_per_sector = self.version().dir_entries_per_sector();
+        let index = stream_id as usize / dir_entries_per_sector;
+        let (sector_id, validation, read_dir_sector) = match self.lazy.as_ref()
+        {
+            Some(lazy) if index < lazy.dir_sector_ids.len() => {
+                if lazy.loaded_sectors[index] {
+                    return Ok(());
                 }
-                let entry = &self.dir_entry(left_sibling);
-                if internal::path::compare_names(&entry.name, &dir_entry.name)
-                    != Ordering::Less
-                {
-                    malformed!(
-                        "name ordering, {:?} vs {:?}",
-                        dir_entry.name,
-                        entry.name
-                    );
+                let sector_id = lazy.dir_sector_ids[index];
+                (sector_id, lazy.validation, lazy.read_dir_sector)
+            }
+            _ => return Ok(()),
+        };
+        let dir_entries =
+            read_dir_sector(&mut self.allocator, sector_id, validation)?;
+        let start = index * dir_entries_per_sector;
+        for (offset, dir_entry) in dir_entries.into_iter().enumerate() {
+            self.dir_entries[start + offset] = dir_entry;
+        }
+        if let Some(lazy) = self.lazy.as_mut() {
+            lazy.loaded_sectors[index] = true;
+        }
+        Ok(())
+    }
+
+    /// Loads and validates the entries directly within the given storage (or
+    /// root) entry, if that hasn't been done yet.  The entry itself must
+    /// already be loaded.
+    pub fn load_storage(&mut self, storage_id: u32) -> io::Result<()> {
+        let validation = match self.lazy.as_ref() {
+            Some(lazy) if !lazy.loaded_storages.contains(&storage_id) => {
+                lazy.validation
+            }
+            _ => return Ok(()),
+        };
+        let child = self.dir_entry(storage_id).child;
+        let mut stack = Vec::new();
+        if child != consts::NO_STREAM {
+            stack.push((child, false));
+        }
+        while let Some((stream_id, parent_is_red)) = stack.pop() {
+            if let Some(lazy) = self.lazy.as_mut() {
+                if !lazy.visited.insert(stream_id) {
+                    malformed!("loop in tree");
                 }
-                stack.push((left_sibling, node_is_red));
             }
+            self.load_dir_entry(stream_id)?;
+            let dir_entry = self.dir_entry(stream_id);
+            let left_sibling = dir_entry.left_sibling;
             let right_sibling = dir_entry.right_sibling;
+            let node_is_red = dir_entry.color == Color::Red;
+            self.load_dir_entry(left_sibling)?;
+            self.load_dir_entry(right_sibling)?;
+            self.validate_node(stream_id, parent_is_red, validation)?;
+            if left_sibling != consts::NO_STREAM {
+                stack.push((left_sibling, node_is_red));
+            }
             if right_sibling != consts::NO_STREAM {
-                if right_sibling as usize >= self.dir_entries.len() {
-                    malformed!(
-                        "right sibling index is {}, but directory entry count \
-                         is {}",
-                        right_sibling, self.dir_entries.len());
-                }
-                let entry = &self.dir_entry(right_sibling);
-                if internal::path::compare_names(&dir_entry.name, &entry.name)
-                    != Ordering::Less
-                {
-                    malformed!(
-                        "name ordering, {:?} vs {:?}",
-                        dir_entry.name,
-                        entry.name
-                    );
-                }
                 stack.push((right_sibling, node_is_red));
             }
-            let child = dir_entry.child;
-            if child != consts::NO_STREAM {
-                if child as usize >= self.dir_entries.len() {
-                    malformed!(
-                        "child index is {}, but directory entry count is {}",
-                        child,
-                        self.dir_entries.len()
-                    );
+        }
+        if let Some(lazy) = self.lazy.as_mut() {
+            lazy.loaded_storages.insert(storage_id);
+        }
+        Ok(())
+    }
+
+    /// Loads and validates every storage along the given path, so that the
+    /// path can then be looked up.
+    pub fn load_path(&mut self, names: &[&str]) -> io::Result<()> {
+        if self.lazy.is_none() {
+            return Ok(());
+        }
+        let mut stream_id = consts::ROOT_STREAM_ID;
+        for index in 0..names.len() {
+            if self.dir_entry(stream_id).obj_type == ObjType::Stream {
+                break;
+            }
+            self.load_storage(stream_id)?;
+            stream_id = match self.stream_id_for_name_chain(&names[..=index]) {
+                Some(stream_id) => stream_id,
+                None => break,
+            };
+        }
+        Ok(())
+    }
+
+    /// Loads and validates all storages within the subtree rooted at the
+    /// given entry.
+    pub fn load_subtree(&mut self, stream_id: u32) -> io::Result<()> {
+        if self.lazy.is_none() {
+            return Ok(());
+        }
+        let mut storages = vec![stream_id];
+        while let Some(storage_id) = storages.pop() {
+            if self.dir_entry(storage_id).obj_type == ObjType::Stream {
+                continue;
+            }
+            self.load_storage(storage_id)?;
+            let mut stack = vec![self.dir_entry(storage_id).child];
+            while let Some(stream_id) = stack.pop() {
+                if stream_id != consts::NO_STREAM {
+                    let dir_entry = self.dir_entry(stream_id);
+                    stack.push(dir_entry.left_sibling);
+                    stack.push(dir_entry.right_sibling);
+                    storages.push(stream_id);
                 }
-                stack.push((child, false));
             }
         }
         Ok(())
     }
+
+    /// Loads the entire directory and validates it just as a non-lazy
+    /// directory would have been when opened.
+    pub fn load_all(&mut self) -> io::Result<()> {
+        let validation = match self.lazy.as_ref() {
+            Some(lazy) => lazy.validation,
+            None => return Ok(()),
+        };
+        let dir_entries_per_sector = self.version().dir_entries_per_sector();
+        for stream_id in
+            (0..self.dir_entries.len()).step_by(dir_entries_per_sector)
+        {
+            self.load_dir_entry(stream_id as u32)?;
+        }
+        self.validate(validation)?;
+        self.lazy = None;
+        Ok(())
+    }
+}
+
+impl<F: Read + Seek> Directory<F> {
+    /// Creates a directory that reads its entries from the given directory
+    /// sectors only as they're needed.  Only the root entry and the entries
+    /// directly within the root storage are loaded (and validated) up front.
+    pub fn new_lazy(
+        allocator: Allocator<F>,
+        dir_sector_ids: Vec<u32>,
+        validation: Validation,
+    ) -> io::Result<Directory<F>> {
+        let dir_entries_per_sector =
+            allocator.version().dir_entries_per_sector();
+        let dir_entries = vec![
+            DirEntry::unallocated();
+            dir_sector_ids.len() * dir_entries_per_sector
+        ];
+        let mut directory = Directory {
+            allocator,
+            dir_entries,
+            dir_start_sector: dir_sector_ids[0],
+            lazy: Some(LazyDirectory {
+                validation,
+                read_dir_sector: read_dir_sector::<F>,
+                loaded_sectors: vec![false; dir_sector_ids.len()],
+                dir_sector_ids,
+                loaded_storages: FnvHashSet::default(),
+                visited: FnvHashSet::default(),
+            }),
+            fixed_timestamp: None,
+            structure_generation: 0,
+            parent_ids: None,
+        };
+        directory.load_dir_entry(consts::ROOT_STREAM_ID)?;
+        directory.validate_root()?;
+        directory.validate_node(consts::ROOT_STREAM_ID, false, validation)?;
+        if let Some(lazy) = directory.lazy.as_mut() {
+            lazy.visited.insert(consts::ROOT_STREAM_ID);
+        }
+        directory.load_storage(consts::ROOT_STREAM_ID)?;
+        Ok(directory)
+    }
 }
 
 impl<F: Seek> Directory<F> {
@@ -204,6 +581,43 @@ impl<F: Seek> Directory<F> {
         self.allocator.seek_within_header(offset_within_header)
     }
 
+    pub fn seek_to_sector(
+        &mut self,
+        sector_id: u32,
+    ) -> io::Result<Sector<'_, F>> {
+        self.allocator.seek_to_sector(sector_id)
+    }
+
+    pub fn read_at(&mut self, offset: u64, len: u64) -> io::Result<Vec<u8>>
+    where
+        F: Read,
+    {
+        self.allocator.read_at(offset, len)
+    }
+
+    /// Returns the sector number at the given index within the chain starting
+    /// at the given sector.
+    pub fn chain_sector_id(
+        &mut self,
+        start_sector_id: u32,
+        index: usize,
+    ) -> io::Result<u32> {
+        self.allocator.chain_sector_id(start_sector_id, index)
+    }
+
+    pub fn seek_within_sectors(
+        &mut self,
+        sector_id: u32,
+        num_sectors: u32,
+        offset_within_run: u64,
+    ) -> io::Result<Sector<'_, F>> {
+        self.allocator.seek_within_sectors(
+            sector_id,
+            num_sectors,
+            offset_within_run,
+        )
+    }
+
     fn seek_to_dir_entry(
         &mut self,
         stream_id: u32,
@@ -251,6 +665,16 @@ impl<F: Write + Seek> Directory<F> {
         self.allocator.extend_chain(start_sector_id, init)
     }
 
+    /// Reserves enough free sectors to extend the chain ending at the given
+    /// sector by `num_sectors` sectors.
+    pub fn reserve(
+        &mut self,
+        last_sector_id: u32,
+        num_sectors: u32,
+    ) -> io::Result<()> {
+        self.allocator.reserve(last_sector_id, num_sectors)
+    }
+
     /// Given the start sector of a chain, deallocates the entire chain.
     pub fn free_chain(&mut self, start_sector_id: u32) -> io::Result<()> {
         self.allocator.free_chain(start_sector_id)
@@ -267,12 +691,13 @@ impl<F: Write + Seek> Directory<F> {
         debug_assert!(
             obj_type == ObjType::Storage || obj_type == ObjType::Stream
         );
+        self.load_all()?;
         // Create a new directory entry.
         let stream_id = self.allocate_dir_entry()?;
         // 2.6.1 streams must have creation and modified time of 0
         let mut ts = Timestamp::zero();
         if obj_type == ObjType::Storage {
-            ts = Timestamp::now();
+            ts = self.now();
         }
         *self.dir_entry_mut(stream_id) = DirEntry::new(name, obj_type, ts);
 
@@ -314,6 +739,7 @@ impl<F: Write + Seek> Directory<F> {
 
         // Write new entry to underyling file.
         self.write_dir_entry(stream_id)?;
+        self.structure_generation += 1;
         Ok(stream_id)
     }
 
@@ -450,11 +876,13 @@ impl<F: Write + Seek> Directory<F> {
     }
 
     /// Deallocates the specified directory entry.
-    fn free_dir_entry(&mut self, stream_id: u32) -> io::Result<()> {
+    /// Overwrites the specified directory entry with an unallocated entry.
+    pub fn free_dir_entry(&mut self, stream_id: u32) -> io::Result<()> {
         debug_assert_ne!(stream_id, consts::ROOT_STREAM_ID);
         let dir_entry = DirEntry::unallocated();
         dir_entry.write_to(&mut self.seek_to_dir_entry(stream_id)?)?;
         *self.dir_entry_mut(stream_id) = dir_entry;
+        self.structure_generation += 1;
         // TODO: Truncate directory chain if last directory sector is now all
         //       unallocated.
         //       In that case, also call update_num_dir_sectors()
@@ -495,6 +923,10 @@ impl<F: Write + Seek> Directory<F> {
         self.dir_entries[stream_id as usize].write_to(&mut chain)
     }
 
+    pub fn write_at(&mut self, offset: u64, data: &[u8]) -> io::Result<()> {
+        self.allocator.write_at(offset, data)
+    }
+
     /// Flushes all changes to the underlying file.
     pub fn flush(&mut self) -> io::Result<()> {
         self.allocator.flush()
@@ -503,6 +935,26 @@ impl<F: Write + Seek> Directory<F> {
 
 //===========================================================================//
 
+fn read_dir_sector<F: Read + Seek>(
+    allocator: &mut Allocator<F>,
+    sector_id: u32,
+    validation: Validation,
+) -> io::Result<Vec<DirEntry>> {
+    let version = allocator.version();
+    let mut sector = allocator.seek_to_sector(sector_id)?;
+    let mut dir_entries = Vec::with_capacity(version.dir_entries_per_sector());
+    for _ in 0..version.dir_entries_per_sector() {
+        dir_entries.push(DirEntry::read_from(
+            &mut sector,
+            version,
+            validation,
+        )?);
+    }
+    Ok(dir_entries)
+}
+
+//===========================================================================//
+
 #[cfg(test)]
 mod tests {
     use super::Directory;
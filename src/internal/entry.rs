@@ -1,7 +1,11 @@
-use crate::internal::{consts, DirEntry, MiniAllocator, ObjType, Timestamp};
+use crate::internal::{
+    consts, Color, DirEntry, MiniAllocator, ObjType, Timestamp,
+};
+use std::collections::VecDeque;
 use std::fmt;
+use std::io;
 use std::path::{Path, PathBuf};
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, RwLock, Weak};
 use std::time::SystemTime;
 use uuid::Uuid;
 
@@ -18,10 +22,15 @@ pub struct Entry {
     creation_time: Timestamp,
     modified_time: Timestamp,
     stream_len: u64,
+    raw: DirEntryInfo,
 }
 
 impl Entry {
-    pub(crate) fn new(dir_entry: &DirEntry, path: PathBuf) -> Entry {
+    pub(crate) fn new(
+        stream_id: u32,
+        dir_entry: &DirEntry,
+        path: PathBuf,
+    ) -> Entry {
         Entry {
             name: dir_entry.name.clone(),
             path,
@@ -31,6 +40,14 @@ impl Entry {
             creation_time: dir_entry.creation_time,
             modified_time: dir_entry.modified_time,
             stream_len: dir_entry.stream_len,
+            raw: DirEntryInfo {
+                stream_id,
+                color: dir_entry.color,
+                left_sibling: dir_entry.left_sibling,
+                right_sibling: dir_entry.right_sibling,
+                child: dir_entry.child,
+                start_sector: dir_entry.start_sector,
+            },
         }
     }
 
@@ -94,6 +111,25 @@ impl Entry {
     pub fn modified(&self) -> SystemTime {
         self.modified_time.to_system_time()
     }
+
+    /// Returns the raw timestamp recording when the object that this entry
+    /// represents was created.  Unlike `created`, this doesn't lose any
+    /// precision or range by converting to `SystemTime`.
+    pub fn created_timestamp(&self) -> Timestamp {
+        self.creation_time
+    }
+
+    /// Returns the raw timestamp recording when the object that this entry
+    /// represents was last modified.  Unlike `modified`, this doesn't lose any
+    /// precision or range by converting to `SystemTime`.
+    pub fn modified_timestamp(&self) -> Timestamp {
+        self.modified_time
+    }
+
+    /// Returns the low-level details of the directory entry for this object.
+    pub fn raw(&self) -> &DirEntryInfo {
+        &self.raw
+    }
 }
 
 impl fmt::Debug for Entry {
@@ -109,23 +145,245 @@ impl fmt::Debug for Entry {
 
 //===========================================================================//
 
+/// Low-level details of a directory entry, as stored in the compound file.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DirEntryInfo {
+    stream_id: u32,
+    color: Color,
+    left_sibling: u32,
+    right_sibling: u32,
+    child: u32,
+    start_sector: u32,
+}
+
+impl DirEntryInfo {
+    /// Returns the stream ID of the directory entry (that is, its index
+    /// within the directory), which other structures (such as MSI tables and
+    /// property sets) sometimes use to refer to it.  The root entry's stream
+    /// ID is always zero.
+    pub fn stream_id(&self) -> u32 {
+        self.stream_id
+    }
+
+    /// Returns the color of the entry's node in the red-black tree of its
+    /// siblings.
+    pub fn color(&self) -> Color {
+        self.color
+    }
+
+    /// Returns the stream ID of the entry's left sibling in the red-black
+    /// tree, or `NO_STREAM` (0xFFFFFFFF) if there is none.
+    pub fn left_sibling(&self) -> u32 {
+        self.left_sibling
+    }
+
+    /// Returns the stream ID of the entry's right sibling in the red-black
+    /// tree, or `NO_STREAM` (0xFFFFFFFF) if there is none.
+    pub fn right_sibling(&self) -> u32 {
+        self.right_sibling
+    }
+
+    /// Returns the stream ID of the root of the red-black tree of the entry's
+    /// children, or `NO_STREAM` (0xFFFFFFFF) if there are none (which is
+    /// always the case for streams).
+    pub fn child(&self) -> u32 {
+        self.child
+    }
+
+    /// Returns the starting sector of the entry's data.  For streams, this is
+    /// a mini sector number if the stream is shorter than 4096 bytes, or a
+    /// regular sector number otherwise.  For the root entry, it's the first
+    /// sector of the mini stream.  For other storages, it's always zero.
+    pub fn start_sector(&self) -> u32 {
+        self.start_sector
+    }
+}
+
+//===========================================================================//
+
 #[derive(Clone, Copy, Eq, PartialEq)]
 pub enum EntriesOrder {
     Nonrecursive,
     Preorder,
+    Postorder,
+    BreadthFirst,
+}
+
+impl From<WalkOrder> for EntriesOrder {
+    fn from(order: WalkOrder) -> EntriesOrder {
+        match order {
+            WalkOrder::PreOrder => EntriesOrder::Preorder,
+            WalkOrder::PostOrder => EntriesOrder::Postorder,
+            WalkOrder::BreadthFirst => EntriesOrder::BreadthFirst,
+        }
+    }
+}
+
+/// A pending step of a traversal.
+enum Step {
+    /// Visit an entry at the given depth, and (if the bool is true) its
+    /// right siblings.
+    Visit(PathBuf, u32, usize, bool),
+    /// Yield an entry whose children have all been visited (for postorder
+    /// traversal).
+    Finish(Entry, usize),
+}
+
+/// The state of a traversal of the storage tree, independent of how the
+/// directory is accessed.
+struct Traversal {
+    order: EntriesOrder,
+    options: WalkOptions,
+    stack: Vec<Step>,
+    // Storages whose children have yet to be visited (for breadth-first
+    // traversal).
+    queue: VecDeque<(PathBuf, u32, usize)>,
+}
+
+impl Traversal {
+    fn new<F>(
+        order: EntriesOrder,
+        minialloc: &RwLock<MiniAllocator<F>>,
+        parent_path: PathBuf,
+        start: u32,
+    ) -> Traversal {
+        let mut traversal = Traversal {
+            order,
+            options: WalkOptions::new(),
+            stack: Vec::new(),
+            queue: VecDeque::new(),
+        };
+        match order {
+            EntriesOrder::Nonrecursive => {
+                traversal.stack_left_spine(minialloc, &parent_path, start, 1);
+            }
+            _ => {
+                let step = Step::Visit(parent_path, start, 0, false);
+                traversal.stack.push(step);
+            }
+        }
+        traversal
+    }
+
+    fn stack_left_spine<F>(
+        &mut self,
+        minialloc: &RwLock<MiniAllocator<F>>,
+        parent_path: &Path,
+        mut current_id: u32,
+        depth: usize,
+    ) {
+        let minialloc = minialloc.read().unwrap();
+        while current_id != consts::NO_STREAM {
+            let parent_path = parent_path.to_path_buf();
+            self.stack.push(Step::Visit(parent_path, current_id, depth, true));
+            current_id = minialloc.dir_entry(current_id).left_sibling;
+        }
+    }
+
+    /// Returns true if an entry at the given depth should be yielded (as
+    /// opposed to just traversed).
+    fn should_yield(&self, entry: &Entry, depth: usize) -> bool {
+        (depth > 0 || self.options.includes_root())
+            && (self.options.includes_streams() || !entry.is_stream())
+            && (self.options.includes_storages() || !entry.is_storage())
+    }
+
+    /// Returns the next entry that `predicate` accepts, skipping the
+    /// children of any rejected entries.
+    fn next_filtered<F, P>(
+        &mut self,
+        minialloc: &RwLock<MiniAllocator<F>>,
+        mut predicate: P,
+    ) -> Option<Entry>
+    where
+        P: FnMut(&Entry) -> bool,
+    {
+        loop {
+            let step = match self.stack.pop() {
+                Some(step) => step,
+                None => {
+                    let (path, child, depth) = self.queue.pop_front()?;
+                    self.stack_left_spine(minialloc, &path, child, depth);
+                    continue;
+                }
+            };
+            let (parent, stream_id, depth, visit_siblings) = match step {
+                Step::Visit(parent, stream_id, depth, visit_siblings) => {
+                    (parent, stream_id, depth, visit_siblings)
+                }
+                Step::Finish(entry, depth) => {
+                    if self.should_yield(&entry, depth) {
+                        return Some(entry);
+                    }
+                    continue;
+                }
+            };
+            let (entry, sibling, child) = {
+                let minialloc = minialloc.read().unwrap();
+                let dir_entry = minialloc.dir_entry(stream_id);
+                let path = join_path(&parent, dir_entry);
+                let child = if dir_entry.obj_type == ObjType::Stream {
+                    consts::NO_STREAM
+                } else {
+                    dir_entry.child
+                };
+                let entry = Entry::new(stream_id, dir_entry, path);
+                (entry, dir_entry.right_sibling, child)
+            };
+            if visit_siblings {
+                self.stack_left_spine(minialloc, &parent, sibling, depth);
+            }
+            if !predicate(&entry) {
+                continue;
+            }
+            let descend = child != consts::NO_STREAM
+                && self.options.max_depth().is_none_or(|max| depth < max);
+            match self.order {
+                EntriesOrder::Nonrecursive => {}
+                EntriesOrder::Preorder => {
+                    if descend {
+                        let path = entry.path();
+                        self.stack_left_spine(
+                            minialloc,
+                            path,
+                            child,
+                            depth + 1,
+                        );
+                    }
+                }
+                EntriesOrder::Postorder => {
+                    let path = entry.path().to_path_buf();
+                    self.stack.push(Step::Finish(entry, depth));
+                    if descend {
+                        self.stack_left_spine(
+                            minialloc,
+                            &path,
+                            child,
+                            depth + 1,
+                        );
+                    }
+                    continue;
+                }
+                EntriesOrder::BreadthFirst => {
+                    if descend {
+                        let path = entry.path().to_path_buf();
+                        self.queue.push_back((path, child, depth + 1));
+                    }
+                }
+            }
+            if self.should_yield(&entry, depth) {
+                return Some(entry);
+            }
+        }
+    }
 }
 
 //===========================================================================//
 
 /// An iterator over the entries in a storage object.
 pub struct Entries<'a, F: 'a> {
-    order: EntriesOrder,
-    // TODO: Consider storing a Weak<RefCell<MiniAllocator<F>>> here instead of
-    // a reference to the Rc.  That would allow e.g. opening streams during
-    // iteration.  But we'd need to think about how the iterator should behave
-    // if the CFB tree structure is modified during iteration.
     minialloc: &'a Arc<RwLock<MiniAllocator<F>>>,
-    stack: Vec<(PathBuf, u32, bool)>,
+    traversal: Traversal,
 }
 
 impl<'a, F> Entries<'a, F> {
@@ -135,53 +393,162 @@ impl<'a, F> Entries<'a, F> {
         parent_path: PathBuf,
         start: u32,
     ) -> Entries<'a, F> {
-        let mut entries = Entries { order, minialloc, stack: Vec::new() };
-        match order {
-            EntriesOrder::Nonrecursive => {
-                entries.stack_left_spine(&parent_path, start);
-            }
-            EntriesOrder::Preorder => {
-                entries.stack.push((parent_path, start, false));
-            }
-        }
+        let traversal = Traversal::new(order, minialloc, parent_path, start);
+        Entries { minialloc, traversal }
+    }
+
+    pub(crate) fn with_options(
+        minialloc: &'a Arc<RwLock<MiniAllocator<F>>>,
+        parent_path: PathBuf,
+        start: u32,
+        options: &WalkOptions,
+    ) -> Entries<'a, F> {
+        let mut entries = Entries::new(
+            options.order().into(),
+            minialloc,
+            parent_path,
+            start,
+        );
+        entries.traversal.options = options.clone();
         entries
     }
 
-    fn stack_left_spine(&mut self, parent_path: &Path, mut current_id: u32) {
-        let minialloc = self.minialloc.read().unwrap();
-        while current_id != consts::NO_STREAM {
-            self.stack.push((parent_path.to_path_buf(), current_id, true));
-            current_id = minialloc.dir_entry(current_id).left_sibling;
+    /// Returns an iterator that yields only the entries that `predicate`
+    /// accepts.  Unlike `Iterator::filter`, when `predicate` rejects a
+    /// storage during a recursive walk, the iterator skips that storage's
+    /// entire subtree without visiting any of its descendants.
+    pub fn filter_entries<P>(self, predicate: P) -> FilterEntries<'a, F, P>
+    where
+        P: FnMut(&Entry) -> bool,
+    {
+        FilterEntries { entries: self, predicate }
+    }
+
+    /// Converts this iterator into one that doesn't borrow the compound
+    /// file, so that streams can be opened, rewritten, or resized (and
+    /// entries' attributes changed) while iterating.  See `DetachedEntries`
+    /// for what happens if entries are added or removed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::{Cursor, Write};
+    /// let mut comp = cfb::CompoundFile::create(Cursor::new(Vec::new()))?;
+    /// comp.create_stream("/foo")?;
+    /// comp.create_stream("/bar")?;
+    /// for entry in comp.walk().detach() {
+    ///     let entry = entry?;
+    ///     if entry.is_stream() {
+    ///         comp.create_stream(entry.path())?.write_all(b"new data")?;
+    ///     }
+    /// }
+    /// assert_eq!(comp.entry("/foo")?.len(), 8);
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn detach(self) -> DetachedEntries<F> {
+        let generation = self.minialloc.read().unwrap().structure_generation();
+        DetachedEntries {
+            minialloc: Arc::downgrade(self.minialloc),
+            generation,
+            traversal: Some(self.traversal),
         }
     }
+
+    /// Returns the next entry that `predicate` accepts, skipping the
+    /// children of any rejected entries.
+    pub(crate) fn next_filtered<P>(&mut self, predicate: P) -> Option<Entry>
+    where
+        P: FnMut(&Entry) -> bool,
+    {
+        self.traversal.next_filtered(self.minialloc, predicate)
+    }
 }
 
 impl<'a, F> Iterator for Entries<'a, F> {
     type Item = Entry;
 
     fn next(&mut self) -> Option<Entry> {
-        if let Some((parent, stream_id, visit_siblings)) = self.stack.pop() {
-            let minialloc = self.minialloc.read().unwrap();
-            let dir_entry = minialloc.dir_entry(stream_id);
-            let path = join_path(&parent, dir_entry);
-            if visit_siblings {
-                self.stack_left_spine(&parent, dir_entry.right_sibling);
+        self.next_filtered(|_| true)
+    }
+}
+
+//===========================================================================//
+
+/// An iterator over the entries in a storage object that doesn't borrow the
+/// compound file.  See `Entries::detach`.
+///
+/// Opening, reading, writing, or resizing streams, and changing entries'
+/// attributes, don't affect the iteration (though entries yielded after such
+/// a change reflect it).  But if any entry is added to or removed from the
+/// compound file during iteration, or the compound file is dropped, the
+/// iterator yields an error and then stops.
+pub struct DetachedEntries<F> {
+    minialloc: Weak<RwLock<MiniAllocator<F>>>,
+    generation: u64,
+    // None once the iterator has stopped.
+    traversal: Option<Traversal>,
+}
+
+impl<F> DetachedEntries<F> {
+    fn try_next(&mut self) -> io::Result<Option<Entry>> {
+        let traversal = match self.traversal.as_mut() {
+            Some(traversal) => traversal,
+            None => return Ok(None),
+        };
+        let minialloc = self
+            .minialloc
+            .upgrade()
+            .ok_or_else(|| io::Error::other("CompoundFile was dropped"))?;
+        if minialloc.read().unwrap().structure_generation() != self.generation
+        {
+            return Err(io::Error::other(
+                "Entries were added or removed during iteration",
+            ));
+        }
+        Ok(traversal.next_filtered(&minialloc, |_| true))
+    }
+}
+
+impl<F> Iterator for DetachedEntries<F> {
+    type Item = io::Result<Entry>;
+
+    fn next(&mut self) -> Option<io::Result<Entry>> {
+        match self.try_next() {
+            Ok(Some(entry)) => Some(Ok(entry)),
+            Ok(None) => {
+                self.traversal = None;
+                None
             }
-            if self.order == EntriesOrder::Preorder
-                && dir_entry.obj_type != ObjType::Stream
-                && dir_entry.child != consts::NO_STREAM
-            {
-                self.stack_left_spine(&path, dir_entry.child);
+            Err(error) => {
+                self.traversal = None;
+                Some(Err(error))
             }
-            Some(Entry::new(dir_entry, path))
-        } else {
-            None
         }
     }
 }
 
 //===========================================================================//
 
+/// An iterator over the entries in a storage object that skips the subtrees
+/// of rejected storages.  See `Entries::filter_entries`.
+pub struct FilterEntries<'a, F: 'a, P> {
+    entries: Entries<'a, F>,
+    predicate: P,
+}
+
+impl<'a, F, P> Iterator for FilterEntries<'a, F, P>
+where
+    P: FnMut(&Entry) -> bool,
+{
+    type Item = Entry;
+
+    fn next(&mut self) -> Option<Entry> {
+        self.entries.next_filtered(&mut self.predicate)
+    }
+}
+
+//===========================================================================//
+
 fn join_path(parent_path: &Path, dir_entry: &DirEntry) -> PathBuf {
     if dir_entry.obj_type == ObjType::Root {
         parent_path.to_path_buf()
@@ -192,13 +559,119 @@ fn join_path(parent_path: &Path, dir_entry: &DirEntry) -> PathBuf {
 
 //===========================================================================//
 
+/// The order in which `CompoundFile::walk_storage_with` visits entries.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum WalkOrder {
+    /// Visit each storage before its children (as `walk` does).
+    #[default]
+    PreOrder,
+    /// Visit each storage after its children, so that (for example) every
+    /// entry can be removed in the order visited.
+    PostOrder,
+    /// Visit all entries at one depth before any entries at the next depth.
+    BreadthFirst,
+}
+
+/// Options for walking a storage tree with
+/// `CompoundFile::walk_storage_with`.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct WalkOptions {
+    order: WalkOrder,
+    max_depth: Option<usize>,
+    root: bool,
+    streams: bool,
+    storages: bool,
+}
+
+impl WalkOptions {
+    /// Returns the default options, which visit every entry in the subtree,
+    /// including the starting entry, in preorder.
+    pub fn new() -> WalkOptions {
+        WalkOptions {
+            order: WalkOrder::PreOrder,
+            max_depth: None,
+            root: true,
+            streams: true,
+            storages: true,
+        }
+    }
+
+    /// Returns the order in which entries are visited.
+    pub fn order(&self) -> WalkOrder {
+        self.order
+    }
+
+    /// Sets the order in which entries are visited (preorder by default).
+    pub fn with_order(mut self, order: WalkOrder) -> WalkOptions {
+        self.order = order;
+        self
+    }
+
+    /// Returns the maximum depth of entries to visit, if any.
+    pub fn max_depth(&self) -> Option<usize> {
+        self.max_depth
+    }
+
+    /// Sets the maximum depth of entries to visit, where the starting entry
+    /// is at depth zero and its children are at depth one (unlimited by
+    /// default).  Storages at the maximum depth are visited, but their
+    /// children are not.
+    pub fn with_max_depth(mut self, max_depth: usize) -> WalkOptions {
+        self.max_depth = Some(max_depth);
+        self
+    }
+
+    /// Returns true if the starting entry itself is visited.
+    pub fn includes_root(&self) -> bool {
+        self.root
+    }
+
+    /// Sets whether the starting entry itself is visited (true by default).
+    /// Its children are visited either way.
+    pub fn with_root(mut self, include: bool) -> WalkOptions {
+        self.root = include;
+        self
+    }
+
+    /// Returns true if stream entries are visited.
+    pub fn includes_streams(&self) -> bool {
+        self.streams
+    }
+
+    /// Sets whether stream entries are visited (true by default).
+    pub fn with_streams(mut self, include: bool) -> WalkOptions {
+        self.streams = include;
+        self
+    }
+
+    /// Returns true if storage entries are visited.
+    pub fn includes_storages(&self) -> bool {
+        self.storages
+    }
+
+    /// Sets whether storage entries are visited (true by default).  Even if
+    /// not, the entries within them still are.
+    pub fn with_storages(mut self, include: bool) -> WalkOptions {
+        self.storages = include;
+        self
+    }
+}
+
+impl Default for WalkOptions {
+    fn default() -> WalkOptions {
+        WalkOptions::new()
+    }
+}
+
+//===========================================================================//
+
 #[cfg(test)]
 mod tests {
     use super::{Entries, EntriesOrder, Entry};
     use crate::internal::consts::{self, NO_STREAM, ROOT_DIR_NAME};
     use crate::internal::{
         Allocator, DirEntry, Directory, MiniAllocator, ObjType, Sectors,
-        Timestamp, Validation, Version,
+        Timestamp, Validation, Version, WalkOptions, WalkOrder,
     };
     use std::path::{Path, PathBuf};
     use std::sync::{Arc, RwLock};
@@ -356,6 +829,111 @@ mod tests {
             ]
         );
     }
+
+    fn walk_with_options(options: &WalkOptions) -> Vec<PathBuf> {
+        let minialloc = make_minialloc();
+        let entries: Vec<Entry> =
+            Entries::with_options(&minialloc, PathBuf::from("/"), 0, options)
+                .collect();
+        entries.iter().map(|entry| entry.path().to_path_buf()).collect()
+    }
+
+    #[test]
+    fn postorder_entries_from_root() {
+        let options = WalkOptions::new().with_order(WalkOrder::PostOrder);
+        assert_eq!(
+            walk_with_options(&options),
+            vec![
+                Path::new("/1"),
+                Path::new("/2"),
+                Path::new("/3/7"),
+                Path::new("/3/8"),
+                Path::new("/3/9"),
+                Path::new("/3"),
+                Path::new("/4"),
+                Path::new("/5"),
+                Path::new("/6"),
+                Path::new("/"),
+            ]
+        );
+    }
+
+    #[test]
+    fn breadth_first_entries_from_root() {
+        let options = WalkOptions::new().with_order(WalkOrder::BreadthFirst);
+        assert_eq!(
+            walk_with_options(&options),
+            vec![
+                Path::new("/"),
+                Path::new("/1"),
+                Path::new("/2"),
+                Path::new("/3"),
+                Path::new("/4"),
+                Path::new("/5"),
+                Path::new("/6"),
+                Path::new("/3/7"),
+                Path::new("/3/8"),
+                Path::new("/3/9"),
+            ]
+        );
+    }
+
+    #[test]
+    fn depth_limited_entries_from_root() {
+        let options = WalkOptions::new().with_max_depth(1).with_root(false);
+        assert_eq!(
+            walk_with_options(&options),
+            vec![
+                Path::new("/1"),
+                Path::new("/2"),
+                Path::new("/3"),
+                Path::new("/4"),
+                Path::new("/5"),
+                Path::new("/6"),
+            ]
+        );
+        let options = WalkOptions::new()
+            .with_order(WalkOrder::PostOrder)
+            .with_storages(false);
+        assert_eq!(
+            walk_with_options(&options),
+            vec![
+                Path::new("/1"),
+                Path::new("/2"),
+                Path::new("/3/7"),
+                Path::new("/3/8"),
+                Path::new("/3/9"),
+                Path::new("/4"),
+                Path::new("/5"),
+            ]
+        );
+        let options = WalkOptions::new().with_max_depth(0).with_streams(false);
+        assert_eq!(walk_with_options(&options), vec![Path::new("/")]);
+    }
+
+    #[test]
+    fn filtered_preorder_entries_skip_subtrees() {
+        let minialloc = make_minialloc();
+        let entries: Vec<Entry> = Entries::new(
+            EntriesOrder::Preorder,
+            &minialloc,
+            PathBuf::from("/"),
+            0,
+        )
+        .filter_entries(|entry| entry.name() != "3" && entry.name() != "5")
+        .collect();
+        let paths = paths_for_entries(&entries);
+        assert_eq!(
+            paths,
+            vec![
+                Path::new("/"),
+                Path::new("/1"),
+                Path::new("/2"),
+                Path::new("/4"),
+                Path::new("/6"),
+            ]
+        );
+    }
 }
 
 //===========================================================================//
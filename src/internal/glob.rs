@@ -0,0 +1,316 @@
+use crate::internal::path::{cfb_uppercase_char, name_chain_from_path};
+use crate::internal::{Entries, Entry};
+use std::io;
+use std::path::Path;
+
+//===========================================================================//
+
+/// A glob pattern for matching paths within a compound file.
+///
+/// A pattern is a `/`-separated list of components, each of which must
+/// match one level of the storage tree, starting from the root (a leading
+/// `/` is optional).  Within a component, `*` matches any sequence of
+/// characters, `?` matches any single character, `[abc]` or `[a-z]`
+/// matches any one of the given characters (`[!abc]` matches any other
+/// character), and `\` escapes the next character.  A component of just
+/// `**` matches any number of levels, including zero.  Like CFB name
+/// comparison, matching is case-insensitive.
+#[derive(Clone, Debug)]
+pub struct Glob {
+    pattern: String,
+    components: Vec<Component>,
+}
+
+#[derive(Clone, Debug)]
+enum Component {
+    AnyLevels,
+    Name(Vec<Token>),
+}
+
+#[derive(Clone, Debug)]
+enum Token {
+    Char(char),
+    AnyChar,
+    AnySequence,
+    Class { negated: bool, ranges: Vec<(char, char)> },
+}
+
+impl Glob {
+    /// Parses a glob pattern.  Returns an error if the pattern is malformed
+    /// (e.g. it has an unterminated `[` character class).
+    pub fn new(pattern: &str) -> io::Result<Glob> {
+        let mut components = Vec::new();
+        for component in pattern.split('/').filter(|c| !c.is_empty()) {
+            if component == "**" {
+                // Consecutive "**" components are redundant.
+                if !matches!(components.last(), Some(Component::AnyLevels)) {
+                    components.push(Component::AnyLevels);
+                }
+            } else {
+                components.push(Component::Name(parse_tokens(component)?));
+            }
+        }
+        Ok(Glob { pattern: pattern.to_string(), components })
+    }
+
+    /// Returns the pattern string that this glob was parsed from.
+    pub fn pattern(&self) -> &str {
+        &self.pattern
+    }
+
+    /// Returns true if the given path within a compound file matches this
+    /// pattern.
+    pub fn matches<P: AsRef<Path>>(&self, path: P) -> bool {
+        match name_chain_from_path(path.as_ref()) {
+            Ok(names) => self.match_names(0, &names, false),
+            Err(_) => false,
+        }
+    }
+
+    /// Returns true if the given path, or any path underneath it, could
+    /// match this pattern.
+    fn could_match_within(&self, path: &Path) -> bool {
+        match name_chain_from_path(path) {
+            Ok(names) => self.match_names(0, &names, true),
+            Err(_) => false,
+        }
+    }
+
+    fn match_names(&self, index: usize, names: &[&str], prefix: bool) -> bool {
+        let component = match self.components.get(index) {
+            Some(component) => component,
+            None => return names.is_empty(),
+        };
+        match component {
+            Component::AnyLevels => {
+                self.match_names(index + 1, names, prefix)
+                    || (!names.is_empty()
+                        && self.match_names(index, &names[1..], prefix))
+            }
+            Component::Name(tokens) => match names.split_first() {
+                Some((name, rest)) => {
+                    let chars: Vec<char> =
+                        name.chars().map(cfb_uppercase_char).collect();
+                    match_tokens(tokens, &chars)
+                        && self.match_names(index + 1, rest, prefix)
+                }
+                None => prefix,
+            },
+        }
+    }
+}
+
+fn parse_tokens(component: &str) -> io::Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = component.chars();
+    while let Some(chr) = chars.next() {
+        let token = match chr {
+            '*' => {
+                if matches!(tokens.last(), Some(Token::AnySequence)) {
+                    continue;
+                }
+                Token::AnySequence
+            }
+            '?' => Token::AnyChar,
+            '[' => parse_class(&mut chars, component)?,
+            '\\' => match chars.next() {
+                Some(chr) => Token::Char(cfb_uppercase_char(chr)),
+                None => invalid_input!(
+                    "Glob component {:?} ends with an escape character",
+                    component
+                ),
+            },
+            chr => Token::Char(cfb_uppercase_char(chr)),
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+fn parse_class(
+    chars: &mut std::str::Chars<'_>,
+    component: &str,
+) -> io::Result<Token> {
+    let mut negated = false;
+    let mut ranges = Vec::new();
+    let mut first = true;
+    loop {
+        let mut chr = match chars.next() {
+            Some(chr) => chr,
+            None => invalid_input!(
+                "Glob component {:?} has an unterminated character class",
+                component
+            ),
+        };
+        if chr == '!' && first && !negated {
+            negated = true;
+            continue;
+        }
+        if chr == ']' && !first {
+            break;
+        }
+        first = false;
+        if chr == '\\' {
+            chr = chars.next().unwrap_or('\\');
+        }
+        let start = cfb_uppercase_char(chr);
+        let mut lookahead = chars.clone();
+        let end = match (lookahead.next(), lookahead.next()) {
+            (Some('-'), Some(end)) if end != ']' => {
+                *chars = lookahead;
+                cfb_uppercase_char(end)
+            }
+            _ => start,
+        };
+        ranges.push((start, end));
+    }
+    Ok(Token::Class { negated, ranges })
+}
+
+/// Matches a name (already uppercased) against a component's tokens.
+fn match_tokens(tokens: &[Token], chars: &[char]) -> bool {
+    match tokens.split_first() {
+        None => chars.is_empty(),
+        Some((Token::AnySequence, rest)) => {
+            (0..=chars.len()).any(|skip| match_tokens(rest, &chars[skip..]))
+        }
+        Some((token, rest)) => match chars.split_first() {
+            Some((&chr, remaining)) => {
+                let matched = match *token {
+                    Token::Char(expected) => chr == expected,
+                    Token::AnyChar => true,
+                    Token::Class { negated, ref ranges } => {
+                        negated
+                            != ranges.iter().any(|&(start, end)| {
+                                start <= chr && chr <= end
+                            })
+                    }
+                    Token::AnySequence => unreachable!(),
+                };
+                matched && match_tokens(rest, remaining)
+            }
+            None => false,
+        },
+    }
+}
+
+//===========================================================================//
+
+/// An iterator over the entries in a compound file that match a glob
+/// pattern.  See `CompoundFile::find`.
+pub struct Find<'a, F: 'a> {
+    entries: Entries<'a, F>,
+    glob: Glob,
+}
+
+impl<'a, F> Find<'a, F> {
+    pub(crate) fn new(entries: Entries<'a, F>, glob: Glob) -> Find<'a, F> {
+        Find { entries, glob }
+    }
+}
+
+impl<'a, F> Iterator for Find<'a, F> {
+    type Item = Entry;
+
+    fn next(&mut self) -> Option<Entry> {
+        let glob = &self.glob;
+        while let Some(entry) = self
+            .entries
+            .next_filtered(|entry| glob.could_match_within(entry.path()))
+        {
+            if glob.matches(entry.path()) {
+                return Some(entry);
+            }
+        }
+        None
+    }
+}
+
+//===========================================================================//
+
+#[cfg(test)]
+mod tests {
+    use super::Glob;
+
+    fn matches(pattern: &str, path: &str) -> bool {
+        Glob::new(pattern).unwrap().matches(path)
+    }
+
+    #[test]
+    fn literal_patterns() {
+        assert!(matches("/foo/bar", "/foo/bar"));
+        assert!(matches("foo/bar", "/foo/bar"));
+        assert!(matches("/FOO/Bar", "/foo/bar"));
+        assert!(!matches("/foo", "/foo/bar"));
+        assert!(!matches("/foo/bar", "/foo"));
+        assert!(matches("/", "/"));
+        assert!(!matches("/", "/foo"));
+        assert!(matches("/\x01Ole10Native", "/\x01ole10native"));
+    }
+
+    #[test]
+    fn wildcards() {
+        assert!(matches("/*", "/foo"));
+        assert!(!matches("/*", "/"));
+        assert!(!matches("/*", "/foo/bar"));
+        assert!(matches("/f*o", "/fo"));
+        assert!(matches("/f*o", "/foooo"));
+        assert!(!matches("/f*o", "/foob"));
+        assert!(matches("/f?o", "/FOO"));
+        assert!(!matches("/f?o", "/fo"));
+        assert!(matches(
+            "/ObjectPool/*/\x01Ole10Native",
+            "/ObjectPool/_1/\x01Ole10Native"
+        ));
+        assert!(!matches(
+            "/ObjectPool/*/\x01Ole10Native",
+            "/ObjectPool/\x01Ole10Native"
+        ));
+    }
+
+    #[test]
+    fn recursive_wildcards() {
+        let pattern = "**/__substg1.0_*";
+        assert!(matches(pattern, "/__substg1.0_0037001F"));
+        assert!(matches(pattern, "/a/b/__SUBSTG1.0_1000001F"));
+        assert!(!matches(pattern, "/a/b/__properties_version1.0"));
+        assert!(matches("**", "/"));
+        assert!(matches("**", "/a/b"));
+        assert!(matches("/a/**/b", "/a/b"));
+        assert!(matches("/a/**/b", "/a/x/y/b"));
+        assert!(!matches("/a/**/b", "/a/x/y/b/c"));
+    }
+
+    #[test]
+    fn character_classes() {
+        assert!(matches("/[abc]", "/B"));
+        assert!(!matches("/[abc]", "/d"));
+        assert!(matches("/[!abc]", "/d"));
+        assert!(!matches("/[!abc]", "/a"));
+        assert!(matches("/x[0-9]", "/x5"));
+        assert!(!matches("/x[0-9]", "/xa"));
+        assert!(matches("/[]]", "/]"));
+        assert!(matches("/[a-]", "/-"));
+        assert!(matches("/\\*", "/*"));
+        assert!(!matches("/\\*", "/a"));
+    }
+
+    #[test]
+    fn malformed_patterns() {
+        assert!(Glob::new("/[abc").is_err());
+        assert!(Glob::new("/abc\\").is_err());
+    }
+
+    #[test]
+    fn prefix_matching() {
+        let glob = Glob::new("/a/*/c").unwrap();
+        assert!(glob.could_match_within("/".as_ref()));
+        assert!(glob.could_match_within("/a".as_ref()));
+        assert!(glob.could_match_within("/a/b".as_ref()));
+        assert!(glob.could_match_within("/a/b/c".as_ref()));
+        assert!(!glob.could_match_within("/b".as_ref()));
+        assert!(!glob.could_match_within("/a/b/c/d".as_ref()));
+    }
+}
+
+//===========================================================================//
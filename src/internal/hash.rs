@@ -0,0 +1,269 @@
+use crate::internal::{path, StreamOptions};
+use crate::CompoundFile;
+#[cfg(feature = "sha256")]
+use sha2::Digest;
+use std::hash::Hasher;
+use std::io::{self, Read, Seek};
+use std::path::Path;
+
+//===========================================================================//
+
+/// The most bytes of stream data to hash at a time.
+const CHUNK_LEN: usize = 64 * 1024;
+
+/// Tags distinguishing the two kinds of node in the hash tree.
+const STORAGE_TAG: u8 = b'D';
+const STREAM_TAG: u8 = b'F';
+
+//===========================================================================//
+
+/// A hash function for computing content digests with
+/// `CompoundFile::hash_entry`.
+///
+/// The default is FNV-1a, whether or not the `sha256` cargo feature is
+/// enabled, so that enabling the feature doesn't change existing digests.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum HashAlgorithm {
+    /// SHA-256 (as implemented by the `sha2` crate), giving a 32-byte
+    /// digest.  Suitable for deduplication and for detecting deliberate
+    /// tampering.  This is only available with the `sha256` cargo feature.
+    #[cfg(feature = "sha256")]
+    Sha256,
+    /// 64-bit FNV-1a, giving an 8-byte digest.  Much faster than SHA-256,
+    /// but only suitable for detecting accidental changes.
+    #[default]
+    Fnv1a64,
+}
+
+impl HashAlgorithm {
+    /// Returns the length, in bytes, of the digests this algorithm produces.
+    pub fn digest_len(self) -> usize {
+        match self {
+            #[cfg(feature = "sha256")]
+            HashAlgorithm::Sha256 => 32,
+            HashAlgorithm::Fnv1a64 => 8,
+        }
+    }
+
+    fn hasher(self) -> Digester {
+        match self {
+            #[cfg(feature = "sha256")]
+            HashAlgorithm::Sha256 => Digester::Sha256(sha2::Sha256::new()),
+            HashAlgorithm::Fnv1a64 => {
+                Digester::Fnv1a64(fnv::FnvHasher::default())
+            }
+        }
+    }
+}
+
+//===========================================================================//
+
+/// Computes the digest of the object at the given path.
+///
+/// Each object's digest is the hash of a one-byte tag for its kind
+/// (storage or stream), then whichever of its CLSID, state bits and
+/// timestamps the options include, then its contents.  A stream's contents
+/// are its length (as a little-endian `u64`) followed by its data.  A
+/// storage's contents are its number of children (as a little-endian
+/// `u32`), followed by each child in CFB order, with each child given as the
+/// length of its name in UTF-16 code units (as a little-endian `u32`), the
+/// name in UTF-16LE, and then the child's own digest.  An object's own name
+/// is not part of its digest, so that equal objects at different paths hash
+/// the same.
+pub fn hash_entry<F: Read + Seek>(
+    comp: &CompoundFile<F>,
+    path: &Path,
+    options: &HashOptions,
+) -> io::Result<Vec<u8>> {
+    let entry = comp.entry(path)?;
+    let mut hasher = options.algorithm().hasher();
+    hasher.update(&[if entry.is_stream() { STREAM_TAG } else { STORAGE_TAG }]);
+    if options.includes_clsids() {
+        hasher.update(entry.clsid().as_bytes());
+    }
+    if options.includes_state_bits() {
+        hasher.update(&entry.state_bits().to_le_bytes());
+    }
+    if options.includes_timestamps() {
+        hasher.update(&entry.created_timestamp().value().to_le_bytes());
+        hasher.update(&entry.modified_timestamp().value().to_le_bytes());
+    }
+    if entry.is_stream() {
+        hasher.update(&entry.len().to_le_bytes());
+        let mut stream =
+            comp.open_stream_with_path(entry.path(), &StreamOptions::new())?;
+        let mut buffer = vec![0u8; CHUNK_LEN];
+        let mut remaining = entry.len();
+        while remaining > 0 {
+            let num_bytes = match stream.read(&mut buffer) {
+                Ok(0) => invalid_data!(
+                    "Stream {:?} ended before its declared length",
+                    entry.path()
+                ),
+                Ok(num_bytes) => num_bytes,
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => {
+                    continue;
+                }
+                Err(error) => return Err(error),
+            };
+            hasher.update(&buffer[..num_bytes]);
+            remaining -= num_bytes as u64;
+        }
+    } else {
+        let mut children: Vec<_> = comp.read_storage(entry.path())?.collect();
+        children.sort_by(|a, b| path::compare_names(a.name(), b.name()));
+        hasher.update(&(children.len() as u32).to_le_bytes());
+        for child in children {
+            let name: Vec<u16> = child.name().encode_utf16().collect();
+            hasher.update(&(name.len() as u32).to_le_bytes());
+            for unit in name {
+                hasher.update(&unit.to_le_bytes());
+            }
+            hasher.update(&hash_entry(comp, child.path(), options)?);
+        }
+    }
+    Ok(hasher.finish())
+}
+
+//===========================================================================//
+
+enum Digester {
+    #[cfg(feature = "sha256")]
+    Sha256(sha2::Sha256),
+    Fnv1a64(fnv::FnvHasher),
+}
+
+impl Digester {
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            #[cfg(feature = "sha256")]
+            Digester::Sha256(sha) => sha.update(data),
+            Digester::Fnv1a64(fnv) => fnv.write(data),
+        }
+    }
+
+    fn finish(self) -> Vec<u8> {
+        match self {
+            #[cfg(feature = "sha256")]
+            Digester::Sha256(sha) => sha.finalize().to_vec(),
+            Digester::Fnv1a64(fnv) => fnv.finish().to_be_bytes().to_vec(),
+        }
+    }
+}
+
+//===========================================================================//
+
+/// Options for computing a content digest with
+/// `CompoundFile::hash_entry_with_options`.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct HashOptions {
+    algorithm: HashAlgorithm,
+    clsids: bool,
+    state_bits: bool,
+    timestamps: bool,
+}
+
+impl HashOptions {
+    /// Returns the default options, which hash names and stream contents
+    /// (but no other attributes) with the default `HashAlgorithm`.
+    pub fn new() -> HashOptions {
+        HashOptions {
+            algorithm: HashAlgorithm::default(),
+            clsids: false,
+            state_bits: false,
+            timestamps: false,
+        }
+    }
+
+    /// Returns the hash function to use.
+    pub fn algorithm(&self) -> HashAlgorithm {
+        self.algorithm
+    }
+
+    /// Sets the hash function to use (see `HashAlgorithm` for the default).
+    pub fn with_algorithm(mut self, algorithm: HashAlgorithm) -> HashOptions {
+        self.algorithm = algorithm;
+        self
+    }
+
+    /// Returns true if each entry's CLSID is part of the digest.
+    pub fn includes_clsids(&self) -> bool {
+        self.clsids
+    }
+
+    /// Sets whether each entry's CLSID is part of the digest (false by
+    /// default).
+    pub fn with_clsids(mut self, include: bool) -> HashOptions {
+        self.clsids = include;
+        self
+    }
+
+    /// Returns true if each entry's state bits are part of the digest.
+    pub fn includes_state_bits(&self) -> bool {
+        self.state_bits
+    }
+
+    /// Sets whether each entry's state bits are part of the digest (false by
+    /// default).
+    pub fn with_state_bits(mut self, include: bool) -> HashOptions {
+        self.state_bits = include;
+        self
+    }
+
+    /// Returns true if each entry's creation and modified times are part of
+    /// the digest.
+    pub fn includes_timestamps(&self) -> bool {
+        self.timestamps
+    }
+
+    /// Sets whether each entry's creation and modified times are part of the
+    /// digest (false by default).
+    pub fn with_timestamps(mut self, include: bool) -> HashOptions {
+        self.timestamps = include;
+        self
+    }
+}
+
+impl Default for HashOptions {
+    fn default() -> HashOptions {
+        HashOptions::new()
+    }
+}
+
+//===========================================================================//
+
+#[cfg(test)]
+mod tests {
+    use super::HashAlgorithm;
+
+    fn digest(algorithm: HashAlgorithm, data: &[u8]) -> String {
+        let mut hasher = algorithm.hasher();
+        hasher.update(data);
+        let digest = hasher.finish();
+        assert_eq!(digest.len(), algorithm.digest_len());
+        digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    #[cfg(feature = "sha256")]
+    #[test]
+    fn sha256_test_vectors() {
+        let sha256 = |data: &[u8]| digest(HashAlgorithm::Sha256, data);
+        assert_eq!(
+            sha256(b""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            sha256(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn fnv_test_vectors() {
+        let fnv = |data: &[u8]| digest(HashAlgorithm::Fnv1a64, data);
+        assert_eq!(fnv(b""), "cbf29ce484222325");
+        assert_eq!(fnv(b"a"), "af63dc4c8601ec8c");
+    }
+}
+
+//===========================================================================//
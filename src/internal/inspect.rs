@@ -0,0 +1,418 @@
+use crate::internal::{consts, MiniAllocator, ObjType};
+use crate::ReadLeNumber;
+use std::borrow::Cow;
+use std::io::{self, Read, Seek};
+
+//===========================================================================//
+
+/// The raw field values of a compound file's header, as they appear on disk.
+///
+/// Unlike the values that the rest of this crate works with, these are not
+/// corrected or normalized in any way (so, for example, a version 3 file that
+/// incorrectly records a nonzero number of directory sectors will report that
+/// number here).
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct HeaderInfo {
+    clsid_reserved: [u8; 16],
+    minor_version: u16,
+    major_version: u16,
+    byte_order_mark: u16,
+    sector_shift: u16,
+    mini_sector_shift: u16,
+    reserved: [u8; 6],
+    num_dir_sectors: u32,
+    num_fat_sectors: u32,
+    first_dir_sector: u32,
+    transaction_signature: u32,
+    mini_stream_cutoff: u32,
+    first_minifat_sector: u32,
+    num_minifat_sectors: u32,
+    first_difat_sector: u32,
+    num_difat_sectors: u32,
+    initial_difat_entries: Vec<u32>,
+}
+
+impl HeaderInfo {
+    pub(crate) fn read_from<R: Read>(
+        reader: &mut R,
+    ) -> io::Result<HeaderInfo> {
+        reader.read_exact(&mut [0u8; 8])?; // magic number
+        let mut clsid_reserved = [0u8; 16];
+        reader.read_exact(&mut clsid_reserved)?;
+        let minor_version = reader.read_le_u16()?;
+        let major_version = reader.read_le_u16()?;
+        let byte_order_mark = reader.read_le_u16()?;
+        let sector_shift = reader.read_le_u16()?;
+        let mini_sector_shift = reader.read_le_u16()?;
+        let mut reserved = [0u8; 6];
+        reader.read_exact(&mut reserved)?;
+        let num_dir_sectors = reader.read_le_u32()?;
+        let num_fat_sectors = reader.read_le_u32()?;
+        let first_dir_sector = reader.read_le_u32()?;
+        let transaction_signature = reader.read_le_u32()?;
+        let mini_stream_cutoff = reader.read_le_u32()?;
+        let first_minifat_sector = reader.read_le_u32()?;
+        let num_minifat_sectors = reader.read_le_u32()?;
+        let first_difat_sector = reader.read_le_u32()?;
+        let num_difat_sectors = reader.read_le_u32()?;
+        let mut initial_difat_entries =
+            Vec::with_capacity(consts::NUM_DIFAT_ENTRIES_IN_HEADER);
+        for _ in 0..consts::NUM_DIFAT_ENTRIES_IN_HEADER {
+            initial_difat_entries.push(reader.read_le_u32()?);
+        }
+        Ok(HeaderInfo {
+            clsid_reserved,
+            minor_version,
+            major_version,
+            byte_order_mark,
+            sector_shift,
+            mini_sector_shift,
+            reserved,
+            num_dir_sectors,
+            num_fat_sectors,
+            first_dir_sector,
+            transaction_signature,
+            mini_stream_cutoff,
+            first_minifat_sector,
+            num_minifat_sectors,
+            first_difat_sector,
+            num_difat_sectors,
+            initial_difat_entries,
+        })
+    }
+
+    /// Returns the reserved header CLSID field, which should be all zeros.
+    pub fn clsid_reserved(&self) -> &[u8; 16] {
+        &self.clsid_reserved
+    }
+
+    /// Returns the minor version number (normally 0x3E).
+    pub fn minor_version(&self) -> u16 {
+        self.minor_version
+    }
+
+    /// Returns the major version number (3 or 4).
+    pub fn major_version(&self) -> u16 {
+        self.major_version
+    }
+
+    /// Returns the byte order mark (which must be 0xFFFE).
+    pub fn byte_order_mark(&self) -> u16 {
+        self.byte_order_mark
+    }
+
+    /// Returns the base-2 logarithm of the sector length (9 for version 3
+    /// files, 12 for version 4 files).
+    pub fn sector_shift(&self) -> u16 {
+        self.sector_shift
+    }
+
+    /// Returns the base-2 logarithm of the mini sector length (which must be
+    /// 6).
+    pub fn mini_sector_shift(&self) -> u16 {
+        self.mini_sector_shift
+    }
+
+    /// Returns the six reserved bytes following the mini sector shift, which
+    /// should be all zeros.
+    pub fn reserved(&self) -> &[u8; 6] {
+        &self.reserved
+    }
+
+    /// Returns the number of directory sectors recorded in the header (which
+    /// should be zero for version 3 files).
+    pub fn num_dir_sectors(&self) -> u32 {
+        self.num_dir_sectors
+    }
+
+    /// Returns the number of FAT sectors recorded in the header.
+    pub fn num_fat_sectors(&self) -> u32 {
+        self.num_fat_sectors
+    }
+
+    /// Returns the first sector of the directory chain.
+    pub fn first_dir_sector(&self) -> u32 {
+        self.first_dir_sector
+    }
+
+    /// Returns the transaction signature number, which is only used by
+    /// implementations that support transactions (and is otherwise zero).
+    pub fn transaction_signature(&self) -> u32 {
+        self.transaction_signature
+    }
+
+    /// Returns the mini stream cutoff size (which must be 4096).
+    pub fn mini_stream_cutoff(&self) -> u32 {
+        self.mini_stream_cutoff
+    }
+
+    /// Returns the first sector of the MiniFAT chain.
+    pub fn first_minifat_sector(&self) -> u32 {
+        self.first_minifat_sector
+    }
+
+    /// Returns the number of MiniFAT sectors recorded in the header.
+    pub fn num_minifat_sectors(&self) -> u32 {
+        self.num_minifat_sectors
+    }
+
+    /// Returns the first sector of the DIFAT chain.
+    pub fn first_difat_sector(&self) -> u32 {
+        self.first_difat_sector
+    }
+
+    /// Returns the number of DIFAT sectors recorded in the header.
+    pub fn num_difat_sectors(&self) -> u32 {
+        self.num_difat_sectors
+    }
+
+    /// Returns all 109 DIFAT entries stored in the header, including unused
+    /// ones.
+    pub fn initial_difat_entries(&self) -> &[u32] {
+        &self.initial_difat_entries
+    }
+}
+
+//===========================================================================//
+
+/// A single entry of a FAT or MiniFAT.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum FatEntry {
+    /// The next sector in the chain.
+    Next(u32),
+    /// The value `0xFFFFFFFB`, which is reserved and not valid in a FAT.
+    Invalid,
+    /// `DIFAT_SECTOR`: the sector is used by the DIFAT.
+    DifatSector,
+    /// `FAT_SECTOR`: the sector is used by the FAT.
+    FatSector,
+    /// `END_OF_CHAIN`: the sector is the last one in its chain.
+    EndOfChain,
+    /// `FREE_SECTOR`: the sector is unallocated.
+    Free,
+}
+
+impl FatEntry {
+    /// Interprets a raw FAT entry value.
+    pub fn from_value(value: u32) -> FatEntry {
+        match value {
+            consts::INVALID_SECTOR => FatEntry::Invalid,
+            consts::DIFAT_SECTOR => FatEntry::DifatSector,
+            consts::FAT_SECTOR => FatEntry::FatSector,
+            consts::END_OF_CHAIN => FatEntry::EndOfChain,
+            consts::FREE_SECTOR => FatEntry::Free,
+            next => FatEntry::Next(next),
+        }
+    }
+
+    /// Returns the raw value of this FAT entry.
+    pub fn value(self) -> u32 {
+        match self {
+            FatEntry::Next(next) => next,
+            FatEntry::Invalid => consts::INVALID_SECTOR,
+            FatEntry::DifatSector => consts::DIFAT_SECTOR,
+            FatEntry::FatSector => consts::FAT_SECTOR,
+            FatEntry::EndOfChain => consts::END_OF_CHAIN,
+            FatEntry::Free => consts::FREE_SECTOR,
+        }
+    }
+}
+
+//===========================================================================//
+
+/// The location of one sector (or mini sector) of a chain.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ChainSector {
+    sector_id: u32,
+    offset: u64,
+    len: u64,
+}
+
+impl ChainSector {
+    /// Returns the sector number (or, for a mini chain, the mini sector
+    /// number).
+    pub fn sector_id(&self) -> u32 {
+        self.sector_id
+    }
+
+    /// Returns the byte offset of the start of the sector within the
+    /// underlying file.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Returns the length of the sector, in bytes.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns true if the sector has zero length (which never happens).
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+/// The chain of sectors holding the data of a single stream.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct StreamChain {
+    in_mini_stream: bool,
+    sectors: Vec<ChainSector>,
+}
+
+impl StreamChain {
+    /// Returns true if the stream is stored in the mini stream, in which case
+    /// the chain is made of mini sectors rather than regular sectors.
+    pub fn in_mini_stream(&self) -> bool {
+        self.in_mini_stream
+    }
+
+    /// Returns the sectors of the chain, in order.
+    pub fn sectors(&self) -> &[ChainSector] {
+        &self.sectors
+    }
+}
+
+//===========================================================================//
+
+pub fn read_header<F: Read + Seek>(
+    minialloc: &mut MiniAllocator<F>,
+) -> io::Result<HeaderInfo> {
+    let data = minialloc.read_at(0, consts::HEADER_LEN as u64)?;
+    HeaderInfo::read_from(&mut data.as_slice())
+}
+
+/// Lists the chain of the stream with the given ID.  For the root entry, this
+/// is the chain of sectors holding the mini stream.
+pub fn stream_chain<F>(
+    minialloc: &MiniAllocator<F>,
+    stream_id: u32,
+) -> io::Result<StreamChain> {
+    let sector_len = minialloc.sector_len() as u64;
+    let sector_offset = |sector_id: u32| (sector_id as u64 + 1) * sector_len;
+    let dir_entry = minialloc.dir_entry(stream_id);
+    let in_mini_stream = match dir_entry.obj_type {
+        ObjType::Stream => {
+            dir_entry.stream_len < consts::MINI_STREAM_CUTOFF as u64
+        }
+        ObjType::Root => false,
+        ObjType::Storage | ObjType::Unallocated => {
+            invalid_input!("Not a stream: {}", dir_entry.name);
+        }
+    };
+    let sectors = if in_mini_stream {
+        let mini_stream_sectors = minialloc
+            .chain_sector_ids(minialloc.root_dir_entry().start_sector)?;
+        let mini_sector_len = consts::MINI_SECTOR_LEN as u64;
+        let mut sectors = Vec::new();
+        for mini_sector in
+            minialloc.mini_chain_sector_ids(dir_entry.start_sector)?
+        {
+            let offset = mini_sector as u64 * mini_sector_len;
+            let sector_id = match mini_stream_sectors
+                .get((offset / sector_len) as usize)
+            {
+                Some(&sector_id) => sector_id,
+                None => invalid_data!(
+                    "Mini sector {} is beyond the end of the mini stream",
+                    mini_sector
+                ),
+            };
+            sectors.push(ChainSector {
+                sector_id: mini_sector,
+                offset: sector_offset(sector_id) + offset % sector_len,
+                len: mini_sector_len,
+            });
+        }
+        sectors
+    } else {
+        minialloc
+            .chain_sector_ids(dir_entry.start_sector)?
+            .into_iter()
+            .map(|sector_id| ChainSector {
+                sector_id,
+                offset: sector_offset(sector_id),
+                len: sector_len,
+            })
+            .collect()
+    };
+    Ok(StreamChain { in_mini_stream, sectors })
+}
+
+/// Returns the first `len` bytes of the given chain's data, given the
+/// complete contents of the underlying file.  If the data is stored
+/// contiguously within the file, it is borrowed rather than copied.  Any part
+/// of the chain past the end of the file reads as zeros.
+pub fn chain_data<'a>(
+    file_data: &'a [u8],
+    chain: &StreamChain,
+    len: u64,
+) -> io::Result<Cow<'a, [u8]>> {
+    let sectors = chain.sectors();
+    let chain_len: u64 = sectors.iter().map(|sector| sector.len).sum();
+    if chain_len < len {
+        invalid_data!(
+            "Chain has only {} bytes, but stream length is {}",
+            chain_len,
+            len
+        );
+    }
+    let is_contiguous = sectors
+        .windows(2)
+        .all(|pair| pair[0].offset + pair[0].len == pair[1].offset);
+    if let Some(first) = sectors.first() {
+        if is_contiguous && first.offset + len <= file_data.len() as u64 {
+            let start = first.offset as usize;
+            return Ok(Cow::Borrowed(&file_data[start..start + len as usize]));
+        }
+    }
+    let mut data = Vec::with_capacity(len as usize);
+    for sector in sectors {
+        let remaining = len - data.len() as u64;
+        if remaining == 0 {
+            break;
+        }
+        let start = (sector.offset as usize).min(file_data.len());
+        let end = (sector.offset + sector.len.min(remaining))
+            .min(file_data.len() as u64) as usize;
+        data.extend_from_slice(&file_data[start..end]);
+        data.resize(
+            data.len() + (sector.len.min(remaining) as usize - (end - start)),
+            0,
+        );
+    }
+    Ok(Cow::Owned(data))
+}
+
+//===========================================================================//
+
+#[cfg(test)]
+mod tests {
+    use super::FatEntry;
+    use crate::internal::consts;
+
+    #[test]
+    fn fat_entry_round_trip() {
+        for &value in &[
+            0,
+            1234,
+            consts::MAX_REGULAR_SECTOR,
+            consts::INVALID_SECTOR,
+            consts::DIFAT_SECTOR,
+            consts::FAT_SECTOR,
+            consts::END_OF_CHAIN,
+            consts::FREE_SECTOR,
+        ] {
+            assert_eq!(FatEntry::from_value(value).value(), value);
+        }
+        assert_eq!(
+            FatEntry::from_value(consts::MAX_REGULAR_SECTOR),
+            FatEntry::Next(consts::MAX_REGULAR_SECTOR)
+        );
+        assert_eq!(
+            FatEntry::from_value(consts::END_OF_CHAIN),
+            FatEntry::EndOfChain
+        );
+    }
+}
+
+//===========================================================================//
@@ -0,0 +1,415 @@
+use crate::internal::diff::{compare_paths, sorted_entries, streams_equal};
+use crate::internal::{hash, Entry, HashOptions, StreamOptions, Timestamp};
+use crate::CompoundFile;
+use std::cmp::Ordering;
+use std::io::{self, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+//===========================================================================//
+
+/// Which version of an entry to keep when resolving a merge conflict.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum MergeResolution {
+    /// Keep the entry as it is in the base file.
+    Base,
+    /// Keep the entry as it is in "our" file.
+    Ours,
+    /// Keep the entry as it is in "their" file.
+    Theirs,
+}
+
+/// An entry that was changed in different ways in "our" file and "their"
+/// file during a three-way merge.
+#[derive(Clone, Debug)]
+pub struct MergeConflict {
+    path: PathBuf,
+    base: Option<Entry>,
+    ours: Option<Entry>,
+    theirs: Option<Entry>,
+}
+
+impl MergeConflict {
+    /// Returns the path of the conflicting entry.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the entry as it is in the base file, or `None` if it doesn't
+    /// exist there.
+    pub fn base(&self) -> Option<&Entry> {
+        self.base.as_ref()
+    }
+
+    /// Returns the entry as it is in "our" file, or `None` if it doesn't
+    /// exist there.
+    pub fn ours(&self) -> Option<&Entry> {
+        self.ours.as_ref()
+    }
+
+    /// Returns the entry as it is in "their" file, or `None` if it doesn't
+    /// exist there.
+    pub fn theirs(&self) -> Option<&Entry> {
+        self.theirs.as_ref()
+    }
+}
+
+/// The result of a three-way merge with `cfb::merge`.
+#[derive(Clone, Debug)]
+pub struct MergeReport {
+    conflicts: Vec<(MergeConflict, MergeResolution)>,
+}
+
+impl MergeReport {
+    /// Returns each conflict that was found, in path order, along with how
+    /// it was resolved.
+    pub fn conflicts(&self) -> &[(MergeConflict, MergeResolution)] {
+        &self.conflicts
+    }
+
+    /// Returns true if any conflicts were found.
+    pub fn has_conflicts(&self) -> bool {
+        !self.conflicts.is_empty()
+    }
+}
+
+//===========================================================================//
+
+/// What an entry contains, for the purposes of merging.
+#[derive(Clone, Eq, PartialEq)]
+enum Content {
+    Absent,
+    Storage,
+    Stream(Vec<u8>),
+}
+
+/// An entry's attributes, for the purposes of merging.
+#[derive(Clone, Eq, PartialEq)]
+struct Metadata {
+    clsid: Uuid,
+    state_bits: u32,
+    created: Timestamp,
+    modified: Timestamp,
+}
+
+impl Metadata {
+    fn of(entry: &Option<Entry>) -> Option<Metadata> {
+        entry.as_ref().map(|entry| Metadata {
+            clsid: *entry.clsid(),
+            state_bits: entry.state_bits(),
+            created: entry.created_timestamp(),
+            modified: entry.modified_timestamp(),
+        })
+    }
+}
+
+/// Decides which side's version of something to keep, or returns `None` if
+/// both sides changed it differently.  `eq` tells whether two sides'
+/// versions are the same.
+fn merge3<E>(mut eq: E) -> io::Result<Option<MergeResolution>>
+where
+    E: FnMut(MergeResolution, MergeResolution) -> io::Result<bool>,
+{
+    use MergeResolution::{Base, Ours, Theirs};
+    Ok(if eq(Ours, Theirs)? || eq(Base, Theirs)? {
+        Some(Ours)
+    } else if eq(Base, Ours)? {
+        Some(Theirs)
+    } else {
+        None
+    })
+}
+
+fn content<F: Read + Seek>(
+    comp: &CompoundFile<F>,
+    entry: &Option<Entry>,
+) -> io::Result<Content> {
+    Ok(match entry {
+        None => Content::Absent,
+        Some(entry) if entry.is_storage() => Content::Storage,
+        Some(entry) => Content::Stream(hash::hash_entry(
+            comp,
+            entry.path(),
+            &HashOptions::new(),
+        )?),
+    })
+}
+
+/// Returns true if two versions of an entry have the same content.  Stream
+/// digests only serve to rule out most differences cheaply; streams whose
+/// digests match are then compared byte by byte, so that a hash collision
+/// can't make an edit look like no change.
+fn same_content<A: Read + Seek, B: Read + Seek>(
+    (a_comp, a_entry, a_content): (&CompoundFile<A>, &Option<Entry>, &Content),
+    (b_comp, b_entry, b_content): (&CompoundFile<B>, &Option<Entry>, &Content),
+) -> io::Result<bool> {
+    if a_content != b_content {
+        return Ok(false);
+    }
+    match (a_entry, b_entry) {
+        (Some(a), Some(b)) if a.is_stream() => {
+            streams_equal(a_comp, a, b_comp, b)
+        }
+        _ => Ok(true),
+    }
+}
+
+/// The versions of a single entry in each of the three files.
+struct Versions {
+    base: Option<Entry>,
+    ours: Option<Entry>,
+    theirs: Option<Entry>,
+}
+
+impl Versions {
+    fn get(&self, side: MergeResolution) -> &Option<Entry> {
+        match side {
+            MergeResolution::Base => &self.base,
+            MergeResolution::Ours => &self.ours,
+            MergeResolution::Theirs => &self.theirs,
+        }
+    }
+}
+
+/// Groups the entries of three files by path.
+fn group_by_path<B, O, T>(
+    base: &CompoundFile<B>,
+    ours: &CompoundFile<O>,
+    theirs: &CompoundFile<T>,
+) -> io::Result<Vec<Versions>> {
+    let mut all: Vec<(MergeResolution, Entry)> = Vec::new();
+    for (side, entries) in [
+        (MergeResolution::Base, sorted_entries(base)?),
+        (MergeResolution::Ours, sorted_entries(ours)?),
+        (MergeResolution::Theirs, sorted_entries(theirs)?),
+    ] {
+        all.extend(entries.into_iter().map(|entry| (side, entry)));
+    }
+    all.sort_by(|(_, a), (_, b)| compare_paths(a.path(), b.path()));
+    let mut groups: Vec<Versions> = Vec::new();
+    for (side, entry) in all {
+        let same_path = groups.last().is_some_and(|group| {
+            let existing = group
+                .base
+                .as_ref()
+                .or(group.ours.as_ref())
+                .or(group.theirs.as_ref())
+                .unwrap();
+            compare_paths(existing.path(), entry.path()) == Ordering::Equal
+        });
+        if !same_path {
+            groups.push(Versions { base: None, ours: None, theirs: None });
+        }
+        let group = groups.last_mut().unwrap();
+        match side {
+            MergeResolution::Base => group.base = Some(entry),
+            MergeResolution::Ours => group.ours = Some(entry),
+            MergeResolution::Theirs => group.theirs = Some(entry),
+        }
+    }
+    Ok(groups)
+}
+
+fn copy_stream<S: Read + Seek, W: Read + Write + Seek>(
+    source: &CompoundFile<S>,
+    path: &Path,
+    output: &mut CompoundFile<W>,
+) -> io::Result<()> {
+    let options = StreamOptions::new();
+    let mut reader = source.open_stream_with_path(path, &options)?;
+    let mut writer = output.create_new_stream(path)?;
+    io::copy(&mut reader, &mut writer)?;
+    writer.flush()
+}
+
+/// Merges the changes made in two descendants of a common base file ("our"
+/// file and "their" file), writing the result into `output`, which must be
+/// a newly created compound file with no entries.  See `merge_with` for
+/// details.  Any conflicts are resolved by keeping our version of the
+/// conflicting entry, and are listed in the returned report.
+pub fn merge<B, O, T, W>(
+    base: &CompoundFile<B>,
+    ours: &CompoundFile<O>,
+    theirs: &CompoundFile<T>,
+    output: &mut CompoundFile<W>,
+) -> io::Result<MergeReport>
+where
+    B: Read + Seek,
+    O: Read + Seek,
+    T: Read + Seek,
+    W: Read + Write + Seek,
+{
+    merge_with(base, ours, theirs, output, |_| MergeResolution::Ours)
+}
+
+/// Merges the changes made in two descendants of a common base file ("our"
+/// file and "their" file), writing the result into `output`, which must be
+/// a newly created compound file with no entries.
+///
+/// Entries are matched up by path (using case-insensitive CFB name
+/// comparison).  For each entry, its existence and contents (that is, its
+/// type and, for a stream, its data) are merged separately from its
+/// attributes (CLSID, state bits and timestamps): whatever changed on only
+/// one side is kept, and if both sides made the same change, it is kept
+/// once.  If the two sides changed the same entry in different ways, the
+/// `resolve` callback is called to decide which version of the whole entry
+/// to keep.  Every conflict and its resolution is listed in the returned
+/// report.
+///
+/// Storages are merged entry by entry, so (for example) streams added to
+/// the same storage on each side are both kept.  If an entry is kept but its
+/// parent storage was removed, the parent storage is recreated with default
+/// attributes.  Returns an error if an entry is kept but its parent was
+/// replaced by a stream.
+pub fn merge_with<B, O, T, W, R>(
+    base: &CompoundFile<B>,
+    ours: &CompoundFile<O>,
+    theirs: &CompoundFile<T>,
+    output: &mut CompoundFile<W>,
+    mut resolve: R,
+) -> io::Result<MergeReport>
+where
+    B: Read + Seek,
+    O: Read + Seek,
+    T: Read + Seek,
+    W: Read + Write + Seek,
+    R: FnMut(&MergeConflict) -> MergeResolution,
+{
+    if output.read_root_storage().next().is_some() {
+        invalid_input!("Merge output must be an empty compound file");
+    }
+    let mut conflicts = Vec::new();
+    // For each path, which side's content and which side's metadata to
+    // keep.
+    let mut resolved: Vec<(Versions, MergeResolution, MergeResolution)> =
+        Vec::new();
+    for versions in group_by_path(base, ours, theirs)? {
+        let contents = (
+            content(base, &versions.base)?,
+            content(ours, &versions.ours)?,
+            content(theirs, &versions.theirs)?,
+        );
+        let base_version = (base, &versions.base, &contents.0);
+        let ours_version = (ours, &versions.ours, &contents.1);
+        let theirs_version = (theirs, &versions.theirs, &contents.2);
+        let content_side = merge3(|a, b| match (a, b) {
+            (MergeResolution::Base, MergeResolution::Ours) => {
+                same_content(base_version, ours_version)
+            }
+            (MergeResolution::Base, _) => {
+                same_content(base_version, theirs_version)
+            }
+            _ => same_content(ours_version, theirs_version),
+        })?;
+        let metadata_side = merge3(|a, b| {
+            Ok(Metadata::of(versions.get(a)) == Metadata::of(versions.get(b)))
+        })?;
+        let (content_side, metadata_side) = match (content_side, metadata_side)
+        {
+            (Some(content_side), Some(metadata_side))
+                if versions.get(content_side).is_none()
+                    || versions.get(metadata_side).is_some() =>
+            {
+                (content_side, metadata_side)
+            }
+            _ => {
+                let conflict = MergeConflict {
+                    path: versions
+                        .ours
+                        .as_ref()
+                        .or(versions.theirs.as_ref())
+                        .or(versions.base.as_ref())
+                        .unwrap()
+                        .path()
+                        .to_path_buf(),
+                    base: versions.base.clone(),
+                    ours: versions.ours.clone(),
+                    theirs: versions.theirs.clone(),
+                };
+                let side = resolve(&conflict);
+                conflicts.push((conflict, side));
+                (side, side)
+            }
+        };
+        if versions.get(content_side).is_some() {
+            resolved.push((versions, content_side, metadata_side));
+        }
+    }
+
+    // Write out the kept entries, parents first.
+    let mut kept_streams: Vec<PathBuf> = Vec::new();
+    for (versions, content_side, metadata_side) in resolved.iter() {
+        let entry = versions.get(*content_side).as_ref().unwrap();
+        let path = entry.path();
+        if let Some(stream) = kept_streams.iter().find(|stream| {
+            path.ancestors().skip(1).any(|ancestor| {
+                compare_paths(ancestor, stream) == Ordering::Equal
+            })
+        }) {
+            invalid_input!(
+                "Cannot keep {:?} because {:?} was replaced by a stream",
+                path,
+                stream
+            );
+        }
+        if let Some(parent) = path.parent() {
+            output.create_storage_all(parent)?;
+        }
+        if entry.is_stream() {
+            match content_side {
+                MergeResolution::Base => copy_stream(base, path, output)?,
+                MergeResolution::Ours => copy_stream(ours, path, output)?,
+                MergeResolution::Theirs => copy_stream(theirs, path, output)?,
+            }
+            kept_streams.push(path.to_path_buf());
+        } else if !entry.is_root() {
+            output.create_storage(path)?;
+        }
+        let attributes = versions.get(*metadata_side).as_ref().unwrap();
+        if entry.is_storage() {
+            output.set_storage_clsid(path, *attributes.clsid())?;
+        }
+        output.set_state_bits(path, attributes.state_bits())?;
+    }
+    // Set timestamps last, so that they're exactly as merged.
+    for (versions, content_side, metadata_side) in resolved.iter() {
+        let entry = versions.get(*content_side).as_ref().unwrap();
+        let attributes = versions.get(*metadata_side).as_ref().unwrap();
+        if entry.is_storage() {
+            output.set_entry_with_path(entry.path(), |dir_entry| {
+                dir_entry.creation_time = attributes.created_timestamp();
+                dir_entry.modified_time = attributes.modified_timestamp();
+            })?;
+        }
+    }
+    Ok(MergeReport { conflicts })
+}
+
+//===========================================================================//
+
+#[cfg(test)]
+mod tests {
+    use super::{same_content, Content};
+    use crate::CompoundFile;
+    use std::io::{Cursor, Write};
+
+    #[test]
+    fn matching_digests_are_confirmed_by_comparing_data() {
+        let mut comp = CompoundFile::create(Cursor::new(Vec::new())).unwrap();
+        comp.create_stream("/a").unwrap().write_all(b"foo").unwrap();
+        comp.create_stream("/b").unwrap().write_all(b"bar").unwrap();
+        comp.create_stream("/c").unwrap().write_all(b"foo").unwrap();
+        let a = Some(comp.entry("/a").unwrap());
+        let b = Some(comp.entry("/b").unwrap());
+        let c = Some(comp.entry("/c").unwrap());
+        // Pretend that all three streams' digests collide.
+        let digest = Content::Stream(vec![0; 8]);
+        let same = |x, y| {
+            same_content((&comp, x, &digest), (&comp, y, &digest)).unwrap()
+        };
+        assert!(same(&a, &c));
+        assert!(!same(&a, &b));
+    }
+}
+
+//===========================================================================//
@@ -1,11 +1,11 @@
-use std::io::{self, Seek, SeekFrom, Write};
+use std::io::{self, Read, Seek, SeekFrom, Write};
 use std::mem::size_of;
 
-use fnv::FnvHashSet;
+use fnv::{FnvHashMap, FnvHashSet};
 
 use crate::internal::{
-    consts, Chain, DirEntry, Directory, MiniChain, ObjType, Sector,
-    SectorInit, Validation, Version,
+    consts, AllocationPolicy, Chain, DirEntry, Directory, MiniChain, ObjType,
+    Sector, SectorInit, Timestamp, Validation, Version,
 };
 use crate::WriteLeNumber;
 
@@ -26,6 +26,13 @@ pub struct MiniAllocator<F> {
     directory: Directory<F>,
     minifat: Vec<u32>,
     minifat_start_sector: u32,
+    /// No mini sector before this index in the MiniFAT is free, so searches
+    /// for a free mini sector can start here.
+    free_hint: usize,
+    /// The lengths that streams have reserved space for, keyed by stream ID.
+    reserved_lens: FnvHashMap<u32, u64>,
+    /// Incremented whenever a mini sector is freed.
+    generation: u64,
 }
 
 impl<F> MiniAllocator<F> {
@@ -35,8 +42,14 @@ impl<F> MiniAllocator<F> {
         minifat_start_sector: u32,
         validation: Validation,
     ) -> io::Result<MiniAllocator<F>> {
-        let mut minialloc =
-            MiniAllocator { directory, minifat, minifat_start_sector };
+        let mut minialloc = MiniAllocator {
+            directory,
+            minifat,
+            minifat_start_sector,
+            free_hint: 0,
+            reserved_lens: FnvHashMap::default(),
+            generation: 0,
+        };
         minialloc.validate(validation)?;
         Ok(minialloc)
     }
@@ -49,6 +62,123 @@ impl<F> MiniAllocator<F> {
         self.directory.inner()
     }
 
+    pub fn sector_len(&self) -> usize {
+        self.directory.sector_len()
+    }
+
+    pub fn num_sectors(&self) -> u32 {
+        self.directory.num_sectors()
+    }
+
+    pub fn secure_delete(&self) -> bool {
+        self.directory.secure_delete()
+    }
+
+    /// Sets whether freed sectors and mini sectors should be overwritten with
+    /// zeros.
+    pub fn set_secure_delete(&mut self, secure_delete: bool) {
+        self.directory.set_secure_delete(secure_delete);
+    }
+
+    pub fn allocation_policy(&self) -> AllocationPolicy {
+        self.directory.allocation_policy()
+    }
+
+    /// Sets the strategy used to choose new sectors for growing chains.
+    pub fn set_allocation_policy(&mut self, policy: AllocationPolicy) {
+        self.directory.set_allocation_policy(policy);
+    }
+
+    pub fn fixed_timestamp(&self) -> Option<Timestamp> {
+        self.directory.fixed_timestamp()
+    }
+
+    /// Sets the timestamp to use in place of the current time, if any.
+    pub fn set_fixed_timestamp(&mut self, timestamp: Option<Timestamp>) {
+        self.directory.set_fixed_timestamp(timestamp);
+    }
+
+    pub fn now(&self) -> Timestamp {
+        self.directory.now()
+    }
+
+    /// Returns a number that changes whenever any sector or mini sector is
+    /// freed (which is the only way that an existing chain can change, other
+    /// than by growing).
+    pub fn generation(&self) -> u64 {
+        self.directory.generation() + self.generation
+    }
+
+    #[cfg(test)]
+    pub fn fat_visits(&self) -> u64 {
+        self.directory.fat_visits()
+    }
+
+    /// Returns a number that changes whenever an entry is added to or removed
+    /// from the storage tree.
+    pub fn structure_generation(&self) -> u64 {
+        self.directory.structure_generation()
+    }
+
+    pub fn parent_id(&mut self, stream_id: u32) -> io::Result<Option<u32>> {
+        self.directory.parent_id(stream_id)
+    }
+
+    pub fn fat(&self) -> &[u32] {
+        self.directory.fat()
+    }
+
+    pub fn difat(&self) -> &[u32] {
+        self.directory.difat()
+    }
+
+    pub fn difat_sector_ids(&self) -> &[u32] {
+        self.directory.difat_sector_ids()
+    }
+
+    /// Returns the in-memory copy of the MiniFAT, indexed by mini sector
+    /// number.
+    pub fn minifat(&self) -> &[u32] {
+        &self.minifat
+    }
+
+    pub fn minifat_start_sector(&self) -> u32 {
+        self.minifat_start_sector
+    }
+
+    pub fn dir_start_sector(&self) -> u32 {
+        self.directory.dir_start_sector()
+    }
+
+    pub fn chain_sector_ids(
+        &self,
+        start_sector_id: u32,
+    ) -> io::Result<Vec<u32>> {
+        self.directory.chain_sector_ids(start_sector_id)
+    }
+
+    /// Returns the mini sector numbers of the mini chain starting at the given
+    /// mini sector, without opening the chain for I/O.
+    pub fn mini_chain_sector_ids(
+        &self,
+        start_mini_sector: u32,
+    ) -> io::Result<Vec<u32>> {
+        let mut mini_sectors = Vec::<u32>::new();
+        let mut current_mini_sector = start_mini_sector;
+        while current_mini_sector != consts::END_OF_CHAIN {
+            if mini_sectors.len() >= self.minifat.len() {
+                invalid_data!(
+                    "Mini chain starting at mini sector {} contains a loop",
+                    start_mini_sector
+                );
+            }
+            mini_sectors.push(current_mini_sector);
+            current_mini_sector =
+                self.next_mini_sector(current_mini_sector)?;
+        }
+        Ok(mini_sectors)
+    }
+
     pub fn next_mini_sector(&self, sector_id: u32) -> io::Result<u32> {
         let index = sector_id as usize;
         if index >= self.minifat.len() {
@@ -77,6 +207,26 @@ impl<F> MiniAllocator<F> {
         self.directory.stream_id_for_name_chain(names)
     }
 
+    pub fn is_lazy(&self) -> bool {
+        self.directory.is_lazy()
+    }
+
+    pub fn load_storage(&mut self, storage_id: u32) -> io::Result<()> {
+        self.directory.load_storage(storage_id)
+    }
+
+    pub fn load_path(&mut self, names: &[&str]) -> io::Result<()> {
+        self.directory.load_path(names)
+    }
+
+    pub fn load_subtree(&mut self, stream_id: u32) -> io::Result<()> {
+        self.directory.load_subtree(stream_id)
+    }
+
+    pub fn load_all(&mut self) -> io::Result<()> {
+        self.directory.load_all()
+    }
+
     pub fn open_chain(
         &mut self,
         start_sector_id: u32,
@@ -100,6 +250,10 @@ impl<F> MiniAllocator<F> {
         self.directory.dir_entry(stream_id)
     }
 
+    pub fn dir_entries(&self) -> io::Result<&[DirEntry]> {
+        self.directory.dir_entries()
+    }
+
     fn validate(&mut self, validation: Validation) -> io::Result<()> {
         let root_entry = self.directory.root_dir_entry();
         let root_stream_mini_sectors =
@@ -144,24 +298,76 @@ impl<F> MiniAllocator<F> {
 }
 
 impl<F: Seek> MiniAllocator<F> {
+    pub fn seek_to_sector(
+        &mut self,
+        sector_id: u32,
+    ) -> io::Result<Sector<'_, F>> {
+        self.directory.seek_to_sector(sector_id)
+    }
+
+    pub fn read_at(&mut self, offset: u64, len: u64) -> io::Result<Vec<u8>>
+    where
+        F: Read,
+    {
+        self.directory.read_at(offset, len)
+    }
+
     pub fn seek_within_mini_sector(
         &mut self,
         mini_sector: u32,
         offset_within_mini_sector: u64,
     ) -> io::Result<Sector<'_, F>> {
+        self.seek_within_mini_sectors(
+            mini_sector,
+            1,
+            offset_within_mini_sector,
+        )
+    }
+
+    /// Returns a `Sector` spanning the run of up to `num_mini_sectors`
+    /// adjacent mini sectors starting at `mini_sector`, positioned at the
+    /// given offset within the first mini sector.  The run is cut short where
+    /// the mini stream's underlying sectors stop being adjacent in the file.
+    pub fn seek_within_mini_sectors(
+        &mut self,
+        mini_sector: u32,
+        num_mini_sectors: u32,
+        offset_within_mini_sector: u64,
+    ) -> io::Result<Sector<'_, F>> {
+        debug_assert!(num_mini_sectors > 0);
         debug_assert!(
             offset_within_mini_sector < consts::MINI_SECTOR_LEN as u64
         );
         let mini_stream_start_sector =
             self.directory.root_dir_entry().start_sector;
-        let chain = self
+        let sector_len = self.directory.sector_len();
+        let mini_sectors_per_sector =
+            (sector_len / consts::MINI_SECTOR_LEN) as u32;
+        let first_index = mini_sector / mini_sectors_per_sector;
+        let last_index =
+            (mini_sector + num_mini_sectors - 1) / mini_sectors_per_sector;
+        let sector_id = self
             .directory
-            .open_chain(mini_stream_start_sector, SectorInit::Fat)?;
-        chain.into_subsector(
-            mini_sector,
-            consts::MINI_SECTOR_LEN,
-            offset_within_mini_sector,
-        )
+            .chain_sector_id(mini_stream_start_sector, first_index as usize)?;
+        let mut num_sectors = 1;
+        while first_index + num_sectors <= last_index
+            && self.directory.chain_sector_id(
+                mini_stream_start_sector,
+                (first_index + num_sectors) as usize,
+            )? == sector_id + num_sectors
+        {
+            num_sectors += 1;
+        }
+        let subsector_start = (mini_sector % mini_sectors_per_sector) as usize
+            * consts::MINI_SECTOR_LEN;
+        let run_len = (num_mini_sectors as usize * consts::MINI_SECTOR_LEN)
+            .min(num_sectors as usize * sector_len - subsector_start);
+        let sector = self.directory.seek_within_sectors(
+            sector_id,
+            num_sectors,
+            subsector_start as u64 + offset_within_mini_sector,
+        )?;
+        Ok(sector.subsector(subsector_start, run_len))
     }
 }
 
@@ -171,6 +377,53 @@ impl<F: Write + Seek> MiniAllocator<F> {
         self.directory.free_chain(start_sector_id)
     }
 
+    /// Reserves enough free sectors for the specified stream to grow to
+    /// `len` bytes.  If the stream is currently in the mini stream, the
+    /// sectors are reserved once it moves out of it.
+    pub fn reserve_stream(
+        &mut self,
+        stream_id: u32,
+        len: u64,
+    ) -> io::Result<()> {
+        let reserved_len = self.reserved_lens.entry(stream_id).or_insert(0);
+        *reserved_len = (*reserved_len).max(len);
+        self.apply_stream_reservation(stream_id)
+    }
+
+    /// Sets aside the sectors that the specified stream has reserved space
+    /// for, if it is no longer in the mini stream.
+    pub fn apply_stream_reservation(
+        &mut self,
+        stream_id: u32,
+    ) -> io::Result<()> {
+        let reserved_len = match self.reserved_lens.get(&stream_id) {
+            Some(&reserved_len) => reserved_len,
+            None => return Ok(()),
+        };
+        let (start_sector, stream_len) = {
+            let dir_entry = self.directory.dir_entry(stream_id);
+            debug_assert_eq!(dir_entry.obj_type, ObjType::Stream);
+            (dir_entry.start_sector, dir_entry.stream_len)
+        };
+        if reserved_len <= stream_len {
+            self.reserved_lens.remove(&stream_id);
+            return Ok(());
+        }
+        if stream_len < consts::MINI_STREAM_CUTOFF as u64 {
+            return Ok(());
+        }
+        let sector_len = self.directory.sector_len() as u64;
+        let num_sectors = stream_len.div_ceil(sector_len);
+        let num_reserved_sectors = reserved_len.div_ceil(sector_len);
+        let last_sector_id = self
+            .directory
+            .chain_sector_id(start_sector, num_sectors as usize - 1)?;
+        let num_new_sectors = (num_reserved_sectors - num_sectors)
+            .min(consts::MAX_REGULAR_SECTOR as u64)
+            as u32;
+        self.directory.reserve(last_sector_id, num_new_sectors)
+    }
+
     /// Inserts a new directory entry into the tree under the specified parent
     /// entry, then returns the new stream ID.
     pub fn insert_dir_entry(
@@ -179,7 +432,16 @@ impl<F: Write + Seek> MiniAllocator<F> {
         name: &str,
         obj_type: ObjType,
     ) -> io::Result<u32> {
-        self.directory.insert_dir_entry(parent_id, name, obj_type)
+        let stream_id =
+            self.directory.insert_dir_entry(parent_id, name, obj_type)?;
+        // The stream ID may have belonged to a since-removed stream.
+        self.reserved_lens.remove(&stream_id);
+        Ok(stream_id)
+    }
+
+    /// Overwrites the specified directory entry with an unallocated entry.
+    pub fn free_dir_entry(&mut self, stream_id: u32) -> io::Result<()> {
+        self.directory.free_dir_entry(stream_id)
     }
 
     /// Removes a directory entry from the tree and deallocates it.
@@ -234,12 +496,25 @@ impl<F: Write + Seek> MiniAllocator<F> {
     }
 
     /// Allocates a new entry in the MiniFAT, sets its value to `value`, and
-    /// returns the new mini sector number.
+    /// returns the new mini sector number.  The contents of the new mini
+    /// sector are zeroed, since it may have held data from before it was
+    /// freed.
     fn allocate_mini_sector(&mut self, value: u32) -> io::Result<u32> {
+        let mini_sector = self.allocate_minifat_entry(value)?;
+        self.seek_within_mini_sector(mini_sector, 0)?
+            .write_all(&[0; consts::MINI_SECTOR_LEN])?;
+        Ok(mini_sector)
+    }
+
+    /// Allocates a new entry in the MiniFAT, sets its value to `value`, and
+    /// returns the new mini sector number, without initializing the mini
+    /// sector's contents.
+    fn allocate_minifat_entry(&mut self, value: u32) -> io::Result<u32> {
         // If there's an existing free mini sector, use that.
-        for mini_sector in 0..self.minifat.len() {
-            if self.minifat[mini_sector] == consts::FREE_SECTOR {
-                let mini_sector = mini_sector as u32;
+        while self.free_hint < self.minifat.len() {
+            let mini_sector = self.free_hint as u32;
+            self.free_hint += 1;
+            if self.minifat[mini_sector as usize] == consts::FREE_SECTOR {
                 self.set_minifat(mini_sector, value)?;
                 return Ok(mini_sector);
             }
@@ -303,9 +578,16 @@ impl<F: Write + Seek> MiniAllocator<F> {
         })
     }
 
-    /// Deallocates the specified mini sector.
+    /// Deallocates the specified mini sector (zeroing its contents first, if
+    /// secure deletion is enabled).
     fn free_mini_sector(&mut self, mini_sector: u32) -> io::Result<()> {
+        if self.directory.secure_delete() {
+            self.seek_within_mini_sector(mini_sector, 0)?
+                .write_all(&[0; consts::MINI_SECTOR_LEN])?;
+        }
         self.set_minifat(mini_sector, consts::FREE_SECTOR)?;
+        self.free_hint = self.free_hint.min(mini_sector as usize);
+        self.generation += 1;
         let mut mini_stream_len = self.directory.root_dir_entry().stream_len;
         debug_assert_eq!(mini_stream_len % consts::MINI_SECTOR_LEN as u64, 0);
         while self.minifat.last() == Some(&consts::FREE_SECTOR) {
@@ -367,6 +649,10 @@ impl<F: Write + Seek> MiniAllocator<F> {
         Ok(())
     }
 
+    pub fn write_at(&mut self, offset: u64, data: &[u8]) -> io::Result<()> {
+        self.directory.write_at(offset, data)
+    }
+
     /// Flushes all changes to the underlying file.
     pub fn flush(&mut self) -> io::Result<()> {
         self.directory.flush()
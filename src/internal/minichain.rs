@@ -38,9 +38,90 @@ impl<'a, F> MiniChain<'a, F> {
     pub fn len(&self) -> u64 {
         (consts::MINI_SECTOR_LEN as u64) * (self.sector_ids.len() as u64)
     }
+
+    /// Returns the number of mini sectors (at most `max_sectors`) in the run
+    /// of adjacent mini sectors that starts at the given index within the
+    /// chain.
+    fn run_len(&self, index: usize, max_sectors: usize) -> u32 {
+        let first_sector_id = self.sector_ids[index] as u64;
+        self.sector_ids[index..]
+            .iter()
+            .take(max_sectors)
+            .enumerate()
+            .take_while(|&(offset, &sector_id)| {
+                sector_id as u64 == first_sector_id + offset as u64
+            })
+            .count() as u32
+    }
 }
 
 impl<'a, F: Read + Write + Seek> MiniChain<'a, F> {
+    /// Extends the chain as needed to hold all of the data, so that it can
+    /// be written a run of adjacent sectors at a time, and then writes it.
+    fn extend_and_write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let end = self.offset_from_start + buf.len() as u64;
+        while self.len() < end {
+            let new_sector_id =
+                if let Some(&last_sector_id) = self.sector_ids.last() {
+                    self.minialloc.extend_mini_chain(last_sector_id)?
+                } else {
+                    self.minialloc.begin_mini_chain()?
+                };
+            self.sector_ids.push(new_sector_id);
+        }
+        let total_len = self.len();
+        let sector_len = consts::MINI_SECTOR_LEN as u64;
+        let mut bytes_written = 0;
+        while bytes_written < buf.len() {
+            let current_sector_index =
+                (self.offset_from_start / sector_len) as usize;
+            debug_assert!(current_sector_index < self.sector_ids.len());
+            let current_sector_id = self.sector_ids[current_sector_index];
+            let offset_within_sector = self.offset_from_start % sector_len;
+            let remaining = &buf[bytes_written..];
+            let max_sectors = (offset_within_sector + remaining.len() as u64)
+                .div_ceil(sector_len);
+            let num_sectors =
+                self.run_len(current_sector_index, max_sectors as usize);
+            let result = self
+                .minialloc
+                .seek_within_mini_sectors(
+                    current_sector_id,
+                    num_sectors,
+                    offset_within_sector,
+                )
+                .and_then(|mut sector| sector.write(remaining));
+            match result {
+                Ok(0) => break,
+                Ok(num_bytes) => {
+                    bytes_written += num_bytes;
+                    self.offset_from_start += num_bytes as u64;
+                }
+                // Report the data that did get written; the error will come
+                // up again on the next call.
+                Err(_) if bytes_written > 0 => break,
+                Err(error) => return Err(error),
+            }
+        }
+        debug_assert!(self.offset_from_start <= total_len);
+        Ok(bytes_written)
+    }
+
+    /// Frees any sectors in the chain past the first `num_sectors`.
+    fn truncate(&mut self, num_sectors: usize) -> io::Result<()> {
+        if num_sectors >= self.sector_ids.len() {
+            return Ok(());
+        }
+        if num_sectors == 0 {
+            self.minialloc.free_mini_chain(self.sector_ids[0])?;
+        } else {
+            self.minialloc
+                .free_mini_chain_after(self.sector_ids[num_sectors - 1])?;
+        }
+        self.sector_ids.truncate(num_sectors);
+        Ok(())
+    }
+
     /// Resizes the chain to the minimum number of sectors large enough to old
     /// `new_len` bytes, allocating or freeing sectors as needed.
     pub fn set_len(&mut self, new_len: u64) -> io::Result<()> {
@@ -52,13 +133,26 @@ impl<'a, F: Read + Write + Seek> MiniChain<'a, F> {
             if let Some(&start_sector) = self.sector_ids.first() {
                 self.minialloc.free_mini_chain(start_sector)?;
             }
+            self.sector_ids.clear();
         } else if new_num_sectors <= self.sector_ids.len() {
             if new_num_sectors < self.sector_ids.len() {
                 self.minialloc.free_mini_chain_after(
                     self.sector_ids[new_num_sectors - 1],
                 )?;
+                self.sector_ids.truncate(new_num_sectors);
+            }
+            // Zero the remainder of the final mini sector.
+            let offset_within_sector = new_len % sector_len;
+            if offset_within_sector != 0 {
+                let last_sector_id = self.sector_ids[new_num_sectors - 1];
+                let remainder = (sector_len - offset_within_sector) as usize;
+                self.minialloc
+                    .seek_within_mini_sector(
+                        last_sector_id,
+                        offset_within_sector,
+                    )?
+                    .write_all(&vec![0; remainder])?;
             }
-            // TODO: zero remainder of final sector
         } else {
             for _ in self.sector_ids.len()..new_num_sectors {
                 let new_sector_id =
@@ -113,8 +207,13 @@ impl<'a, F: Read + Seek> Read for MiniChain<'a, F> {
         debug_assert!(current_sector_index < self.sector_ids.len());
         let current_sector_id = self.sector_ids[current_sector_index];
         let offset_within_sector = self.offset_from_start % sector_len;
-        let mut sector = self.minialloc.seek_within_mini_sector(
+        let max_sectors =
+            (offset_within_sector + max_len as u64).div_ceil(sector_len);
+        let num_sectors =
+            self.run_len(current_sector_index, max_sectors as usize);
+        let mut sector = self.minialloc.seek_within_mini_sectors(
             current_sector_id,
+            num_sectors,
             offset_within_sector,
         )?;
         let bytes_read = sector.read(&mut buf[0..max_len])?;
@@ -129,32 +228,15 @@ impl<'a, F: Read + Write + Seek> Write for MiniChain<'a, F> {
         if buf.is_empty() {
             return Ok(0);
         }
-        let mut total_len = self.len();
+        let old_num_sectors = self.sector_ids.len();
+        let result = self.extend_and_write(buf);
+        // If the data stopped short (say, because of an I/O error), free any
+        // new sectors that it didn't reach, so that the chain doesn't end up
+        // longer than its stream.
         let sector_len = consts::MINI_SECTOR_LEN as u64;
-        if self.offset_from_start == total_len {
-            let new_sector_id =
-                if let Some(&last_sector_id) = self.sector_ids.last() {
-                    self.minialloc.extend_mini_chain(last_sector_id)?
-                } else {
-                    self.minialloc.begin_mini_chain()?
-                };
-            self.sector_ids.push(new_sector_id);
-            total_len += sector_len;
-            debug_assert_eq!(total_len, self.len());
-        }
-        let current_sector_index =
-            (self.offset_from_start / sector_len) as usize;
-        debug_assert!(current_sector_index < self.sector_ids.len());
-        let current_sector_id = self.sector_ids[current_sector_index];
-        let offset_within_sector = self.offset_from_start % sector_len;
-        let mut sector = self.minialloc.seek_within_mini_sector(
-            current_sector_id,
-            offset_within_sector,
-        )?;
-        let bytes_written = sector.write(buf)?;
-        self.offset_from_start += bytes_written as u64;
-        debug_assert!(self.offset_from_start <= total_len);
-        Ok(bytes_written)
+        let num_sectors = self.offset_from_start.div_ceil(sector_len) as usize;
+        self.truncate(num_sectors.max(old_num_sectors))?;
+        result
     }
 
     fn flush(&mut self) -> io::Result<()> {
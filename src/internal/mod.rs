@@ -2,35 +2,75 @@
 mod macros;
 
 mod alloc;
+#[cfg(feature = "async")]
+mod async_io;
+mod builder;
 mod chain;
 mod color;
 pub mod consts;
+mod diff;
 mod directory;
 mod direntry;
 mod entry;
+mod glob;
+pub mod hash;
 mod header;
+pub mod inspect;
+mod merge;
 mod minialloc;
 mod minichain;
 mod objtype;
+mod owners;
+mod patch;
 pub mod path;
+mod policy;
+mod readat;
+pub mod recovery;
 mod sector;
+pub mod slack;
+mod spool;
 mod stream;
 mod timestamp;
+pub mod usage;
 mod validate;
 mod version;
+mod writer;
 
 pub use self::alloc::Allocator;
+#[cfg(feature = "async")]
+pub use self::async_io::{AsyncCompoundFile, AsyncStream};
+pub use self::builder::{CfbBuilder, EntrySpec};
 pub use self::chain::Chain;
 pub use self::color::Color;
+pub use self::diff::{diff, CfbDiff, EntryChange, MetadataField};
 pub use self::directory::Directory;
 pub use self::direntry::DirEntry;
-pub use self::entry::{Entries, EntriesOrder, Entry};
+pub use self::entry::{
+    DetachedEntries, DirEntryInfo, Entries, EntriesOrder, Entry,
+    FilterEntries, WalkOptions, WalkOrder,
+};
+pub use self::glob::{Find, Glob};
+pub use self::hash::{HashAlgorithm, HashOptions};
 pub use self::header::Header;
+pub use self::inspect::{ChainSector, FatEntry, HeaderInfo, StreamChain};
+pub use self::merge::{
+    merge, merge_with, MergeConflict, MergeReport, MergeResolution,
+};
 pub use self::minialloc::MiniAllocator;
 pub use self::minichain::MiniChain;
 pub use self::objtype::ObjType;
+pub use self::patch::{apply_patch, CfbPatch, PatchOp};
+pub use self::policy::AllocationPolicy;
+pub use self::readat::ReadAt;
+pub use self::recovery::{
+    RecoveredStream, RecoveryConfidence, RecoveryReport,
+};
 pub use self::sector::{Sector, SectorInit, Sectors};
-pub use self::stream::Stream;
+pub use self::slack::{SlackKind, SlackRegion};
+pub use self::spool::{Spool, SpoolOptions};
+pub use self::stream::{Stream, StreamOptions};
 pub use self::timestamp::Timestamp;
+pub use self::usage::{FileUsage, StorageUsage};
 pub use self::validate::Validation;
 pub use self::version::Version;
+pub use self::writer::CfbWriter;
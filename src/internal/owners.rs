@@ -0,0 +1,121 @@
+use crate::internal::{consts, DirEntry, MiniAllocator, ObjType};
+use fnv::{FnvHashMap, FnvHashSet};
+use std::io;
+
+//===========================================================================//
+
+/// The live structure that a given sector of a compound file belongs to.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SectorOwner {
+    Fat,
+    Difat,
+    Directory,
+    MiniFat,
+    MiniStream,
+    Stream(u32),
+}
+
+//===========================================================================//
+
+/// A map from sectors and mini sectors to the live structures that are
+/// currently using them.  Any sector or mini sector that doesn't appear in
+/// this map is not reachable from the header or the directory tree.
+pub struct SectorOwners {
+    sectors: FnvHashMap<u32, SectorOwner>,
+    mini_sectors: FnvHashMap<u32, u32>,
+    mini_stream_sectors: Vec<u32>,
+}
+
+impl SectorOwners {
+    pub fn compute<F>(
+        minialloc: &MiniAllocator<F>,
+    ) -> io::Result<SectorOwners> {
+        let mut sectors = FnvHashMap::default();
+        for &sector_id in minialloc.difat() {
+            sectors.insert(sector_id, SectorOwner::Fat);
+        }
+        for &sector_id in minialloc.difat_sector_ids() {
+            sectors.insert(sector_id, SectorOwner::Difat);
+        }
+        for sector_id in
+            minialloc.chain_sector_ids(minialloc.dir_start_sector())?
+        {
+            sectors.insert(sector_id, SectorOwner::Directory);
+        }
+        for sector_id in
+            minialloc.chain_sector_ids(minialloc.minifat_start_sector())?
+        {
+            sectors.insert(sector_id, SectorOwner::MiniFat);
+        }
+        let mini_stream_sectors = minialloc
+            .chain_sector_ids(minialloc.root_dir_entry().start_sector)?;
+        for &sector_id in mini_stream_sectors.iter() {
+            sectors.insert(sector_id, SectorOwner::MiniStream);
+        }
+        let mut mini_sectors = FnvHashMap::default();
+        for stream_id in reachable_stream_ids(minialloc.dir_entries()?) {
+            let dir_entry = minialloc.dir_entry(stream_id);
+            if dir_entry.obj_type != ObjType::Stream {
+                continue;
+            }
+            if dir_entry.stream_len < consts::MINI_STREAM_CUTOFF as u64 {
+                for mini_sector in
+                    minialloc.mini_chain_sector_ids(dir_entry.start_sector)?
+                {
+                    mini_sectors.insert(mini_sector, stream_id);
+                }
+            } else {
+                for sector_id in
+                    minialloc.chain_sector_ids(dir_entry.start_sector)?
+                {
+                    sectors.insert(sector_id, SectorOwner::Stream(stream_id));
+                }
+            }
+        }
+        Ok(SectorOwners { sectors, mini_sectors, mini_stream_sectors })
+    }
+
+    /// Returns the owner of the given sector, if any.
+    pub fn sector(&self, sector_id: u32) -> Option<SectorOwner> {
+        self.sectors.get(&sector_id).copied()
+    }
+
+    /// Returns the stream ID of the stream that owns the given mini sector,
+    /// if any.
+    pub fn mini_sector(&self, mini_sector: u32) -> Option<u32> {
+        self.mini_sectors.get(&mini_sector).copied()
+    }
+
+    /// Returns the sectors of the chain holding the mini stream.
+    pub fn mini_stream_sectors(&self) -> &[u32] {
+        &self.mini_stream_sectors
+    }
+}
+
+//===========================================================================//
+
+/// Returns the IDs of all directory entries that are reachable from the root
+/// entry (including the root entry itself).
+pub fn reachable_stream_ids(dir_entries: &[DirEntry]) -> FnvHashSet<u32> {
+    let mut reachable = FnvHashSet::default();
+    let mut stack = vec![consts::ROOT_STREAM_ID];
+    while let Some(stream_id) = stack.pop() {
+        let dir_entry = match dir_entries.get(stream_id as usize) {
+            Some(dir_entry) => dir_entry,
+            None => continue,
+        };
+        if !reachable.insert(stream_id) {
+            continue;
+        }
+        for &next_id in
+            &[dir_entry.left_sibling, dir_entry.right_sibling, dir_entry.child]
+        {
+            if next_id != consts::NO_STREAM {
+                stack.push(next_id);
+            }
+        }
+    }
+    reachable
+}
+
+//===========================================================================//
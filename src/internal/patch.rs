@@ -0,0 +1,438 @@
+use crate::internal::{
+    diff, hash, EntryChange, HashAlgorithm, HashOptions, MetadataField,
+    ObjType, StreamOptions, Timestamp,
+};
+use crate::{CompoundFile, ReadLeNumber, WriteLeNumber};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+//===========================================================================//
+
+/// The magic number at the start of a serialized patch.
+const PATCH_MAGIC: [u8; 8] = *b"CFBPATCH";
+
+/// The version of the serialization format written by `CfbPatch::write_to`.
+const PATCH_FORMAT_VERSION: u32 = 1;
+
+const TAG_CREATE_STORAGE: u8 = 1;
+const TAG_CREATE_STREAM: u8 = 2;
+const TAG_REMOVE: u8 = 3;
+const TAG_SET_CLSID: u8 = 4;
+const TAG_SET_STATE_BITS: u8 = 5;
+const TAG_SET_TIMESTAMPS: u8 = 6;
+const TAG_SET_LEN: u8 = 7;
+const TAG_WRITE: u8 = 8;
+
+//===========================================================================//
+
+/// A single edit recorded in a `CfbPatch`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum PatchOp {
+    /// Creates a new, empty storage.
+    CreateStorage(PathBuf),
+    /// Creates a new, empty stream.
+    CreateStream(PathBuf),
+    /// Removes a stream, or a storage and everything within it.
+    Remove(PathBuf),
+    /// Sets the CLSID of a storage.
+    SetClsid(PathBuf, Uuid),
+    /// Sets the user-defined bitflags of a storage or stream.
+    SetStateBits(PathBuf, u32),
+    /// Sets the creation and modified times of a storage.  (Streams always
+    /// have timestamps of zero, so this has no effect on them.)
+    SetTimestamps(PathBuf, Timestamp, Timestamp),
+    /// Truncates or extends a stream to the given length.
+    SetLen(PathBuf, u64),
+    /// Writes data into a stream at the given offset, first extending the
+    /// stream with zeros if the offset is past its end.
+    Write(PathBuf, u64, Vec<u8>),
+}
+
+impl PatchOp {
+    /// Returns the path of the object this edit applies to.
+    pub fn path(&self) -> &Path {
+        match self {
+            PatchOp::CreateStorage(path)
+            | PatchOp::CreateStream(path)
+            | PatchOp::Remove(path)
+            | PatchOp::SetClsid(path, _)
+            | PatchOp::SetStateBits(path, _)
+            | PatchOp::SetTimestamps(path, _, _)
+            | PatchOp::SetLen(path, _)
+            | PatchOp::Write(path, _, _) => path,
+        }
+    }
+}
+
+//===========================================================================//
+
+/// A recorded sequence of edits to a compound file, which can be serialized
+/// and later applied to a copy of the same base file with `apply_patch`.
+///
+/// A patch records a digest of the logical contents of the file it applies
+/// to (including every entry's attributes), and `apply_patch` refuses to
+/// apply it to any other file.  The digest always uses 64-bit FNV-1a, so
+/// that a patch can be applied whether or not the `sha256` cargo feature is
+/// enabled; it guards against applying a patch to the wrong file by
+/// mistake, but not against deliberate tampering.
+///
+/// ```
+/// use cfb::{CfbPatch, CompoundFile};
+/// use std::io::{Cursor, Read, Write};
+/// let mut base = CompoundFile::create(Cursor::new(Vec::new())).unwrap();
+/// base.create_stream("/foo").unwrap().write_all(b"hello").unwrap();
+/// let mut edited = CompoundFile::create(Cursor::new(Vec::new())).unwrap();
+/// edited.create_stream("/foo").unwrap().write_all(b"jello").unwrap();
+/// let patch = CfbPatch::between(&base, &edited).unwrap();
+/// cfb::apply_patch(&mut base, &patch).unwrap();
+/// let mut data = Vec::new();
+/// base.open_stream("/foo").unwrap().read_to_end(&mut data).unwrap();
+/// assert_eq!(data, b"jello");
+/// ```
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CfbPatch {
+    base_digest: Vec<u8>,
+    ops: Vec<PatchOp>,
+}
+
+impl CfbPatch {
+    /// Returns an empty patch for the given base file.  Edits can then be
+    /// recorded with `push`.
+    pub fn for_base<F: Read + Seek>(
+        base: &CompoundFile<F>,
+    ) -> io::Result<CfbPatch> {
+        Ok(CfbPatch { base_digest: base_digest(base)?, ops: Vec::new() })
+    }
+
+    /// Returns a patch that turns the `old` file into one with the same
+    /// logical contents as the `new` file (as determined by `cfb::diff`).
+    /// Only the parts of streams that changed are included.
+    pub fn between<F: Read + Seek, G: Read + Seek>(
+        old: &CompoundFile<F>,
+        new: &CompoundFile<G>,
+    ) -> io::Result<CfbPatch> {
+        let mut patch = CfbPatch::for_base(old)?;
+        let changes = diff(old, new)?;
+        // Do all removals first, in case something else is then created at
+        // the same path.
+        let mut removed: Vec<&Path> = Vec::new();
+        for change in changes.changes() {
+            let path = match change {
+                EntryChange::Removed(entry) => entry.path(),
+                EntryChange::Renamed { old, .. } => old.path(),
+                _ => continue,
+            };
+            if !removed.iter().any(|storage| path.starts_with(storage)) {
+                patch.push(PatchOp::Remove(path.to_path_buf()));
+                removed.push(path);
+            }
+        }
+        for change in changes.changes() {
+            match change {
+                EntryChange::Added(entry) => {
+                    patch.push_added(new, entry.path(), false)?;
+                }
+                EntryChange::Renamed { new: entry, .. } => {
+                    patch.push_added(new, entry.path(), true)?;
+                }
+                EntryChange::Removed(_) => {}
+                EntryChange::ContentChanged { old, new: entry, ranges } => {
+                    let path = entry.path().to_path_buf();
+                    if old.len() != entry.len() {
+                        patch.push(PatchOp::SetLen(path.clone(), entry.len()));
+                    }
+                    let options = StreamOptions::new();
+                    let mut stream =
+                        new.open_stream_with_path(entry.path(), &options)?;
+                    for range in ranges.iter() {
+                        let end = range.end.min(entry.len());
+                        if range.start >= end {
+                            continue;
+                        }
+                        stream.seek(SeekFrom::Start(range.start))?;
+                        let mut data = vec![0u8; (end - range.start) as usize];
+                        stream.read_exact(&mut data)?;
+                        patch.push(PatchOp::Write(
+                            path.clone(),
+                            range.start,
+                            data,
+                        ));
+                    }
+                }
+                EntryChange::MetadataChanged {
+                    new: entry, fields, ..
+                } => {
+                    let path = entry.path().to_path_buf();
+                    if fields.contains(&MetadataField::Clsid) {
+                        patch.push(PatchOp::SetClsid(
+                            path.clone(),
+                            *entry.clsid(),
+                        ));
+                    }
+                    if fields.contains(&MetadataField::StateBits) {
+                        patch.push(PatchOp::SetStateBits(
+                            path.clone(),
+                            entry.state_bits(),
+                        ));
+                    }
+                    if fields.contains(&MetadataField::Created)
+                        || fields.contains(&MetadataField::Modified)
+                    {
+                        patch.push(PatchOp::SetTimestamps(
+                            path,
+                            entry.created_timestamp(),
+                            entry.modified_timestamp(),
+                        ));
+                    }
+                }
+            }
+        }
+        Ok(patch)
+    }
+
+    /// Records the edits needed to recreate the entry at the given path in
+    /// `new` (and, if `recursive` is true, everything within it).
+    fn push_added<G: Read + Seek>(
+        &mut self,
+        new: &CompoundFile<G>,
+        path: &Path,
+        recursive: bool,
+    ) -> io::Result<()> {
+        let entries: Vec<_> = if recursive {
+            new.walk_storage(path)?.collect()
+        } else {
+            vec![new.entry(path)?]
+        };
+        for entry in entries {
+            let path = entry.path().to_path_buf();
+            if entry.is_stream() {
+                self.push(PatchOp::CreateStream(path.clone()));
+                if !entry.is_empty() {
+                    let options = StreamOptions::new();
+                    let mut data = Vec::with_capacity(entry.len() as usize);
+                    new.open_stream_with_path(&path, &options)?
+                        .read_to_end(&mut data)?;
+                    self.push(PatchOp::Write(path.clone(), 0, data));
+                }
+            } else {
+                self.push(PatchOp::CreateStorage(path.clone()));
+                if !entry.clsid().is_nil() {
+                    self.push(PatchOp::SetClsid(path.clone(), *entry.clsid()));
+                }
+                self.push(PatchOp::SetTimestamps(
+                    path.clone(),
+                    entry.created_timestamp(),
+                    entry.modified_timestamp(),
+                ));
+            }
+            if entry.state_bits() != 0 {
+                self.push(PatchOp::SetStateBits(path, entry.state_bits()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the digest of the base file that this patch applies to.
+    pub fn base_digest(&self) -> &[u8] {
+        &self.base_digest
+    }
+
+    /// Returns the recorded edits, in the order they will be applied.
+    pub fn ops(&self) -> &[PatchOp] {
+        &self.ops
+    }
+
+    /// Records an edit at the end of the patch.
+    pub fn push(&mut self, op: PatchOp) {
+        self.ops.push(op);
+    }
+
+    /// Reads a patch previously serialized with `write_to`.
+    pub fn read_from<R: Read>(mut reader: R) -> io::Result<CfbPatch> {
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        if magic != PATCH_MAGIC {
+            invalid_data!("Invalid CFB patch (wrong magic number)");
+        }
+        let format_version = reader.read_le_u32()?;
+        if format_version != PATCH_FORMAT_VERSION {
+            invalid_data!(
+                "Unsupported CFB patch format version {}",
+                format_version
+            );
+        }
+        let base_digest = read_bytes(&mut reader)?;
+        let num_ops = reader.read_le_u32()?;
+        let mut ops = Vec::new();
+        for _ in 0..num_ops {
+            let mut tag = [0u8; 1];
+            reader.read_exact(&mut tag)?;
+            let path = read_path(&mut reader)?;
+            let op = match tag[0] {
+                TAG_CREATE_STORAGE => PatchOp::CreateStorage(path),
+                TAG_CREATE_STREAM => PatchOp::CreateStream(path),
+                TAG_REMOVE => PatchOp::Remove(path),
+                TAG_SET_CLSID => {
+                    let mut bytes = [0u8; 16];
+                    reader.read_exact(&mut bytes)?;
+                    PatchOp::SetClsid(path, Uuid::from_bytes_le(bytes))
+                }
+                TAG_SET_STATE_BITS => {
+                    PatchOp::SetStateBits(path, reader.read_le_u32()?)
+                }
+                TAG_SET_TIMESTAMPS => {
+                    let created = Timestamp::read_from(&mut reader)?;
+                    let modified = Timestamp::read_from(&mut reader)?;
+                    PatchOp::SetTimestamps(path, created, modified)
+                }
+                TAG_SET_LEN => PatchOp::SetLen(path, reader.read_le_u64()?),
+                TAG_WRITE => {
+                    let offset = reader.read_le_u64()?;
+                    PatchOp::Write(path, offset, read_bytes(&mut reader)?)
+                }
+                tag => invalid_data!("Invalid CFB patch operation {}", tag),
+            };
+            ops.push(op);
+        }
+        Ok(CfbPatch { base_digest, ops })
+    }
+
+    /// Serializes the patch in a compact binary format, which can be read
+    /// back with `read_from`.
+    pub fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_all(&PATCH_MAGIC)?;
+        writer.write_le_u32(PATCH_FORMAT_VERSION)?;
+        write_bytes(&mut writer, &self.base_digest)?;
+        writer.write_le_u32(self.ops.len() as u32)?;
+        for op in self.ops.iter() {
+            let tag = match op {
+                PatchOp::CreateStorage(_) => TAG_CREATE_STORAGE,
+                PatchOp::CreateStream(_) => TAG_CREATE_STREAM,
+                PatchOp::Remove(_) => TAG_REMOVE,
+                PatchOp::SetClsid(_, _) => TAG_SET_CLSID,
+                PatchOp::SetStateBits(_, _) => TAG_SET_STATE_BITS,
+                PatchOp::SetTimestamps(_, _, _) => TAG_SET_TIMESTAMPS,
+                PatchOp::SetLen(_, _) => TAG_SET_LEN,
+                PatchOp::Write(_, _, _) => TAG_WRITE,
+            };
+            writer.write_all(&[tag])?;
+            match op.path().to_str() {
+                Some(path) => write_bytes(&mut writer, path.as_bytes())?,
+                None => invalid_input!("Non-UTF-8 path: {:?}", op.path()),
+            }
+            match op {
+                PatchOp::CreateStorage(_)
+                | PatchOp::CreateStream(_)
+                | PatchOp::Remove(_) => {}
+                PatchOp::SetClsid(_, clsid) => {
+                    writer.write_all(&clsid.to_bytes_le())?;
+                }
+                PatchOp::SetStateBits(_, bits) => {
+                    writer.write_le_u32(*bits)?
+                }
+                PatchOp::SetTimestamps(_, created, modified) => {
+                    created.write_to(&mut writer)?;
+                    modified.write_to(&mut writer)?;
+                }
+                PatchOp::SetLen(_, len) => writer.write_le_u64(*len)?,
+                PatchOp::Write(_, offset, data) => {
+                    writer.write_le_u64(*offset)?;
+                    write_bytes(&mut writer, data)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+//===========================================================================//
+
+/// Applies a patch to a compound file.  Returns an error without changing
+/// anything if the file's logical contents (including every entry's
+/// attributes) don't match those of the base file that the patch was made
+/// for.  Otherwise, the edits are applied in order; if one of them fails,
+/// the error is returned and the edits before it remain applied.
+pub fn apply_patch<F: Read + Write + Seek>(
+    comp: &mut CompoundFile<F>,
+    patch: &CfbPatch,
+) -> io::Result<()> {
+    if base_digest(comp)? != patch.base_digest {
+        invalid_input!("Patch does not apply: base file contents differ");
+    }
+    for op in patch.ops.iter() {
+        match op {
+            PatchOp::CreateStorage(path) => comp.create_storage(path)?,
+            PatchOp::CreateStream(path) => {
+                comp.create_new_stream(path)?;
+            }
+            PatchOp::Remove(path) => {
+                if comp.is_storage(path) {
+                    comp.remove_storage_all(path)?;
+                } else {
+                    comp.remove_stream(path)?;
+                }
+            }
+            PatchOp::SetClsid(path, clsid) => {
+                comp.set_storage_clsid(path, *clsid)?
+            }
+            PatchOp::SetStateBits(path, bits) => {
+                comp.set_state_bits(path, *bits)?
+            }
+            PatchOp::SetTimestamps(path, created, modified) => {
+                comp.set_entry_with_path(path, |dir_entry| {
+                    if dir_entry.obj_type != ObjType::Stream {
+                        dir_entry.creation_time = *created;
+                        dir_entry.modified_time = *modified;
+                    }
+                })?;
+            }
+            PatchOp::SetLen(path, len) => {
+                comp.open_stream(path)?.set_len(*len)?;
+            }
+            PatchOp::Write(path, offset, data) => {
+                let mut stream = comp.open_stream(path)?;
+                if *offset > stream.len() {
+                    stream.set_len(*offset)?;
+                }
+                stream.seek(SeekFrom::Start(*offset))?;
+                stream.write_all(data)?;
+                stream.flush()?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn base_digest<F: Read + Seek>(comp: &CompoundFile<F>) -> io::Result<Vec<u8>> {
+    let options = HashOptions::new()
+        .with_algorithm(HashAlgorithm::Fnv1a64)
+        .with_clsids(true)
+        .with_state_bits(true)
+        .with_timestamps(true);
+    hash::hash_entry(comp, Path::new("/"), &options)
+}
+
+fn read_bytes<R: Read>(reader: &mut R) -> io::Result<Vec<u8>> {
+    let len = reader.read_le_u64()?;
+    let mut bytes = Vec::new();
+    reader.take(len).read_to_end(&mut bytes)?;
+    if (bytes.len() as u64) < len {
+        invalid_data!("CFB patch is truncated");
+    }
+    Ok(bytes)
+}
+
+fn read_path<R: Read>(reader: &mut R) -> io::Result<PathBuf> {
+    match String::from_utf8(read_bytes(reader)?) {
+        Ok(path) => Ok(PathBuf::from(path)),
+        Err(_) => invalid_data!("Invalid path in CFB patch"),
+    }
+}
+
+fn write_bytes<W: Write>(writer: &mut W, bytes: &[u8]) -> io::Result<()> {
+    writer.write_le_u64(bytes.len() as u64)?;
+    writer.write_all(bytes)
+}
+
+//===========================================================================//
@@ -13,7 +13,7 @@ const CASE_MAPPER: CaseMapper = CaseMapper::new();
 /// Converts a char to uppercase as defined in MS-CFB,
 /// using simple capitalization and the ability to add exceptions.
 /// Used when two directory entry names need to be compared.
-fn cfb_uppercase_char(c: char) -> char {
+pub fn cfb_uppercase_char(c: char) -> char {
     // TODO: Edge cases can be added that appear
     // in the table from Appendix A, <3> Section 2.6.4
 
@@ -0,0 +1,21 @@
+//===========================================================================//
+
+/// A strategy for choosing which sectors to allocate as streams grow.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum AllocationPolicy {
+    /// Each new sector is the lowest-numbered free sector in the file (or a
+    /// new sector at the end of the file, if there are no free sectors).  This
+    /// keeps files compact, but when several streams grow at the same time
+    /// their chains end up interleaved.
+    #[default]
+    FirstFit,
+    /// Each chain reserves a run of adjacent sectors as it grows, and takes
+    /// new sectors from that run, so that sequential reads of a stream touch
+    /// contiguous regions of the file even when several streams are written
+    /// at once.  Runs start small and double in length as a chain keeps
+    /// growing.  Reserved sectors that end up unused remain free sectors in
+    /// the file.
+    Contiguous,
+}
+
+//===========================================================================//
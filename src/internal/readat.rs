@@ -0,0 +1,129 @@
+use std::fs;
+use std::io::{self, Cursor};
+use std::sync::Arc;
+
+//===========================================================================//
+
+/// A source of data that can be read from at arbitrary positions through a
+/// shared reference, without moving a cursor, so that several threads can
+/// read from it at once.  This is implemented for files (via `pread` on Unix
+/// platforms) and for in-memory buffers.
+pub trait ReadAt {
+    /// Reads some bytes starting at the given offset, returning the number of
+    /// bytes read, which is zero only at the end of the data (or if `buf` is
+    /// empty).
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize>;
+
+    /// Reads exactly enough bytes starting at the given offset to fill
+    /// `buf`, or returns an `UnexpectedEof` error if the data ends first.
+    fn read_exact_at(
+        &self,
+        mut buf: &mut [u8],
+        mut offset: u64,
+    ) -> io::Result<()> {
+        while !buf.is_empty() {
+            match self.read_at(buf, offset) {
+                Ok(0) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "failed to fill whole buffer",
+                    ));
+                }
+                Ok(num_bytes) => {
+                    buf = &mut buf[num_bytes..];
+                    offset += num_bytes as u64;
+                }
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+                Err(error) => return Err(error),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ReadAt for [u8] {
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        if offset >= self.len() as u64 {
+            return Ok(0);
+        }
+        let data = &self[offset as usize..];
+        let num_bytes = data.len().min(buf.len());
+        buf[..num_bytes].copy_from_slice(&data[..num_bytes]);
+        Ok(num_bytes)
+    }
+}
+
+impl ReadAt for Vec<u8> {
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        self.as_slice().read_at(buf, offset)
+    }
+}
+
+/// Reads from the cursor's underlying buffer, ignoring the cursor's current
+/// position.
+impl<T: AsRef<[u8]>> ReadAt for Cursor<T> {
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        self.get_ref().as_ref().read_at(buf, offset)
+    }
+}
+
+impl<T: ReadAt + ?Sized> ReadAt for &T {
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        (**self).read_at(buf, offset)
+    }
+}
+
+impl<T: ReadAt + ?Sized> ReadAt for Arc<T> {
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        (**self).read_at(buf, offset)
+    }
+}
+
+#[cfg(unix)]
+impl ReadAt for fs::File {
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        std::os::unix::fs::FileExt::read_at(self, buf, offset)
+    }
+}
+
+/// Note that on Windows, reading at an offset also moves the file's cursor.
+#[cfg(windows)]
+impl ReadAt for fs::File {
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        std::os::windows::fs::FileExt::seek_read(self, buf, offset)
+    }
+}
+
+//===========================================================================//
+
+#[cfg(test)]
+mod tests {
+    use super::ReadAt;
+    use std::io::{self, Cursor};
+
+    #[test]
+    fn read_from_slice() {
+        let data: &[u8] = b"hello, world";
+        let mut buf = [0u8; 5];
+        assert_eq!(data.read_at(&mut buf, 7).unwrap(), 5);
+        assert_eq!(&buf, b"world");
+        assert_eq!(data.read_at(&mut buf, 10).unwrap(), 2);
+        assert_eq!(&buf[..2], b"ld");
+        assert_eq!(data.read_at(&mut buf, 12).unwrap(), 0);
+        assert_eq!(data.read_at(&mut buf, 100).unwrap(), 0);
+    }
+
+    #[test]
+    fn read_exact_from_cursor() {
+        let mut cursor = Cursor::new(b"hello, world".to_vec());
+        cursor.set_position(3);
+        let mut buf = [0u8; 5];
+        cursor.read_exact_at(&mut buf, 0).unwrap();
+        assert_eq!(&buf, b"hello");
+        assert_eq!(cursor.position(), 3);
+        let error = cursor.read_exact_at(&mut buf, 9).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
+
+//===========================================================================//
@@ -0,0 +1,318 @@
+use crate::internal::owners::{reachable_stream_ids, SectorOwners};
+use crate::internal::{consts, MiniAllocator, ObjType};
+use fnv::FnvHashSet;
+use std::io::{self, Read, Seek};
+
+//===========================================================================//
+
+/// How much trust to place in the contents of a recovered stream.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum RecoveryConfidence {
+    /// Some of the stream's sectors are missing or are now in use by live
+    /// data, so the recovered bytes are probably at least partly wrong.
+    Low,
+    /// The stream's chain could not be followed through the FAT, so some of
+    /// its sectors were assumed to be laid out contiguously.
+    Medium,
+    /// The stream's entire chain was still intact in the FAT, and none of its
+    /// sectors are in use by live data.
+    High,
+}
+
+//===========================================================================//
+
+/// A stream reconstructed from a directory entry that is no longer part of
+/// the storage tree.
+#[derive(Clone)]
+pub struct RecoveredStream {
+    stream_id: u32,
+    name: String,
+    deleted: bool,
+    expected_len: u64,
+    data: Vec<u8>,
+    confidence: RecoveryConfidence,
+}
+
+impl RecoveredStream {
+    /// Returns the ID of the directory entry that this stream was recovered
+    /// from.
+    pub fn stream_id(&self) -> u32 {
+        self.stream_id
+    }
+
+    /// Returns the name recorded in the stream's directory entry.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns true if the stream's directory entry is marked unallocated
+    /// (i.e. the stream was deleted), or false if the entry is still marked
+    /// as a stream but is unreachable from the root storage (i.e. the stream
+    /// was orphaned).
+    pub fn is_deleted(&self) -> bool {
+        self.deleted
+    }
+
+    /// Returns the stream length recorded in the stream's directory entry.
+    pub fn expected_len(&self) -> u64 {
+        self.expected_len
+    }
+
+    /// Returns true if as many bytes were recovered as the directory entry
+    /// says the stream had.
+    pub fn is_complete(&self) -> bool {
+        self.data.len() as u64 == self.expected_len
+    }
+
+    /// Returns the recovered bytes.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Consumes the `RecoveredStream`, returning the recovered bytes.
+    pub fn into_data(self) -> Vec<u8> {
+        self.data
+    }
+
+    /// Returns how much trust to place in the recovered bytes.
+    pub fn confidence(&self) -> RecoveryConfidence {
+        self.confidence
+    }
+}
+
+//===========================================================================//
+
+/// The results of scanning a compound file for deleted and orphaned data.
+#[derive(Clone)]
+pub struct RecoveryReport {
+    streams: Vec<RecoveredStream>,
+    nonzero_free_sectors: Vec<u32>,
+    nonzero_free_mini_sectors: Vec<u32>,
+}
+
+impl RecoveryReport {
+    /// Returns the streams that could be reconstructed from unallocated or
+    /// unreachable directory entries, in stream ID order.
+    pub fn streams(&self) -> &[RecoveredStream] {
+        &self.streams
+    }
+
+    /// Returns the sectors that are not used by any live structure or stream
+    /// but that still contain non-zero data.
+    pub fn nonzero_free_sectors(&self) -> &[u32] {
+        &self.nonzero_free_sectors
+    }
+
+    /// Returns the mini sectors that are not used by any live stream but that
+    /// still contain non-zero data.
+    pub fn nonzero_free_mini_sectors(&self) -> &[u32] {
+        &self.nonzero_free_mini_sectors
+    }
+}
+
+//===========================================================================//
+
+pub fn scan<F: Read + Seek>(
+    minialloc: &mut MiniAllocator<F>,
+) -> io::Result<RecoveryReport> {
+    let owners = SectorOwners::compute(minialloc)?;
+    let reachable = reachable_stream_ids(minialloc.dir_entries()?);
+    let candidates: Vec<(u32, String, bool, u32, u64)> = minialloc
+        .dir_entries()?
+        .iter()
+        .enumerate()
+        .filter(|&(stream_id, dir_entry)| {
+            dir_entry.stream_len > 0
+                && match dir_entry.obj_type {
+                    ObjType::Unallocated => !dir_entry.name.is_empty(),
+                    ObjType::Stream => {
+                        !reachable.contains(&(stream_id as u32))
+                    }
+                    ObjType::Storage | ObjType::Root => false,
+                }
+        })
+        .map(|(stream_id, dir_entry)| {
+            (
+                stream_id as u32,
+                dir_entry.name.clone(),
+                dir_entry.obj_type == ObjType::Unallocated,
+                dir_entry.start_sector,
+                dir_entry.stream_len,
+            )
+        })
+        .collect();
+    let mut streams = Vec::with_capacity(candidates.len());
+    for (stream_id, name, deleted, start_sector, stream_len) in candidates {
+        let (data, confidence) = if stream_len
+            < consts::MINI_STREAM_CUTOFF as u64
+        {
+            recover_mini_chain(minialloc, &owners, start_sector, stream_len)?
+        } else {
+            recover_chain(minialloc, &owners, start_sector, stream_len)?
+        };
+        streams.push(RecoveredStream {
+            stream_id,
+            name,
+            deleted,
+            expected_len: stream_len,
+            data,
+            confidence,
+        });
+    }
+
+    let mut nonzero_free_sectors = Vec::new();
+    for sector_id in 0..minialloc.num_sectors() {
+        if owners.sector(sector_id).is_none()
+            && read_sector(minialloc, sector_id)?.iter().any(|&b| b != 0)
+        {
+            nonzero_free_sectors.push(sector_id);
+        }
+    }
+    let mut nonzero_free_mini_sectors = Vec::new();
+    for mini_sector in 0..num_mini_sectors(minialloc, &owners) {
+        if owners.mini_sector(mini_sector).is_none()
+            && read_mini_sector(minialloc, mini_sector)?
+                .iter()
+                .any(|&b| b != 0)
+        {
+            nonzero_free_mini_sectors.push(mini_sector);
+        }
+    }
+
+    Ok(RecoveryReport {
+        streams,
+        nonzero_free_sectors,
+        nonzero_free_mini_sectors,
+    })
+}
+
+/// Reconstructs a stream stored in regular sectors.  Stale FAT links are
+/// followed where they still exist; otherwise the chain is assumed to
+/// continue with the next sector in the file.
+fn recover_chain<F: Read + Seek>(
+    minialloc: &mut MiniAllocator<F>,
+    owners: &SectorOwners,
+    start_sector: u32,
+    stream_len: u64,
+) -> io::Result<(Vec<u8>, RecoveryConfidence)> {
+    let sector_len = minialloc.sector_len() as u64;
+    let num_sectors = stream_len.div_ceil(sector_len);
+    let mut data = Vec::<u8>::new();
+    let mut confidence = RecoveryConfidence::High;
+    let mut visited = FnvHashSet::default();
+    let mut sector_id = start_sector;
+    for index in 0..num_sectors {
+        if sector_id >= minialloc.num_sectors() || !visited.insert(sector_id) {
+            confidence = RecoveryConfidence::Low;
+            break;
+        }
+        if owners.sector(sector_id).is_some() {
+            confidence = RecoveryConfidence::Low;
+        }
+        let sector = read_sector(minialloc, sector_id)?;
+        let remaining = (stream_len - data.len() as u64) as usize;
+        data.extend_from_slice(&sector[..sector.len().min(remaining)]);
+        if index + 1 < num_sectors {
+            let next = minialloc
+                .fat()
+                .get(sector_id as usize)
+                .copied()
+                .unwrap_or(consts::FREE_SECTOR);
+            if next <= consts::MAX_REGULAR_SECTOR
+                && owners.sector(sector_id).is_none()
+            {
+                sector_id = next;
+            } else {
+                confidence = confidence.min(RecoveryConfidence::Medium);
+                sector_id = sector_id.wrapping_add(1);
+            }
+        }
+    }
+    if (data.len() as u64) < stream_len {
+        confidence = RecoveryConfidence::Low;
+    }
+    Ok((data, confidence))
+}
+
+/// Like `recover_chain`, but for a stream stored in the mini stream.
+fn recover_mini_chain<F: Read + Seek>(
+    minialloc: &mut MiniAllocator<F>,
+    owners: &SectorOwners,
+    start_mini_sector: u32,
+    stream_len: u64,
+) -> io::Result<(Vec<u8>, RecoveryConfidence)> {
+    let mini_sector_len = consts::MINI_SECTOR_LEN as u64;
+    let num_mini_sectors_needed = stream_len.div_ceil(mini_sector_len);
+    let capacity = num_mini_sectors(minialloc, owners);
+    let mut data = Vec::<u8>::new();
+    let mut confidence = RecoveryConfidence::High;
+    let mut visited = FnvHashSet::default();
+    let mut mini_sector = start_mini_sector;
+    for index in 0..num_mini_sectors_needed {
+        if mini_sector >= capacity || !visited.insert(mini_sector) {
+            confidence = RecoveryConfidence::Low;
+            break;
+        }
+        if owners.mini_sector(mini_sector).is_some() {
+            confidence = RecoveryConfidence::Low;
+        }
+        let sector = read_mini_sector(minialloc, mini_sector)?;
+        let remaining = (stream_len - data.len() as u64) as usize;
+        data.extend_from_slice(&sector[..sector.len().min(remaining)]);
+        if index + 1 < num_mini_sectors_needed {
+            let next = minialloc
+                .minifat()
+                .get(mini_sector as usize)
+                .copied()
+                .unwrap_or(consts::FREE_SECTOR);
+            if next <= consts::MAX_REGULAR_SECTOR
+                && owners.mini_sector(mini_sector).is_none()
+            {
+                mini_sector = next;
+            } else {
+                confidence = confidence.min(RecoveryConfidence::Medium);
+                mini_sector = mini_sector.wrapping_add(1);
+            }
+        }
+    }
+    if (data.len() as u64) < stream_len {
+        confidence = RecoveryConfidence::Low;
+    }
+    Ok((data, confidence))
+}
+
+/// Returns the number of mini sectors that fit in the sectors currently
+/// allocated to the mini stream (which may be more than the root entry's
+/// stream length indicates, if the mini stream has shrunk).
+fn num_mini_sectors<F>(
+    minialloc: &MiniAllocator<F>,
+    owners: &SectorOwners,
+) -> u32 {
+    let mini_sectors_per_sector =
+        minialloc.sector_len() / consts::MINI_SECTOR_LEN;
+    (owners.mini_stream_sectors().len() * mini_sectors_per_sector) as u32
+}
+
+/// Reads the contents of a sector (which may be short, if it is the partial
+/// final sector of the file).
+pub fn read_sector<F: Read + Seek>(
+    minialloc: &mut MiniAllocator<F>,
+    sector_id: u32,
+) -> io::Result<Vec<u8>> {
+    let mut data = Vec::with_capacity(minialloc.sector_len());
+    minialloc.seek_to_sector(sector_id)?.read_to_end(&mut data)?;
+    Ok(data)
+}
+
+fn read_mini_sector<F: Read + Seek>(
+    minialloc: &mut MiniAllocator<F>,
+    mini_sector: u32,
+) -> io::Result<Vec<u8>> {
+    let mut data = Vec::with_capacity(consts::MINI_SECTOR_LEN);
+    minialloc
+        .seek_within_mini_sector(mini_sector, 0)?
+        .read_to_end(&mut data)?;
+    Ok(data)
+}
+
+//===========================================================================//
@@ -68,24 +68,51 @@ impl<F: Seek> Sectors<F> {
         sector_id: u32,
         offset_within_sector: u64,
     ) -> io::Result<Sector<'_, F>> {
-        debug_assert!(offset_within_sector <= self.sector_len() as u64);
-        if sector_id >= self.num_sectors {
+        self.seek_within_sectors(sector_id, 1, offset_within_sector)
+    }
+
+    /// Like `seek_within_sector`, but the returned `Sector` spans the run of
+    /// `num_sectors` adjacent sectors starting at `sector_id`, so that the
+    /// whole run can be read or written with a single I/O call.
+    pub fn seek_within_sectors(
+        &mut self,
+        sector_id: u32,
+        num_sectors: u32,
+        offset_within_run: u64,
+    ) -> io::Result<Sector<'_, F>> {
+        debug_assert!(num_sectors > 0);
+        let run_len = num_sectors as usize * self.sector_len();
+        debug_assert!(offset_within_run <= run_len as u64);
+        if sector_id as u64 + num_sectors as u64 > self.num_sectors as u64 {
             invalid_data!(
                 "Tried to seek to sector {}, but sector count is only {}",
-                sector_id,
+                sector_id as u64 + num_sectors as u64 - 1,
                 self.num_sectors
             );
         }
         let sector_len = self.sector_len();
         self.inner.seek(SeekFrom::Start(
-            (sector_id + 1) as u64 * sector_len as u64 + offset_within_sector,
+            (sector_id + 1) as u64 * sector_len as u64 + offset_within_run,
         ))?;
         Ok(Sector {
             inner: &mut self.inner,
-            sector_len,
-            offset_within_sector: offset_within_sector as usize,
+            sector_len: run_len,
+            offset_within_sector: offset_within_run as usize,
         })
     }
+
+    /// Reads up to `len` bytes starting at the given absolute offset within
+    /// the underlying file, ignoring sector boundaries.  The returned data
+    /// will be shorter than `len` if the file ends first.
+    pub fn read_at(&mut self, offset: u64, len: u64) -> io::Result<Vec<u8>>
+    where
+        F: Read,
+    {
+        let mut data = Vec::new();
+        self.inner.seek(SeekFrom::Start(offset))?;
+        (&mut self.inner).take(len).read_to_end(&mut data)?;
+        Ok(data)
+    }
 }
 
 impl<F: Write + Seek> Sectors<F> {
@@ -109,6 +136,13 @@ impl<F: Write + Seek> Sectors<F> {
         Ok(())
     }
 
+    /// Writes the given data at the given absolute offset within the
+    /// underlying file, ignoring sector boundaries.
+    pub fn write_at(&mut self, offset: u64, data: &[u8]) -> io::Result<()> {
+        self.inner.seek(SeekFrom::Start(offset))?;
+        self.inner.write_all(data)
+    }
+
     /// Flushes all changes to the underlying file.
     pub fn flush(&mut self) -> io::Result<()> {
         self.inner.flush()
@@ -0,0 +1,355 @@
+use crate::internal::owners::{reachable_stream_ids, SectorOwners};
+use crate::internal::{consts, MiniAllocator, ObjType, Timestamp, Version};
+use std::io::{self, Read, Seek, Write};
+use uuid::Uuid;
+
+//===========================================================================//
+
+/// The kind of place within a compound file that a `SlackRegion` covers.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum SlackKind {
+    /// A reserved field of the file header, or (for version 4 files) the
+    /// padding between the end of the header and the end of its sector.
+    HeaderReserved,
+    /// The unused entries at the end of the DIFAT array in the file header.
+    /// Unlike other regions, these are expected to hold `FREE_SECTOR`
+    /// (all `0xFF` bytes) rather than zeros.
+    HeaderDifat,
+    /// The bytes of a stream's final sector (or mini sector) that lie past
+    /// the logical end of the stream with the given ID.
+    StreamTail(u32),
+    /// A sector that is not used by any live structure or stream.
+    FreeSector(u32),
+    /// A mini sector that is not used by any live stream.
+    FreeMiniSector(u32),
+    /// Data at the end of the file, beyond the last sector that the FAT is
+    /// able to describe.
+    TrailingData,
+    /// A field of an allocated directory entry with the given ID that is
+    /// unused for that kind of object (e.g. the padding after the entry's
+    /// name, or the timestamps of a stream entry).
+    DirEntryReserved(u32),
+    /// An unallocated directory entry with the given ID.
+    UnusedDirEntry(u32),
+}
+
+//===========================================================================//
+
+/// A region of a compound file that a normal reader never looks at, but that
+/// could nonetheless hold data.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SlackRegion {
+    kind: SlackKind,
+    offset: u64,
+    len: u64,
+    nonzero: bool,
+}
+
+impl SlackRegion {
+    /// Returns what kind of region this is.
+    pub fn kind(&self) -> SlackKind {
+        self.kind
+    }
+
+    /// Returns the byte offset of the start of this region within the
+    /// underlying file.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Returns the length of this region, in bytes.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns true if the region has zero length.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns true if the region contains anything other than the filler
+    /// that a well-behaved writer would leave there.  For most regions the
+    /// filler is zero bytes; the exceptions are unused header DIFAT entries
+    /// (which hold `FREE_SECTOR`) and the sibling, child and name length
+    /// fields of unallocated directory entries.
+    pub fn is_nonzero(&self) -> bool {
+        self.nonzero
+    }
+}
+
+//===========================================================================//
+
+struct Analysis<'a, F> {
+    minialloc: &'a mut MiniAllocator<F>,
+    regions: Vec<SlackRegion>,
+}
+
+impl<F: Read + Seek> Analysis<'_, F> {
+    fn add(
+        &mut self,
+        kind: SlackKind,
+        offset: u64,
+        len: u64,
+    ) -> io::Result<()> {
+        if len == 0 {
+            return Ok(());
+        }
+        let data = self.minialloc.read_at(offset, len)?;
+        if data.is_empty() {
+            return Ok(());
+        }
+        let nonzero = data
+            .iter()
+            .enumerate()
+            .any(|(index, &byte)| !is_filler(kind, index, byte));
+        self.regions.push(SlackRegion {
+            kind,
+            offset,
+            len: data.len() as u64,
+            nonzero,
+        });
+        Ok(())
+    }
+}
+
+/// Returns true if the given byte, at the given index within a region of the
+/// given kind, is what a well-behaved writer would put there.
+fn is_filler(kind: SlackKind, index: usize, byte: u8) -> bool {
+    match kind {
+        SlackKind::HeaderDifat => byte == 0xff,
+        // Unallocated directory entries are all zeros except for the sibling
+        // and child fields, which must be NO_STREAM.  Some implementations
+        // also record a name length of 2 (for the null terminator) or 0.
+        SlackKind::UnusedDirEntry(_) => match index {
+            64..=65 | 68..=79 => true,
+            _ => byte == 0,
+        },
+        _ => byte == 0,
+    }
+}
+
+pub fn analyze<F: Read + Seek>(
+    minialloc: &mut MiniAllocator<F>,
+) -> io::Result<Vec<SlackRegion>> {
+    let owners = SectorOwners::compute(minialloc)?;
+    let sector_len = minialloc.sector_len() as u64;
+    let version = minialloc.version();
+    let mut analysis = Analysis { minialloc, regions: Vec::new() };
+
+    // Header:
+    analysis.add(SlackKind::HeaderReserved, 8, 16)?;
+    analysis.add(SlackKind::HeaderReserved, 34, 6)?;
+    if version == Version::V4 {
+        let header_len = consts::HEADER_LEN as u64;
+        analysis.add(
+            SlackKind::HeaderReserved,
+            header_len,
+            sector_len - header_len,
+        )?;
+    }
+    let num_used_difat_slots = analysis
+        .minialloc
+        .difat()
+        .len()
+        .min(consts::NUM_DIFAT_ENTRIES_IN_HEADER);
+    analysis.add(
+        SlackKind::HeaderDifat,
+        76 + 4 * num_used_difat_slots as u64,
+        4 * (consts::NUM_DIFAT_ENTRIES_IN_HEADER - num_used_difat_slots)
+            as u64,
+    )?;
+
+    // Sectors:
+    let sector_offset = |sector_id: u32| (sector_id as u64 + 1) * sector_len;
+    let num_sectors = analysis.minialloc.num_sectors();
+    let fat_capacity = (analysis.minialloc.difat().len() as u64
+        * (sector_len / 4))
+        .min(num_sectors as u64) as u32;
+    for sector_id in 0..fat_capacity {
+        if owners.sector(sector_id).is_none() {
+            analysis.add(
+                SlackKind::FreeSector(sector_id),
+                sector_offset(sector_id),
+                sector_len,
+            )?;
+        }
+    }
+    if fat_capacity < num_sectors {
+        analysis.add(
+            SlackKind::TrailingData,
+            sector_offset(fat_capacity),
+            (num_sectors - fat_capacity) as u64 * sector_len,
+        )?;
+    }
+
+    // Mini sectors:
+    let mini_sector_len = consts::MINI_SECTOR_LEN as u64;
+    let mini_stream_sectors = owners.mini_stream_sectors();
+    let mini_sector_offset = |mini_sector: u32| {
+        let offset = mini_sector as u64 * mini_sector_len;
+        let sector_id = mini_stream_sectors[(offset / sector_len) as usize];
+        sector_offset(sector_id) + offset % sector_len
+    };
+    let num_mini_sectors =
+        mini_stream_sectors.len() as u64 * (sector_len / mini_sector_len);
+    for mini_sector in 0..num_mini_sectors as u32 {
+        if owners.mini_sector(mini_sector).is_none() {
+            analysis.add(
+                SlackKind::FreeMiniSector(mini_sector),
+                mini_sector_offset(mini_sector),
+                mini_sector_len,
+            )?;
+        }
+    }
+
+    // Stream tails:
+    let reachable = reachable_stream_ids(analysis.minialloc.dir_entries()?);
+    for stream_id in 0..analysis.minialloc.dir_entries()?.len() as u32 {
+        let dir_entry = analysis.minialloc.dir_entry(stream_id);
+        if dir_entry.obj_type != ObjType::Stream
+            || dir_entry.stream_len == 0
+            || !reachable.contains(&stream_id)
+        {
+            continue;
+        }
+        let stream_len = dir_entry.stream_len;
+        let start_sector = dir_entry.start_sector;
+        if stream_len < consts::MINI_STREAM_CUTOFF as u64 {
+            let mini_sectors =
+                analysis.minialloc.mini_chain_sector_ids(start_sector)?;
+            if let Some(&last) = mini_sectors.last() {
+                let used = stream_len.checked_sub(
+                    (mini_sectors.len() as u64 - 1) * mini_sector_len,
+                );
+                if let Some(used) = used.filter(|&used| {
+                    used <= mini_sector_len && (last as u64) < num_mini_sectors
+                }) {
+                    analysis.add(
+                        SlackKind::StreamTail(stream_id),
+                        mini_sector_offset(last) + used,
+                        mini_sector_len - used,
+                    )?;
+                }
+            }
+        } else {
+            let sector_ids =
+                analysis.minialloc.chain_sector_ids(start_sector)?;
+            if let Some(&last) = sector_ids.last() {
+                let used = stream_len
+                    .checked_sub((sector_ids.len() as u64 - 1) * sector_len);
+                if let Some(used) = used.filter(|&used| used <= sector_len) {
+                    analysis.add(
+                        SlackKind::StreamTail(stream_id),
+                        sector_offset(last) + used,
+                        sector_len - used,
+                    )?;
+                }
+            }
+        }
+    }
+
+    // Directory entries:
+    let dir_entry_len = consts::DIR_ENTRY_LEN as u64;
+    let dir_entries_per_sector = version.dir_entries_per_sector() as u64;
+    let dir_start_sector = analysis.minialloc.dir_start_sector();
+    let dir_sectors = analysis.minialloc.chain_sector_ids(dir_start_sector)?;
+    for stream_id in 0..analysis.minialloc.dir_entries()?.len() as u32 {
+        let offset = {
+            let index = stream_id as u64;
+            let sector_id =
+                dir_sectors[(index / dir_entries_per_sector) as usize];
+            sector_offset(sector_id)
+                + (index % dir_entries_per_sector) * dir_entry_len
+        };
+        let obj_type = analysis.minialloc.dir_entry(stream_id).obj_type;
+        if obj_type == ObjType::Unallocated {
+            analysis.add(
+                SlackKind::UnusedDirEntry(stream_id),
+                offset,
+                dir_entry_len,
+            )?;
+            continue;
+        }
+        let kind = SlackKind::DirEntryReserved(stream_id);
+        // The name padding starts right after the name's null terminator,
+        // whose position is given by the name length field.
+        let name_len_bytes = {
+            let data = analysis.minialloc.read_at(offset + 64, 2)?;
+            u16::from_le_bytes([data[0], data[1]]).min(64) as u64
+        };
+        analysis.add(kind, offset + name_len_bytes, 64 - name_len_bytes)?;
+        match obj_type {
+            ObjType::Stream => {
+                analysis.add(kind, offset + 80, 16)?; // CLSID
+                analysis.add(kind, offset + 100, 16)?; // timestamps
+            }
+            ObjType::Storage => {
+                // starting sector and stream length
+                analysis.add(kind, offset + 116, 12)?;
+                continue;
+            }
+            ObjType::Root | ObjType::Unallocated => {}
+        }
+        if version == Version::V3 {
+            // high 32 bits of the stream length
+            analysis.add(kind, offset + 124, 4)?;
+        }
+    }
+
+    let mut regions = analysis.regions;
+    regions.sort_by_key(|region| region.offset);
+    Ok(regions)
+}
+
+pub fn scrub<F: Read + Write + Seek>(
+    minialloc: &mut MiniAllocator<F>,
+) -> io::Result<()> {
+    let mut rewritten_dir_entry = None;
+    for region in analyze(minialloc)? {
+        if !region.is_nonzero() {
+            continue;
+        }
+        match region.kind {
+            SlackKind::UnusedDirEntry(stream_id) => {
+                minialloc.free_dir_entry(stream_id)?;
+            }
+            SlackKind::DirEntryReserved(stream_id) => {
+                // Rewrite the whole entry from the cached copy, rather than
+                // patching the file directly, so that the two can't disagree
+                // (and a later update to the entry can't write stale data
+                // back out).  Each entry only needs rewriting once.
+                if rewritten_dir_entry == Some(stream_id) {
+                    continue;
+                }
+                rewritten_dir_entry = Some(stream_id);
+                let version = minialloc.version();
+                minialloc.with_dir_entry_mut(stream_id, |dir_entry| {
+                    match dir_entry.obj_type {
+                        ObjType::Stream => {
+                            dir_entry.clsid = Uuid::nil();
+                            dir_entry.creation_time = Timestamp::zero();
+                            dir_entry.modified_time = Timestamp::zero();
+                        }
+                        ObjType::Storage => {
+                            dir_entry.start_sector = 0;
+                            dir_entry.stream_len = 0;
+                        }
+                        ObjType::Root | ObjType::Unallocated => {}
+                    }
+                    dir_entry.stream_len &= version.stream_len_mask();
+                })?;
+            }
+            SlackKind::HeaderDifat => {
+                let filler = vec![0xff; region.len as usize];
+                minialloc.write_at(region.offset, &filler)?;
+            }
+            _ => {
+                let filler = vec![0; region.len as usize];
+                minialloc.write_at(region.offset, &filler)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+//===========================================================================//
@@ -0,0 +1,318 @@
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+//===========================================================================//
+
+/// The most bytes to read from the input at a time.
+const CHUNK_LEN: usize = 64 * 1024;
+
+/// Used to give each temporary file created by this process a unique name.
+static NEXT_SPILL_ID: AtomicUsize = AtomicUsize::new(0);
+
+//===========================================================================//
+
+/// Adapts a non-seekable reader (such as a pipe or a network connection) into
+/// a seekable one, so that a `CompoundFile` can be opened from it.
+///
+/// The input is read once, in order, as far as needed to satisfy each read
+/// or seek.  Everything read is kept, so that earlier parts of the input can
+/// be read again: up to a configurable number of bytes are kept in memory,
+/// and the rest is spilled to a temporary file, which is deleted when the
+/// `Spool` is dropped.  Since opening a compound file requires knowing its
+/// length, the whole input is read by `CompoundFile::open`.
+pub struct Spool<R> {
+    reader: R,
+    reached_end: bool,
+    options: SpoolOptions,
+    /// The start of the input, up to the memory limit.
+    memory: Vec<u8>,
+    /// The rest of the input, if there has been any.
+    spill: Option<SpillFile>,
+    /// The number of bytes read from the input so far.
+    len: u64,
+    position: u64,
+}
+
+impl<R> Spool<R> {
+    /// Returns the number of bytes read from the input so far.
+    pub fn len_read(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns true if any of the input has been spilled to a temporary
+    /// file.
+    pub fn is_spilled(&self) -> bool {
+        self.spill.is_some()
+    }
+
+    /// Consumes the `Spool`, returning the underlying reader (positioned
+    /// after the last byte read from it) and deleting any temporary file.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: Read> Spool<R> {
+    /// Creates a spool for the given reader, with the default options.
+    pub fn new(reader: R) -> Spool<R> {
+        Spool::with_options(reader, &SpoolOptions::new())
+    }
+
+    /// Creates a spool for the given reader, with the given options.
+    pub fn with_options(reader: R, options: &SpoolOptions) -> Spool<R> {
+        Spool {
+            reader,
+            reached_end: false,
+            options: options.clone(),
+            memory: Vec::new(),
+            spill: None,
+            len: 0,
+            position: 0,
+        }
+    }
+
+    /// Reads from the input until at least `target` bytes have been read in
+    /// total, or until the input ends.
+    fn read_until(&mut self, target: u64) -> io::Result<()> {
+        let mut chunk = Vec::new();
+        while self.len < target && !self.reached_end {
+            let wanted = (target - self.len).min(CHUNK_LEN as u64) as usize;
+            chunk.resize(wanted, 0);
+            let num_bytes = match self.reader.read(&mut chunk) {
+                Ok(num_bytes) => num_bytes,
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => {
+                    continue;
+                }
+                Err(error) => return Err(error),
+            };
+            if num_bytes == 0 {
+                self.reached_end = true;
+                break;
+            }
+            self.append(&chunk[..num_bytes])?;
+        }
+        Ok(())
+    }
+
+    fn append(&mut self, mut data: &[u8]) -> io::Result<()> {
+        if self.spill.is_none() {
+            let room = self.options.memory_limit() - self.memory.len();
+            let num_bytes = room.min(data.len());
+            self.memory.extend_from_slice(&data[..num_bytes]);
+            self.len += num_bytes as u64;
+            data = &data[num_bytes..];
+            if data.is_empty() {
+                return Ok(());
+            }
+            self.spill = Some(SpillFile::create(&self.options)?);
+        }
+        let spill = self.spill.as_mut().unwrap();
+        let offset = self.len - self.memory.len() as u64;
+        spill.file.seek(SeekFrom::Start(offset))?;
+        spill.file.write_all(data)?;
+        self.len += data.len() as u64;
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for Spool<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.read_until(self.position.saturating_add(buf.len() as u64))?;
+        if self.position >= self.len {
+            return Ok(0);
+        }
+        let max_len = ((self.len - self.position) as usize).min(buf.len());
+        let num_bytes = if self.position < self.memory.len() as u64 {
+            let start = self.position as usize;
+            let num_bytes = max_len.min(self.memory.len() - start);
+            buf[..num_bytes]
+                .copy_from_slice(&self.memory[start..start + num_bytes]);
+            num_bytes
+        } else {
+            let offset = self.position - self.memory.len() as u64;
+            let spill = self.spill.as_mut().unwrap();
+            spill.file.seek(SeekFrom::Start(offset))?;
+            spill.file.read(&mut buf[..max_len])?
+        };
+        self.position += num_bytes as u64;
+        Ok(num_bytes)
+    }
+}
+
+impl<R: Read> Seek for Spool<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(delta) => {
+                self.read_until(u64::MAX)?;
+                self.len.checked_add_signed(delta)
+            }
+            SeekFrom::Current(delta) => {
+                self.position.checked_add_signed(delta)
+            }
+        };
+        match position {
+            Some(position) => {
+                self.position = position;
+                Ok(position)
+            }
+            None => invalid_input!("Invalid seek to {:?}", pos),
+        }
+    }
+}
+
+//===========================================================================//
+
+/// A temporary file that is deleted when dropped.
+struct SpillFile {
+    file: fs::File,
+    path: PathBuf,
+}
+
+impl SpillFile {
+    fn create(options: &SpoolOptions) -> io::Result<SpillFile> {
+        let dir = match options.temp_dir() {
+            Some(dir) => dir.to_path_buf(),
+            None => std::env::temp_dir(),
+        };
+        loop {
+            let id = NEXT_SPILL_ID.fetch_add(1, Ordering::Relaxed);
+            let name = format!("cfb-spool-{}-{}.tmp", std::process::id(), id);
+            let path = dir.join(name);
+            match fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create_new(true)
+                .open(&path)
+            {
+                Ok(file) => return Ok(SpillFile { file, path }),
+                Err(error) if error.kind() == io::ErrorKind::AlreadyExists => {
+                    continue;
+                }
+                Err(error) => return Err(error),
+            }
+        }
+    }
+}
+
+impl Drop for SpillFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+//===========================================================================//
+
+/// The number of bytes a `Spool` keeps in memory unless otherwise specified.
+pub const DEFAULT_SPOOL_MEMORY_LIMIT: usize = 16 * 1024 * 1024;
+
+/// Options for spooling a non-seekable input with a `Spool`.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct SpoolOptions {
+    memory_limit: usize,
+    temp_dir: Option<PathBuf>,
+}
+
+impl SpoolOptions {
+    /// Returns the default options, which are the ones used by
+    /// `Spool::new`.
+    pub fn new() -> SpoolOptions {
+        SpoolOptions {
+            memory_limit: DEFAULT_SPOOL_MEMORY_LIMIT,
+            temp_dir: None,
+        }
+    }
+
+    /// Returns the maximum number of bytes of input to hold in memory.
+    pub fn memory_limit(&self) -> usize {
+        self.memory_limit
+    }
+
+    /// Sets the maximum number of bytes of input to hold in memory (16 MiB
+    /// by default).  Any input past this point is spilled to a temporary
+    /// file.  A limit of `usize::MAX` keeps everything in memory.
+    pub fn with_memory_limit(mut self, memory_limit: usize) -> SpoolOptions {
+        self.memory_limit = memory_limit;
+        self
+    }
+
+    /// Returns the directory that temporary files are created in, if one has
+    /// been set.
+    pub fn temp_dir(&self) -> Option<&Path> {
+        self.temp_dir.as_deref()
+    }
+
+    /// Sets the directory to create temporary files in (by default,
+    /// `std::env::temp_dir()`).
+    pub fn with_temp_dir<P: Into<PathBuf>>(mut self, dir: P) -> SpoolOptions {
+        self.temp_dir = Some(dir.into());
+        self
+    }
+}
+
+impl Default for SpoolOptions {
+    fn default() -> SpoolOptions {
+        SpoolOptions::new()
+    }
+}
+
+//===========================================================================//
+
+#[cfg(test)]
+mod tests {
+    use super::{Spool, SpoolOptions};
+    use std::io::{Read, Seek, SeekFrom};
+
+    fn input(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i % 251) as u8).collect()
+    }
+
+    #[test]
+    fn reads_input_only_as_needed() {
+        let data = input(1000);
+        let mut spool = Spool::new(data.as_slice());
+        let mut buf = [0; 10];
+        spool.seek(SeekFrom::Start(100)).unwrap();
+        assert_eq!(spool.len_read(), 0);
+        spool.read_exact(&mut buf).unwrap();
+        assert_eq!(spool.len_read(), 110);
+        assert_eq!(&buf[..], &data[100..110]);
+        spool.seek(SeekFrom::Start(5)).unwrap();
+        spool.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf[..], &data[5..15]);
+        assert_eq!(spool.len_read(), 110);
+        assert_eq!(spool.seek(SeekFrom::End(-1)).unwrap(), 999);
+        assert_eq!(spool.len_read(), 1000);
+        assert!(!spool.is_spilled());
+    }
+
+    #[test]
+    fn spills_past_memory_limit() {
+        let data = input(200000);
+        let options = SpoolOptions::new().with_memory_limit(1000);
+        let mut spool = Spool::with_options(data.as_slice(), &options);
+        let mut buf = vec![0; 3000];
+        spool.seek(SeekFrom::Start(500)).unwrap();
+        spool.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, &data[500..3500]);
+        assert!(spool.is_spilled());
+        spool.seek(SeekFrom::Start(0)).unwrap();
+        let mut actual = Vec::new();
+        spool.read_to_end(&mut actual).unwrap();
+        assert_eq!(actual, data);
+    }
+
+    #[test]
+    fn seek_past_end() {
+        let data = input(10);
+        let mut spool = Spool::new(data.as_slice());
+        assert_eq!(spool.seek(SeekFrom::Start(20)).unwrap(), 20);
+        let mut buf = [0; 4];
+        assert_eq!(spool.read(&mut buf).unwrap(), 0);
+        assert!(spool.seek(SeekFrom::End(-11)).is_err());
+    }
+}
+
+//===========================================================================//
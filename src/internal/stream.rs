@@ -1,10 +1,8 @@
-use crate::internal::{consts, MiniAllocator, ObjType, SectorInit};
+use crate::internal::{
+    consts, inspect, MiniAllocator, ObjType, ReadAt, SectorInit, StreamChain,
+};
 use std::io::{self, BufRead, Read, Seek, SeekFrom, Write};
-use std::sync::{Arc, RwLock, Weak};
-
-//===========================================================================//
-
-const BUFFER_SIZE: usize = 8192;
+use std::sync::{Arc, Mutex, RwLock, Weak};
 
 //===========================================================================//
 
@@ -13,17 +11,38 @@ pub struct Stream<F> {
     minialloc: Weak<RwLock<MiniAllocator<F>>>,
     stream_id: u32,
     total_len: u64,
-    buffer: Box<[u8; BUFFER_SIZE]>,
+    buffer: Box<[u8]>,
+    /// True if the stream was opened without a buffer, in which case any
+    /// buffer it has is only temporary, for use as a `BufRead`.
+    unbuffered: bool,
     buf_pos: usize,
     buf_cap: usize,
     buf_offset_from_start: u64,
-    flusher: Option<Box<dyn Flusher<F>>>,
+    flusher: Option<Box<dyn Flusher<F> + Send + Sync>>,
+    /// The stream's chain as of the last call to `read_at`.
+    cached_chain: Mutex<Option<CachedChain>>,
+}
+
+/// A stream's chain, along with the state of the compound file at the time it
+/// was looked up, so that we can tell when it needs to be looked up again.
+struct CachedChain {
+    generation: u64,
+    start_sector: u32,
+    chain: StreamChain,
 }
 
 impl<F> Stream<F> {
     pub(crate) fn new(
         minialloc: &Arc<RwLock<MiniAllocator<F>>>,
         stream_id: u32,
+    ) -> Stream<F> {
+        Stream::with_options(minialloc, stream_id, &StreamOptions::new())
+    }
+
+    pub(crate) fn with_options(
+        minialloc: &Arc<RwLock<MiniAllocator<F>>>,
+        stream_id: u32,
+        options: &StreamOptions,
     ) -> Stream<F> {
         let total_len =
             minialloc.read().unwrap().dir_entry(stream_id).stream_len;
@@ -31,11 +50,13 @@ impl<F> Stream<F> {
             minialloc: Arc::downgrade(minialloc),
             stream_id,
             total_len,
-            buffer: Box::new([0; BUFFER_SIZE]),
+            buffer: vec![0; options.buffer_size()].into_boxed_slice(),
+            unbuffered: options.buffer_size() == 0,
             buf_pos: 0,
             buf_cap: 0,
             buf_offset_from_start: 0,
             flusher: None,
+            cached_chain: Mutex::new(None),
         }
     }
 
@@ -55,6 +76,15 @@ impl<F> Stream<F> {
         self.total_len == 0
     }
 
+    /// Returns the capacity of the stream's read/write buffer, in bytes.
+    pub fn buffer_size(&self) -> usize {
+        if self.unbuffered {
+            0
+        } else {
+            self.buffer.len()
+        }
+    }
+
     fn current_position(&self) -> u64 {
         self.buf_offset_from_start + (self.buf_pos as u64)
     }
@@ -65,6 +95,18 @@ impl<F> Stream<F> {
         }
         Ok(())
     }
+
+    /// Frees the temporary buffer that an unbuffered stream uses as a
+    /// `BufRead`, if it has one, without changing the current position.
+    fn release_temporary_buffer(&mut self) {
+        if self.unbuffered && !self.buffer.is_empty() {
+            debug_assert!(self.flusher.is_none());
+            self.buf_offset_from_start += self.buf_pos as u64;
+            self.buf_pos = 0;
+            self.buf_cap = 0;
+            self.buffer = Box::default();
+        }
+    }
 }
 
 impl<F: Read + Write + Seek> Stream<F> {
@@ -96,14 +138,118 @@ impl<F: Read + Write + Seek> Stream<F> {
         Ok(())
     }
 
+    /// Preallocates space for the stream to grow to `len` bytes, so that
+    /// later writes up to that length can be stored in adjacent sectors,
+    /// regardless of what else is written to the compound file in the
+    /// meantime.  This does not change the length of the stream.
+    ///
+    /// Streams shorter than 4096 bytes are stored in the mini stream rather
+    /// than in sectors of their own, so for such a stream the space is
+    /// reserved once it grows past that size.  Any reserved space that the
+    /// stream never uses remains as free sectors in the file.
+    pub fn reserve(&mut self, len: u64) -> io::Result<()> {
+        self.flush_changes()?;
+        let minialloc = self.minialloc()?;
+        let mut minialloc = minialloc.write().unwrap();
+        minialloc.reserve_stream(self.stream_id, len)
+    }
+
     fn mark_modified(&mut self) {
         if self.flusher.is_none() {
-            let flusher: Box<dyn Flusher<F>> = Box::new(FlushBuffer);
+            let flusher: Box<dyn Flusher<F> + Send + Sync> =
+                Box::new(FlushBuffer);
             self.flusher = Some(flusher);
         }
     }
 }
 
+impl<F: ReadAt> Stream<F> {
+    /// Reads bytes from the stream starting at the given offset, returning
+    /// the number of bytes read (which is zero only if `offset` is at or past
+    /// the end of the stream, or if `buf` is empty).  Unlike `Read::read`,
+    /// this does not use or change the stream's current position.
+    ///
+    /// This takes `&self` and only needs shared access to the compound file,
+    /// with the data read directly from the underlying file via `ReadAt`, so
+    /// any number of threads can read from streams of the same compound file
+    /// at once without waiting on each other.  Any data written to this
+    /// stream that hasn't been flushed yet is included.
+    pub fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        if offset >= self.total_len {
+            return Ok(0);
+        }
+        let len = (self.total_len - offset).min(buf.len() as u64) as usize;
+        let buf = &mut buf[..len];
+        let minialloc = self.minialloc()?;
+        let minialloc = minialloc.read().unwrap();
+        let dir_entry = minialloc.dir_entry(self.stream_id);
+        let num_stored = if offset < dir_entry.stream_len {
+            (dir_entry.stream_len - offset).min(len as u64) as usize
+        } else {
+            0
+        };
+        if num_stored > 0 {
+            let mut cached_chain = self.cached_chain.lock().unwrap();
+            let chain = self.chain_for_read(&minialloc, &mut cached_chain)?;
+            read_data_at(
+                minialloc.inner(),
+                chain,
+                offset,
+                &mut buf[..num_stored],
+            )?;
+        }
+        buf[num_stored..].fill(0);
+        if self.flusher.is_some() {
+            let buf_start = self.buf_offset_from_start;
+            let buf_end = buf_start + self.buf_cap as u64;
+            let start = offset.max(buf_start);
+            let end = (offset + len as u64).min(buf_end);
+            if start < end {
+                buf[(start - offset) as usize..(end - offset) as usize]
+                    .copy_from_slice(
+                        &self.buffer[(start - buf_start) as usize
+                            ..(end - buf_start) as usize],
+                    );
+            }
+        }
+        Ok(len)
+    }
+
+    /// Returns the stream's chain, looking it up again only if it may have
+    /// changed since the last time.
+    fn chain_for_read<'a>(
+        &self,
+        minialloc: &MiniAllocator<F>,
+        cached_chain: &'a mut Option<CachedChain>,
+    ) -> io::Result<&'a StreamChain> {
+        let generation = minialloc.generation();
+        let dir_entry = minialloc.dir_entry(self.stream_id);
+        let in_mini_stream =
+            dir_entry.stream_len < consts::MINI_STREAM_CUTOFF as u64;
+        let is_current = match cached_chain {
+            Some(cached) => {
+                let chain_len: u64 =
+                    cached.chain.sectors().iter().map(|s| s.len()).sum();
+                // Chains only change without the generation changing by
+                // growing, in which case they get longer than we last saw.
+                cached.generation == generation
+                    && cached.start_sector == dir_entry.start_sector
+                    && cached.chain.in_mini_stream() == in_mini_stream
+                    && chain_len >= dir_entry.stream_len
+            }
+            None => false,
+        };
+        if !is_current {
+            *cached_chain = Some(CachedChain {
+                generation,
+                start_sector: dir_entry.start_sector,
+                chain: inspect::stream_chain(minialloc, self.stream_id)?,
+            });
+        }
+        Ok(&cached_chain.as_ref().unwrap().chain)
+    }
+}
+
 impl<F: Read + Seek> BufRead for Stream<F> {
     fn fill_buf(&mut self) -> io::Result<&[u8]> {
         if self.buf_pos >= self.buf_cap
@@ -112,6 +258,12 @@ impl<F: Read + Seek> BufRead for Stream<F> {
             self.flush_changes()?;
             self.buf_offset_from_start += self.buf_pos as u64;
             self.buf_pos = 0;
+            self.buf_cap = 0;
+            if self.buffer.is_empty() {
+                // An unbuffered stream still needs a buffer to be used as a
+                // BufRead, but only until the data in it has been consumed.
+                self.buffer = vec![0; DEFAULT_BUFFER_SIZE].into_boxed_slice();
+            }
             let minialloc = self.minialloc()?;
             self.buf_cap = read_data_from_stream(
                 &mut minialloc.write().unwrap(),
@@ -125,11 +277,32 @@ impl<F: Read + Seek> BufRead for Stream<F> {
 
     fn consume(&mut self, amt: usize) {
         self.buf_pos = self.buf_cap.min(self.buf_pos + amt);
+        if self.buf_pos >= self.buf_cap {
+            self.release_temporary_buffer();
+        }
     }
 }
 
 impl<F: Read + Seek> Read for Stream<F> {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // If there's no buffered data left to read, skip the buffer for large
+        // reads, so that they can be serviced with fewer, larger reads from
+        // the underlying file.
+        if self.buf_pos >= self.buf_cap && buf.len() >= self.buffer.len() {
+            self.flush_changes()?;
+            self.buf_offset_from_start += self.buf_pos as u64;
+            self.buf_pos = 0;
+            self.buf_cap = 0;
+            let minialloc = self.minialloc()?;
+            let num_bytes = read_data_from_stream(
+                &mut minialloc.write().unwrap(),
+                self.stream_id,
+                self.buf_offset_from_start,
+                buf,
+            )?;
+            self.buf_offset_from_start += num_bytes as u64;
+            return Ok(num_bytes);
+        }
         let mut buffered_data = self.fill_buf()?;
         let num_bytes = buffered_data.read(buf)?;
         self.consume(num_bytes);
@@ -214,7 +387,28 @@ impl<F: Read + Seek> Seek for Stream<F> {
 
 impl<F: Read + Write + Seek> Write for Stream<F> {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // Any data left over from using an unbuffered stream as a BufRead
+        // mustn't turn it into a buffered one.
+        self.release_temporary_buffer();
         debug_assert!(self.buf_pos <= self.buffer.len());
+        // Writes that wouldn't fit in an empty buffer go straight to the
+        // underlying chain, after writing out any buffered changes first.
+        if !buf.is_empty() && buf.len() >= self.buffer.len() {
+            self.flush_changes()?;
+            self.buf_offset_from_start += self.buf_pos as u64;
+            self.buf_pos = 0;
+            self.buf_cap = 0;
+            let minialloc = self.minialloc()?;
+            write_data_to_stream(
+                &mut minialloc.write().unwrap(),
+                self.stream_id,
+                self.buf_offset_from_start,
+                buf,
+            )?;
+            self.buf_offset_from_start += buf.len() as u64;
+            self.total_len = self.total_len.max(self.buf_offset_from_start);
+            return Ok(buf.len());
+        }
         if self.buf_pos >= self.buffer.len() {
             self.flush_changes()?;
             self.buf_offset_from_start += self.buf_pos as u64;
@@ -274,6 +468,44 @@ impl<F: Read + Write + Seek> Flusher<F> for FlushBuffer {
 
 //===========================================================================//
 
+/// Reads data starting at the given offset within a stream with the given
+/// chain, reading each run of adjacent sectors with a single call.
+fn read_data_at<F: ReadAt>(
+    inner: &F,
+    chain: &StreamChain,
+    offset: u64,
+    mut buf: &mut [u8],
+) -> io::Result<()> {
+    let sectors = chain.sectors();
+    let unit_len = match sectors.first() {
+        Some(sector) => sector.len(),
+        None => invalid_data!("Stream chain is too short"),
+    };
+    let mut index = (offset / unit_len) as usize;
+    let mut offset_within_unit = offset % unit_len;
+    while !buf.is_empty() {
+        let run_start = match sectors.get(index) {
+            Some(sector) => sector.offset() + offset_within_unit,
+            None => invalid_data!("Stream chain is too short"),
+        };
+        let mut run_len = unit_len - offset_within_unit;
+        index += 1;
+        while run_len < buf.len() as u64
+            && index < sectors.len()
+            && sectors[index].offset() == run_start + run_len
+        {
+            run_len += unit_len;
+            index += 1;
+        }
+        let num_bytes = run_len.min(buf.len() as u64) as usize;
+        let (run_buf, rest) = buf.split_at_mut(num_bytes);
+        inner.read_exact_at(run_buf, run_start)?;
+        buf = rest;
+        offset_within_unit = 0;
+    }
+    Ok(())
+}
+
 fn read_data_from_stream<F: Read + Seek>(
     minialloc: &mut MiniAllocator<F>,
     stream_id: u32,
@@ -387,7 +619,10 @@ fn write_data_to_stream<F: Read + Write + Seek>(
     minialloc.with_dir_entry_mut(stream_id, |dir_entry| {
         dir_entry.start_sector = new_start_sector;
         dir_entry.stream_len = new_stream_len;
-    })
+    })?;
+    // If the stream has now moved out of the mini stream, any space reserved
+    // for it can now be set aside.
+    minialloc.apply_stream_reservation(stream_id)
 }
 
 /// If `new_stream_len` is less than the stream's current length, then the
@@ -486,3 +721,42 @@ fn resize_stream<F: Read + Write + Seek>(
 }
 
 //===========================================================================//
+
+/// The buffer size used for streams unless otherwise specified.
+pub const DEFAULT_BUFFER_SIZE: usize = 8192;
+
+/// Options for opening a stream within a compound file.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct StreamOptions {
+    buffer_size: usize,
+}
+
+impl StreamOptions {
+    /// Returns the default options, which are the ones used by
+    /// `CompoundFile::open_stream`.
+    pub fn new() -> StreamOptions {
+        StreamOptions { buffer_size: DEFAULT_BUFFER_SIZE }
+    }
+
+    /// Returns the capacity, in bytes, of the stream's read/write buffer.
+    pub fn buffer_size(&self) -> usize {
+        self.buffer_size
+    }
+
+    /// Sets the capacity, in bytes, of the stream's read/write buffer (8192
+    /// bytes by default).  Reads and writes at least this large bypass the
+    /// buffer entirely.  A buffer size of zero makes the stream unbuffered,
+    /// so that every read and write goes straight to the underlying file.
+    pub fn with_buffer_size(mut self, buffer_size: usize) -> StreamOptions {
+        self.buffer_size = buffer_size;
+        self
+    }
+}
+
+impl Default for StreamOptions {
+    fn default() -> StreamOptions {
+        StreamOptions::new()
+    }
+}
+
+//===========================================================================//
@@ -1,4 +1,5 @@
 use crate::{ReadLeNumber, WriteLeNumber};
+use std::ffi::OsStr;
 use std::io::{self, Read, Write};
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
@@ -10,7 +11,16 @@ use std::time::{Duration, SystemTime, UNIX_EPOCH};
 pub struct Timestamp(u64);
 
 impl Timestamp {
-    pub(crate) fn value(self) -> u64 {
+    /// Returns a timestamp with the given raw value (the number of
+    /// 100-nanosecond intervals since January 1, 1601 UTC).
+    pub fn from_value(value: u64) -> Timestamp {
+        Timestamp(value)
+    }
+
+    /// Returns the raw value of this timestamp (the number of 100-nanosecond
+    /// intervals since January 1, 1601 UTC).  Unlike `to_system_time`, this
+    /// is never lossy.
+    pub fn value(self) -> u64 {
         self.0
     }
 
@@ -26,6 +36,16 @@ impl Timestamp {
         Timestamp::from_system_time(SystemTime::now())
     }
 
+    /// Returns the timestamp given by the `SOURCE_DATE_EPOCH` environment
+    /// variable (see <https://reproducible-builds.org/specs/source-date-epoch/>),
+    /// or `None` if the variable isn't set.  Returns an error if it's set to
+    /// anything other than a non-negative number of seconds since the Unix
+    /// epoch.
+    pub fn from_source_date_epoch() -> io::Result<Option<Timestamp>> {
+        let value = std::env::var_os("SOURCE_DATE_EPOCH");
+        parse_source_date_epoch(value.as_deref())
+    }
+
     /// Returns a timestamp representing the given system time.
     pub fn from_system_time(system_time: SystemTime) -> Timestamp {
         Timestamp(timestamp_from_system_time(system_time))
@@ -36,11 +56,11 @@ impl Timestamp {
         system_time_from_timestamp(self.0)
     }
 
-    pub fn read_from<R: Read>(reader: &mut R) -> io::Result<Timestamp> {
+    pub(crate) fn read_from<R: Read>(reader: &mut R) -> io::Result<Timestamp> {
         Ok(Timestamp(reader.read_le_u64()?))
     }
 
-    pub fn write_to<W: Write>(self, writer: &mut W) -> io::Result<()> {
+    pub(crate) fn write_to<W: Write>(self, writer: &mut W) -> io::Result<()> {
         writer.write_le_u64(self.0)
     }
 }
@@ -50,6 +70,23 @@ impl Timestamp {
 /// The CFB timestamp value for the Unix epoch (Jan 1, 1970 UTC).
 const UNIX_EPOCH_TIMESTAMP: u64 = 116444736000000000;
 
+fn parse_source_date_epoch(
+    value: Option<&OsStr>,
+) -> io::Result<Option<Timestamp>> {
+    let value = match value {
+        Some(value) => value,
+        None => return Ok(None),
+    };
+    match value.to_str().and_then(|string| string.parse::<u64>().ok()) {
+        Some(seconds) => {
+            let delta =
+                duration_to_timestamp_delta(Duration::from_secs(seconds));
+            Ok(Some(Timestamp(UNIX_EPOCH_TIMESTAMP.saturating_add(delta))))
+        }
+        None => invalid_input!("Invalid SOURCE_DATE_EPOCH: {:?}", value),
+    }
+}
+
 /// Converts a local `SystemTime` to a CFB file timestamp value.
 fn timestamp_from_system_time(system_time: SystemTime) -> u64 {
     match system_time.duration_since(UNIX_EPOCH) {
@@ -106,10 +143,11 @@ fn timestamp_delta_to_duration(delta: u64) -> Duration {
 #[cfg(test)]
 mod tests {
     use super::{
-        duration_to_timestamp_delta, system_time_from_timestamp,
-        timestamp_delta_to_duration, timestamp_from_system_time,
-        UNIX_EPOCH_TIMESTAMP,
+        duration_to_timestamp_delta, parse_source_date_epoch,
+        system_time_from_timestamp, timestamp_delta_to_duration,
+        timestamp_from_system_time, Timestamp, UNIX_EPOCH_TIMESTAMP,
     };
+    use std::ffi::OsStr;
     use std::time::{Duration, UNIX_EPOCH};
 
     #[test]
@@ -171,6 +209,22 @@ mod tests {
         );
     }
 
+    #[test]
+    fn source_date_epoch() {
+        assert_eq!(parse_source_date_epoch(None).unwrap(), None);
+        let value = parse_source_date_epoch(Some(OsStr::new("1489862796")));
+        let expected = Timestamp::from_value(131343363960000000);
+        assert_eq!(value.unwrap(), Some(expected));
+        let value = parse_source_date_epoch(Some(OsStr::new("0")));
+        let expected = Timestamp::from_value(UNIX_EPOCH_TIMESTAMP);
+        assert_eq!(value.unwrap(), Some(expected));
+        for &invalid in &["", "-5", "12.5", "soon"] {
+            assert!(
+                parse_source_date_epoch(Some(OsStr::new(invalid))).is_err()
+            );
+        }
+    }
+
     #[test]
     fn extreme_timestamps() {
         // If the system we're on can't represent these timestamps in a
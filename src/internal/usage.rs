@@ -0,0 +1,231 @@
+use crate::internal::owners::{SectorOwner, SectorOwners};
+use crate::internal::{consts, MiniAllocator};
+use crate::CompoundFile;
+use std::io;
+use std::path::Path;
+
+//===========================================================================//
+
+/// How much space the streams within a storage subtree take up.  See
+/// `CompoundFile::usage`.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct StorageUsage {
+    logical_len: u64,
+    num_regular_streams: u64,
+    num_mini_streams: u64,
+    num_sectors: u64,
+    num_mini_sectors: u64,
+    sector_len: u64,
+}
+
+impl StorageUsage {
+    /// Returns the total length, in bytes, of all streams in the subtree.
+    pub fn logical_len(&self) -> u64 {
+        self.logical_len
+    }
+
+    /// Returns the number of streams in the subtree that are stored in
+    /// regular sectors (i.e. that are at least 4096 bytes long).
+    pub fn num_regular_streams(&self) -> u64 {
+        self.num_regular_streams
+    }
+
+    /// Returns the number of streams in the subtree that are stored in the
+    /// mini stream (including empty streams).
+    pub fn num_mini_streams(&self) -> u64 {
+        self.num_mini_streams
+    }
+
+    /// Returns the number of regular sectors occupied by streams in the
+    /// subtree.
+    pub fn num_sectors(&self) -> u64 {
+        self.num_sectors
+    }
+
+    /// Returns the number of mini sectors occupied by streams in the
+    /// subtree.
+    pub fn num_mini_sectors(&self) -> u64 {
+        self.num_mini_sectors
+    }
+
+    /// Returns the number of bytes in the sectors and mini sectors occupied
+    /// by streams in the subtree.
+    pub fn allocated_len(&self) -> u64 {
+        self.num_sectors * self.sector_len
+            + self.num_mini_sectors * consts::MINI_SECTOR_LEN as u64
+    }
+
+    /// Returns the number of bytes in the occupied sectors and mini sectors
+    /// that lie past the logical end of their streams.
+    pub fn slack_len(&self) -> u64 {
+        self.allocated_len().saturating_sub(self.logical_len)
+    }
+}
+
+//===========================================================================//
+
+/// A breakdown of the space taken up by each part of a compound file.  See
+/// `CompoundFile::file_usage`.
+///
+/// All lengths are in bytes, and are multiples of the sector length, so
+/// they add up to the length of the file rounded up to a whole sector.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct FileUsage {
+    header_len: u64,
+    fat_len: u64,
+    difat_len: u64,
+    minifat_len: u64,
+    directory_len: u64,
+    mini_stream_len: u64,
+    unused_mini_stream_len: u64,
+    streams_len: u64,
+    free_len: u64,
+    orphaned_len: u64,
+    trailing_len: u64,
+}
+
+impl FileUsage {
+    /// Returns the length of the header, which takes up a whole sector
+    /// (including, for version 4 files, the padding after it).
+    pub fn header_len(&self) -> u64 {
+        self.header_len
+    }
+
+    /// Returns the length of the FAT sectors.
+    pub fn fat_len(&self) -> u64 {
+        self.fat_len
+    }
+
+    /// Returns the length of the DIFAT sectors.
+    pub fn difat_len(&self) -> u64 {
+        self.difat_len
+    }
+
+    /// Returns the length of the MiniFAT sectors.
+    pub fn minifat_len(&self) -> u64 {
+        self.minifat_len
+    }
+
+    /// Returns the length of the directory sectors.
+    pub fn directory_len(&self) -> u64 {
+        self.directory_len
+    }
+
+    /// Returns the length of the sectors holding the mini stream, which
+    /// contains every stream shorter than 4096 bytes.
+    pub fn mini_stream_len(&self) -> u64 {
+        self.mini_stream_len
+    }
+
+    /// Returns how much of the mini stream's sectors (as counted by
+    /// `mini_stream_len`) is made of mini sectors that no stream occupies.
+    pub fn unused_mini_stream_len(&self) -> u64 {
+        self.unused_mini_stream_len
+    }
+
+    /// Returns the length of the sectors occupied by streams that are stored
+    /// in regular sectors.
+    pub fn streams_len(&self) -> u64 {
+        self.streams_len
+    }
+
+    /// Returns the length of the sectors that the FAT marks as free.
+    pub fn free_len(&self) -> u64 {
+        self.free_len
+    }
+
+    /// Returns the length of the sectors that the FAT marks as in use, but
+    /// that don't belong to any live structure or stream (e.g. the leftover
+    /// chains of streams whose directory entries are unreachable).
+    pub fn orphaned_len(&self) -> u64 {
+        self.orphaned_len
+    }
+
+    /// Returns the length of the data at the end of the file, beyond the
+    /// last sector that the FAT is able to describe.
+    pub fn trailing_len(&self) -> u64 {
+        self.trailing_len
+    }
+
+    /// Returns the total of all the other lengths, which is the length of
+    /// the file rounded up to a whole sector.
+    pub fn total_len(&self) -> u64 {
+        self.header_len
+            + self.fat_len
+            + self.difat_len
+            + self.minifat_len
+            + self.directory_len
+            + self.mini_stream_len
+            + self.streams_len
+            + self.free_len
+            + self.orphaned_len
+            + self.trailing_len
+    }
+}
+
+//===========================================================================//
+
+pub fn storage_usage<F>(
+    comp: &CompoundFile<F>,
+    path: &Path,
+) -> io::Result<StorageUsage> {
+    let sector_len = comp.minialloc().sector_len() as u64;
+    let mut usage = StorageUsage { sector_len, ..StorageUsage::default() };
+    for entry in comp.walk_storage(path)? {
+        if !entry.is_stream() {
+            continue;
+        }
+        let start_sector = entry.raw().start_sector();
+        let minialloc = comp.minialloc();
+        usage.logical_len += entry.len();
+        if entry.len() < consts::MINI_STREAM_CUTOFF as u64 {
+            usage.num_mini_streams += 1;
+            usage.num_mini_sectors +=
+                minialloc.mini_chain_sector_ids(start_sector)?.len() as u64;
+        } else {
+            usage.num_regular_streams += 1;
+            usage.num_sectors +=
+                minialloc.chain_sector_ids(start_sector)?.len() as u64;
+        }
+    }
+    Ok(usage)
+}
+
+pub fn file_usage<F>(minialloc: &MiniAllocator<F>) -> io::Result<FileUsage> {
+    let owners = SectorOwners::compute(minialloc)?;
+    let sector_len = minialloc.sector_len() as u64;
+    let mut usage =
+        FileUsage { header_len: sector_len, ..FileUsage::default() };
+
+    let num_sectors = minialloc.num_sectors();
+    let fat_capacity = (minialloc.difat().len() as u64 * (sector_len / 4))
+        .min(num_sectors as u64) as u32;
+    let fat = minialloc.fat();
+    for sector_id in 0..fat_capacity {
+        let len = match owners.sector(sector_id) {
+            Some(SectorOwner::Fat) => &mut usage.fat_len,
+            Some(SectorOwner::Difat) => &mut usage.difat_len,
+            Some(SectorOwner::Directory) => &mut usage.directory_len,
+            Some(SectorOwner::MiniFat) => &mut usage.minifat_len,
+            Some(SectorOwner::MiniStream) => &mut usage.mini_stream_len,
+            Some(SectorOwner::Stream(_)) => &mut usage.streams_len,
+            None => match fat.get(sector_id as usize) {
+                None | Some(&consts::FREE_SECTOR) => &mut usage.free_len,
+                Some(_) => &mut usage.orphaned_len,
+            },
+        };
+        *len += sector_len;
+    }
+    usage.trailing_len = (num_sectors - fat_capacity) as u64 * sector_len;
+
+    let mini_sector_len = consts::MINI_SECTOR_LEN as u64;
+    let num_mini_sectors = owners.mini_stream_sectors().len() as u64
+        * (sector_len / mini_sector_len);
+    let num_unused_mini_sectors = (0..num_mini_sectors as u32)
+        .filter(|&mini_sector| owners.mini_sector(mini_sector).is_none())
+        .count() as u64;
+    usage.unused_mini_stream_len = num_unused_mini_sectors * mini_sector_len;
+    Ok(usage)
+}
+
+//===========================================================================//
@@ -0,0 +1,737 @@
+use crate::internal::{
+    consts, path, Color, DirEntry, Header, ObjType, Timestamp, Version,
+};
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use uuid::Uuid;
+
+//===========================================================================//
+
+/// A node in the tree of storages and streams declared so far.
+struct Node<'a> {
+    name: String,
+    obj_type: ObjType,
+    /// The indices of this storage's children, in declaration order.
+    children: Vec<usize>,
+    stream_len: u64,
+    reader: Option<Box<dyn Read + 'a>>,
+    clsid: Uuid,
+    state_bits: u32,
+    /// The creation and modification times, if set explicitly.
+    timestamps: Option<(Timestamp, Timestamp)>,
+}
+
+impl<'a> Node<'a> {
+    fn new(
+        name: &str,
+        obj_type: ObjType,
+        stream_len: u64,
+        reader: Option<Box<dyn Read + 'a>>,
+    ) -> Node<'a> {
+        Node {
+            name: name.to_string(),
+            obj_type,
+            children: Vec::new(),
+            stream_len,
+            reader,
+            clsid: Uuid::nil(),
+            state_bits: 0,
+            timestamps: None,
+        }
+    }
+}
+
+/// Where each part of the file goes, in units of sectors (or of mini sectors
+/// for the mini stream).
+struct Layout {
+    num_sectors: u32,
+    mini_stream_start: u32,
+    mini_stream_len: u64,
+    num_mini_sectors: u32,
+    first_minifat_sector: u32,
+    num_minifat_sectors: u32,
+    first_dir_sector: u32,
+    num_dir_sectors: u32,
+    first_fat_sector: u32,
+    num_fat_sectors: u32,
+    first_difat_sector: u32,
+    num_difat_sectors: u32,
+}
+
+//===========================================================================//
+
+/// Writes a new compound file in a single forward pass, to a sink that only
+/// needs to implement `Write` (such as a socket, an HTTP response body, or an
+/// entry in an archive).
+///
+/// The tree of storages and streams is declared up front, with each stream's
+/// contents supplied as a reader along with its exact length.  Nothing is
+/// written until `finish()` is called, which lays out the whole file and then
+/// writes the header, the stream data, the mini stream, the MiniFAT, the
+/// directory, the FAT and the DIFAT, in that order, reading each stream's
+/// reader exactly once.  Only the file's metadata is held in memory.
+///
+/// ```
+/// use cfb::CfbWriter;
+/// let mut writer = CfbWriter::new(Vec::new());
+/// writer.create_storage("/foo").unwrap();
+/// writer.add_stream("/foo/bar", 5, &b"hello"[..]).unwrap();
+/// let data = writer.finish().unwrap();
+/// let comp = cfb::CompoundFile::open(std::io::Cursor::new(data)).unwrap();
+/// assert_eq!(comp.entry("/foo/bar").unwrap().len(), 5);
+/// ```
+pub struct CfbWriter<'a, W> {
+    sink: W,
+    version: Version,
+    /// All declared entries; the root storage is always the first.
+    nodes: Vec<Node<'a>>,
+    fixed_timestamp: Option<Timestamp>,
+}
+
+impl<'a, W: Write> CfbWriter<'a, W> {
+    /// Creates a writer for a new compound file with no contents.
+    pub fn new(sink: W) -> CfbWriter<'a, W> {
+        CfbWriter::with_version(Version::V4, sink)
+    }
+
+    /// Creates a writer for a new compound file of the given version with no
+    /// contents.
+    pub fn with_version(version: Version, sink: W) -> CfbWriter<'a, W> {
+        let root = Node::new(consts::ROOT_DIR_NAME, ObjType::Root, 0, None);
+        CfbWriter { sink, version, nodes: vec![root], fixed_timestamp: None }
+    }
+
+    /// Returns the CFB format version that will be written.
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    /// Returns the timestamp given to storages whose timestamps aren't set
+    /// explicitly, if one has been set.  See `set_fixed_timestamp` for
+    /// details.
+    pub fn fixed_timestamp(&self) -> Option<Timestamp> {
+        self.fixed_timestamp
+    }
+
+    /// Sets the creation and modified time given to storages whose
+    /// timestamps aren't set explicitly with `set_timestamps`.  By default
+    /// (or if this is set to `None`), they are given the time at which
+    /// `finish()` is called.  Setting a fixed timestamp makes the output
+    /// depend only on what was declared, so that the same declarations
+    /// always produce identical bytes.
+    pub fn set_fixed_timestamp(&mut self, timestamp: Option<Timestamp>) {
+        self.fixed_timestamp = timestamp;
+    }
+
+    /// Sets the CLSID for the storage object at the provided path, which
+    /// must already have been declared.  (Streams cannot have a CLSID.)
+    pub fn set_storage_clsid<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        clsid: Uuid,
+    ) -> io::Result<()> {
+        let node = self.node_mut(path.as_ref())?;
+        if node.obj_type == ObjType::Stream {
+            invalid_input!("Not a storage: {:?}", path.as_ref());
+        }
+        node.clsid = clsid;
+        Ok(())
+    }
+
+    /// Sets the user-defined bitflags for the object at the provided path,
+    /// which must already have been declared.
+    pub fn set_state_bits<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        bits: u32,
+    ) -> io::Result<()> {
+        self.node_mut(path.as_ref())?.state_bits = bits;
+        Ok(())
+    }
+
+    /// Sets the creation and modified times for the object at the provided
+    /// path, which must already have been declared.  Has no effect on
+    /// streams due to requirements imposed by CFB spec.
+    pub fn set_timestamps<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        created: Timestamp,
+        modified: Timestamp,
+    ) -> io::Result<()> {
+        let node = self.node_mut(path.as_ref())?;
+        if node.obj_type != ObjType::Stream {
+            node.timestamps = Some((created, modified));
+        }
+        Ok(())
+    }
+
+    /// Declares a new, empty storage object at the provided path.  The parent
+    /// storage object must already have been declared.
+    pub fn create_storage<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+    ) -> io::Result<()> {
+        self.add_node(path.as_ref(), ObjType::Storage, 0, None)
+    }
+
+    /// Declares a storage and all of its parent storages if they haven't
+    /// been declared yet.
+    pub fn create_storage_all<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+    ) -> io::Result<()> {
+        let names = path::name_chain_from_path(path.as_ref())?;
+        for length in 1..(names.len() + 1) {
+            match self.find(&names[..length]) {
+                Some(index)
+                    if self.nodes[index].obj_type != ObjType::Stream =>
+                {
+                    continue;
+                }
+                _ => {}
+            }
+            let prefix_path = path::path_from_name_chain(&names[..length]);
+            self.add_node(&prefix_path, ObjType::Storage, 0, None)?;
+        }
+        Ok(())
+    }
+
+    /// Declares a new stream object at the provided path, whose contents
+    /// will be the first `len` bytes read from `reader`.  The parent storage
+    /// object must already have been declared.  The reader isn't used until
+    /// `finish()` is called; if it runs out of data before supplying `len`
+    /// bytes, `finish()` will fail.
+    pub fn add_stream<P: AsRef<Path>, R: Read + 'a>(
+        &mut self,
+        path: P,
+        len: u64,
+        reader: R,
+    ) -> io::Result<()> {
+        if self.version == Version::V3 && len > u32::MAX as u64 {
+            invalid_input!(
+                "Stream length of {} bytes is too large for a version 3 \
+                 compound file",
+                len
+            );
+        }
+        self.add_node(
+            path.as_ref(),
+            ObjType::Stream,
+            len,
+            Some(Box::new(reader)),
+        )
+    }
+
+    fn find(&self, names: &[&str]) -> Option<usize> {
+        let mut index = 0;
+        for &name in names {
+            index = *self.nodes[index].children.iter().find(|&&child| {
+                path::compare_names(&self.nodes[child].name, name)
+                    == Ordering::Equal
+            })?;
+        }
+        Some(index)
+    }
+
+    fn node_mut(&mut self, path: &Path) -> io::Result<&mut Node<'a>> {
+        let names = path::name_chain_from_path(path)?;
+        match self.find(&names) {
+            Some(index) => Ok(&mut self.nodes[index]),
+            None => not_found!(
+                "No such object: {:?}",
+                path::path_from_name_chain(&names)
+            ),
+        }
+    }
+
+    fn add_node(
+        &mut self,
+        path: &Path,
+        obj_type: ObjType,
+        stream_len: u64,
+        reader: Option<Box<dyn Read + 'a>>,
+    ) -> io::Result<()> {
+        let mut names = path::name_chain_from_path(path)?;
+        let path = path::path_from_name_chain(&names);
+        if let Some(index) = self.find(&names) {
+            let existing = if self.nodes[index].obj_type == ObjType::Stream {
+                "stream"
+            } else {
+                "storage"
+            };
+            let new =
+                if obj_type == ObjType::Stream { "stream" } else { "storage" };
+            already_exists!(
+                "Cannot create {} at {:?} because a {} already exists there",
+                new,
+                path,
+                existing
+            );
+        }
+        // If names is empty, that means we're trying to create the root.  But
+        // the root always already exists and will have been rejected above.
+        debug_assert!(!names.is_empty());
+        let name = names.pop().unwrap();
+        path::validate_name(name)?;
+        let parent = match self.find(&names) {
+            Some(index) => index,
+            None => not_found!("Parent storage doesn't exist"),
+        };
+        if self.nodes[parent].obj_type == ObjType::Stream {
+            invalid_input!(
+                "Not a storage: {:?}",
+                path::path_from_name_chain(&names)
+            );
+        }
+        if self.nodes.len() > consts::MAX_REGULAR_STREAM_ID as usize {
+            invalid_input!("Too many entries in compound file");
+        }
+        let index = self.nodes.len();
+        self.nodes[parent].children.push(index);
+        self.nodes.push(Node::new(name, obj_type, stream_len, reader));
+        Ok(())
+    }
+
+    /// Writes out the compound file, and returns the sink.
+    pub fn finish(mut self) -> io::Result<W> {
+        let version = self.version;
+        let sector_len = version.sector_len();
+
+        // Number the entries in preorder, with each storage's children in
+        // CFB order.
+        for index in 0..self.nodes.len() {
+            let mut children = std::mem::take(&mut self.nodes[index].children);
+            children.sort_by(|&a, &b| {
+                path::compare_names(&self.nodes[a].name, &self.nodes[b].name)
+            });
+            self.nodes[index].children = children;
+        }
+        let mut order = Vec::with_capacity(self.nodes.len());
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            order.push(index);
+            stack.extend(self.nodes[index].children.iter().rev());
+        }
+        let mut stream_ids = vec![0u32; self.nodes.len()];
+        for (stream_id, &index) in order.iter().enumerate() {
+            stream_ids[index] = stream_id as u32;
+        }
+
+        let now = self.fixed_timestamp.unwrap_or_else(Timestamp::now);
+        let mut dir_entries: Vec<DirEntry> = order
+            .iter()
+            .map(|&index| {
+                let node = &self.nodes[index];
+                let timestamp = if node.obj_type == ObjType::Storage {
+                    now
+                } else {
+                    // 2.6.1 streams must have creation and modified time of 0
+                    Timestamp::zero()
+                };
+                let mut dir_entry =
+                    DirEntry::new(&node.name, node.obj_type, timestamp);
+                if let Some((created, modified)) = node.timestamps {
+                    dir_entry.creation_time = created;
+                    dir_entry.modified_time = modified;
+                }
+                dir_entry.clsid = node.clsid;
+                dir_entry.state_bits = node.state_bits;
+                dir_entry.stream_len = node.stream_len;
+                dir_entry
+            })
+            .collect();
+        for &index in order.iter() {
+            let children: Vec<u32> = self.nodes[index]
+                .children
+                .iter()
+                .map(|&child| stream_ids[child])
+                .collect();
+            let stream_id = stream_ids[index] as usize;
+            dir_entries[stream_id].child =
+                build_tree(&mut dir_entries, &children);
+        }
+
+        // Lay out the stream data.
+        let mut next_sector: u32 = 0;
+        let mut next_mini_sector: u32 = 0;
+        for dir_entry in dir_entries.iter_mut() {
+            if dir_entry.obj_type != ObjType::Stream
+                || dir_entry.stream_len == 0
+            {
+                continue;
+            }
+            if dir_entry.stream_len < consts::MINI_STREAM_CUTOFF as u64 {
+                dir_entry.start_sector = next_mini_sector;
+                next_mini_sector = add_sectors(
+                    next_mini_sector,
+                    dir_entry.stream_len,
+                    consts::MINI_SECTOR_LEN,
+                )?;
+            } else {
+                dir_entry.start_sector = next_sector;
+                next_sector = add_sectors(
+                    next_sector,
+                    dir_entry.stream_len,
+                    sector_len,
+                )?;
+            }
+        }
+        let layout = compute_layout(
+            version,
+            next_sector,
+            next_mini_sector,
+            dir_entries.len(),
+        )?;
+        let root_entry = &mut dir_entries[0];
+        root_entry.stream_len = layout.mini_stream_len;
+        root_entry.start_sector = if layout.mini_stream_len > 0 {
+            layout.mini_stream_start
+        } else {
+            consts::END_OF_CHAIN
+        };
+
+        // Write the header.
+        let mut header = Header {
+            version,
+            // 2.2 requires this to be zero in V3
+            num_dir_sectors: if version == Version::V3 {
+                0
+            } else {
+                layout.num_dir_sectors
+            },
+            num_fat_sectors: layout.num_fat_sectors,
+            first_dir_sector: layout.first_dir_sector,
+            first_minifat_sector: if layout.num_minifat_sectors > 0 {
+                layout.first_minifat_sector
+            } else {
+                consts::END_OF_CHAIN
+            },
+            num_minifat_sectors: layout.num_minifat_sectors,
+            first_difat_sector: if layout.num_difat_sectors > 0 {
+                layout.first_difat_sector
+            } else {
+                consts::END_OF_CHAIN
+            },
+            num_difat_sectors: layout.num_difat_sectors,
+            initial_difat_entries: [consts::FREE_SECTOR;
+                consts::NUM_DIFAT_ENTRIES_IN_HEADER],
+        };
+        for (index, entry) in
+            header.initial_difat_entries.iter_mut().enumerate()
+        {
+            if index < layout.num_fat_sectors as usize {
+                *entry = layout.first_fat_sector + index as u32;
+            }
+        }
+        let mut buffer = Vec::with_capacity(sector_len);
+        header.write_to(&mut buffer)?;
+        buffer.resize(sector_len, 0);
+        self.sink.write_all(&buffer)?;
+
+        // Write the stream data, followed by the mini stream.
+        for &mini in &[false, true] {
+            for &index in order.iter() {
+                let node = &mut self.nodes[index];
+                if node.obj_type != ObjType::Stream
+                    || node.stream_len == 0
+                    || (node.stream_len < consts::MINI_STREAM_CUTOFF as u64)
+                        != mini
+                {
+                    continue;
+                }
+                let reader: &mut dyn Read = node.reader.as_mut().unwrap();
+                let unit =
+                    if mini { consts::MINI_SECTOR_LEN } else { sector_len };
+                copy_stream(reader, &mut self.sink, node.stream_len, unit)
+                    .map_err(|error| {
+                        io::Error::new(
+                            error.kind(),
+                            format!(
+                                "Failed to write stream {:?}: {}",
+                                node.name, error
+                            ),
+                        )
+                    })?;
+            }
+        }
+        let mini_stream_padding = padding(layout.mini_stream_len, sector_len);
+        write_zeros(&mut self.sink, mini_stream_padding)?;
+
+        // Write the MiniFAT.
+        let mut minifat = Vec::with_capacity(layout.num_mini_sectors as usize);
+        for dir_entry in dir_entries.iter() {
+            if dir_entry.obj_type == ObjType::Stream
+                && dir_entry.stream_len > 0
+                && dir_entry.stream_len < consts::MINI_STREAM_CUTOFF as u64
+            {
+                let num_mini_sectors = dir_entry
+                    .stream_len
+                    .div_ceil(consts::MINI_SECTOR_LEN as u64)
+                    as u32;
+                push_chain(
+                    &mut minifat,
+                    dir_entry.start_sector,
+                    num_mini_sectors,
+                );
+            }
+        }
+        write_table(
+            &mut self.sink,
+            &minifat,
+            layout.num_minifat_sectors,
+            sector_len,
+        )?;
+
+        // Write the directory.
+        let mut buffer =
+            Vec::with_capacity(layout.num_dir_sectors as usize * sector_len);
+        for dir_entry in dir_entries.iter() {
+            dir_entry.write_to(&mut buffer)?;
+        }
+        while buffer.len() < layout.num_dir_sectors as usize * sector_len {
+            DirEntry::unallocated().write_to(&mut buffer)?;
+        }
+        self.sink.write_all(&buffer)?;
+
+        // Write the FAT.
+        let mut fat = Vec::with_capacity(layout.num_sectors as usize);
+        for dir_entry in dir_entries.iter() {
+            if dir_entry.obj_type == ObjType::Stream
+                && dir_entry.stream_len >= consts::MINI_STREAM_CUTOFF as u64
+            {
+                let num_sectors =
+                    dir_entry.stream_len.div_ceil(sector_len as u64) as u32;
+                push_chain(&mut fat, dir_entry.start_sector, num_sectors);
+            }
+        }
+        let num_mini_stream_sectors =
+            layout.mini_stream_len.div_ceil(sector_len as u64) as u32;
+        push_chain(
+            &mut fat,
+            layout.mini_stream_start,
+            num_mini_stream_sectors,
+        );
+        push_chain(
+            &mut fat,
+            layout.first_minifat_sector,
+            layout.num_minifat_sectors,
+        );
+        push_chain(&mut fat, layout.first_dir_sector, layout.num_dir_sectors);
+        fat.resize(
+            fat.len() + layout.num_fat_sectors as usize,
+            consts::FAT_SECTOR,
+        );
+        fat.resize(
+            fat.len() + layout.num_difat_sectors as usize,
+            consts::DIFAT_SECTOR,
+        );
+        debug_assert_eq!(fat.len(), layout.num_sectors as usize);
+        write_table(&mut self.sink, &fat, layout.num_fat_sectors, sector_len)?;
+
+        // Write the DIFAT.
+        let entries_per_difat_sector = sector_len / 4 - 1;
+        for difat_index in 0..layout.num_difat_sectors {
+            let mut entries: Vec<u32> = (0..entries_per_difat_sector)
+                .map(|index| {
+                    consts::NUM_DIFAT_ENTRIES_IN_HEADER
+                        + difat_index as usize * entries_per_difat_sector
+                        + index
+                })
+                .filter(|&index| index < layout.num_fat_sectors as usize)
+                .map(|index| layout.first_fat_sector + index as u32)
+                .collect();
+            entries.resize(entries_per_difat_sector, consts::FREE_SECTOR);
+            entries.push(if difat_index + 1 < layout.num_difat_sectors {
+                layout.first_difat_sector + difat_index + 1
+            } else {
+                consts::END_OF_CHAIN
+            });
+            write_table(&mut self.sink, &entries, 1, sector_len)?;
+        }
+
+        self.sink.flush()?;
+        Ok(self.sink)
+    }
+}
+
+//===========================================================================//
+
+/// Links the given sibling entries (which must already be in CFB order) into
+/// a red-black tree, and returns the ID of the tree's root (or `NO_STREAM` if
+/// there are no siblings).
+///
+/// The tree is built by repeatedly splitting at the midpoint, so that the
+/// depths of any two leaves differ by at most one; coloring just the nodes on
+/// the deepest level red then gives every path the same number of black
+/// nodes, without any two red nodes being adjacent.
+fn build_tree(dir_entries: &mut [DirEntry], siblings: &[u32]) -> u32 {
+    if siblings.is_empty() {
+        return consts::NO_STREAM;
+    }
+    let max_depth = siblings.len().ilog2();
+    build_subtree(dir_entries, siblings, 0, max_depth)
+}
+
+fn build_subtree(
+    dir_entries: &mut [DirEntry],
+    siblings: &[u32],
+    depth: u32,
+    max_depth: u32,
+) -> u32 {
+    if siblings.is_empty() {
+        return consts::NO_STREAM;
+    }
+    let middle = siblings.len() / 2;
+    let left =
+        build_subtree(dir_entries, &siblings[..middle], depth + 1, max_depth);
+    let right = build_subtree(
+        dir_entries,
+        &siblings[middle + 1..],
+        depth + 1,
+        max_depth,
+    );
+    let dir_entry = &mut dir_entries[siblings[middle] as usize];
+    dir_entry.left_sibling = left;
+    dir_entry.right_sibling = right;
+    dir_entry.color = if depth > 0 && depth == max_depth {
+        Color::Red
+    } else {
+        Color::Black
+    };
+    siblings[middle]
+}
+
+/// Returns the sector index following a run of sectors starting at `start`
+/// that holds `len` bytes.
+fn add_sectors(start: u32, len: u64, sector_len: usize) -> io::Result<u32> {
+    let num_sectors = len.div_ceil(sector_len as u64);
+    match u32::try_from(start as u64 + num_sectors) {
+        Ok(end) if end <= consts::MAX_REGULAR_SECTOR => Ok(end),
+        _ => invalid_input!("Compound file contents are too large"),
+    }
+}
+
+/// Decides where the mini stream and each table go, given the number of
+/// sectors and mini sectors of stream data, and the number of directory
+/// entries.
+fn compute_layout(
+    version: Version,
+    num_data_sectors: u32,
+    num_mini_sectors: u32,
+    num_dir_entries: usize,
+) -> io::Result<Layout> {
+    let sector_len = version.sector_len();
+    let mini_stream_len =
+        num_mini_sectors as u64 * consts::MINI_SECTOR_LEN as u64;
+    let mini_stream_start = num_data_sectors;
+    let first_minifat_sector =
+        add_sectors(mini_stream_start, mini_stream_len, sector_len)?;
+    let num_minifat_sectors =
+        (num_mini_sectors as u64 * 4).div_ceil(sector_len as u64) as u32;
+    let first_dir_sector = first_minifat_sector + num_minifat_sectors;
+    let num_dir_sectors =
+        num_dir_entries.div_ceil(version.dir_entries_per_sector()) as u32;
+    let first_fat_sector = add_sectors(
+        first_dir_sector,
+        num_dir_sectors as u64 * sector_len as u64,
+        sector_len,
+    )?;
+    // The FAT has to cover every sector, including its own sectors and
+    // those of the DIFAT, which in turn depends on the size of the FAT.
+    let entries_per_sector = (sector_len / 4) as u64;
+    let entries_per_difat_sector = entries_per_sector - 1;
+    let mut num_fat_sectors =
+        (first_fat_sector as u64).div_ceil(entries_per_sector).max(1);
+    loop {
+        let num_difat_sectors = (num_fat_sectors
+            .saturating_sub(consts::NUM_DIFAT_ENTRIES_IN_HEADER as u64))
+        .div_ceil(entries_per_difat_sector);
+        let num_sectors =
+            first_fat_sector as u64 + num_fat_sectors + num_difat_sectors;
+        if num_sectors > consts::MAX_REGULAR_SECTOR as u64 {
+            invalid_input!("Compound file contents are too large");
+        }
+        if num_sectors <= num_fat_sectors * entries_per_sector {
+            return Ok(Layout {
+                num_sectors: num_sectors as u32,
+                mini_stream_start,
+                mini_stream_len,
+                num_mini_sectors,
+                first_minifat_sector,
+                num_minifat_sectors,
+                first_dir_sector,
+                num_dir_sectors,
+                first_fat_sector,
+                num_fat_sectors: num_fat_sectors as u32,
+                first_difat_sector: first_fat_sector + num_fat_sectors as u32,
+                num_difat_sectors: num_difat_sectors as u32,
+            });
+        }
+        num_fat_sectors += 1;
+    }
+}
+
+/// Appends a chain of consecutive sectors to a FAT or MiniFAT.
+fn push_chain(table: &mut Vec<u32>, start: u32, len: u32) {
+    debug_assert!(len == 0 || table.len() == start as usize);
+    for index in 1..len {
+        table.push(start + index);
+    }
+    if len > 0 {
+        table.push(consts::END_OF_CHAIN);
+    }
+}
+
+/// Writes a FAT, MiniFAT or DIFAT, padded with free entries to fill the given
+/// number of sectors.
+fn write_table<W: Write>(
+    sink: &mut W,
+    table: &[u32],
+    num_sectors: u32,
+    sector_len: usize,
+) -> io::Result<()> {
+    let num_entries = num_sectors as usize * sector_len / 4;
+    debug_assert!(table.len() <= num_entries);
+    let mut buffer = Vec::with_capacity(sector_len);
+    for index in 0..num_entries {
+        let entry = table.get(index).copied().unwrap_or(consts::FREE_SECTOR);
+        buffer.extend_from_slice(&entry.to_le_bytes());
+        if buffer.len() == sector_len {
+            sink.write_all(&buffer)?;
+            buffer.clear();
+        }
+    }
+    Ok(())
+}
+
+/// Returns the number of bytes needed to pad `len` to a multiple of `unit`.
+fn padding(len: u64, unit: usize) -> u64 {
+    len.next_multiple_of(unit as u64) - len
+}
+
+fn write_zeros<W: Write>(sink: &mut W, len: u64) -> io::Result<()> {
+    io::copy(&mut io::repeat(0).take(len), sink)?;
+    Ok(())
+}
+
+/// Copies exactly `len` bytes from the reader to the sink, followed by enough
+/// zeros to pad the data to a multiple of `unit` bytes.
+fn copy_stream<W: Write>(
+    reader: &mut dyn Read,
+    sink: &mut W,
+    len: u64,
+    unit: usize,
+) -> io::Result<()> {
+    let copied = io::copy(&mut reader.take(len), sink)?;
+    if copied < len {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            format!("reader ended after {} of {} bytes", copied, len),
+        ));
+    }
+    write_zeros(sink, padding(len, unit))
+}
+
+//===========================================================================//
@@ -52,9 +52,10 @@ pub mod ffi;
 /// This module contains the actual C FFI implementations used by C++ wrappers.
 pub mod cffi;
 
+use std::borrow::Cow;
 use std::fmt;
 use std::fs;
-use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
 use std::mem::size_of;
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
@@ -65,9 +66,21 @@ use uuid::Uuid;
 use crate::internal::consts;
 use crate::internal::{
     Allocator, DirEntry, Directory, EntriesOrder, Header, MiniAllocator,
-    ObjType, SectorInit, Sectors, Timestamp, Validation,
+    ObjType, SectorInit, Sectors, Validation,
 };
-pub use crate::internal::{Entries, Entry, Stream, Version};
+
+pub use crate::internal::{
+    apply_patch, diff, merge, merge_with, AllocationPolicy, CfbBuilder,
+    CfbDiff, CfbPatch, CfbWriter, ChainSector, Color, DetachedEntries,
+    DirEntryInfo, Entries, Entry, EntryChange, EntrySpec, FatEntry, FileUsage,
+    FilterEntries, Find, Glob, HashAlgorithm, HashOptions, HeaderInfo,
+    MergeConflict, MergeReport, MergeResolution, MetadataField, PatchOp,
+    ReadAt, RecoveredStream, RecoveryConfidence, RecoveryReport, SlackKind,
+    SlackRegion, Spool, SpoolOptions, StorageUsage, Stream, StreamChain,
+    StreamOptions, Timestamp, Version, WalkOptions, WalkOrder,
+};
+#[cfg(feature = "async")]
+pub use crate::internal::{AsyncCompoundFile, AsyncStream};
 
 #[macro_use]
 mod internal;
@@ -107,6 +120,38 @@ fn create_with_path(path: &Path) -> io::Result<CompoundFile<fs::File>> {
     CompoundFile::create(file)
 }
 
+/// Reads a compound file from a non-seekable reader (such as a pipe or an
+/// HTTP response body), calling the visitor on each stream in turn, in
+/// preorder.
+///
+/// The input is read once, in order, using a `Spool` with the default
+/// options; to control how much of the input is kept in memory, open a
+/// `CompoundFile` on a `Spool` directly instead.
+///
+/// ```no_run
+/// use std::io::Read;
+/// cfb::read_all_streams(std::io::stdin(), |entry, stream| {
+///     let mut data = Vec::new();
+///     stream.read_to_end(&mut data)?;
+///     println!("{}: {} bytes", entry.path().display(), data.len());
+///     Ok(())
+/// })
+/// .unwrap();
+/// ```
+pub fn read_all_streams<R, V>(reader: R, mut visitor: V) -> io::Result<()>
+where
+    R: Read,
+    V: FnMut(&Entry, &mut Stream<Spool<R>>) -> io::Result<()>,
+{
+    let mut comp = CompoundFile::open(Spool::new(reader))?;
+    let streams: Vec<Entry> = comp.walk().filter(Entry::is_stream).collect();
+    for entry in streams {
+        let mut stream = comp.open_stream(entry.path())?;
+        visitor(&entry, &mut stream)?;
+    }
+    Ok(())
+}
+
 //===========================================================================//
 
 /// A compound file, backed by an underlying reader/writer (such as a
@@ -130,14 +175,86 @@ impl<F> CompoundFile<F> {
         self.minialloc().version()
     }
 
-    fn stream_id_for_name_chain(&self, names: &[&str]) -> Option<u32> {
-        self.minialloc().stream_id_for_name_chain(names)
+    /// Returns true if secure deletion is enabled for this compound file.
+    /// See `set_secure_delete` for details.
+    pub fn secure_delete(&self) -> bool {
+        self.minialloc().secure_delete()
+    }
+
+    /// Enables or disables secure deletion (which is disabled by default).
+    /// When enabled, sectors and mini sectors are overwritten with zeros as
+    /// they are freed (e.g. when a stream is removed, truncated, or moved
+    /// into or out of the mini stream), so that removed content does not
+    /// survive in the file.  Directory entries are always wiped when their
+    /// objects are removed, and the unused remainder of a stream's final
+    /// sector is always zeroed when the stream is truncated, regardless of
+    /// this setting.
+    ///
+    /// This only affects data freed from now on; to sanitize data that was
+    /// freed previously (possibly by other implementations), use `scrub`.
+    pub fn set_secure_delete(&mut self, secure_delete: bool) {
+        self.minialloc_mut().set_secure_delete(secure_delete);
+    }
+
+    /// Returns the strategy used to choose new sectors as streams (and other
+    /// chains) grow.  See `set_allocation_policy` for details.
+    pub fn allocation_policy(&self) -> AllocationPolicy {
+        self.minialloc().allocation_policy()
+    }
+
+    /// Sets the strategy used to choose new sectors as streams (and other
+    /// chains) grow.  The default is `AllocationPolicy::FirstFit`; use
+    /// `AllocationPolicy::Contiguous` to keep each stream's data together
+    /// when writing several streams at once.  Changing the policy does not
+    /// move any existing data.
+    pub fn set_allocation_policy(&mut self, policy: AllocationPolicy) {
+        self.minialloc_mut().set_allocation_policy(policy);
+    }
+
+    /// Returns the timestamp used in place of the current time, if any.  See
+    /// `set_fixed_timestamp` for details.
+    pub fn fixed_timestamp(&self) -> Option<Timestamp> {
+        self.minialloc().fixed_timestamp()
+    }
+
+    /// Sets a timestamp to use in place of the current time whenever one is
+    /// needed: as the creation and modified time of new storages, and by
+    /// `touch`.  By default (or if this is set to `None`), the actual current
+    /// time is used.  For reproducible builds, use the timestamp from
+    /// `Timestamp::from_source_date_epoch`, and serialize the result with
+    /// `write_canonical`.
+    pub fn set_fixed_timestamp(&mut self, timestamp: Option<Timestamp>) {
+        self.minialloc_mut().set_fixed_timestamp(timestamp);
+    }
+
+    fn stream_id_for_name_chain(
+        &self,
+        names: &[&str],
+    ) -> io::Result<Option<u32>> {
+        self.load_lazily(|minialloc| minialloc.load_path(names))?;
+        Ok(self.minialloc().stream_id_for_name_chain(names))
+    }
+
+    /// If the compound file was opened in lazy mode and its directory hasn't
+    /// been fully loaded yet, calls the given function to load part of it.
+    fn load_lazily<L>(&self, load: L) -> io::Result<()>
+    where
+        L: FnOnce(&mut MiniAllocator<F>) -> io::Result<()>,
+    {
+        if self.minialloc().is_lazy() {
+            load(&mut self.minialloc.write().unwrap())?;
+        }
+        Ok(())
     }
 
     /// Returns information about the root storage object.  This is equivalent
     /// to `self.entry("/").unwrap()` (but always succeeds).
     pub fn root_entry(&self) -> Entry {
-        Entry::new(self.minialloc().root_dir_entry(), PathBuf::from("/"))
+        Entry::new(
+            consts::ROOT_STREAM_ID,
+            self.minialloc().root_dir_entry(),
+            PathBuf::from("/"),
+        )
     }
 
     /// Given a path within the compound file, get information about that
@@ -149,11 +266,47 @@ impl<F> CompoundFile<F> {
     fn entry_with_path(&self, path: &Path) -> io::Result<Entry> {
         let names = internal::path::name_chain_from_path(path)?;
         let path = internal::path::path_from_name_chain(&names);
-        let stream_id = match self.stream_id_for_name_chain(&names) {
+        let stream_id = match self.stream_id_for_name_chain(&names)? {
             Some(stream_id) => stream_id,
             None => not_found!("No such object: {:?}", path),
         };
-        Ok(Entry::new(self.minialloc().dir_entry(stream_id), path))
+        Ok(Entry::new(stream_id, self.minialloc().dir_entry(stream_id), path))
+    }
+
+    /// Returns information about the object with the given stream ID (that
+    /// is, the index of its directory entry within the directory).  Returns
+    /// an error if there is no such entry, if the entry is unallocated, or if
+    /// the entry isn't reachable from the root storage.
+    pub fn dir_entry_by_id(&self, stream_id: u32) -> io::Result<Entry> {
+        self.load_lazily(|minialloc| minialloc.load_all())?;
+        let mut minialloc = self.minialloc.write().unwrap();
+        let num_dir_entries = minialloc.dir_entries()?.len();
+        if stream_id as usize >= num_dir_entries {
+            not_found!(
+                "No such stream ID: {} (directory has {} entries)",
+                stream_id,
+                num_dir_entries
+            );
+        }
+        let mut ancestor_ids = Vec::new();
+        let mut current_id = stream_id;
+        while current_id != consts::ROOT_STREAM_ID {
+            ancestor_ids.push(current_id);
+            current_id = match minialloc.parent_id(current_id)? {
+                Some(parent_id) => parent_id,
+                None => not_found!(
+                    "Stream ID {} is not reachable from the root storage",
+                    stream_id
+                ),
+            };
+        }
+        let names: Vec<&str> = ancestor_ids
+            .iter()
+            .rev()
+            .map(|&id| minialloc.dir_entry(id).name.as_str())
+            .collect();
+        let path = internal::path::path_from_name_chain(&names);
+        Ok(Entry::new(stream_id, minialloc.dir_entry(stream_id), path))
     }
 
     /// Returns an iterator over the entries within the root storage object.
@@ -183,10 +336,11 @@ impl<F> CompoundFile<F> {
     ) -> io::Result<Entries<'_, F>> {
         let names = internal::path::name_chain_from_path(path)?;
         let path = internal::path::path_from_name_chain(&names);
-        let stream_id = match self.stream_id_for_name_chain(&names) {
+        let stream_id = match self.stream_id_for_name_chain(&names)? {
             Some(stream_id) => stream_id,
             None => not_found!("No such storage: {:?}", path),
         };
+        self.load_lazily(|minialloc| minialloc.load_storage(stream_id))?;
         let start = {
             let minialloc = self.minialloc();
             let dir_entry = minialloc.dir_entry(stream_id);
@@ -210,8 +364,22 @@ impl<F> CompoundFile<F> {
     /// Returns an iterator over all entries within the compound file, starting
     /// from and including the root entry.  The iterator walks the storage tree
     /// in a preorder traversal.  This is equivalent to
-    /// `self.walk_storage("/").unwrap()` (but always succeeds).
+    /// `self.walk_storage("/")`, except that it cannot fail.
+    ///
+    /// # Panics
+    ///
+    /// For a compound file opened with `open_lazy()`, this first loads every
+    /// storage in the directory, and panics if any of them turns out to be
+    /// malformed (which `open()` would instead have reported as an error).
+    /// Code that reads untrusted files lazily should use `walk_storage("/")`
+    /// instead, which returns the error.
     pub fn walk(&self) -> Entries<'_, F> {
+        let root_id = consts::ROOT_STREAM_ID;
+        if let Err(error) =
+            self.load_lazily(|minialloc| minialloc.load_subtree(root_id))
+        {
+            panic!("Malformed directory: {}", error);
+        }
         Entries::new(
             EntriesOrder::Preorder,
             &self.minialloc,
@@ -227,36 +395,98 @@ impl<F> CompoundFile<F> {
         &self,
         path: P,
     ) -> io::Result<Entries<'_, F>> {
-        self.walk_storage_with_path(path.as_ref())
+        self.walk_storage_with_path(path.as_ref(), &WalkOptions::new())
+    }
+
+    /// Returns an iterator over the entries under a storage subtree, using
+    /// the given options to choose the traversal order, limit the depth,
+    /// and skip the starting entry, streams, or storages.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cfb::{WalkOptions, WalkOrder};
+    /// use std::io::Cursor;
+    /// let mut comp = cfb::CompoundFile::create(Cursor::new(Vec::new()))?;
+    /// comp.create_storage_all("/foo/bar")?;
+    /// comp.create_stream("/foo/bar/baz")?;
+    /// // Remove everything under /foo, deepest entries first.
+    /// let options = WalkOptions::new().with_order(WalkOrder::PostOrder);
+    /// let entries: Vec<_> = comp.walk_storage_with("/foo", &options)?.collect();
+    /// for entry in entries {
+    ///     if entry.is_stream() {
+    ///         comp.remove_stream(entry.path())?;
+    ///     } else {
+    ///         comp.remove_storage(entry.path())?;
+    ///     }
+    /// }
+    /// assert!(!comp.exists("/foo"));
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn walk_storage_with<P: AsRef<Path>>(
+        &self,
+        path: P,
+        options: &WalkOptions,
+    ) -> io::Result<Entries<'_, F>> {
+        self.walk_storage_with_path(path.as_ref(), options)
     }
 
     fn walk_storage_with_path(
         &self,
         path: &Path,
+        options: &WalkOptions,
     ) -> io::Result<Entries<'_, F>> {
         let mut names = internal::path::name_chain_from_path(path)?;
-        let stream_id = match self.stream_id_for_name_chain(&names) {
+        let stream_id = match self.stream_id_for_name_chain(&names)? {
             Some(stream_id) => stream_id,
             None => not_found!(
                 "No such object: {:?}",
                 internal::path::path_from_name_chain(&names)
             ),
         };
+        self.load_lazily(|minialloc| minialloc.load_subtree(stream_id))?;
         names.pop();
         let parent_path = internal::path::path_from_name_chain(&names);
-        Ok(Entries::new(
-            EntriesOrder::Preorder,
+        Ok(Entries::with_options(
             &self.minialloc,
             parent_path,
             stream_id,
+            options,
         ))
     }
 
+    /// Returns an iterator over all entries within the compound file whose
+    /// paths match the given glob pattern (see `Glob` for the syntax), in
+    /// the same order as `walk()`.  Storages that can't contain any matches
+    /// are skipped entirely.  Returns an error if the pattern is malformed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::Cursor;
+    /// let mut comp = cfb::CompoundFile::create(Cursor::new(Vec::new()))?;
+    /// comp.create_storage("/ObjectPool")?;
+    /// comp.create_storage("/ObjectPool/_1")?;
+    /// comp.create_stream("/ObjectPool/_1/\x01Ole10Native")?;
+    /// let found: Vec<_> =
+    ///     comp.find("/objectpool/*/\x01ole10native")?.collect();
+    /// assert_eq!(found.len(), 1);
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn find(&self, pattern: &str) -> io::Result<Find<'_, F>> {
+        let glob = Glob::new(pattern)?;
+        let entries =
+            self.walk_storage_with_path(Path::new("/"), &WalkOptions::new())?;
+        Ok(Find::new(entries, glob))
+    }
+
     /// Returns true if there is an existing stream or storage at the given
     /// path, or false if there is nothing at that path.
     pub fn exists<P: AsRef<Path>>(&self, path: P) -> bool {
         match internal::path::name_chain_from_path(path.as_ref()) {
-            Ok(names) => self.stream_id_for_name_chain(&names).is_some(),
+            Ok(names) => {
+                matches!(self.stream_id_for_name_chain(&names), Ok(Some(_)))
+            }
             Err(_) => false,
         }
     }
@@ -266,11 +496,11 @@ impl<F> CompoundFile<F> {
     pub fn is_stream<P: AsRef<Path>>(&self, path: P) -> bool {
         match internal::path::name_chain_from_path(path.as_ref()) {
             Ok(names) => match self.stream_id_for_name_chain(&names) {
-                Some(stream_id) => {
+                Ok(Some(stream_id)) => {
                     self.minialloc().dir_entry(stream_id).obj_type
                         == ObjType::Stream
                 }
-                None => false,
+                Ok(None) | Err(_) => false,
             },
             Err(_) => false,
         }
@@ -281,16 +511,74 @@ impl<F> CompoundFile<F> {
     pub fn is_storage<P: AsRef<Path>>(&self, path: P) -> bool {
         match internal::path::name_chain_from_path(path.as_ref()) {
             Ok(names) => match self.stream_id_for_name_chain(&names) {
-                Some(stream_id) => {
+                Ok(Some(stream_id)) => {
                     self.minialloc().dir_entry(stream_id).obj_type
                         != ObjType::Stream
                 }
-                None => false,
+                Ok(None) | Err(_) => false,
             },
             Err(_) => false,
         }
     }
 
+    /// Returns the entries of the FAT, indexed by sector number.  Any free
+    /// entries at the end of the FAT are omitted.
+    pub fn fat_entries(&self) -> Vec<FatEntry> {
+        let minialloc = self.minialloc();
+        let fat = minialloc.fat();
+        let len = fat
+            .iter()
+            .rposition(|&value| value != consts::FREE_SECTOR)
+            .map_or(0, |index| index + 1);
+        fat[..len].iter().map(|&value| FatEntry::from_value(value)).collect()
+    }
+
+    /// Returns the entries of the MiniFAT, indexed by mini sector number.
+    /// Any free entries at the end of the MiniFAT are omitted.
+    pub fn minifat_entries(&self) -> Vec<FatEntry> {
+        self.minialloc()
+            .minifat()
+            .iter()
+            .map(|&value| FatEntry::from_value(value))
+            .collect()
+    }
+
+    /// Returns the entries of the DIFAT (i.e. the sector numbers of the FAT
+    /// sectors, in order), including both the entries stored in the header
+    /// and those stored in DIFAT sectors.
+    pub fn difat_entries(&self) -> Vec<u32> {
+        self.minialloc().difat().to_vec()
+    }
+
+    /// Returns the sector numbers of the DIFAT sectors, in chain order.
+    pub fn difat_sector_ids(&self) -> Vec<u32> {
+        self.minialloc().difat_sector_ids().to_vec()
+    }
+
+    /// Lists the sectors (or, for a small stream, the mini sectors) holding
+    /// the data of the stream at the given path, along with their offsets
+    /// within the underlying file.  For the root storage (`"/"`), this lists
+    /// the sectors holding the mini stream.
+    pub fn stream_chain<P: AsRef<Path>>(
+        &self,
+        path: P,
+    ) -> io::Result<StreamChain> {
+        self.stream_chain_with_path(path.as_ref())
+    }
+
+    fn stream_chain_with_path(&self, path: &Path) -> io::Result<StreamChain> {
+        let names = internal::path::name_chain_from_path(path)?;
+        let path = internal::path::path_from_name_chain(&names);
+        let stream_id = match self.stream_id_for_name_chain(&names)? {
+            Some(stream_id) => stream_id,
+            None => not_found!("No such stream: {:?}", path),
+        };
+        if self.minialloc().dir_entry(stream_id).obj_type == ObjType::Storage {
+            invalid_input!("Not a stream: {:?}", path);
+        }
+        internal::inspect::stream_chain(&self.minialloc(), stream_id)
+    }
+
     // TODO: pub fn copy_stream
 
     // TODO: pub fn rename
@@ -314,20 +602,46 @@ impl<F: Seek> CompoundFile<F> {
         &mut self,
         path: P,
     ) -> io::Result<Stream<F>> {
-        self.open_stream_with_path(path.as_ref())
+        self.open_stream_with_path(path.as_ref(), &StreamOptions::new())
     }
 
-    fn open_stream_with_path(&mut self, path: &Path) -> io::Result<Stream<F>> {
+    /// Like `open_stream`, but with the given options.  For example, a
+    /// smaller buffer can be used to save memory when opening many small
+    /// streams at once, or a larger one to speed up bulk copies:
+    ///
+    /// ```no_run
+    /// use cfb::StreamOptions;
+    /// # let mut comp = cfb::open("path/to/cfb/file").unwrap();
+    /// let options = StreamOptions::new().with_buffer_size(0);
+    /// let stream = comp.open_stream_with_options("/foo", &options).unwrap();
+    /// ```
+    ///
+    /// With a buffer size of zero, all reads and writes go directly to the
+    /// underlying file, except that a buffer of the default size is still
+    /// allocated if the stream is used as a `BufRead`.
+    pub fn open_stream_with_options<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        options: &StreamOptions,
+    ) -> io::Result<Stream<F>> {
+        self.open_stream_with_path(path.as_ref(), options)
+    }
+
+    fn open_stream_with_path(
+        &self,
+        path: &Path,
+        options: &StreamOptions,
+    ) -> io::Result<Stream<F>> {
         let names = internal::path::name_chain_from_path(path)?;
         let path = internal::path::path_from_name_chain(&names);
-        let stream_id = match self.stream_id_for_name_chain(&names) {
+        let stream_id = match self.stream_id_for_name_chain(&names)? {
             Some(stream_id) => stream_id,
             None => not_found!("No such stream: {:?}", path),
         };
         if self.minialloc().dir_entry(stream_id).obj_type != ObjType::Stream {
             invalid_input!("Not a stream: {:?}", path);
         }
-        Ok(Stream::new(&self.minialloc, stream_id))
+        Ok(Stream::with_options(&self.minialloc, stream_id, options))
     }
 }
 
@@ -336,7 +650,7 @@ impl<F: Read + Seek> CompoundFile<F> {
     /// underlying reader also supports the `Write` trait, then the
     /// `CompoundFile` object will be writable as well.
     pub fn open(inner: F) -> io::Result<CompoundFile<F>> {
-        CompoundFile::open_internal(inner, Validation::Permissive)
+        CompoundFile::open_internal(inner, Validation::Permissive, false)
     }
 
     /// Like `open()`, but is stricter when parsing and will return an error if
@@ -345,12 +659,33 @@ impl<F: Read + Seek> CompoundFile<F> {
     /// implemention (such as this crate itself) to help ensure compatibility
     /// with other readers.
     pub fn open_strict(inner: F) -> io::Result<CompoundFile<F>> {
-        CompoundFile::open_internal(inner, Validation::Strict)
+        CompoundFile::open_internal(inner, Validation::Strict, false)
+    }
+
+    /// Like `open()`, but reads the directory lazily: only the root storage's
+    /// entries are read when the file is opened, and the entries of any other
+    /// storage are read (and validated) the first time that storage is
+    /// visited, for example by looking up a path within it.  This makes
+    /// opening files with very large directories much faster when only a few
+    /// of their objects are needed.
+    ///
+    /// Operations that need the whole directory (such as `walk()`, creating
+    /// new objects, or `analyze_slack()`) load and validate all of it first.
+    pub fn open_lazy(inner: F) -> io::Result<CompoundFile<F>> {
+        CompoundFile::open_internal(inner, Validation::Permissive, true)
+    }
+
+    /// Like `open_lazy()`, but with the stricter parsing of `open_strict()`.
+    /// Each part of the directory is held to the same rules as with
+    /// `open_strict()` when it is loaded.
+    pub fn open_lazy_strict(inner: F) -> io::Result<CompoundFile<F>> {
+        CompoundFile::open_internal(inner, Validation::Strict, true)
     }
 
     fn open_internal(
         mut inner: F,
         validation: Validation,
+        lazy: bool,
     ) -> io::Result<CompoundFile<F>> {
         let inner_len = inner.seek(SeekFrom::End(0))?;
         if inner_len < consts::HEADER_LEN as u64 {
@@ -514,8 +849,8 @@ impl<F: Read + Seek> CompoundFile<F> {
         let mut allocator =
             Allocator::new(sectors, difat_sector_ids, difat, fat, validation)?;
 
-        // Read in directory.
-        let mut dir_entries = Vec::<DirEntry>::new();
+        // Find the sectors of the directory chain.
+        let mut dir_sector_ids = Vec::<u32>::new();
         let mut seen_dir_sectors = FnvHashSet::default();
         let mut current_dir_sector = header.first_dir_sector;
         let mut dir_sector_count = 1;
@@ -550,9 +885,18 @@ impl<F: Read + Seek> CompoundFile<F> {
                 );
             }
             seen_dir_sectors.insert(current_dir_sector);
-            {
-                let mut sector =
-                    allocator.seek_to_sector(current_dir_sector)?;
+            dir_sector_ids.push(current_dir_sector);
+            current_dir_sector = allocator.next(current_dir_sector)?;
+            dir_sector_count += 1;
+        }
+
+        // Read in directory (or, in lazy mode, just the root storage).
+        let mut directory = if lazy && !dir_sector_ids.is_empty() {
+            Directory::new_lazy(allocator, dir_sector_ids, validation)?
+        } else {
+            let mut dir_entries = Vec::<DirEntry>::new();
+            for &sector_id in dir_sector_ids.iter() {
+                let mut sector = allocator.seek_to_sector(sector_id)?;
                 for _ in 0..header.version.dir_entries_per_sector() {
                     dir_entries.push(DirEntry::read_from(
                         &mut sector,
@@ -561,16 +905,13 @@ impl<F: Read + Seek> CompoundFile<F> {
                     )?);
                 }
             }
-            current_dir_sector = allocator.next(current_dir_sector)?;
-            dir_sector_count += 1;
-        }
-
-        let mut directory = Directory::new(
-            allocator,
-            dir_entries,
-            header.first_dir_sector,
-            validation,
-        )?;
+            Directory::new(
+                allocator,
+                dir_entries,
+                header.first_dir_sector,
+                validation,
+            )?
+        };
 
         // Read in MiniFAT.
         let minifat = {
@@ -606,6 +947,206 @@ impl<F: Read + Seek> CompoundFile<F> {
 
         Ok(CompoundFile { minialloc: Arc::new(RwLock::new(minialloc)) })
     }
+
+    /// Reads the raw field values of the compound file's header.
+    pub fn header(&self) -> io::Result<HeaderInfo> {
+        // Reading from the underlying file needs exclusive access to it.
+        internal::inspect::read_header(&mut self.minialloc.write().unwrap())
+    }
+
+    /// Scans the compound file for data left behind by deleted or orphaned
+    /// streams.  Directory entries that are marked unallocated but still have
+    /// a name and a stream length, as well as stream entries that are no
+    /// longer reachable from the root storage, are reconstructed as far as
+    /// possible, following stale FAT/MiniFAT links where they still exist and
+    /// assuming contiguous sectors where they don't.  The report also lists
+    /// free sectors and mini sectors that still contain non-zero data.
+    ///
+    /// Note that this crate itself clears directory entries when removing
+    /// objects, so this is mostly useful on files written by other
+    /// implementations.
+    pub fn recover_deleted(&mut self) -> io::Result<RecoveryReport> {
+        self.load_lazily(|minialloc| minialloc.load_all())?;
+        internal::recovery::scan(&mut self.minialloc_mut())
+    }
+
+    /// Finds every region of the compound file where data could be hidden
+    /// from a normal reader: the bytes past the logical end of each stream
+    /// within its final sector, free sectors and mini sectors, unused DIFAT
+    /// entries in the header, data appended after the last sector, and
+    /// reserved fields of the header and directory entries.  Each region is
+    /// reported with its offset and length within the underlying file, and
+    /// whether it contains any non-zero data.  The regions are returned in
+    /// order of offset.
+    pub fn analyze_slack(&mut self) -> io::Result<Vec<SlackRegion>> {
+        self.load_lazily(|minialloc| minialloc.load_all())?;
+        internal::slack::analyze(&mut self.minialloc_mut())
+    }
+
+    /// Adds up the space taken by the streams under the given path (which
+    /// may be a storage or a single stream): their total logical length,
+    /// how many of them are stored in regular sectors versus the mini
+    /// stream, how many sectors and mini sectors they occupy, and how many
+    /// of the occupied bytes lie past the ends of the streams.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::{Cursor, Write};
+    /// let mut comp = cfb::CompoundFile::create(Cursor::new(Vec::new()))?;
+    /// comp.create_storage("/foo")?;
+    /// comp.create_stream("/foo/small")?.write_all(&[1; 100])?;
+    /// comp.create_stream("/foo/big")?.write_all(&[2; 5000])?;
+    /// let usage = comp.usage("/foo")?;
+    /// assert_eq!(usage.logical_len(), 5100);
+    /// assert_eq!(usage.num_mini_streams(), 1);
+    /// assert_eq!(usage.num_mini_sectors(), 2);
+    /// assert_eq!(usage.num_regular_streams(), 1);
+    /// assert_eq!(usage.num_sectors(), 2); // 4096-byte sectors
+    /// assert_eq!(usage.slack_len(), 28 + 3192);
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn usage<P: AsRef<Path>>(&self, path: P) -> io::Result<StorageUsage> {
+        internal::usage::storage_usage(self, path.as_ref())
+    }
+
+    /// Breaks down the space taken up by the whole compound file into the
+    /// header, FAT, DIFAT, MiniFAT and directory sectors, the mini stream's
+    /// sectors, the sectors of larger streams, free and orphaned sectors,
+    /// and any data after the last sector that the FAT can describe.
+    pub fn file_usage(&self) -> io::Result<FileUsage> {
+        self.load_lazily(|minialloc| minialloc.load_all())?;
+        internal::usage::file_usage(&self.minialloc())
+    }
+
+    /// Computes a digest of the contents of the object at the given path,
+    /// using the given hash function.  For a stream, this covers the
+    /// stream's data; for a storage, it covers the names and contents of
+    /// everything within it, recursively (but not the storage's own name).
+    /// The digest depends only on this logical content, and not on how it
+    /// is laid out in the file, so two objects with equal contents hash the
+    /// same even if one of them has been compacted or rewritten.
+    ///
+    /// ```
+    /// use cfb::{CompoundFile, HashAlgorithm};
+    /// use std::io::{Cursor, Write};
+    /// let mut comp = CompoundFile::create(Cursor::new(Vec::new())).unwrap();
+    /// comp.create_storage("/a").unwrap();
+    /// comp.create_stream("/a/foo").unwrap().write_all(b"data").unwrap();
+    /// comp.create_storage("/b").unwrap();
+    /// comp.create_stream("/b/foo").unwrap().write_all(b"data").unwrap();
+    /// let hash = |path| {
+    ///     comp.hash_entry(path, HashAlgorithm::Fnv1a64).unwrap()
+    /// };
+    /// assert_eq!(hash("/a"), hash("/b"));
+    /// ```
+    pub fn hash_entry<P: AsRef<Path>>(
+        &self,
+        path: P,
+        algorithm: HashAlgorithm,
+    ) -> io::Result<Vec<u8>> {
+        let options = HashOptions::new().with_algorithm(algorithm);
+        internal::hash::hash_entry(self, path.as_ref(), &options)
+    }
+
+    /// Like `hash_entry`, but with the given options, which can also make
+    /// each entry's CLSID, state bits and/or timestamps part of the digest.
+    /// See `HashOptions` for details.
+    pub fn hash_entry_with_options<P: AsRef<Path>>(
+        &self,
+        path: P,
+        options: &HashOptions,
+    ) -> io::Result<Vec<u8>> {
+        internal::hash::hash_entry(self, path.as_ref(), options)
+    }
+
+    /// Writes a copy of this compound file to the given sink in a canonical
+    /// form, and returns the sink.  The copy has the same version, entries,
+    /// stream contents and attributes (including timestamps) as this file,
+    /// but its layout depends only on those: entries are numbered in sorted
+    /// order, each storage's children form a balanced tree, stream data is
+    /// stored contiguously in entry order, and all unused space is zeroed.
+    /// So two compound files with the same logical contents are always
+    /// written out as identical bytes, however they were edited.  (To keep
+    /// new storages' timestamps from making the contents differ, see
+    /// `set_fixed_timestamp`.)
+    pub fn write_canonical<W: Write>(&mut self, sink: W) -> io::Result<W> {
+        let mut writer = CfbWriter::with_version(self.version(), sink);
+        let entries: Vec<Entry> = self.walk_storage("/")?.collect();
+        // Every stream stays open until the writer finishes, so open them
+        // without a buffer to keep memory use from growing with their number.
+        let options = StreamOptions::new().with_buffer_size(0);
+        for entry in entries.iter() {
+            if entry.is_stream() {
+                let stream =
+                    self.open_stream_with_options(entry.path(), &options)?;
+                writer.add_stream(entry.path(), entry.len(), stream)?;
+            } else {
+                if !entry.is_root() {
+                    writer.create_storage(entry.path())?;
+                }
+                writer.set_storage_clsid(entry.path(), *entry.clsid())?;
+                writer.set_timestamps(
+                    entry.path(),
+                    entry.created_timestamp(),
+                    entry.modified_timestamp(),
+                )?;
+            }
+            writer.set_state_bits(entry.path(), entry.state_bits())?;
+        }
+        writer.finish()
+    }
+}
+
+impl<'a> CompoundFile<Cursor<&'a [u8]>> {
+    /// Opens an existing compound file held entirely in memory, such as a
+    /// byte buffer or a memory-mapped file.  The file is validated just as
+    /// with `open()`, and the resulting `CompoundFile` is read-only.  Stream
+    /// data can then be read without copying using `stream_bytes()`.
+    pub fn open_slice(data: &'a [u8]) -> io::Result<Self> {
+        CompoundFile::open(Cursor::new(data))
+    }
+
+    /// Like `open_slice()`, but uses the stricter parsing of
+    /// `open_strict()`.
+    pub fn open_slice_strict(data: &'a [u8]) -> io::Result<Self> {
+        CompoundFile::open_strict(Cursor::new(data))
+    }
+
+    /// Returns the contents of the stream at the given path.  If the stream's
+    /// sectors are contiguous within the file (which is typical for files
+    /// that were written sequentially), the returned data borrows directly
+    /// from the underlying buffer; otherwise, the stream's sectors are copied
+    /// into a new buffer.
+    pub fn stream_bytes<P: AsRef<Path>>(
+        &self,
+        path: P,
+    ) -> io::Result<Cow<'a, [u8]>> {
+        self.stream_bytes_with_path(path.as_ref())
+    }
+
+    fn stream_bytes_with_path(
+        &self,
+        path: &Path,
+    ) -> io::Result<Cow<'a, [u8]>> {
+        let names = internal::path::name_chain_from_path(path)?;
+        let path = internal::path::path_from_name_chain(&names);
+        let stream_id = match self.stream_id_for_name_chain(&names)? {
+            Some(stream_id) => stream_id,
+            None => not_found!("No such stream: {:?}", path),
+        };
+        let minialloc = self.minialloc();
+        let dir_entry = minialloc.dir_entry(stream_id);
+        if dir_entry.obj_type != ObjType::Stream {
+            invalid_input!("Not a stream: {:?}", path);
+        }
+        if dir_entry.stream_len == 0 {
+            return Ok(Cow::Borrowed(&[]));
+        }
+        let chain = internal::inspect::stream_chain(&minialloc, stream_id)?;
+        let file_data: &'a [u8] = minialloc.inner().get_ref();
+        internal::inspect::chain_data(file_data, &chain, dir_entry.stream_len)
+    }
 }
 
 impl<F: Read + Write + Seek> CompoundFile<F> {
@@ -696,7 +1237,7 @@ impl<F: Read + Write + Seek> CompoundFile<F> {
 
     fn create_storage_with_path(&mut self, path: &Path) -> io::Result<()> {
         let mut names = internal::path::name_chain_from_path(path)?;
-        if let Some(stream_id) = self.stream_id_for_name_chain(&names) {
+        if let Some(stream_id) = self.stream_id_for_name_chain(&names)? {
             let path = internal::path::path_from_name_chain(&names);
             if self.minialloc().dir_entry(stream_id).obj_type
                 != ObjType::Stream
@@ -718,10 +1259,11 @@ impl<F: Read + Write + Seek> CompoundFile<F> {
         // the root always already exists and will have been rejected above.
         debug_assert!(!names.is_empty());
         let name = names.pop().unwrap();
-        let parent_id = match self.stream_id_for_name_chain(&names) {
+        let parent_id = match self.stream_id_for_name_chain(&names)? {
             Some(stream_id) => stream_id,
             None => not_found!("Parent storage doesn't exist"),
         };
+        self.load_lazily(|minialloc| minialloc.load_all())?;
         self.minialloc_mut().insert_dir_entry(
             parent_id,
             name,
@@ -763,7 +1305,7 @@ impl<F: Read + Write + Seek> CompoundFile<F> {
 
     fn remove_storage_with_path(&mut self, path: &Path) -> io::Result<()> {
         let mut names = internal::path::name_chain_from_path(path)?;
-        let stream_id = match self.stream_id_for_name_chain(&names) {
+        let stream_id = match self.stream_id_for_name_chain(&names)? {
             Some(parent_id) => parent_id,
             None => not_found!("No such storage: {:?}", path),
         };
@@ -783,7 +1325,7 @@ impl<F: Read + Write + Seek> CompoundFile<F> {
         }
         debug_assert!(!names.is_empty());
         let name = names.pop().unwrap();
-        let parent_id = self.stream_id_for_name_chain(&names).unwrap();
+        let parent_id = self.stream_id_for_name_chain(&names)?.unwrap();
         self.minialloc_mut().remove_dir_entry(parent_id, name)?;
         Ok(())
     }
@@ -827,7 +1369,7 @@ impl<F: Read + Write + Seek> CompoundFile<F> {
         clsid: Uuid,
     ) -> io::Result<()> {
         let names = internal::path::name_chain_from_path(path)?;
-        let stream_id = match self.stream_id_for_name_chain(&names) {
+        let stream_id = match self.stream_id_for_name_chain(&names)? {
             Some(stream_id) => stream_id,
             None => not_found!(
                 "No such storage: {:?}",
@@ -872,7 +1414,7 @@ impl<F: Read + Write + Seek> CompoundFile<F> {
         overwrite: bool,
     ) -> io::Result<Stream<F>> {
         let mut names = internal::path::name_chain_from_path(path)?;
-        if let Some(stream_id) = self.stream_id_for_name_chain(&names) {
+        if let Some(stream_id) = self.stream_id_for_name_chain(&names)? {
             if self.minialloc().dir_entry(stream_id).obj_type
                 != ObjType::Stream
             {
@@ -897,10 +1439,11 @@ impl<F: Read + Write + Seek> CompoundFile<F> {
         // the root always already exists and will have been rejected above.
         debug_assert!(!names.is_empty());
         let name = names.pop().unwrap();
-        let parent_id = match self.stream_id_for_name_chain(&names) {
+        let parent_id = match self.stream_id_for_name_chain(&names)? {
             Some(stream_id) => stream_id,
             None => not_found!("Parent storage doesn't exist"),
         };
+        self.load_lazily(|minialloc| minialloc.load_all())?;
         let new_stream_id = self.minialloc_mut().insert_dir_entry(
             parent_id,
             name,
@@ -919,7 +1462,7 @@ impl<F: Read + Write + Seek> CompoundFile<F> {
 
     fn remove_stream_with_path(&mut self, path: &Path) -> io::Result<()> {
         let mut names = internal::path::name_chain_from_path(path)?;
-        let stream_id = match self.stream_id_for_name_chain(&names) {
+        let stream_id = match self.stream_id_for_name_chain(&names)? {
             Some(parent_id) => parent_id,
             None => not_found!("No such stream: {:?}", path),
         };
@@ -942,7 +1485,7 @@ impl<F: Read + Write + Seek> CompoundFile<F> {
         }
         debug_assert!(!names.is_empty());
         let name = names.pop().unwrap();
-        let parent_id = self.stream_id_for_name_chain(&names).unwrap();
+        let parent_id = self.stream_id_for_name_chain(&names)?.unwrap();
         self.minialloc_mut().remove_dir_entry(parent_id, name)?;
         Ok(())
     }
@@ -960,10 +1503,16 @@ impl<F: Read + Write + Seek> CompoundFile<F> {
         })
     }
 
-    /// Sets the modified time for the object at the given path to now.  Has no
-    /// effect when called on the root storage.
+    /// Sets the modified time for the object at the given path to now (or to
+    /// the fixed timestamp, if one has been set with `set_fixed_timestamp`).
+    /// Has no effect on streams due to requirements imposed by CFB spec.
     pub fn touch<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
-        self.set_modified_time(path, std::time::SystemTime::now())
+        let now = self.minialloc().now();
+        self.set_entry_with_path(path.as_ref(), |dir_entry| {
+            if dir_entry.obj_type != ObjType::Stream {
+                dir_entry.modified_time = now;
+            }
+        })
     }
 
     /// Sets the modified time for the object at the given path.
@@ -1001,7 +1550,7 @@ impl<F: Read + Write + Seek> CompoundFile<F> {
     ) -> io::Result<()> {
         let names = internal::path::name_chain_from_path(path)?;
         let path = internal::path::path_from_name_chain(&names);
-        let stream_id = match self.stream_id_for_name_chain(&names) {
+        let stream_id = match self.stream_id_for_name_chain(&names)? {
             Some(stream_id) => stream_id,
             None => not_found!("No such object: {:?}", path),
         };
@@ -1009,6 +1558,18 @@ impl<F: Read + Write + Seek> CompoundFile<F> {
         Ok(())
     }
 
+    /// Sanitizes the compound file by overwriting every region reported by
+    /// `analyze_slack` that contains non-zero data with the filler that a
+    /// well-behaved writer would leave there (zeros, except for unused header
+    /// DIFAT entries and unallocated directory entries).  This erases the
+    /// contents of free sectors and mini sectors, slack past the ends of
+    /// streams, stale unallocated directory entries, and data appended to the
+    /// end of the file (although the file's length is left unchanged).
+    pub fn scrub(&mut self) -> io::Result<()> {
+        self.load_lazily(|minialloc| minialloc.load_all())?;
+        internal::slack::scrub(&mut self.minialloc_mut())
+    }
+
     /// Flushes all changes to the underlying file.
     pub fn flush(&mut self) -> io::Result<()> {
         self.minialloc_mut().flush()
@@ -1056,11 +1617,13 @@ impl<T: Write> WriteLeNumber for T {}
 
 #[cfg(test)]
 mod tests {
-    use std::io::{self, Cursor, Seek, SeekFrom};
+    use std::io::{self, Cursor, Seek, SeekFrom, Write};
     use std::mem::size_of;
     use std::path::Path;
 
-    use crate::internal::{consts, DirEntry, Header, Version};
+    use crate::internal::{
+        consts, AllocationPolicy, DirEntry, Header, Version,
+    };
     use crate::{ReadLeNumber, WriteLeNumber};
 
     use super::CompoundFile;
@@ -1279,6 +1842,50 @@ mod tests {
         assert_eq!(Timestamp::from_system_time(entry.created()), ts);
         assert_eq!(Timestamp::from_system_time(entry.modified()), ts);
     }
+
+    /// Grows two streams in alternation, a megabyte at a time, and returns
+    /// how many FAT entries were examined while writing each megabyte.
+    fn fat_visits_per_megabyte(policy: AllocationPolicy) -> Vec<u64> {
+        let cursor = Cursor::new(Vec::new());
+        let mut comp =
+            CompoundFile::create_with_version(Version::V3, cursor).unwrap();
+        comp.set_allocation_policy(policy);
+        let mut first = comp.create_stream("/first").unwrap();
+        let mut second = comp.create_stream("/second").unwrap();
+        let chunk = vec![0x5a; 1 << 20];
+        let mut visits = Vec::new();
+        for _ in 0..8 {
+            let before = comp.minialloc().fat_visits();
+            for piece in chunk.chunks(64 * 1024) {
+                first.write_all(piece).unwrap();
+                second.write_all(&piece[..1024]).unwrap();
+            }
+            first.flush().unwrap();
+            second.flush().unwrap();
+            visits.push(comp.minialloc().fat_visits() - before);
+        }
+        visits
+    }
+
+    #[test]
+    fn stream_growth_does_not_rescan_fat() {
+        // Writing each further megabyte should examine about as many FAT
+        // entries as writing the second one did (the first also includes
+        // moving the streams out of the mini stream), no matter how long
+        // the chains already are.
+        for policy in
+            [AllocationPolicy::FirstFit, AllocationPolicy::Contiguous]
+        {
+            let visits = fat_visits_per_megabyte(policy);
+            let limit = visits[1] + visits[1] / 4;
+            assert!(
+                visits[2..].iter().all(|&count| count <= limit),
+                "{:?}: {:?}",
+                policy,
+                visits
+            );
+        }
+    }
 }
 
 //===========================================================================//
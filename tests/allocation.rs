@@ -0,0 +1,126 @@
+use cfb::{AllocationPolicy, CompoundFile};
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+
+//===========================================================================//
+
+const CHUNK_LEN: usize = 10_000;
+const NUM_CHUNKS: usize = 50;
+
+fn chunk(stream_index: usize, chunk_index: usize) -> Vec<u8> {
+    vec![(stream_index * NUM_CHUNKS + chunk_index) as u8; CHUNK_LEN]
+}
+
+/// Writes three streams, a chunk at a time, in alternation.
+fn write_interleaved(comp: &mut CompoundFile<Cursor<Vec<u8>>>) {
+    let paths = ["/a", "/b", "/c"];
+    for path in paths.iter() {
+        comp.create_stream(path).unwrap();
+    }
+    for chunk_index in 0..NUM_CHUNKS {
+        for (stream_index, path) in paths.iter().enumerate() {
+            let mut stream = comp.open_stream(path).unwrap();
+            stream.seek(SeekFrom::End(0)).unwrap();
+            stream.write_all(&chunk(stream_index, chunk_index)).unwrap();
+        }
+    }
+}
+
+/// Returns the number of places where the stream's chain jumps to a sector
+/// other than the next one.
+fn num_discontinuities(
+    comp: &CompoundFile<Cursor<Vec<u8>>>,
+    path: &str,
+) -> usize {
+    let chain = comp.stream_chain(path).unwrap();
+    chain
+        .sectors()
+        .windows(2)
+        .filter(|pair| pair[1].sector_id() != pair[0].sector_id() + 1)
+        .count()
+}
+
+fn check_contents(comp: CompoundFile<Cursor<Vec<u8>>>) {
+    let cursor = comp.into_inner();
+    let mut comp = CompoundFile::open_strict(cursor).unwrap();
+    for (stream_index, path) in ["/a", "/b", "/c"].iter().enumerate() {
+        let mut actual = Vec::new();
+        comp.open_stream(path).unwrap().read_to_end(&mut actual).unwrap();
+        let expected: Vec<u8> = (0..NUM_CHUNKS)
+            .flat_map(|chunk_index| chunk(stream_index, chunk_index))
+            .collect();
+        assert_eq!(actual, expected);
+    }
+    assert!(comp.analyze_slack().unwrap().iter().all(|r| !r.is_nonzero()));
+}
+
+//===========================================================================//
+
+#[test]
+fn first_fit_interleaves_chains() {
+    let mut comp = CompoundFile::create(Cursor::new(Vec::new())).unwrap();
+    assert_eq!(comp.allocation_policy(), AllocationPolicy::FirstFit);
+    write_interleaved(&mut comp);
+    assert!(num_discontinuities(&comp, "/a") > 20);
+    check_contents(comp);
+}
+
+#[test]
+fn contiguous_policy_keeps_chains_together() {
+    let mut comp = CompoundFile::create(Cursor::new(Vec::new())).unwrap();
+    comp.set_allocation_policy(AllocationPolicy::Contiguous);
+    assert_eq!(comp.allocation_policy(), AllocationPolicy::Contiguous);
+    write_interleaved(&mut comp);
+    // Each stream is about 1000 sectors long, so with run lengths doubling
+    // from 16 sectors, each chain should be made of only a handful of runs.
+    for path in ["/a", "/b", "/c"].iter() {
+        assert!(num_discontinuities(&comp, path) <= 8);
+    }
+    check_contents(comp);
+}
+
+#[test]
+fn reserve_stream() {
+    let mut comp = CompoundFile::create(Cursor::new(Vec::new())).unwrap();
+    write_interleaved(&mut comp);
+    let mut stream = comp.create_stream("/d").unwrap();
+    stream.reserve(1_000_000).unwrap();
+    assert_eq!(stream.len(), 0);
+    drop(stream);
+    let mut stream = comp.create_stream("/e").unwrap();
+    stream.reserve(1_000_000).unwrap();
+    drop(stream);
+    for chunk_index in 0..100 {
+        for path in ["/d", "/e"].iter() {
+            let mut stream = comp.open_stream(path).unwrap();
+            stream.seek(SeekFrom::End(0)).unwrap();
+            stream.write_all(&[chunk_index as u8; CHUNK_LEN]).unwrap();
+        }
+    }
+    // The reservation can only take effect once the stream is too big for
+    // the mini stream, so the start of each chain may still be interleaved.
+    for path in ["/d", "/e"].iter() {
+        assert!(num_discontinuities(&comp, path) <= 2);
+    }
+    check_contents(comp);
+}
+
+#[test]
+fn reserve_then_shrink() {
+    for &policy in &[AllocationPolicy::FirstFit, AllocationPolicy::Contiguous]
+    {
+        let mut comp = CompoundFile::create(Cursor::new(Vec::new())).unwrap();
+        comp.set_allocation_policy(policy);
+        comp.set_secure_delete(true);
+        let mut stream = comp.create_stream("/big").unwrap();
+        stream.write_all(&[1; 5000]).unwrap();
+        stream.reserve(200_000).unwrap();
+        stream.set_len(100).unwrap();
+        stream.write_all(&[2; 300]).unwrap();
+        drop(stream);
+        write_interleaved(&mut comp);
+        comp.remove_stream("/big").unwrap();
+        check_contents(comp);
+    }
+}
+
+//===========================================================================//
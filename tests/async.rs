@@ -0,0 +1,174 @@
+#![cfg(feature = "async")]
+
+use cfb::{AsyncCompoundFile, CompoundFile, Version};
+use std::io::{self, Cursor, Read, SeekFrom, Write};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+
+//===========================================================================//
+
+fn stream_data(seed: usize, len: usize) -> Vec<u8> {
+    (0..len).map(|i| ((i * 5 + seed) % 251) as u8).collect()
+}
+
+/// Creates a compound file (synchronously) that spans several pages of the
+/// async page cache.
+fn make_cfb() -> Vec<u8> {
+    let mut comp = CompoundFile::create(Cursor::new(Vec::new())).unwrap();
+    comp.create_storage("/dir").unwrap();
+    comp.create_stream("/small")
+        .unwrap()
+        .write_all(&stream_data(1, 100))
+        .unwrap();
+    comp.create_stream("/dir/large")
+        .unwrap()
+        .write_all(&stream_data(2, 300000))
+        .unwrap();
+    comp.flush().unwrap();
+    comp.into_inner().into_inner()
+}
+
+fn read_sync(data: Vec<u8>, path: &str) -> Vec<u8> {
+    let mut comp = CompoundFile::open_strict(Cursor::new(data)).unwrap();
+    let mut buffer = Vec::new();
+    comp.open_stream(path).unwrap().read_to_end(&mut buffer).unwrap();
+    buffer
+}
+
+//===========================================================================//
+
+#[tokio::test]
+async fn open_and_read() {
+    let cursor = Cursor::new(make_cfb());
+    let mut comp = AsyncCompoundFile::open_strict(cursor).await.unwrap();
+    assert_eq!(comp.version(), Version::V4);
+    assert!(comp.is_storage("/dir"));
+    assert!(comp.is_stream("/dir/large"));
+    assert!(!comp.exists("/missing"));
+    let names: Vec<String> =
+        comp.walk().map(|entry| entry.path().display().to_string()).collect();
+    assert_eq!(names, vec!["/", "/dir", "/dir/large", "/small"]);
+    assert_eq!(comp.entry("/dir/large").unwrap().len(), 300000);
+
+    let mut data = Vec::new();
+    let mut stream = comp.open_stream("/dir/large").unwrap();
+    stream.read_to_end(&mut data).await.unwrap();
+    assert_eq!(data, stream_data(2, 300000));
+    let mut data = Vec::new();
+    let mut stream = comp.open_stream("/small").unwrap();
+    stream.read_to_end(&mut data).await.unwrap();
+    assert_eq!(data, stream_data(1, 100));
+}
+
+#[tokio::test]
+async fn seek_within_stream() {
+    let cursor = Cursor::new(make_cfb());
+    let mut comp = AsyncCompoundFile::open(cursor).await.unwrap();
+    let expected = stream_data(2, 300000);
+    let mut stream = comp.open_stream("/dir/large").unwrap();
+    let mut buffer = vec![0; 1000];
+    for &offset in &[250000, 5, 131070] {
+        let position = stream.seek(SeekFrom::Start(offset)).await.unwrap();
+        assert_eq!(position, offset);
+        stream.read_exact(&mut buffer).await.unwrap();
+        let offset = offset as usize;
+        assert_eq!(buffer, &expected[offset..offset + 1000]);
+    }
+    let position = stream.seek(SeekFrom::End(-10)).await.unwrap();
+    assert_eq!(position, 299990);
+    assert!(stream.seek(SeekFrom::Current(100)).await.is_err());
+}
+
+#[tokio::test]
+async fn create_and_write() {
+    let cursor = Cursor::new(Vec::new());
+    let mut comp = AsyncCompoundFile::create_with_version(Version::V3, cursor)
+        .await
+        .unwrap();
+    comp.create_storage_all("/a/b").await.unwrap();
+    let mut stream = comp.create_stream("/a/b/data").await.unwrap();
+    stream.write_all(&stream_data(3, 200000)).await.unwrap();
+    stream.flush().await.unwrap();
+    assert_eq!(stream.len(), 200000);
+    drop(stream);
+    comp.create_stream("/removed").await.unwrap();
+    comp.remove_stream("/removed").await.unwrap();
+    comp.flush().await.unwrap();
+    let data = comp.into_inner().into_inner();
+    assert_eq!(read_sync(data, "/a/b/data"), stream_data(3, 200000));
+}
+
+#[tokio::test]
+async fn modify_existing_file() {
+    let cursor = Cursor::new(make_cfb());
+    let mut comp = AsyncCompoundFile::open(cursor).await.unwrap();
+    {
+        let mut stream = comp.open_stream("/dir/large").unwrap();
+        stream.seek(SeekFrom::Start(70000)).await.unwrap();
+        stream.write_all(b"hello").await.unwrap();
+    }
+    comp.remove_stream("/small").await.unwrap();
+    comp.create_stream("/new").await.unwrap().write_all(b"new").await.unwrap();
+    comp.flush().await.unwrap();
+    let data = comp.into_inner().into_inner();
+    assert_eq!(read_sync(data.clone(), "/new"), b"new");
+    let mut expected = stream_data(2, 300000);
+    expected[70000..70005].copy_from_slice(b"hello");
+    assert_eq!(read_sync(data.clone(), "/dir/large"), expected);
+    let comp = CompoundFile::open_strict(Cursor::new(data)).unwrap();
+    assert!(!comp.exists("/small"));
+}
+
+#[tokio::test]
+async fn unflushed_changes_are_not_written() {
+    let original = make_cfb();
+    let cursor = Cursor::new(original.clone());
+    let mut comp = AsyncCompoundFile::open(cursor).await.unwrap();
+    comp.create_stream("/new").await.unwrap().write_all(b"x").await.unwrap();
+    assert!(comp.is_stream("/new"));
+    assert_eq!(comp.into_inner().into_inner(), original);
+}
+
+#[tokio::test]
+async fn tokio_file_round_trip() -> io::Result<()> {
+    let path = std::env::temp_dir()
+        .join(format!("cfb-async-test-{}.cfb", std::process::id()));
+    let file = tokio::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&path)
+        .await?;
+    let mut comp = AsyncCompoundFile::create(file).await?;
+    let mut stream = comp.create_stream("/foo").await?;
+    stream.write_all(&stream_data(4, 100000)).await?;
+    stream.flush().await?;
+    drop(stream);
+    drop(comp);
+
+    let file = tokio::fs::File::open(&path).await?;
+    let mut comp = AsyncCompoundFile::open_strict(file).await?;
+    let mut data = Vec::new();
+    comp.open_stream("/foo")?.read_to_end(&mut data).await?;
+    assert_eq!(data, stream_data(4, 100000));
+    drop(comp);
+    std::fs::remove_file(&path)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn open_invalid_file() {
+    let cursor = Cursor::new(vec![0u8; 1000]);
+    assert!(AsyncCompoundFile::open(cursor).await.is_err());
+    let mut data = make_cfb();
+    data.truncate(70000);
+    let cursor = Cursor::new(data);
+    let result = AsyncCompoundFile::open(cursor).await;
+    if let Ok(mut comp) = result {
+        let mut stream = comp.open_stream("/dir/large").unwrap();
+        let mut buffer = Vec::new();
+        assert!(stream.read_to_end(&mut buffer).await.is_err());
+    }
+}
+
+//===========================================================================//
@@ -1,4 +1,6 @@
-use cfb::{CompoundFile, Entry, Version};
+use cfb::{
+    Color, CompoundFile, Entry, Timestamp, Version, WalkOptions, WalkOrder,
+};
 use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
 use std::path::Path;
 use uuid::Uuid;
@@ -161,6 +163,121 @@ fn walk_directory_tree() {
     assert_eq!(walk_to_vec(&entries), vec![Path::new("/baz")]);
 }
 
+#[test]
+fn walk_directory_tree_with_options() {
+    let cursor = Cursor::new(Vec::new());
+    let mut comp = CompoundFile::create(cursor).expect("create");
+    comp.create_storage("/foo").unwrap();
+    comp.create_stream("/baz").unwrap();
+    comp.create_storage("/foo/bar").unwrap();
+    comp.create_stream("/foo/bar/quux").unwrap();
+    comp.create_stream("/foo/x").unwrap();
+    let walk = |options: WalkOptions| -> Vec<Entry> {
+        comp.walk_storage_with("/", &options).unwrap().collect()
+    };
+    let entries = walk(WalkOptions::new().with_order(WalkOrder::PostOrder));
+    assert_eq!(
+        walk_to_vec(&entries),
+        vec![
+            Path::new("/baz"),
+            Path::new("/foo/x"),
+            Path::new("/foo/bar/quux"),
+            Path::new("/foo/bar"),
+            Path::new("/foo"),
+            Path::new("/")
+        ]
+    );
+    let entries = walk(
+        WalkOptions::new()
+            .with_order(WalkOrder::BreadthFirst)
+            .with_root(false),
+    );
+    assert_eq!(
+        walk_to_vec(&entries),
+        vec![
+            Path::new("/baz"),
+            Path::new("/foo"),
+            Path::new("/foo/x"),
+            Path::new("/foo/bar"),
+            Path::new("/foo/bar/quux")
+        ]
+    );
+    let entries =
+        walk(WalkOptions::new().with_max_depth(2).with_streams(false));
+    assert_eq!(
+        walk_to_vec(&entries),
+        vec![Path::new("/"), Path::new("/foo"), Path::new("/foo/bar")]
+    );
+    let options = WalkOptions::new().with_root(false);
+    let entries: Vec<Entry> =
+        comp.walk_storage_with("/foo/bar", &options).unwrap().collect();
+    assert_eq!(walk_to_vec(&entries), vec![Path::new("/foo/bar/quux")]);
+}
+
+#[test]
+fn rewrite_streams_while_walking() {
+    let cursor = Cursor::new(Vec::new());
+    let mut comp = CompoundFile::create(cursor).expect("create");
+    comp.create_storage("/foo").unwrap();
+    comp.create_stream("/foo/bar").unwrap().write_all(b"bar").unwrap();
+    comp.create_stream("/baz").unwrap().write_all(&[1; 5000]).unwrap();
+    let mut paths = Vec::new();
+    for entry in comp.walk().detach() {
+        let entry = entry.unwrap();
+        if entry.is_stream() {
+            let mut data = Vec::new();
+            let mut stream = comp.open_stream(entry.path()).unwrap();
+            stream.read_to_end(&mut data).unwrap();
+            data.reverse();
+            data.push(b'!');
+            comp.create_stream(entry.path())
+                .unwrap()
+                .write_all(&data)
+                .unwrap();
+            comp.set_state_bits(entry.path(), 7).unwrap();
+        }
+        paths.push(entry.path().to_path_buf());
+    }
+    assert_eq!(
+        paths,
+        vec![
+            Path::new("/"),
+            Path::new("/baz"),
+            Path::new("/foo"),
+            Path::new("/foo/bar")
+        ]
+    );
+    let mut data = Vec::new();
+    comp.open_stream("/foo/bar").unwrap().read_to_end(&mut data).unwrap();
+    assert_eq!(data, b"rab!");
+    assert_eq!(comp.entry("/baz").unwrap().len(), 5001);
+    assert_eq!(comp.entry("/baz").unwrap().state_bits(), 7);
+}
+
+#[test]
+fn detached_entries_after_structural_change() {
+    let cursor = Cursor::new(Vec::new());
+    let mut comp = CompoundFile::create(cursor).expect("create");
+    comp.create_stream("/foo").unwrap();
+    comp.create_stream("/bar").unwrap();
+    let mut entries = comp.read_root_storage().detach();
+    assert_eq!(entries.next().unwrap().unwrap().name(), "bar");
+    comp.create_stream("/baz").unwrap();
+    let error = entries.next().unwrap().unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::Other);
+    assert!(entries.next().is_none());
+
+    let mut entries = comp.walk().detach();
+    comp.remove_stream("/baz").unwrap();
+    assert!(entries.next().unwrap().is_err());
+
+    let entries = comp.walk().detach();
+    drop(comp);
+    let results: Vec<io::Result<Entry>> = entries.collect();
+    assert_eq!(results.len(), 1);
+    assert!(results[0].is_err());
+}
+
 #[test]
 #[should_panic(expected = "Not a storage: \\\"/foo\\\"")]
 fn read_storage_on_stream() {
@@ -179,6 +296,87 @@ fn open_stream_on_storage() {
     comp.open_stream("/foo").unwrap();
 }
 
+//===========================================================================//
+// Tests for raw directory entry details:
+
+#[test]
+fn raw_dir_entry_details() {
+    let cursor = Cursor::new(Vec::new());
+    let mut comp = CompoundFile::create(cursor).expect("create");
+    comp.create_storage("/foo").unwrap();
+    comp.create_stream("/baz").unwrap().write_all(&[1; 100]).unwrap();
+    comp.create_stream("/foo/bar").unwrap().write_all(&[2; 100]).unwrap();
+
+    let cursor = comp.into_inner();
+    let comp = CompoundFile::open_strict(cursor).expect("open");
+    let root = comp.root_entry();
+    assert_eq!(root.raw().stream_id(), 0);
+    assert_eq!(root.raw().child(), 1);
+    assert_eq!(root.raw().left_sibling(), 0xffffffff);
+    assert_eq!(root.raw().right_sibling(), 0xffffffff);
+
+    let foo = comp.entry("/foo").unwrap();
+    assert_eq!(foo.raw().stream_id(), 1);
+    assert_eq!(foo.raw().color(), Color::Black);
+    assert_eq!(foo.raw().left_sibling(), 2);
+    assert_eq!(foo.raw().right_sibling(), 0xffffffff);
+    assert_eq!(foo.raw().child(), 3);
+    assert_eq!(foo.raw().start_sector(), 0);
+
+    let baz = comp.entry("/baz").unwrap();
+    assert_eq!(baz.raw().stream_id(), 2);
+    assert_eq!(baz.raw().child(), 0xffffffff);
+    assert_eq!(baz.raw().start_sector(), 0);
+    let bar = comp.entry("/foo/bar").unwrap();
+    assert_eq!(bar.raw().stream_id(), 3);
+    assert_eq!(bar.raw().start_sector(), 2);
+}
+
+#[test]
+fn dir_entry_by_id() {
+    let cursor = Cursor::new(Vec::new());
+    let mut comp = CompoundFile::create(cursor).expect("create");
+    comp.create_storage("/foo").unwrap();
+    comp.create_stream("/baz").unwrap();
+    comp.create_stream("/foo/bar").unwrap();
+    let entries: Vec<Entry> = comp.walk().collect();
+    for entry in entries.iter() {
+        let by_id = comp.dir_entry_by_id(entry.raw().stream_id()).unwrap();
+        assert_eq!(by_id.path(), entry.path());
+        assert_eq!(by_id.raw(), entry.raw());
+    }
+    assert!(comp.dir_entry_by_id(4).is_err());
+    assert!(comp.dir_entry_by_id(0xffffffff).is_err());
+
+    // Removed entries can no longer be looked up by ID.
+    comp.remove_stream("/baz").unwrap();
+    assert!(comp.dir_entry_by_id(2).is_err());
+    // A new entry that reuses the ID can be, at its new path.
+    comp.create_storage("/foo/qux").unwrap();
+    comp.create_stream("/foo/qux/quux").unwrap();
+    let quux = comp.entry("/foo/qux/quux").unwrap();
+    let by_id = comp.dir_entry_by_id(quux.raw().stream_id()).unwrap();
+    assert_eq!(by_id.path(), Path::new("/foo/qux/quux"));
+    assert_eq!(comp.dir_entry_by_id(0).unwrap().path(), Path::new("/"));
+}
+
+#[test]
+fn raw_timestamps() {
+    let cursor = Cursor::new(Vec::new());
+    let mut comp = CompoundFile::create(cursor).expect("create");
+    comp.create_storage("/foo").unwrap();
+    let time = std::time::UNIX_EPOCH + std::time::Duration::new(1234, 5600);
+    comp.set_created_time("/foo", time).unwrap();
+    comp.set_modified_time("/foo", time).unwrap();
+    let entry = comp.entry("/foo").unwrap();
+    let expected = Timestamp::from_value(116444736000000000 + 12340000056);
+    assert_eq!(entry.created_timestamp(), expected);
+    assert_eq!(entry.modified_timestamp(), expected);
+    assert_eq!(entry.modified_timestamp().to_system_time(), time);
+    assert_eq!(Timestamp::from_value(12345).value(), 12345);
+    assert_eq!(Timestamp::zero().value(), 0);
+}
+
 //===========================================================================//
 // Tests for path methods:
 
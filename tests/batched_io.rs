@@ -0,0 +1,183 @@
+use cfb::{CompoundFile, Version};
+use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+//===========================================================================//
+
+/// A wrapper around a cursor that counts the calls made to `read`, and
+/// records the size of the largest call to `write`.  It can also be made to
+/// fail any write of more than one 512-byte sector.
+struct CountingCursor {
+    inner: Cursor<Vec<u8>>,
+    num_reads: Arc<AtomicUsize>,
+    largest_write: Arc<AtomicUsize>,
+    fail_large_writes: Arc<AtomicBool>,
+}
+
+impl CountingCursor {
+    fn new(data: Vec<u8>) -> CountingCursor {
+        CountingCursor {
+            inner: Cursor::new(data),
+            num_reads: Arc::new(AtomicUsize::new(0)),
+            largest_write: Arc::new(AtomicUsize::new(0)),
+            fail_large_writes: Arc::new(AtomicBool::new(false)),
+        }
+    }
+}
+
+impl Read for CountingCursor {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.num_reads.fetch_add(1, Ordering::SeqCst);
+        self.inner.read(buf)
+    }
+}
+
+impl Write for CountingCursor {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.len() > 512 && self.fail_large_writes.load(Ordering::SeqCst) {
+            return Err(io::Error::other("write failed"));
+        }
+        let num_bytes = self.inner.write(buf)?;
+        self.largest_write.fetch_max(num_bytes, Ordering::SeqCst);
+        Ok(num_bytes)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl Seek for CountingCursor {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+fn test_data(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i % 251) as u8).collect()
+}
+
+fn make_cfb(stream_len: usize) -> Vec<u8> {
+    let cursor = Cursor::new(Vec::new());
+    let mut comp =
+        CompoundFile::create_with_version(Version::V3, cursor).unwrap();
+    comp.create_stream("/foo")
+        .unwrap()
+        .write_all(&test_data(stream_len))
+        .unwrap();
+    comp.into_inner().into_inner()
+}
+
+//===========================================================================//
+
+#[test]
+fn copy_contiguous_stream() {
+    let stream_len = 1024 * 1024;
+    let cursor = CountingCursor::new(make_cfb(stream_len));
+    let num_reads = cursor.num_reads.clone();
+    let mut comp = CompoundFile::open(cursor).unwrap();
+    let mut stream = comp.open_stream("/foo").unwrap();
+    let before = num_reads.load(Ordering::SeqCst);
+    let mut output = Vec::new();
+    io::copy(&mut stream, &mut output).unwrap();
+    assert_eq!(output, test_data(stream_len));
+    // Reading one 512-byte sector at a time would take 2048 reads.
+    let reads = num_reads.load(Ordering::SeqCst) - before;
+    assert!(reads <= 2048 / 16 + 1, "{} reads", reads);
+}
+
+#[test]
+fn read_contiguous_mini_stream() {
+    let stream_len = 4000;
+    let cursor = CountingCursor::new(make_cfb(stream_len));
+    let num_reads = cursor.num_reads.clone();
+    let mut comp = CompoundFile::open(cursor).unwrap();
+    let mut stream = comp.open_stream("/foo").unwrap();
+    let before = num_reads.load(Ordering::SeqCst);
+    let mut output = Vec::new();
+    stream.read_to_end(&mut output).unwrap();
+    assert_eq!(output, test_data(stream_len));
+    // Reading one 64-byte mini sector at a time would take 63 reads.
+    let reads = num_reads.load(Ordering::SeqCst) - before;
+    assert!(reads <= 4, "{} reads", reads);
+}
+
+#[test]
+fn write_contiguous_stream() {
+    let cursor = CountingCursor::new(make_cfb(8192));
+    let largest_write = cursor.largest_write.clone();
+    let mut comp = CompoundFile::open(cursor).unwrap();
+    let mut stream = comp.open_stream("/foo").unwrap();
+    stream.write_all(&[0xff; 8192]).unwrap();
+    stream.flush().unwrap();
+    // The stream's 16 sectors are adjacent, so they should be written all at
+    // once, rather than one 512-byte sector at a time.
+    assert_eq!(largest_write.load(Ordering::SeqCst), 8192);
+    drop(stream);
+    let mut data = Vec::new();
+    comp.open_stream("/foo").unwrap().read_to_end(&mut data).unwrap();
+    assert_eq!(data, vec![0xff; 8192]);
+}
+
+#[test]
+fn read_fragmented_stream() {
+    // Interleave two streams so that neither is contiguous, and check that
+    // reading still works across the gaps.
+    let cursor = Cursor::new(Vec::new());
+    let mut comp =
+        CompoundFile::create_with_version(Version::V3, cursor).unwrap();
+    comp.create_stream("/a").unwrap();
+    comp.create_stream("/b").unwrap();
+    let data = test_data(100_000);
+    for chunk in data.chunks(1000) {
+        for path in ["/a", "/b"].iter() {
+            let mut stream = comp.open_stream(path).unwrap();
+            stream.seek(SeekFrom::End(0)).unwrap();
+            stream.write_all(chunk).unwrap();
+        }
+    }
+    for path in ["/a", "/b"].iter() {
+        let mut stream = comp.open_stream(path).unwrap();
+        let mut output = vec![0; data.len()];
+        stream.read_exact(&mut output).unwrap();
+        assert_eq!(output, data);
+        stream.seek(SeekFrom::Start(777)).unwrap();
+        let mut output = vec![0; 20_000];
+        stream.read_exact(&mut output).unwrap();
+        assert_eq!(&output[..], &data[777..20_777]);
+    }
+}
+
+#[test]
+fn failed_write_leaves_no_extra_sectors() {
+    let cursor = CountingCursor::new(Vec::new());
+    let fail_large_writes = cursor.fail_large_writes.clone();
+    let mut comp =
+        CompoundFile::create_with_version(Version::V3, cursor).unwrap();
+    // Leave two-sector gaps in the file, so that a new chain isn't
+    // contiguous.
+    for index in 0..8 {
+        comp.create_stream(format!("/s{}", index))
+            .unwrap()
+            .write_all(&test_data(1024))
+            .unwrap();
+    }
+    for index in (0..8).step_by(2) {
+        comp.remove_stream(format!("/s{}", index)).unwrap();
+    }
+    // Writing the first run of the new chain fails, so none of the sectors
+    // set aside for the data should stay allocated.
+    let mut stream = comp.create_stream("/new").unwrap();
+    fail_large_writes.store(true, Ordering::SeqCst);
+    assert!(stream.write_all(&test_data(10_000)).is_err());
+    drop(stream);
+    fail_large_writes.store(false, Ordering::SeqCst);
+    comp.flush().unwrap();
+    let data = comp.into_inner().inner.into_inner();
+    let comp = CompoundFile::open_strict(Cursor::new(data)).unwrap();
+    assert_eq!(comp.entry("/new").unwrap().len(), 0);
+    assert_eq!(comp.file_usage().unwrap().orphaned_len(), 0);
+}
+
+//===========================================================================//
@@ -0,0 +1,112 @@
+use cfb::{CfbBuilder, CompoundFile, EntrySpec, Timestamp, Version};
+use std::io::{Cursor, ErrorKind, Read};
+use std::path::Path;
+use uuid::Uuid;
+
+//===========================================================================//
+
+const CLSID: Uuid = Uuid::from_u128(0x12345678_9abc_def0_1234_56789abcdef0);
+
+fn fixture() -> CfbBuilder {
+    let created = Timestamp::from_value(130_000_000_000_000_000);
+    let modified = Timestamp::from_value(131_000_000_000_000_000);
+    CfbBuilder::new()
+        .with_version(Version::V3)
+        .with_entry("/", EntrySpec::storage().with_clsid(CLSID))
+        .with_entry(
+            "/docs",
+            EntrySpec::storage()
+                .with_state_bits(3)
+                .with_created(created)
+                .with_modified(modified),
+        )
+        .with_stream("/docs/small", "hello")
+        .with_stream("/docs/large", vec![7; 10000])
+        .with_entry("/docs/flagged", EntrySpec::stream("x").with_state_bits(9))
+        .with_stream("/a/b/empty", Vec::new())
+}
+
+//===========================================================================//
+
+#[test]
+fn build_fixture() {
+    let mut comp = fixture().build(Cursor::new(Vec::new())).unwrap();
+    assert_eq!(comp.version(), Version::V3);
+    assert_eq!(comp.root_entry().clsid(), &CLSID);
+    let docs = comp.entry("/docs").unwrap();
+    assert_eq!(docs.state_bits(), 3);
+    assert_eq!(docs.created_timestamp().value(), 130_000_000_000_000_000);
+    assert_eq!(docs.modified_timestamp().value(), 131_000_000_000_000_000);
+    assert_eq!(comp.entry("/docs/flagged").unwrap().state_bits(), 9);
+    assert!(comp.is_storage("/a/b"));
+    assert!(comp.is_stream("/a/b/empty"));
+    let mut data = Vec::new();
+    comp.open_stream("/docs/small").unwrap().read_to_end(&mut data).unwrap();
+    assert_eq!(data, b"hello");
+    // The result is flushed, so it can be reopened.
+    let data = comp.into_inner().into_inner();
+    let comp = CompoundFile::open_strict(Cursor::new(data)).unwrap();
+    assert_eq!(comp.entry("/docs/large").unwrap().len(), 10000);
+}
+
+#[test]
+fn snapshot_round_trip() {
+    let mut comp = fixture().build(Cursor::new(Vec::new())).unwrap();
+    let snapshot = CfbBuilder::from_compound_file(&mut comp).unwrap();
+    assert_eq!(snapshot.version(), Version::V3);
+    assert_eq!(
+        snapshot.entry("/"),
+        Some(&EntrySpec::storage().with_clsid(CLSID))
+    );
+    assert_eq!(snapshot.entry("/docs"), fixture().entry("/docs"));
+    assert_eq!(
+        snapshot.entry("/docs/small").unwrap().data(),
+        Some(&b"hello"[..])
+    );
+    assert!(snapshot.entry("/a").unwrap().is_storage());
+    let paths: Vec<&Path> = snapshot.entries().map(|(path, _)| path).collect();
+    assert_eq!(paths.len(), 8);
+
+    // Rebuilding from a snapshot gives an identical snapshot.
+    let mut rebuilt = snapshot.build(Cursor::new(Vec::new())).unwrap();
+    assert_eq!(
+        CfbBuilder::from_compound_file(&mut rebuilt).unwrap(),
+        snapshot
+    );
+}
+
+#[test]
+fn root_without_attributes_is_omitted() {
+    let mut comp = CfbBuilder::new()
+        .with_stream("/foo", "bar")
+        .build(Cursor::new(Vec::new()))
+        .unwrap();
+    let snapshot = CfbBuilder::from_compound_file(&mut comp).unwrap();
+    assert_eq!(snapshot, CfbBuilder::new().with_stream("/foo", "bar"));
+}
+
+#[test]
+fn invalid_descriptions() {
+    let cursor = || Cursor::new(Vec::new());
+    let builder = CfbBuilder::new().with_stream("/", "data");
+    assert_eq!(
+        builder.build(cursor()).unwrap_err().kind(),
+        ErrorKind::InvalidInput
+    );
+    let builder = CfbBuilder::new().with_storage("/foo").with_storage("/FOO");
+    assert_eq!(
+        builder.build(cursor()).unwrap_err().kind(),
+        ErrorKind::AlreadyExists
+    );
+    let builder =
+        CfbBuilder::new().with_stream("/foo", "").with_stream("/foo/bar", "");
+    assert!(builder.build(cursor()).is_err());
+    let builder = CfbBuilder::new()
+        .with_entry("/foo", EntrySpec::stream("data").with_clsid(CLSID));
+    assert!(builder.build(cursor()).is_err());
+    let builder =
+        CfbBuilder::new().with_storage(format!("/{}", "x".repeat(40)));
+    assert!(builder.build(cursor()).is_err());
+}
+
+//===========================================================================//
@@ -0,0 +1,190 @@
+use cfb::{CompoundFile, EntryChange, MetadataField, Timestamp};
+use std::io::{Cursor, Seek, SeekFrom, Write};
+use std::path::Path;
+use uuid::Uuid;
+
+//===========================================================================//
+
+type Comp = CompoundFile<Cursor<Vec<u8>>>;
+
+fn new_comp() -> Comp {
+    let mut comp = CompoundFile::create(Cursor::new(Vec::new())).unwrap();
+    comp.set_fixed_timestamp(Some(Timestamp::from_value(1 << 56)));
+    comp
+}
+
+fn write_stream(comp: &mut Comp, path: &str, data: &[u8]) {
+    comp.create_stream(path).unwrap().write_all(data).unwrap();
+}
+
+fn base() -> Comp {
+    let mut comp = new_comp();
+    comp.create_storage("/docs").unwrap();
+    write_stream(&mut comp, "/docs/body", &[5; 10000]);
+    write_stream(&mut comp, "/docs/title", b"Hello");
+    comp.create_storage("/pics").unwrap();
+    write_stream(&mut comp, "/pics/one", &[1; 100]);
+    write_stream(&mut comp, "/pics/two", &[2; 100]);
+    write_stream(&mut comp, "/meta", b"meta");
+    comp
+}
+
+fn lines(changes: &[EntryChange]) -> Vec<String> {
+    changes.iter().map(|change| change.to_string()).collect()
+}
+
+//===========================================================================//
+
+#[test]
+fn identical_files() {
+    let old = base();
+    let new = base();
+    let diff = cfb::diff(&old, &new).unwrap();
+    assert!(diff.is_empty());
+    assert_eq!(diff.to_string(), "");
+}
+
+#[test]
+fn added_and_removed() {
+    let old = base();
+    let mut new = base();
+    new.remove_stream("/meta").unwrap();
+    new.create_storage("/extra").unwrap();
+    write_stream(&mut new, "/extra/data", b"new data");
+    let diff = cfb::diff(&old, &new).unwrap();
+    assert_eq!(
+        lines(diff.changes()),
+        vec!["- /meta", "+ /extra", "+ /extra/data"]
+    );
+    let reverse = cfb::diff(&new, &old).unwrap();
+    assert_eq!(
+        lines(reverse.changes()),
+        vec!["+ /meta", "- /extra", "- /extra/data"]
+    );
+}
+
+#[test]
+fn changed_stream_contents() {
+    let old = base();
+    let mut new = base();
+    {
+        let mut stream = new.open_stream("/docs/body").unwrap();
+        stream.seek(SeekFrom::Start(100)).unwrap();
+        stream.write_all(&[6; 4]).unwrap();
+        stream.seek(SeekFrom::Start(108)).unwrap();
+        stream.write_all(&[6; 2]).unwrap();
+        stream.seek(SeekFrom::Start(5000)).unwrap();
+        stream.write_all(&[7; 10]).unwrap();
+    }
+    write_stream(&mut new, "/docs/title", b"Hello, world");
+    let diff = cfb::diff(&old, &new).unwrap();
+    match &diff.changes()[0] {
+        EntryChange::ContentChanged { ranges, .. } => {
+            assert_eq!(ranges, &vec![100..110, 5000..5010]);
+        }
+        change => panic!("unexpected change {:?}", change),
+    }
+    match &diff.changes()[1] {
+        EntryChange::ContentChanged { old, new, ranges } => {
+            assert_eq!(old.len(), 5);
+            assert_eq!(new.len(), 12);
+            assert_eq!(ranges.len(), 1);
+            assert_eq!(ranges[0], 5..12);
+        }
+        change => panic!("unexpected change {:?}", change),
+    }
+    assert_eq!(diff.changes().len(), 2);
+    assert_eq!(
+        diff.to_string(),
+        "M /docs/body: 20 bytes differ in 2 ranges \
+         (0x64..0x6e, 0x1388..0x1392)\n\
+         M /docs/title: 7 bytes differ in 1 range (0x5..0xc); \
+         length 5 -> 12\n"
+    );
+}
+
+#[test]
+fn changed_metadata() {
+    let clsid = Uuid::from_u128(0xabcdef);
+    let old = base();
+    let mut new = base();
+    new.set_storage_clsid("/pics", clsid).unwrap();
+    new.set_state_bits("/meta", 3).unwrap();
+    let diff = cfb::diff(&old, &new).unwrap();
+    let changes = diff.changes();
+    assert_eq!(changes.len(), 2);
+    match &changes[0] {
+        EntryChange::MetadataChanged { fields, .. } => {
+            assert_eq!(fields, &vec![MetadataField::StateBits]);
+        }
+        change => panic!("unexpected change {:?}", change),
+    }
+    assert_eq!(changes[0].path(), Path::new("/meta"));
+    assert_eq!(
+        changes[1].to_string(),
+        format!(
+            "A /pics: clsid {} -> {}",
+            Uuid::nil().hyphenated(),
+            clsid.hyphenated()
+        )
+    );
+}
+
+#[test]
+fn renamed_entries() {
+    let mut old = base();
+    old.create_storage("/pics/sub").unwrap();
+    write_stream(&mut old, "/pics/sub/three", &[3; 100]);
+    let mut new = new_comp();
+    new.create_storage("/docs").unwrap();
+    write_stream(&mut new, "/docs/body", &[5; 10000]);
+    write_stream(&mut new, "/docs/heading", b"Hello");
+    new.create_storage("/images").unwrap();
+    write_stream(&mut new, "/images/one", &[1; 100]);
+    write_stream(&mut new, "/images/two", &[2; 100]);
+    new.create_storage("/images/sub").unwrap();
+    write_stream(&mut new, "/images/sub/three", &[3; 100]);
+    write_stream(&mut new, "/meta", b"meta");
+    let diff = cfb::diff(&old, &new).unwrap();
+    assert_eq!(
+        lines(diff.changes()),
+        vec!["R /docs/title -> /docs/heading", "R /pics -> /images"]
+    );
+}
+
+#[test]
+fn empty_entries_are_not_renames() {
+    let mut old = base();
+    write_stream(&mut old, "/empty1", b"");
+    write_stream(&mut old, "/empty2", b"");
+    old.create_storage("/spare").unwrap();
+    let mut new = base();
+    write_stream(&mut new, "/blank1", b"");
+    write_stream(&mut new, "/blank2", b"");
+    new.create_storage("/unused").unwrap();
+    let diff = cfb::diff(&old, &new).unwrap();
+    // The empty streams could match up either way, so they're reported as
+    // removed and added; the only empty storage on each side is a rename.
+    assert_eq!(
+        lines(diff.changes()),
+        vec![
+            "+ /blank1",
+            "+ /blank2",
+            "- /empty1",
+            "- /empty2",
+            "R /spare -> /unused",
+        ]
+    );
+}
+
+#[test]
+fn changed_type() {
+    let old = base();
+    let mut new = base();
+    new.remove_stream("/meta").unwrap();
+    new.create_storage("/META").unwrap();
+    let diff = cfb::diff(&old, &new).unwrap();
+    assert_eq!(lines(diff.changes()), vec!["- /meta", "+ /META"]);
+}
+
+//===========================================================================//
@@ -0,0 +1,95 @@
+use cfb::CompoundFile;
+use std::io::{Cursor, ErrorKind};
+use std::path::Path;
+
+//===========================================================================//
+
+type Comp = CompoundFile<Cursor<Vec<u8>>>;
+
+fn sample() -> Comp {
+    let mut comp = CompoundFile::create(Cursor::new(Vec::new())).unwrap();
+    comp.create_stream("/__substg1.0_0037001F").unwrap();
+    comp.create_storage("/ObjectPool").unwrap();
+    comp.create_storage("/ObjectPool/_1").unwrap();
+    comp.create_stream("/ObjectPool/_1/\x01Ole10Native").unwrap();
+    comp.create_stream("/ObjectPool/_1/\x01CompObj").unwrap();
+    comp.create_storage("/ObjectPool/_2").unwrap();
+    comp.create_stream("/ObjectPool/_2/\x01Ole10Native").unwrap();
+    comp.create_storage("/__attach_version1.0_#00000000").unwrap();
+    comp.create_stream("/__attach_version1.0_#00000000/__substg1.0_3701")
+        .unwrap();
+    comp
+}
+
+fn found(comp: &Comp, pattern: &str) -> Vec<String> {
+    comp.find(pattern)
+        .unwrap()
+        .map(|entry| entry.path().to_string_lossy().into_owned())
+        .collect()
+}
+
+//===========================================================================//
+
+#[test]
+fn find_with_wildcards() {
+    let comp = sample();
+    assert_eq!(
+        found(&comp, "/ObjectPool/*/\x01Ole10Native"),
+        vec![
+            "/ObjectPool/_1/\x01Ole10Native",
+            "/ObjectPool/_2/\x01Ole10Native"
+        ]
+    );
+    assert_eq!(
+        found(&comp, "/objectpool/_1/\x01*"),
+        vec!["/ObjectPool/_1/\x01CompObj", "/ObjectPool/_1/\x01Ole10Native"]
+    );
+    assert_eq!(
+        found(&comp, "**/__substg1.0_*"),
+        vec![
+            "/__substg1.0_0037001F",
+            "/__attach_version1.0_#00000000/__substg1.0_3701",
+        ]
+    );
+    assert_eq!(found(&comp, "/"), vec!["/"]);
+    assert_eq!(found(&comp, "/*").len(), 3);
+    assert_eq!(found(&comp, "**").len(), comp.walk().count());
+    assert!(found(&comp, "/nothing/**").is_empty());
+}
+
+#[test]
+fn find_with_malformed_pattern() {
+    let comp = sample();
+    let error = comp.find("/ObjectPool/[_1").err().unwrap();
+    assert_eq!(error.kind(), ErrorKind::InvalidInput);
+}
+
+#[test]
+fn filter_entries_prunes_subtrees() {
+    let comp = sample();
+    let mut visited = Vec::new();
+    let paths: Vec<_> = comp
+        .walk()
+        .filter_entries(|entry| {
+            visited.push(entry.path().to_path_buf());
+            entry.name() != "ObjectPool"
+        })
+        .map(|entry| entry.path().to_path_buf())
+        .collect();
+    assert_eq!(paths.len(), 4);
+    assert!(!paths.iter().any(|path| path.starts_with("/ObjectPool")));
+    // Nothing underneath the rejected storage was ever visited.
+    assert_eq!(visited.len(), 5);
+    assert!(visited.contains(&Path::new("/ObjectPool").to_path_buf()));
+
+    // For non-recursive iterators, filter_entries is just a filter.
+    let names: Vec<_> = comp
+        .read_storage("/ObjectPool/_1")
+        .unwrap()
+        .filter_entries(|entry| entry.name().ends_with("Native"))
+        .map(|entry| entry.name().to_string())
+        .collect();
+    assert_eq!(names, vec!["\x01Ole10Native"]);
+}
+
+//===========================================================================//
@@ -0,0 +1,144 @@
+use cfb::{CompoundFile, HashAlgorithm, HashOptions, Version};
+use std::io::{Cursor, Write};
+use uuid::Uuid;
+
+//===========================================================================//
+
+type Comp = CompoundFile<Cursor<Vec<u8>>>;
+
+fn new_comp(version: Version) -> Comp {
+    CompoundFile::create_with_version(version, Cursor::new(Vec::new()))
+        .unwrap()
+}
+
+fn write_stream(comp: &mut Comp, path: &str, data: &[u8]) {
+    comp.create_stream(path).unwrap().write_all(data).unwrap();
+}
+
+fn digest(comp: &Comp, path: &str) -> Vec<u8> {
+    comp.hash_entry(path, HashAlgorithm::default()).unwrap()
+}
+
+//===========================================================================//
+
+#[test]
+fn digest_lengths() {
+    let mut comp = new_comp(Version::V4);
+    write_stream(&mut comp, "/foo", b"foo");
+    let algorithms = [
+        #[cfg(feature = "sha256")]
+        HashAlgorithm::Sha256,
+        HashAlgorithm::Fnv1a64,
+    ];
+    for &algorithm in algorithms.iter() {
+        for path in &["/", "/foo"] {
+            let digest = comp.hash_entry(path, algorithm).unwrap();
+            assert_eq!(digest.len(), algorithm.digest_len());
+        }
+    }
+    assert!(comp.hash_entry("/missing", HashAlgorithm::default()).is_err());
+    // The default doesn't depend on which cargo features are enabled.
+    assert_eq!(HashAlgorithm::default(), HashAlgorithm::Fnv1a64);
+}
+
+#[test]
+fn hash_is_independent_of_layout() {
+    let mut first = new_comp(Version::V3);
+    first.create_storage("/a").unwrap();
+    write_stream(&mut first, "/a/small", b"small");
+    write_stream(&mut first, "/a/big", &[3; 10000]);
+    write_stream(&mut first, "/b", b"b");
+
+    // Same content, built in a different order with leftover free space.
+    let mut second = new_comp(Version::V3);
+    write_stream(&mut second, "/junk", &[9; 50000]);
+    write_stream(&mut second, "/b", &[1; 5000]);
+    second.create_storage("/a").unwrap();
+    write_stream(&mut second, "/a/big", &[3; 10000]);
+    write_stream(&mut second, "/a/small", b"small");
+    second.remove_stream("/junk").unwrap();
+    write_stream(&mut second, "/b", b"b");
+    assert_eq!(digest(&first, "/"), digest(&second, "/"));
+
+    // Compacting doesn't change anything either.
+    let compacted = second.write_canonical(Vec::new()).unwrap();
+    let third = CompoundFile::open(Cursor::new(compacted)).unwrap();
+    assert_eq!(digest(&first, "/"), digest(&third, "/"));
+    // A version 4 file with the same content hashes the same as well.
+    let mut fourth = new_comp(Version::V4);
+    fourth.create_storage("/a").unwrap();
+    write_stream(&mut fourth, "/a/big", &[3; 10000]);
+    write_stream(&mut fourth, "/a/small", b"small");
+    write_stream(&mut fourth, "/b", b"b");
+    assert_eq!(digest(&first, "/"), digest(&fourth, "/"));
+}
+
+#[test]
+fn hash_covers_names_and_contents() {
+    let mut comp = new_comp(Version::V4);
+    comp.create_storage("/a").unwrap();
+    write_stream(&mut comp, "/a/x", b"data");
+    comp.create_storage("/b").unwrap();
+    write_stream(&mut comp, "/b/y", b"data");
+    comp.create_storage("/c").unwrap();
+    write_stream(&mut comp, "/c/x", b"date");
+    comp.create_storage("/d").unwrap();
+    comp.create_storage("/d/x").unwrap();
+    comp.create_storage("/e").unwrap();
+    write_stream(&mut comp, "/e/x", b"data");
+    // Equal streams hash the same regardless of their names.
+    assert_eq!(digest(&comp, "/a/x"), digest(&comp, "/b/y"));
+    assert_eq!(digest(&comp, "/a"), digest(&comp, "/e"));
+    assert_ne!(digest(&comp, "/a"), digest(&comp, "/b"));
+    assert_ne!(digest(&comp, "/a"), digest(&comp, "/c"));
+    assert_ne!(digest(&comp, "/a"), digest(&comp, "/d"));
+    let before = digest(&comp, "/");
+    write_stream(&mut comp, "/e/x", b"changed");
+    assert_ne!(digest(&comp, "/"), before);
+    assert_eq!(
+        digest(&comp, "/"),
+        comp.hash_entry_with_options("/", &HashOptions::new()).unwrap()
+    );
+}
+
+#[test]
+fn optional_attributes() {
+    let clsid = Uuid::from_u128(0x1234);
+    let mut comp = new_comp(Version::V4);
+    comp.create_storage("/a").unwrap();
+    comp.create_storage("/b").unwrap();
+    comp.set_storage_clsid("/b", clsid).unwrap();
+    comp.set_state_bits("/b", 7).unwrap();
+    let hash = |path, options: &HashOptions| {
+        comp.hash_entry_with_options(path, options).unwrap()
+    };
+    let plain = HashOptions::new();
+    assert_eq!(hash("/a", &plain), hash("/b", &plain));
+    let clsids = HashOptions::new().with_clsids(true);
+    assert!(clsids.includes_clsids());
+    assert_ne!(hash("/a", &clsids), hash("/b", &clsids));
+    let state_bits = HashOptions::new().with_state_bits(true);
+    assert_ne!(hash("/a", &state_bits), hash("/b", &state_bits));
+    let fnv = HashOptions::new()
+        .with_algorithm(HashAlgorithm::Fnv1a64)
+        .with_clsids(true)
+        .with_state_bits(true);
+    assert_ne!(hash("/a", &fnv), hash("/b", &fnv));
+}
+
+#[test]
+fn timestamps_are_optional() {
+    let mut comp = new_comp(Version::V4);
+    comp.create_storage("/a").unwrap();
+    comp.create_storage("/b").unwrap();
+    let created = std::time::UNIX_EPOCH;
+    comp.set_created_time("/b", created).unwrap();
+    let options = HashOptions::new().with_timestamps(true);
+    assert!(options.includes_timestamps());
+    assert_eq!(digest(&comp, "/a"), digest(&comp, "/b"));
+    let first = comp.hash_entry_with_options("/a", &options).unwrap();
+    let second = comp.hash_entry_with_options("/b", &options).unwrap();
+    assert_ne!(first, second);
+}
+
+//===========================================================================//
@@ -0,0 +1,148 @@
+use cfb::{CompoundFile, FatEntry, Version};
+use std::io::{Cursor, Write};
+
+//===========================================================================//
+
+fn small_data() -> Vec<u8> {
+    (0..300).map(|i| (i % 7) as u8 + 1).collect()
+}
+
+fn large_data() -> Vec<u8> {
+    (0..5000).map(|i| (i % 251) as u8).collect()
+}
+
+fn make_cfb(version: Version) -> Vec<u8> {
+    let cursor = Cursor::new(Vec::new());
+    let mut comp = CompoundFile::create_with_version(version, cursor).unwrap();
+    comp.create_storage("/dir").unwrap();
+    comp.create_stream("/dir/small")
+        .unwrap()
+        .write_all(&small_data())
+        .unwrap();
+    comp.create_stream("/large").unwrap().write_all(&large_data()).unwrap();
+    comp.flush().unwrap();
+    comp.into_inner().into_inner()
+}
+
+/// Reassembles a stream's data from the raw file bytes, using the offsets
+/// reported by `stream_chain`.
+fn read_chain(
+    comp: &CompoundFile<Cursor<Vec<u8>>>,
+    data: &[u8],
+    path: &str,
+    len: usize,
+) -> Vec<u8> {
+    let chain = comp.stream_chain(path).unwrap();
+    let mut output = Vec::new();
+    for sector in chain.sectors() {
+        let start = sector.offset() as usize;
+        output.extend_from_slice(&data[start..start + sector.len() as usize]);
+    }
+    output.truncate(len);
+    output
+}
+
+//===========================================================================//
+
+#[test]
+fn header_fields() {
+    for &version in &[Version::V3, Version::V4] {
+        let comp = CompoundFile::open(Cursor::new(make_cfb(version))).unwrap();
+        let header = comp.header().unwrap();
+        assert_eq!(header.clsid_reserved(), &[0; 16]);
+        assert_eq!(header.minor_version(), 0x3e);
+        assert_eq!(header.major_version(), version.number());
+        assert_eq!(header.byte_order_mark(), 0xfffe);
+        assert_eq!(1usize << header.sector_shift(), version.sector_len());
+        assert_eq!(header.mini_sector_shift(), 6);
+        assert_eq!(header.reserved(), &[0; 6]);
+        assert_eq!(header.num_fat_sectors(), 1);
+        assert_eq!(header.transaction_signature(), 0);
+        assert_eq!(header.mini_stream_cutoff(), 4096);
+        assert_eq!(header.num_minifat_sectors(), 1);
+        assert_eq!(header.first_difat_sector(), 0xfffffffe);
+        assert_eq!(header.num_difat_sectors(), 0);
+        assert_eq!(header.initial_difat_entries().len(), 109);
+        assert_eq!(header.initial_difat_entries()[0], 0);
+        assert!(header.initial_difat_entries()[1..]
+            .iter()
+            .all(|&entry| entry == 0xffffffff));
+        assert_eq!(comp.difat_entries(), vec![0]);
+        assert!(comp.difat_sector_ids().is_empty());
+    }
+}
+
+#[test]
+fn header_reports_raw_values() {
+    let mut data = make_cfb(Version::V3);
+    data[52] = 0x78; // transaction signature
+    data[35] = 0x12; // reserved field
+    let comp = CompoundFile::open(Cursor::new(data)).unwrap();
+    let header = comp.header().unwrap();
+    assert_eq!(header.transaction_signature(), 0x78);
+    assert_eq!(header.reserved(), &[0, 0x12, 0, 0, 0, 0]);
+}
+
+#[test]
+fn fat_entries() {
+    let comp = CompoundFile::open(Cursor::new(make_cfb(Version::V3))).unwrap();
+    let fat = comp.fat_entries();
+    assert_eq!(fat[0], FatEntry::FatSector);
+    // The directory fits in a single sector (sector 1).
+    assert_eq!(fat[1], FatEntry::EndOfChain);
+    assert!(!fat.contains(&FatEntry::Free));
+
+    // The FAT links between the sectors of "/large" should match the chain
+    // listing.
+    let chain = comp.stream_chain("/large").unwrap();
+    assert!(!chain.in_mini_stream());
+    assert_eq!(chain.sectors().len(), 10);
+    for pair in chain.sectors().windows(2) {
+        assert_eq!(
+            fat[pair[0].sector_id() as usize],
+            FatEntry::Next(pair[1].sector_id())
+        );
+    }
+    let last = chain.sectors().last().unwrap().sector_id();
+    assert_eq!(fat[last as usize], FatEntry::EndOfChain);
+
+    // "/dir/small" takes up five mini sectors.
+    let minifat = comp.minifat_entries();
+    assert_eq!(minifat.len(), 5);
+    assert_eq!(minifat[4], FatEntry::EndOfChain);
+    assert_eq!(minifat[0], FatEntry::Next(1));
+
+    // Freeing the sectors at the end of the file shortens the FAT listing.
+    let mut comp = comp;
+    comp.remove_stream("/large").unwrap();
+    let fat = comp.fat_entries();
+    assert!(fat.len() <= last as usize);
+    assert_ne!(fat.last(), Some(&FatEntry::Free));
+}
+
+#[test]
+fn chain_offsets() {
+    for &version in &[Version::V3, Version::V4] {
+        let data = make_cfb(version);
+        let comp = CompoundFile::open(Cursor::new(data.clone())).unwrap();
+        assert_eq!(read_chain(&comp, &data, "/large", 5000), large_data());
+        let small = comp.stream_chain("/dir/small").unwrap();
+        assert!(small.in_mini_stream());
+        assert!(small.sectors().iter().all(|sector| sector.len() == 64));
+        assert_eq!(read_chain(&comp, &data, "/dir/small", 300), small_data());
+
+        let root = comp.stream_chain("/").unwrap();
+        assert!(!root.in_mini_stream());
+        assert_eq!(root.sectors().len(), 1);
+        assert_eq!(root.sectors()[0].len() as usize, version.sector_len());
+    }
+}
+
+#[test]
+fn chain_of_storage_is_an_error() {
+    let comp = CompoundFile::open(Cursor::new(make_cfb(Version::V3))).unwrap();
+    assert!(comp.stream_chain("/dir").is_err());
+    assert!(comp.stream_chain("/missing").is_err());
+}
+
+//===========================================================================//
@@ -1,7 +1,7 @@
 use cfb::CompoundFile;
 use rand::prelude::{Rng, SeedableRng};
 use rand_pcg::Pcg32;
-use std::io::{Cursor, Seek, SeekFrom, Write};
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
 use std::path::PathBuf;
 
 /// Regression test for https://github.com/mdsteele/rust-cfb/issues/12.
@@ -37,3 +37,42 @@ fn large_file_issue_12() {
     let cursor = comp.into_inner();
     let _comp = CompoundFile::open_strict(cursor).expect("re-open");
 }
+
+#[test]
+fn interleaved_large_streams() {
+    // Grow two large streams in alternation, so that their chains interleave,
+    // then shrink one and regrow the other into the freed sectors.
+    let cursor = Cursor::new(Vec::new());
+    let mut comp = CompoundFile::create(cursor).unwrap();
+    comp.create_stream("/a").unwrap();
+    comp.create_stream("/b").unwrap();
+    let mut expected_a = Vec::new();
+    let mut expected_b = Vec::new();
+    for i in 0..100u8 {
+        let data = vec![i; 3000];
+        let mut stream = comp.open_stream("/a").unwrap();
+        stream.seek(SeekFrom::End(0)).unwrap();
+        stream.write_all(&data).unwrap();
+        expected_a.extend_from_slice(&data);
+        drop(stream);
+        let mut stream = comp.open_stream("/b").unwrap();
+        stream.seek(SeekFrom::End(0)).unwrap();
+        stream.write_all(&data[..1000]).unwrap();
+        expected_b.extend_from_slice(&data[..1000]);
+    }
+    comp.open_stream("/a").unwrap().set_len(5000).unwrap();
+    expected_a.truncate(5000);
+    let mut stream = comp.open_stream("/b").unwrap();
+    stream.seek(SeekFrom::End(0)).unwrap();
+    stream.write_all(&vec![0xff; 200_000]).unwrap();
+    expected_b.resize(expected_b.len() + 200_000, 0xff);
+    drop(stream);
+
+    let cursor = comp.into_inner();
+    let mut comp = CompoundFile::open_strict(cursor).expect("re-open");
+    for (path, expected) in [("/a", expected_a), ("/b", expected_b)] {
+        let mut actual = Vec::new();
+        comp.open_stream(path).unwrap().read_to_end(&mut actual).unwrap();
+        assert_eq!(actual, expected);
+    }
+}
@@ -0,0 +1,168 @@
+use cfb::{CompoundFile, FatEntry, Version};
+use std::convert::TryInto;
+use std::io::{Cursor, Read, Seek, Write};
+use std::path::PathBuf;
+
+//===========================================================================//
+
+/// Creates a compound file with several storages, each holding enough
+/// entries that the directory spans many sectors.
+fn make_cfb() -> Vec<u8> {
+    let cursor = Cursor::new(Vec::new());
+    let mut comp =
+        CompoundFile::create_with_version(Version::V3, cursor).unwrap();
+    for storage in &["a", "b", "c"] {
+        comp.create_storage(format!("/{}", storage)).unwrap();
+        comp.create_storage(format!("/{}/sub", storage)).unwrap();
+        for index in 0..20 {
+            let path = format!("/{}/stream{:02}", storage, index);
+            let mut stream = comp.create_stream(&path).unwrap();
+            stream.write_all(path.as_bytes()).unwrap();
+        }
+        let path = format!("/{}/sub/inner", storage);
+        comp.create_stream(&path).unwrap().write_all(b"inner").unwrap();
+    }
+    comp.flush().unwrap();
+    comp.into_inner().into_inner()
+}
+
+/// Returns the offset within the file of the directory entry with the given
+/// stream ID.
+fn dir_entry_offset(data: &[u8], stream_id: u32) -> usize {
+    let comp = CompoundFile::open(Cursor::new(data)).unwrap();
+    let fat = comp.fat_entries();
+    let mut sector_id = comp.header().unwrap().first_dir_sector();
+    for _ in 0..(stream_id / 4) {
+        sector_id = match fat[sector_id as usize] {
+            FatEntry::Next(next) => next,
+            entry => panic!("unexpected FAT entry {:?}", entry),
+        };
+    }
+    (sector_id as usize + 1) * 512 + (stream_id as usize % 4) * 128
+}
+
+fn stream_id(data: &[u8], path: &str) -> u32 {
+    let comp = CompoundFile::open(Cursor::new(data)).unwrap();
+    comp.entry(path).unwrap().raw().stream_id()
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_stream<F: Read + Seek>(
+    comp: &mut CompoundFile<F>,
+    path: &str,
+) -> Vec<u8> {
+    let mut data = Vec::new();
+    comp.open_stream(path).unwrap().read_to_end(&mut data).unwrap();
+    data
+}
+
+fn walk_paths<F>(comp: &CompoundFile<F>) -> Vec<PathBuf> {
+    comp.walk().map(|entry| entry.path().to_path_buf()).collect()
+}
+
+//===========================================================================//
+
+#[test]
+fn lazy_matches_eager() {
+    let data = make_cfb();
+    let eager = CompoundFile::open_strict(Cursor::new(data.clone())).unwrap();
+    let mut lazy =
+        CompoundFile::open_lazy_strict(Cursor::new(data.clone())).unwrap();
+    assert_eq!(read_stream(&mut lazy, "/b/stream07"), b"/b/stream07");
+    assert_eq!(read_stream(&mut lazy, "/c/sub/inner"), b"inner");
+    assert!(lazy.is_stream("/a/stream19"));
+    assert!(lazy.is_storage("/a/sub"));
+    assert!(!lazy.exists("/a/missing"));
+    let eager_names: Vec<String> = eager
+        .read_storage("/b")
+        .unwrap()
+        .map(|entry| entry.name().to_string())
+        .collect();
+    let lazy_names: Vec<String> = lazy
+        .read_storage("/b")
+        .unwrap()
+        .map(|entry| entry.name().to_string())
+        .collect();
+    assert_eq!(lazy_names, eager_names);
+    assert_eq!(walk_paths(&lazy), walk_paths(&eager));
+    let entry = lazy.dir_entry_by_id(5).unwrap();
+    assert_eq!(entry.path(), eager.dir_entry_by_id(5).unwrap().path());
+}
+
+#[test]
+fn malformed_storage_is_only_reported_when_visited() {
+    let mut data = make_cfb();
+    // Give one of the entries within "/b" an invalid object type.
+    let offset = dir_entry_offset(&data, stream_id(&data, "/b/stream10"));
+    data[offset + 66] = 7;
+    assert!(CompoundFile::open(Cursor::new(data.clone())).is_err());
+
+    let mut comp = CompoundFile::open_lazy(Cursor::new(data)).unwrap();
+    assert_eq!(read_stream(&mut comp, "/a/stream03"), b"/a/stream03");
+    assert!(comp.is_storage("/c/sub"));
+    assert!(comp.open_stream("/b/stream03").is_err());
+    assert!(comp.read_storage("/b").is_err());
+    assert!(comp.walk_storage("/").is_err());
+    assert!(comp.walk_storage("/a").is_ok());
+    // Whole-file comparisons report the error rather than treating the
+    // file as empty.
+    let eager = CompoundFile::create(Cursor::new(Vec::new())).unwrap();
+    assert!(cfb::diff(&comp, &eager).is_err());
+    assert!(cfb::diff(&eager, &comp).is_err());
+    assert!(comp.create_stream("/a/new").is_err());
+    // Storages that were loaded successfully remain usable.
+    assert_eq!(read_stream(&mut comp, "/c/stream00"), b"/c/stream00");
+}
+
+#[test]
+#[should_panic(expected = "Malformed directory")]
+fn walk_panics_on_malformed_lazy_directory() {
+    let mut data = make_cfb();
+    let offset = dir_entry_offset(&data, stream_id(&data, "/b/stream10"));
+    data[offset + 66] = 7;
+    let comp = CompoundFile::open_lazy(Cursor::new(data)).unwrap();
+    comp.walk();
+}
+
+#[test]
+fn lazy_strict_checks_each_storage() {
+    let mut data = make_cfb();
+    // Make two adjacent nodes in the tree of entries within "/a" both red,
+    // which is only an error under strict validation.
+    let storage_offset = dir_entry_offset(&data, stream_id(&data, "/a"));
+    let parent_id = read_u32(&data, storage_offset + 76);
+    let parent_offset = dir_entry_offset(&data, parent_id);
+    let left_id = read_u32(&data, parent_offset + 68);
+    let right_id = read_u32(&data, parent_offset + 72);
+    let child_id = if left_id != 0xffffffff { left_id } else { right_id };
+    let child_offset = dir_entry_offset(&data, child_id);
+    data[parent_offset + 67] = 0;
+    data[child_offset + 67] = 0;
+    assert!(CompoundFile::open_strict(Cursor::new(data.clone())).is_err());
+
+    let mut comp =
+        CompoundFile::open_lazy_strict(Cursor::new(data.clone())).unwrap();
+    assert_eq!(read_stream(&mut comp, "/b/stream01"), b"/b/stream01");
+    assert!(comp.open_stream("/a/stream01").is_err());
+    let mut comp = CompoundFile::open_lazy(Cursor::new(data)).unwrap();
+    assert_eq!(read_stream(&mut comp, "/a/stream01"), b"/a/stream01");
+}
+
+#[test]
+fn modify_lazily_opened_file() {
+    let data = make_cfb();
+    let mut comp = CompoundFile::open_lazy(Cursor::new(data)).unwrap();
+    comp.create_stream("/b/sub/new").unwrap().write_all(b"new").unwrap();
+    comp.remove_stream("/c/stream05").unwrap();
+    comp.flush().unwrap();
+    let data = comp.into_inner().into_inner();
+    let mut comp = CompoundFile::open_strict(Cursor::new(data)).unwrap();
+    assert_eq!(read_stream(&mut comp, "/b/sub/new"), b"new");
+    assert!(!comp.exists("/c/stream05"));
+    assert_eq!(comp.walk().count(), 1 + 3 * 23);
+}
+
+//===========================================================================//
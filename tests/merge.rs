@@ -0,0 +1,185 @@
+use cfb::{CompoundFile, MergeResolution, Timestamp};
+use std::io::{Cursor, ErrorKind, Read, Write};
+use std::path::Path;
+use uuid::Uuid;
+
+//===========================================================================//
+
+type Comp = CompoundFile<Cursor<Vec<u8>>>;
+
+fn new_comp() -> Comp {
+    let mut comp = CompoundFile::create(Cursor::new(Vec::new())).unwrap();
+    comp.set_fixed_timestamp(Some(Timestamp::from_value(1 << 56)));
+    comp
+}
+
+fn write_stream(comp: &mut Comp, path: &str, data: &[u8]) {
+    comp.create_stream(path).unwrap().write_all(data).unwrap();
+}
+
+fn read_stream(comp: &mut Comp, path: &str) -> Vec<u8> {
+    let mut data = Vec::new();
+    comp.open_stream(path).unwrap().read_to_end(&mut data).unwrap();
+    data
+}
+
+fn base() -> Comp {
+    let mut comp = new_comp();
+    comp.create_storage("/docs").unwrap();
+    write_stream(&mut comp, "/docs/one", b"one");
+    write_stream(&mut comp, "/docs/two", b"two");
+    write_stream(&mut comp, "/shared", &[7; 5000]);
+    comp.create_storage("/old").unwrap();
+    write_stream(&mut comp, "/old/data", b"old");
+    comp
+}
+
+//===========================================================================//
+
+#[test]
+fn non_conflicting_changes() {
+    let base = base();
+    let mut ours = base_copy();
+    write_stream(&mut ours, "/docs/one", b"ONE");
+    write_stream(&mut ours, "/docs/ours", b"added by us");
+    ours.set_state_bits("/shared", 5).unwrap();
+    let mut theirs = base_copy();
+    write_stream(&mut theirs, "/docs/two", b"TWO");
+    write_stream(&mut theirs, "/docs/theirs", b"added by them");
+    theirs.remove_storage_all("/old").unwrap();
+    theirs.set_storage_clsid("/docs", Uuid::from_u128(3)).unwrap();
+    // Both sides making the same change is not a conflict.
+    write_stream(&mut ours, "/same", b"same");
+    write_stream(&mut theirs, "/same", b"same");
+
+    let mut output = new_comp();
+    let report = cfb::merge(&base, &ours, &theirs, &mut output).unwrap();
+    assert!(!report.has_conflicts(), "{:?}", report);
+    assert_eq!(read_stream(&mut output, "/docs/one"), b"ONE");
+    assert_eq!(read_stream(&mut output, "/docs/two"), b"TWO");
+    assert_eq!(read_stream(&mut output, "/docs/ours"), b"added by us");
+    assert_eq!(read_stream(&mut output, "/docs/theirs"), b"added by them");
+    assert_eq!(read_stream(&mut output, "/shared"), vec![7; 5000]);
+    assert_eq!(read_stream(&mut output, "/same"), b"same");
+    assert_eq!(output.entry("/shared").unwrap().state_bits(), 5);
+    assert_eq!(output.entry("/docs").unwrap().clsid(), &Uuid::from_u128(3));
+    assert!(!output.exists("/old"));
+    assert_eq!(output.walk().count(), 8);
+}
+
+fn base_copy() -> Comp {
+    let data = base().into_inner().into_inner();
+    let mut comp = CompoundFile::open(Cursor::new(data)).unwrap();
+    comp.set_fixed_timestamp(Some(Timestamp::from_value(1 << 56)));
+    comp
+}
+
+#[test]
+fn conflicts_are_reported_and_resolved() {
+    let base = base();
+    let mut ours = base_copy();
+    write_stream(&mut ours, "/docs/one", b"ours");
+    ours.set_state_bits("/docs/two", 1).unwrap();
+    write_stream(&mut ours, "/new", b"ours");
+    let mut theirs = base_copy();
+    write_stream(&mut theirs, "/docs/one", b"theirs");
+    theirs.set_state_bits("/docs/two", 2).unwrap();
+    write_stream(&mut theirs, "/new", b"theirs");
+
+    let mut output = new_comp();
+    let report = cfb::merge(&base, &ours, &theirs, &mut output).unwrap();
+    let paths: Vec<&Path> = report
+        .conflicts()
+        .iter()
+        .map(|(conflict, _)| conflict.path())
+        .collect();
+    assert_eq!(paths, vec!["/new", "/docs/one", "/docs/two"]);
+    assert!(report
+        .conflicts()
+        .iter()
+        .all(|&(_, resolution)| resolution == MergeResolution::Ours));
+    assert_eq!(read_stream(&mut output, "/docs/one"), b"ours");
+    assert_eq!(output.entry("/docs/two").unwrap().state_bits(), 1);
+
+    // Resolve each conflict differently with a callback.
+    let mut output = new_comp();
+    let report =
+        cfb::merge_with(&base, &ours, &theirs, &mut output, |conflict| {
+            assert!(conflict.ours().is_some());
+            assert!(conflict.theirs().is_some());
+            if conflict.path() == Path::new("/docs/one") {
+                MergeResolution::Base
+            } else if conflict.base().is_some() {
+                MergeResolution::Theirs
+            } else {
+                MergeResolution::Ours
+            }
+        })
+        .unwrap();
+    assert_eq!(report.conflicts().len(), 3);
+    assert_eq!(read_stream(&mut output, "/docs/one"), b"one");
+    assert_eq!(output.entry("/docs/two").unwrap().state_bits(), 2);
+    assert_eq!(read_stream(&mut output, "/new"), b"ours");
+}
+
+#[test]
+fn delete_versus_modify() {
+    let base = base();
+    let mut ours = base_copy();
+    ours.remove_storage_all("/old").unwrap();
+    let mut theirs = base_copy();
+    write_stream(&mut theirs, "/old/data", b"modified");
+    write_stream(&mut theirs, "/old/extra", b"extra");
+
+    // Keeping their modified stream brings back its storage, along with the
+    // other stream they added to it.
+    let mut output = new_comp();
+    let report = cfb::merge_with(&base, &ours, &theirs, &mut output, |_| {
+        MergeResolution::Theirs
+    })
+    .unwrap();
+    let paths: Vec<&Path> = report
+        .conflicts()
+        .iter()
+        .map(|(conflict, _)| conflict.path())
+        .collect();
+    assert_eq!(paths, vec!["/old/data"]);
+    assert_eq!(read_stream(&mut output, "/old/data"), b"modified");
+    assert_eq!(read_stream(&mut output, "/old/extra"), b"extra");
+
+    // Keeping the deletion drops the modified stream.
+    let mut output = new_comp();
+    cfb::merge(&base, &ours, &theirs, &mut output).unwrap();
+    assert!(output.is_storage("/old"));
+    assert!(!output.exists("/old/data"));
+    assert!(output.exists("/old/extra"));
+}
+
+#[test]
+fn output_must_be_empty() {
+    let base = base();
+    let mut output = new_comp();
+    output.create_storage("/foo").unwrap();
+    let error = cfb::merge(&base, &base, &base, &mut output).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::InvalidInput);
+}
+
+#[test]
+fn parent_replaced_by_stream() {
+    let base = base();
+    let mut ours = base_copy();
+    ours.remove_storage_all("/old").unwrap();
+    write_stream(&mut ours, "/old", b"now a stream");
+    let mut theirs = base_copy();
+    write_stream(&mut theirs, "/old/data", b"modified");
+    let mut output = new_comp();
+    let result = cfb::merge_with(&base, &ours, &theirs, &mut output, |_| {
+        MergeResolution::Theirs
+    });
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::InvalidInput);
+    let mut output = new_comp();
+    cfb::merge(&base, &ours, &theirs, &mut output).unwrap();
+    assert_eq!(read_stream(&mut output, "/old"), b"now a stream");
+}
+
+//===========================================================================//
@@ -0,0 +1,159 @@
+use cfb::{CfbPatch, CompoundFile, PatchOp, Timestamp};
+use std::io::{Cursor, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use uuid::Uuid;
+
+//===========================================================================//
+
+type Comp = CompoundFile<Cursor<Vec<u8>>>;
+
+fn new_comp() -> Comp {
+    let mut comp = CompoundFile::create(Cursor::new(Vec::new())).unwrap();
+    comp.set_fixed_timestamp(Some(Timestamp::from_value(1 << 56)));
+    comp
+}
+
+fn write_stream(comp: &mut Comp, path: &str, data: &[u8]) {
+    comp.create_stream(path).unwrap().write_all(data).unwrap();
+}
+
+fn read_stream(comp: &mut Comp, path: &str) -> Vec<u8> {
+    let mut data = Vec::new();
+    comp.open_stream(path).unwrap().read_to_end(&mut data).unwrap();
+    data
+}
+
+fn base() -> Comp {
+    let mut comp = new_comp();
+    comp.create_storage("/docs").unwrap();
+    write_stream(&mut comp, "/docs/body", &[5; 100000]);
+    write_stream(&mut comp, "/docs/title", b"Hello");
+    comp.create_storage("/pics").unwrap();
+    write_stream(&mut comp, "/pics/one", &[1; 100]);
+    comp.create_storage("/pics/more").unwrap();
+    write_stream(&mut comp, "/pics/more/two", &[2; 5000]);
+    write_stream(&mut comp, "/meta", b"meta");
+    write_stream(&mut comp, "/old", b"old");
+    comp
+}
+
+fn edited() -> Comp {
+    let mut comp = base();
+    {
+        let mut stream = comp.open_stream("/docs/body").unwrap();
+        stream.seek(SeekFrom::Start(50000)).unwrap();
+        stream.write_all(b"patched").unwrap();
+    }
+    write_stream(&mut comp, "/docs/title", b"Hello, world");
+    comp.remove_stream("/old").unwrap();
+    comp.remove_stream("/meta").unwrap();
+    comp.create_storage("/meta").unwrap();
+    comp.set_storage_clsid("/meta", Uuid::from_u128(77)).unwrap();
+    write_stream(&mut comp, "/meta/inner", b"inner");
+    comp.set_state_bits("/meta/inner", 4).unwrap();
+    comp.set_storage_clsid("/", Uuid::from_u128(42)).unwrap();
+    // Move /pics to /images.
+    comp.remove_storage_all("/pics").unwrap();
+    comp.create_storage("/images").unwrap();
+    write_stream(&mut comp, "/images/one", &[1; 100]);
+    comp.create_storage("/images/more").unwrap();
+    write_stream(&mut comp, "/images/more/two", &[2; 5000]);
+    comp
+}
+
+//===========================================================================//
+
+#[test]
+fn patch_between_files() {
+    let old = base();
+    let new = edited();
+    let patch = CfbPatch::between(&old, &new).unwrap();
+    // Only the changed part of the big stream is included.
+    let num_bytes: usize = patch
+        .ops()
+        .iter()
+        .map(|op| match op {
+            PatchOp::Write(_, _, data) => data.len(),
+            _ => 0,
+        })
+        .sum();
+    assert!(num_bytes < 10000, "{}", num_bytes);
+    let mut target = base();
+    cfb::apply_patch(&mut target, &patch).unwrap();
+    assert!(cfb::diff(&target, &new).unwrap().is_empty());
+    assert_eq!(read_stream(&mut target, "/docs/title"), b"Hello, world");
+    assert_eq!(read_stream(&mut target, "/images/more/two"), vec![2; 5000]);
+    // The patch no longer applies once it's been applied.
+    let error = cfb::apply_patch(&mut target, &patch).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::InvalidInput);
+}
+
+#[test]
+fn empty_patch() {
+    let old = base();
+    let patch = CfbPatch::between(&old, &base()).unwrap();
+    assert!(patch.ops().is_empty());
+    assert_eq!(patch.base_digest().len(), 8);
+    let mut target = base();
+    cfb::apply_patch(&mut target, &patch).unwrap();
+}
+
+#[test]
+fn patch_checks_base() {
+    let old = base();
+    let patch = CfbPatch::between(&old, &edited()).unwrap();
+    let mut other = base();
+    other.set_state_bits("/docs", 1).unwrap();
+    let before = other.hash_entry("/", Default::default()).unwrap();
+    let error = cfb::apply_patch(&mut other, &patch).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::InvalidInput);
+    assert_eq!(other.hash_entry("/", Default::default()).unwrap(), before);
+}
+
+#[test]
+fn serialization_round_trip() {
+    let patch = CfbPatch::between(&base(), &edited()).unwrap();
+    let mut bytes = Vec::new();
+    patch.write_to(&mut bytes).unwrap();
+    let decoded = CfbPatch::read_from(bytes.as_slice()).unwrap();
+    assert_eq!(decoded, patch);
+    let mut target = base();
+    cfb::apply_patch(&mut target, &decoded).unwrap();
+    assert!(cfb::diff(&target, &edited()).unwrap().is_empty());
+
+    // Truncated or corrupted patches are rejected.
+    for len in [0, 4, 12, bytes.len() - 1] {
+        assert!(CfbPatch::read_from(&bytes[..len]).is_err(), "{}", len);
+    }
+    let mut corrupt = bytes.clone();
+    corrupt[0] = b'X';
+    let error = CfbPatch::read_from(corrupt.as_slice()).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::InvalidData);
+}
+
+#[test]
+fn recorded_patch() {
+    let old = base();
+    let mut patch = CfbPatch::for_base(&old).unwrap();
+    let path = |path: &str| PathBuf::from(path);
+    patch.push(PatchOp::CreateStorage(path("/new")));
+    patch.push(PatchOp::CreateStream(path("/new/data")));
+    patch.push(PatchOp::Write(path("/new/data"), 3, b"xyz".to_vec()));
+    patch.push(PatchOp::SetLen(path("/docs/body"), 10));
+    patch.push(PatchOp::Remove(path("/pics")));
+    let created = Timestamp::from_value(123);
+    let modified = Timestamp::from_value(456);
+    patch.push(PatchOp::SetTimestamps(path("/docs"), created, modified));
+    patch.push(PatchOp::SetStateBits(path("/meta"), 9));
+    let mut target = base();
+    cfb::apply_patch(&mut target, &patch).unwrap();
+    assert_eq!(read_stream(&mut target, "/new/data"), b"\0\0\0xyz");
+    assert_eq!(read_stream(&mut target, "/docs/body"), vec![5; 10]);
+    assert!(!target.exists("/pics"));
+    let docs = target.entry("/docs").unwrap();
+    assert_eq!(docs.created_timestamp(), created);
+    assert_eq!(docs.modified_timestamp(), modified);
+    assert_eq!(target.entry("/meta").unwrap().state_bits(), 9);
+}
+
+//===========================================================================//
@@ -0,0 +1,148 @@
+use cfb::{CompoundFile, ReadAt, Stream};
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+use std::thread;
+
+//===========================================================================//
+
+fn stream_data(seed: usize, len: usize) -> Vec<u8> {
+    (0..len).map(|i| ((i * 7 + seed) % 251) as u8).collect()
+}
+
+/// Stream lengths covering empty streams, mini streams, and streams stored in
+/// regular sectors (including ones that aren't a whole number of sectors).
+const LENGTHS: &[usize] = &[0, 1, 100, 4095, 4096, 5000, 70000];
+
+fn make_cfb() -> CompoundFile<Cursor<Vec<u8>>> {
+    let mut comp = CompoundFile::create(Cursor::new(Vec::new())).unwrap();
+    // Interleave the writes, so that the streams' chains are fragmented.
+    let mut streams: Vec<Stream<_>> = (0..LENGTHS.len())
+        .map(|index| comp.create_stream(format!("/s{}", index)).unwrap())
+        .collect();
+    let mut offset = 0;
+    while offset < *LENGTHS.iter().max().unwrap() {
+        for (index, stream) in streams.iter_mut().enumerate() {
+            let data = stream_data(index, LENGTHS[index]);
+            if offset < data.len() {
+                let end = (offset + 3000).min(data.len());
+                stream.write_all(&data[offset..end]).unwrap();
+            }
+        }
+        offset += 3000;
+    }
+    drop(streams);
+    comp.flush().unwrap();
+    let data = comp.into_inner().into_inner();
+    CompoundFile::open(Cursor::new(data)).unwrap()
+}
+
+fn read_all_at(stream: &Stream<Cursor<Vec<u8>>>, offset: u64) -> Vec<u8> {
+    let mut data = Vec::new();
+    let mut buf = vec![0; 1000];
+    loop {
+        let num_bytes =
+            stream.read_at(offset + data.len() as u64, &mut buf).unwrap();
+        if num_bytes == 0 {
+            return data;
+        }
+        data.extend_from_slice(&buf[..num_bytes]);
+    }
+}
+
+//===========================================================================//
+
+#[test]
+fn read_at_in_memory_buffers() {
+    let data = vec![1, 2, 3, 4, 5];
+    let mut buf = [0; 3];
+    assert_eq!(data.read_at(&mut buf, 3).unwrap(), 2);
+    assert_eq!(&buf[..2], &[4, 5]);
+    assert_eq!(data.read_at(&mut buf, 5).unwrap(), 0);
+    let mut cursor = Cursor::new(data.as_slice());
+    cursor.seek(SeekFrom::End(0)).unwrap();
+    cursor.read_exact_at(&mut buf, 1).unwrap();
+    assert_eq!(buf, [2, 3, 4]);
+    assert!(cursor.read_exact_at(&mut buf, 4).is_err());
+}
+
+#[test]
+fn read_streams_at_offsets() {
+    let mut comp = make_cfb();
+    for (index, &len) in LENGTHS.iter().enumerate() {
+        let expected = stream_data(index, len);
+        let stream = comp.open_stream(format!("/s{}", index)).unwrap();
+        for &offset in &[0, 1, 63, 64, 511, 512, 4095, 4097, 69999] {
+            let actual = read_all_at(&stream, offset);
+            let offset = (offset as usize).min(len);
+            assert_eq!(actual, &expected[offset..]);
+        }
+        let mut buf = vec![0; len + 10];
+        assert_eq!(stream.read_at(0, &mut buf).unwrap(), len);
+        assert_eq!(&buf[..len], expected.as_slice());
+        assert_eq!(stream.read_at(len as u64 + 5, &mut buf).unwrap(), 0);
+    }
+}
+
+#[test]
+fn read_at_does_not_move_position() {
+    let mut comp = make_cfb();
+    let mut stream = comp.open_stream("/s5").unwrap();
+    stream.seek(SeekFrom::Start(10)).unwrap();
+    let mut buf = [0; 100];
+    stream.read_at(2000, &mut buf).unwrap();
+    assert_eq!(stream.stream_position().unwrap(), 10);
+    stream.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf[..], &stream_data(5, 5000)[10..110]);
+}
+
+#[test]
+fn read_at_sees_unflushed_writes() {
+    let mut comp = make_cfb();
+    let mut stream = comp.open_stream("/s2").unwrap();
+    stream.seek(SeekFrom::Start(50)).unwrap();
+    stream.write_all(&[0xff; 100]).unwrap();
+    let mut expected = stream_data(2, 100);
+    expected.truncate(50);
+    expected.resize(150, 0xff);
+    assert_eq!(stream.len(), 150);
+    assert_eq!(read_all_at(&stream, 0), expected);
+    stream.flush().unwrap();
+    assert_eq!(read_all_at(&stream, 0), expected);
+}
+
+#[test]
+fn read_at_after_stream_changes() {
+    let mut comp = make_cfb();
+    let mut stream = comp.open_stream("/s4").unwrap();
+    assert_eq!(read_all_at(&stream, 0), stream_data(4, 4096));
+    // Move the stream into the mini stream, and then back out of it.
+    stream.set_len(100).unwrap();
+    assert_eq!(read_all_at(&stream, 0), stream_data(4, 100));
+    stream.seek(SeekFrom::End(0)).unwrap();
+    stream.write_all(&stream_data(4, 5000)[100..]).unwrap();
+    stream.flush().unwrap();
+    assert_eq!(read_all_at(&stream, 0), stream_data(4, 5000));
+}
+
+#[test]
+fn parallel_reads() {
+    let mut comp = make_cfb();
+    let streams: Vec<Stream<_>> = (0..LENGTHS.len())
+        .map(|index| comp.open_stream(format!("/s{}", index)).unwrap())
+        .collect();
+    thread::scope(|scope| {
+        for (index, stream) in streams.iter().enumerate() {
+            for offset in [0, 777] {
+                scope.spawn(move || {
+                    let expected = stream_data(index, LENGTHS[index]);
+                    let offset = offset.min(expected.len());
+                    for _ in 0..10 {
+                        let actual = read_all_at(stream, offset as u64);
+                        assert_eq!(actual, &expected[offset..]);
+                    }
+                });
+            }
+        }
+    });
+}
+
+//===========================================================================//
@@ -0,0 +1,138 @@
+use cfb::{CompoundFile, RecoveryConfidence, Version};
+use std::io::{Cursor, Write};
+
+//===========================================================================//
+
+const DIR_SECTOR_OFFSET: usize = 1024;
+const DIR_ENTRY_LEN: usize = 128;
+
+fn gone_data() -> Vec<u8> {
+    (0..5000).map(|i| (i % 251) as u8 + 1).collect()
+}
+
+fn tiny_data() -> Vec<u8> {
+    (0..200).map(|i| (i % 13) as u8 + 1).collect()
+}
+
+/// Creates a V3 compound file whose only directory sector holds the root
+/// entry (0), "/keep" (1), "/gone" (2) and "/tiny" (3).  In the directory
+/// tree, "gone" is the left sibling and "tiny" is the right sibling of "keep".
+fn make_cfb() -> Vec<u8> {
+    let cursor = Cursor::new(Vec::new());
+    let mut comp =
+        CompoundFile::create_with_version(Version::V3, cursor).unwrap();
+    comp.create_stream("/keep").unwrap().write_all(&[b'k'; 100]).unwrap();
+    comp.create_stream("/gone").unwrap().write_all(&gone_data()).unwrap();
+    comp.create_stream("/tiny").unwrap().write_all(&tiny_data()).unwrap();
+    comp.flush().unwrap();
+    comp.into_inner().into_inner()
+}
+
+fn dir_entry_offset(stream_id: usize) -> usize {
+    DIR_SECTOR_OFFSET + DIR_ENTRY_LEN * stream_id
+}
+
+/// Removes a stream through the public API (which frees its chain and clears
+/// its directory entry), and then puts back the old directory entry marked as
+/// unallocated, the way some other CFB implementations delete streams.
+fn remove_stream_leaving_entry(
+    data: Vec<u8>,
+    path: &str,
+    id: usize,
+) -> Vec<u8> {
+    let offset = dir_entry_offset(id);
+    let mut old_entry = data[offset..offset + DIR_ENTRY_LEN].to_vec();
+    let mut comp = CompoundFile::open(Cursor::new(data)).unwrap();
+    comp.remove_stream(path).unwrap();
+    comp.flush().unwrap();
+    let mut data = comp.into_inner().into_inner();
+    old_entry[66] = 0; // object type: unallocated
+    data[offset..offset + DIR_ENTRY_LEN].copy_from_slice(&old_entry);
+    data
+}
+
+//===========================================================================//
+
+#[test]
+fn nothing_to_recover() {
+    let mut comp = CompoundFile::open(Cursor::new(make_cfb())).unwrap();
+    let report = comp.recover_deleted().unwrap();
+    assert!(report.streams().is_empty());
+    assert!(report.nonzero_free_sectors().is_empty());
+    assert!(report.nonzero_free_mini_sectors().is_empty());
+}
+
+#[test]
+fn recover_unlinked_stream_with_intact_chain() {
+    let mut data = make_cfb();
+    // Unlink "/gone" from the tree and mark its entry unallocated, but leave
+    // its FAT chain alone.
+    let keep_left_sibling = dir_entry_offset(1) + 68;
+    data[keep_left_sibling..keep_left_sibling + 4].copy_from_slice(&[0xff; 4]);
+    data[dir_entry_offset(2) + 66] = 0;
+
+    let mut comp = CompoundFile::open_strict(Cursor::new(data)).unwrap();
+    assert!(!comp.exists("/gone"));
+    let report = comp.recover_deleted().unwrap();
+    assert_eq!(report.streams().len(), 1);
+    let stream = &report.streams()[0];
+    assert_eq!(stream.stream_id(), 2);
+    assert_eq!(stream.name(), "gone");
+    assert!(stream.is_deleted());
+    assert!(stream.is_complete());
+    assert_eq!(stream.confidence(), RecoveryConfidence::High);
+    assert_eq!(stream.data(), gone_data().as_slice());
+    // The leaked chain isn't used by anything live.
+    assert_eq!(report.nonzero_free_sectors().len(), 10);
+}
+
+#[test]
+fn recover_removed_stream_from_contiguous_sectors() {
+    let data = remove_stream_leaving_entry(make_cfb(), "/gone", 2);
+    let mut comp = CompoundFile::open(Cursor::new(data)).unwrap();
+    assert!(!comp.exists("/gone"));
+    let report = comp.recover_deleted().unwrap();
+    assert_eq!(report.streams().len(), 1);
+    let stream = &report.streams()[0];
+    assert_eq!(stream.name(), "gone");
+    assert!(stream.is_complete());
+    assert_eq!(stream.confidence(), RecoveryConfidence::Medium);
+    assert_eq!(stream.data(), gone_data().as_slice());
+}
+
+#[test]
+fn recover_removed_mini_stream() {
+    let data = remove_stream_leaving_entry(make_cfb(), "/tiny", 3);
+    let mut comp = CompoundFile::open(Cursor::new(data)).unwrap();
+    let report = comp.recover_deleted().unwrap();
+    assert_eq!(report.streams().len(), 1);
+    let stream = &report.streams()[0];
+    assert_eq!(stream.name(), "tiny");
+    assert_eq!(stream.expected_len(), 200);
+    assert_eq!(stream.confidence(), RecoveryConfidence::Medium);
+    assert_eq!(stream.clone().into_data(), tiny_data());
+    // "/keep" occupies mini sectors 0 and 1; "/tiny" occupied 2 through 5.
+    assert_eq!(report.nonzero_free_mini_sectors(), &[2, 3, 4, 5]);
+}
+
+#[test]
+fn recover_stream_whose_sectors_were_reused() {
+    let mut data = make_cfb();
+    // Unlink "/gone" from the tree and mark its entry unallocated, and point
+    // its starting sector at the directory sector (which is still live).
+    let keep_left_sibling = dir_entry_offset(1) + 68;
+    data[keep_left_sibling..keep_left_sibling + 4].copy_from_slice(&[0xff; 4]);
+    data[dir_entry_offset(2) + 66] = 0;
+    let start_sector = dir_entry_offset(2) + 116;
+    data[start_sector..start_sector + 4].copy_from_slice(&[1, 0, 0, 0]);
+
+    let mut comp = CompoundFile::open(Cursor::new(data)).unwrap();
+    let report = comp.recover_deleted().unwrap();
+    assert_eq!(report.streams().len(), 1);
+    let stream = &report.streams()[0];
+    assert_eq!(stream.name(), "gone");
+    assert_eq!(stream.confidence(), RecoveryConfidence::Low);
+    assert_ne!(stream.data(), gone_data().as_slice());
+}
+
+//===========================================================================//
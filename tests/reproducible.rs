@@ -0,0 +1,126 @@
+use cfb::{CfbBuilder, CompoundFile, Timestamp, Version};
+use std::io::{Cursor, Read, Write};
+use uuid::Uuid;
+
+//===========================================================================//
+
+const CLSID: Uuid = Uuid::from_u128(0x0f1e2d3c_4b5a_6978_8796_a5b4c3d2e1f0);
+
+fn fixed() -> Timestamp {
+    Timestamp::from_value(132_000_000_000_000_000)
+}
+
+fn read_stream<F: Read + std::io::Seek>(
+    comp: &mut CompoundFile<F>,
+    path: &str,
+) -> Vec<u8> {
+    let mut data = Vec::new();
+    comp.open_stream(path).unwrap().read_to_end(&mut data).unwrap();
+    data
+}
+
+/// Creates a file with the given contents directly.
+fn direct(version: Version) -> CompoundFile<Cursor<Vec<u8>>> {
+    let mut comp =
+        CompoundFile::create_with_version(version, Cursor::new(Vec::new()))
+            .unwrap();
+    comp.set_fixed_timestamp(Some(fixed()));
+    comp.create_storage("/docs").unwrap();
+    comp.set_storage_clsid("/docs", CLSID).unwrap();
+    comp.create_stream("/docs/big").unwrap().write_all(&[1; 9000]).unwrap();
+    comp.create_stream("/docs/small").unwrap().write_all(b"small").unwrap();
+    comp.create_stream("/README").unwrap().write_all(b"readme").unwrap();
+    comp.set_state_bits("/README", 5).unwrap();
+    comp
+}
+
+/// Creates a file with the same contents as `direct`, but via a roundabout
+/// editing history that leaves junk and free space behind.
+fn roundabout(version: Version) -> CompoundFile<Cursor<Vec<u8>>> {
+    let mut comp =
+        CompoundFile::create_with_version(version, Cursor::new(Vec::new()))
+            .unwrap();
+    comp.set_fixed_timestamp(Some(fixed()));
+    comp.create_stream("/README").unwrap().write_all(&[9; 20000]).unwrap();
+    comp.create_storage("/junk").unwrap();
+    comp.create_stream("/junk/data").unwrap().write_all(&[7; 300]).unwrap();
+    comp.create_storage("/docs").unwrap();
+    comp.create_stream("/docs/small").unwrap().write_all(&[8; 5000]).unwrap();
+    comp.create_stream("/docs/big").unwrap().write_all(&[1; 9000]).unwrap();
+    comp.remove_storage_all("/junk").unwrap();
+    comp.create_stream("/docs/small").unwrap().write_all(b"small").unwrap();
+    comp.create_stream("/README").unwrap().write_all(b"readme").unwrap();
+    comp.set_state_bits("/README", 5).unwrap();
+    comp.set_storage_clsid("/docs", CLSID).unwrap();
+    comp.touch("/docs").unwrap();
+    comp.flush().unwrap();
+    comp
+}
+
+//===========================================================================//
+
+#[test]
+fn fixed_timestamp_is_used_for_new_storages() {
+    let mut comp = CompoundFile::create(Cursor::new(Vec::new())).unwrap();
+    assert_eq!(comp.fixed_timestamp(), None);
+    comp.set_fixed_timestamp(Some(fixed()));
+    assert_eq!(comp.fixed_timestamp(), Some(fixed()));
+    comp.create_storage("/foo").unwrap();
+    let entry = comp.entry("/foo").unwrap();
+    assert_eq!(entry.created_timestamp(), fixed());
+    assert_eq!(entry.modified_timestamp(), fixed());
+    comp.set_modified_time("/foo", std::time::SystemTime::now()).unwrap();
+    assert_ne!(comp.entry("/foo").unwrap().modified_timestamp(), fixed());
+    comp.touch("/foo").unwrap();
+    assert_eq!(comp.entry("/foo").unwrap().modified_timestamp(), fixed());
+}
+
+#[test]
+fn canonical_output_is_independent_of_history() {
+    for &version in &[Version::V3, Version::V4] {
+        let first = direct(version).write_canonical(Vec::new()).unwrap();
+        let second = roundabout(version).write_canonical(Vec::new()).unwrap();
+        assert!(first == second, "{:?}", version);
+        let mut comp = CompoundFile::open_strict(Cursor::new(first)).unwrap();
+        assert_eq!(comp.version(), version);
+        assert_eq!(read_stream(&mut comp, "/docs/big"), vec![1; 9000]);
+        assert_eq!(read_stream(&mut comp, "/docs/small"), b"small");
+        assert_eq!(read_stream(&mut comp, "/README"), b"readme");
+        assert_eq!(comp.entry("/README").unwrap().state_bits(), 5);
+        let docs = comp.entry("/docs").unwrap();
+        assert_eq!(docs.clsid(), &CLSID);
+        assert_eq!(docs.created_timestamp(), fixed());
+        assert_eq!(docs.modified_timestamp(), fixed());
+        assert_eq!(comp.walk().count(), 5);
+        // Canonical output has no hidden data anywhere.
+        let slack = comp.analyze_slack().unwrap();
+        assert!(slack.iter().all(|region| !region.is_nonzero()));
+    }
+}
+
+#[test]
+fn canonical_output_is_a_fixed_point() {
+    let first = roundabout(Version::V4).write_canonical(Vec::new()).unwrap();
+    let mut comp = CompoundFile::open(Cursor::new(first.clone())).unwrap();
+    let second = comp.write_canonical(Vec::new()).unwrap();
+    assert!(first == second);
+}
+
+#[test]
+fn builder_with_fixed_timestamp_is_reproducible() {
+    let builder = || {
+        CfbBuilder::new()
+            .with_fixed_timestamp(fixed())
+            .with_stream("/a/b/c", "data")
+            .with_storage("/d")
+    };
+    assert_eq!(builder().fixed_timestamp(), Some(fixed()));
+    let mut first = builder().build(Cursor::new(Vec::new())).unwrap();
+    assert_eq!(first.entry("/a/b").unwrap().created_timestamp(), fixed());
+    let mut second = builder().build(Cursor::new(Vec::new())).unwrap();
+    let first = first.write_canonical(Vec::new()).unwrap();
+    let second = second.write_canonical(Vec::new()).unwrap();
+    assert!(first == second);
+}
+
+//===========================================================================//
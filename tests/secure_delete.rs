@@ -0,0 +1,147 @@
+use cfb::{CompoundFile, SlackKind};
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+
+//===========================================================================//
+
+const SECRET: u8 = 0xa5;
+
+fn contains_secret(data: &[u8]) -> bool {
+    data.windows(16).any(|window| window.iter().all(|&b| b == SECRET))
+}
+
+fn make_cfb_with_secrets() -> CompoundFile<Cursor<Vec<u8>>> {
+    let mut comp = CompoundFile::create(Cursor::new(Vec::new())).unwrap();
+    comp.create_stream("/public").unwrap().write_all(&[1; 1000]).unwrap();
+    comp.create_stream("/small").unwrap().write_all(&[SECRET; 500]).unwrap();
+    comp.create_stream("/large").unwrap().write_all(&[SECRET; 10000]).unwrap();
+    comp
+}
+
+fn read_stream(
+    comp: &mut CompoundFile<Cursor<Vec<u8>>>,
+    path: &str,
+) -> Vec<u8> {
+    let mut data = Vec::new();
+    comp.open_stream(path).unwrap().read_to_end(&mut data).unwrap();
+    data
+}
+
+//===========================================================================//
+
+#[test]
+fn removed_streams_survive_by_default() {
+    let mut comp = make_cfb_with_secrets();
+    assert!(!comp.secure_delete());
+    comp.remove_stream("/small").unwrap();
+    comp.remove_stream("/large").unwrap();
+    assert!(contains_secret(comp.into_inner().get_ref()));
+}
+
+#[test]
+fn secure_delete_zeroes_removed_streams() {
+    let mut comp = make_cfb_with_secrets();
+    comp.set_secure_delete(true);
+    assert!(comp.secure_delete());
+    comp.remove_stream("/small").unwrap();
+    comp.remove_stream("/large").unwrap();
+    assert!(comp.analyze_slack().unwrap().iter().all(|r| !r.is_nonzero()));
+    assert_eq!(read_stream(&mut comp, "/public"), vec![1; 1000]);
+    assert!(!contains_secret(comp.into_inner().get_ref()));
+}
+
+#[test]
+fn secure_delete_zeroes_truncated_streams() {
+    let mut comp = make_cfb_with_secrets();
+    comp.set_secure_delete(true);
+    comp.open_stream("/small").unwrap().set_len(10).unwrap();
+    comp.open_stream("/large").unwrap().set_len(10).unwrap();
+    assert!(comp.analyze_slack().unwrap().iter().all(|r| !r.is_nonzero()));
+    assert!(!contains_secret(comp.into_inner().get_ref()));
+}
+
+#[test]
+fn secure_delete_zeroes_migrated_streams() {
+    let mut comp = make_cfb_with_secrets();
+    comp.set_secure_delete(true);
+    // Move "/small" out of the mini stream, and "/large" into it.
+    comp.open_stream("/small").unwrap().set_len(5000).unwrap();
+    comp.open_stream("/large").unwrap().set_len(100).unwrap();
+    assert!(comp.analyze_slack().unwrap().iter().all(|r| !r.is_nonzero()));
+    let mut expected = vec![SECRET; 500];
+    expected.resize(5000, 0);
+    assert_eq!(read_stream(&mut comp, "/small"), expected);
+    assert_eq!(read_stream(&mut comp, "/large"), vec![SECRET; 100]);
+}
+
+#[test]
+fn truncate_then_extend_reads_zeros() {
+    let mut comp = make_cfb_with_secrets();
+    for &(path, old_len, short_len) in
+        &[("/small", 500, 100), ("/large", 10000, 4700)]
+    {
+        let mut stream = comp.open_stream(path).unwrap();
+        stream.set_len(short_len).unwrap();
+        stream.set_len(old_len).unwrap();
+        stream.seek(SeekFrom::Start(0)).unwrap();
+        let mut data = Vec::new();
+        stream.read_to_end(&mut data).unwrap();
+        let mut expected = vec![SECRET; short_len as usize];
+        expected.resize(old_len as usize, 0);
+        assert_eq!(data, expected);
+    }
+}
+
+#[test]
+fn scrub_existing_file() {
+    let mut comp = make_cfb_with_secrets();
+    comp.remove_stream("/small").unwrap();
+    comp.remove_stream("/large").unwrap();
+    comp.flush().unwrap();
+    let mut data = comp.into_inner().into_inner();
+    data[20] = 0x42; // reserved header field
+    data.extend_from_slice(&[SECRET; 700]);
+
+    let mut comp = CompoundFile::open(Cursor::new(data)).unwrap();
+    assert!(comp.analyze_slack().unwrap().iter().any(|r| r.is_nonzero()));
+    comp.scrub().unwrap();
+    assert!(comp.analyze_slack().unwrap().iter().all(|r| !r.is_nonzero()));
+    comp.flush().unwrap();
+    let data = comp.into_inner().into_inner();
+    assert!(!contains_secret(&data));
+
+    let mut comp = CompoundFile::open_strict(Cursor::new(data)).unwrap();
+    assert_eq!(read_stream(&mut comp, "/public"), vec![1; 1000]);
+    assert!(comp.recover_deleted().unwrap().streams().is_empty());
+}
+
+#[test]
+fn scrubbed_dir_entries_stay_clean_after_edits() {
+    let mut comp = make_cfb_with_secrets();
+    comp.create_storage("/storage").unwrap();
+    comp.flush().unwrap();
+    let regions: Vec<_> = comp
+        .analyze_slack()
+        .unwrap()
+        .into_iter()
+        .filter(|r| matches!(r.kind(), SlackKind::DirEntryReserved(_)))
+        .collect();
+    let mut data = comp.into_inner().into_inner();
+    for region in regions.iter() {
+        let start = region.offset() as usize;
+        let end = start + region.len() as usize;
+        data[start..end].iter_mut().for_each(|byte| *byte = SECRET);
+    }
+
+    let mut comp = CompoundFile::open(Cursor::new(data)).unwrap();
+    comp.scrub().unwrap();
+    assert!(comp.analyze_slack().unwrap().iter().all(|r| !r.is_nonzero()));
+    // Updating the directory entries afterwards mustn't bring back any of
+    // the scrubbed data.
+    comp.open_stream("/public").unwrap().set_len(2000).unwrap();
+    comp.set_state_bits("/small", 7).unwrap();
+    comp.set_state_bits("/storage", 7).unwrap();
+    comp.flush().unwrap();
+    assert!(comp.analyze_slack().unwrap().iter().all(|r| !r.is_nonzero()));
+}
+
+//===========================================================================//
@@ -0,0 +1,128 @@
+use cfb::{CompoundFile, SlackKind, SlackRegion, Version};
+use std::io::{Cursor, Write};
+
+//===========================================================================//
+
+fn make_cfb(version: Version) -> Vec<u8> {
+    let cursor = Cursor::new(Vec::new());
+    let mut comp = CompoundFile::create_with_version(version, cursor).unwrap();
+    comp.create_stream("/small").unwrap().write_all(&[1; 100]).unwrap();
+    comp.create_stream("/large").unwrap().write_all(&[2; 5000]).unwrap();
+    comp.flush().unwrap();
+    comp.into_inner().into_inner()
+}
+
+fn analyze(data: Vec<u8>) -> Vec<SlackRegion> {
+    let mut comp = CompoundFile::open(Cursor::new(data)).unwrap();
+    comp.analyze_slack().unwrap()
+}
+
+fn find(regions: &[SlackRegion], kind: SlackKind) -> &SlackRegion {
+    regions.iter().find(|region| region.kind() == kind).unwrap()
+}
+
+//===========================================================================//
+
+#[test]
+fn clean_file_has_no_nonzero_slack() {
+    for &version in &[Version::V3, Version::V4] {
+        let regions = analyze(make_cfb(version));
+        assert!(regions.iter().all(|region| !region.is_nonzero()));
+        assert!(regions
+            .windows(2)
+            .all(|pair| pair[0].offset() <= pair[1].offset()));
+
+        let difat = find(&regions, SlackKind::HeaderDifat);
+        assert_eq!(difat.offset(), 80);
+        assert_eq!(difat.len(), 4 * 108);
+
+        // "/small" is 100 bytes, stored in two 64-byte mini sectors.
+        let small_tail = find(&regions, SlackKind::StreamTail(1));
+        assert_eq!(small_tail.len(), 28);
+        // "/large" is 5000 bytes, stored in regular sectors.
+        let sector_len = version.sector_len() as u64;
+        let large_tail = find(&regions, SlackKind::StreamTail(2));
+        assert_eq!(large_tail.len(), sector_len - 5000 % sector_len);
+
+        // The mini stream's first sector holds some free mini sectors.
+        let num_free_mini_sectors = regions
+            .iter()
+            .filter(|region| {
+                matches!(region.kind(), SlackKind::FreeMiniSector(_))
+            })
+            .count();
+        assert_eq!(num_free_mini_sectors as u64, sector_len / 64 - 2);
+    }
+}
+
+#[test]
+fn v4_header_padding() {
+    let mut data = make_cfb(Version::V4);
+    data[3000] = 0x42;
+    let regions = analyze(data);
+    let padding =
+        regions.iter().find(|region| region.offset() == 512).unwrap();
+    assert_eq!(padding.kind(), SlackKind::HeaderReserved);
+    assert_eq!(padding.len(), 4096 - 512);
+    assert!(padding.is_nonzero());
+}
+
+#[test]
+fn data_hidden_in_stream_tail() {
+    let data = make_cfb(Version::V3);
+    let regions = analyze(data.clone());
+    for &stream_id in &[1, 2] {
+        let tail = find(&regions, SlackKind::StreamTail(stream_id)).clone();
+        let mut data = data.clone();
+        data[(tail.offset() + tail.len() - 1) as usize] = 0x42;
+        let regions = analyze(data);
+        assert!(find(&regions, SlackKind::StreamTail(stream_id)).is_nonzero());
+        assert_eq!(regions.iter().filter(|r| r.is_nonzero()).count(), 1);
+    }
+}
+
+#[test]
+fn data_hidden_in_header_difat() {
+    let mut data = make_cfb(Version::V3);
+    data[500] = 0x42;
+    let regions = analyze(data);
+    assert!(find(&regions, SlackKind::HeaderDifat).is_nonzero());
+}
+
+#[test]
+fn data_hidden_in_dir_entry_name_padding() {
+    let mut data = make_cfb(Version::V3);
+    // The "/small" entry is the second entry of the directory sector (sector
+    // 1), and its name is "small\0", so its padding starts at byte 12.
+    data[1024 + 128 + 40] = 0x42;
+    let regions = analyze(data);
+    let reserved: Vec<&SlackRegion> = regions
+        .iter()
+        .filter(|region| region.kind() == SlackKind::DirEntryReserved(1))
+        .collect();
+    assert_eq!(reserved[0].offset(), 1024 + 128 + 12);
+    assert_eq!(reserved[0].len(), 52);
+    assert!(reserved[0].is_nonzero());
+    assert!(reserved[1..].iter().all(|region| !region.is_nonzero()));
+}
+
+#[test]
+fn data_appended_to_file() {
+    let mut data = make_cfb(Version::V3);
+    let num_sectors = data.len() / 512 - 1;
+    // Append enough sectors to run past what a single FAT sector can cover.
+    data.resize(data.len() + 200 * 512, 0xab);
+    let regions = analyze(data);
+    let free_sectors: Vec<&SlackRegion> = regions
+        .iter()
+        .filter(|region| matches!(region.kind(), SlackKind::FreeSector(_)))
+        .collect();
+    assert_eq!(free_sectors.len(), 128 - num_sectors);
+    assert!(free_sectors.iter().all(|region| region.is_nonzero()));
+    let trailing = find(&regions, SlackKind::TrailingData);
+    assert_eq!(trailing.offset(), 129 * 512);
+    assert_eq!(trailing.len(), (num_sectors + 200 - 128) as u64 * 512);
+    assert!(trailing.is_nonzero());
+}
+
+//===========================================================================//
@@ -0,0 +1,116 @@
+use cfb::{CompoundFile, Spool, SpoolOptions};
+use std::io::{self, Cursor, Read, Write};
+
+//===========================================================================//
+
+fn stream_data(seed: usize, len: usize) -> Vec<u8> {
+    (0..len).map(|i| ((i * 11 + seed) % 251) as u8).collect()
+}
+
+fn make_cfb() -> Vec<u8> {
+    let mut comp = CompoundFile::create(Cursor::new(Vec::new())).unwrap();
+    comp.create_storage("/dir").unwrap();
+    comp.create_stream("/dir/mini")
+        .unwrap()
+        .write_all(&stream_data(1, 300))
+        .unwrap();
+    comp.create_stream("/large")
+        .unwrap()
+        .write_all(&stream_data(2, 100000))
+        .unwrap();
+    comp.create_stream("/empty").unwrap();
+    comp.flush().unwrap();
+    comp.into_inner().into_inner()
+}
+
+/// A reader that only supports reading forward, and that hands out data in
+/// small pieces, like a pipe would.
+struct PipeReader {
+    data: Vec<u8>,
+    position: usize,
+    num_reads: usize,
+}
+
+impl PipeReader {
+    fn new(data: Vec<u8>) -> PipeReader {
+        PipeReader { data, position: 0, num_reads: 0 }
+    }
+}
+
+impl Read for PipeReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.num_reads += 1;
+        let end = (self.position + 1000).min(self.data.len());
+        let num_bytes = (end - self.position).min(buf.len());
+        buf[..num_bytes]
+            .copy_from_slice(&self.data[self.position..][..num_bytes]);
+        self.position += num_bytes;
+        Ok(num_bytes)
+    }
+}
+
+//===========================================================================//
+
+#[test]
+fn read_all_streams_from_pipe() {
+    let mut visited = Vec::new();
+    cfb::read_all_streams(PipeReader::new(make_cfb()), |entry, stream| {
+        let mut data = Vec::new();
+        stream.read_to_end(&mut data)?;
+        assert_eq!(data.len() as u64, entry.len());
+        visited.push((entry.path().display().to_string(), data));
+        Ok(())
+    })
+    .unwrap();
+    let expected = vec![
+        ("/dir/mini".to_string(), stream_data(1, 300)),
+        ("/empty".to_string(), Vec::new()),
+        ("/large".to_string(), stream_data(2, 100000)),
+    ];
+    assert_eq!(visited, expected);
+}
+
+#[test]
+fn visitor_errors_stop_the_walk() {
+    let mut num_visited = 0;
+    let result = cfb::read_all_streams(make_cfb().as_slice(), |_, _| {
+        num_visited += 1;
+        Err(io::Error::other("stop"))
+    });
+    assert_eq!(result.unwrap_err().to_string(), "stop");
+    assert_eq!(num_visited, 1);
+}
+
+#[test]
+fn open_spooled_file_with_memory_limit() {
+    let data = make_cfb();
+    let temp_dir = std::env::temp_dir()
+        .join(format!("cfb-spool-test-{}", std::process::id()));
+    std::fs::create_dir_all(&temp_dir).unwrap();
+    let options =
+        SpoolOptions::new().with_memory_limit(4096).with_temp_dir(&temp_dir);
+    let spool = Spool::with_options(PipeReader::new(data.clone()), &options);
+    let mut comp = CompoundFile::open_strict(spool).unwrap();
+    let mut actual = Vec::new();
+    comp.open_stream("/large").unwrap().read_to_end(&mut actual).unwrap();
+    assert_eq!(actual, stream_data(2, 100000));
+    let spool = comp.into_inner();
+    assert!(spool.is_spilled());
+    assert_eq!(spool.len_read(), data.len() as u64);
+    assert_eq!(std::fs::read_dir(&temp_dir).unwrap().count(), 1);
+    let reader = spool.into_inner();
+    assert_eq!(reader.position, data.len());
+    // The input is read in order, and only once.
+    assert!(reader.num_reads <= data.len() / 1000 + 2);
+    assert_eq!(std::fs::read_dir(&temp_dir).unwrap().count(), 0);
+    std::fs::remove_dir(&temp_dir).unwrap();
+}
+
+#[test]
+fn truncated_input() {
+    let mut data = make_cfb();
+    data.truncate(300);
+    assert!(cfb::read_all_streams(data.as_slice(), |_, _| Ok(())).is_err());
+}
+
+//===========================================================================//
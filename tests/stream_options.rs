@@ -0,0 +1,112 @@
+use cfb::{CompoundFile, StreamOptions};
+use std::io::{BufRead, Cursor, Read, Seek, SeekFrom, Write};
+
+//===========================================================================//
+
+fn stream_data(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i % 251) as u8).collect()
+}
+
+fn read_stream(
+    comp: &mut CompoundFile<Cursor<Vec<u8>>>,
+    path: &str,
+) -> Vec<u8> {
+    let mut data = Vec::new();
+    comp.open_stream(path).unwrap().read_to_end(&mut data).unwrap();
+    data
+}
+
+//===========================================================================//
+
+#[test]
+fn default_options() {
+    assert_eq!(StreamOptions::new(), StreamOptions::default());
+    assert_eq!(StreamOptions::new().buffer_size(), 8192);
+    let mut comp = CompoundFile::create(Cursor::new(Vec::new())).unwrap();
+    comp.create_stream("/foo").unwrap();
+    assert_eq!(comp.open_stream("/foo").unwrap().buffer_size(), 8192);
+    let options = StreamOptions::new().with_buffer_size(100);
+    let stream = comp.open_stream_with_options("/foo", &options).unwrap();
+    assert_eq!(stream.buffer_size(), 100);
+}
+
+#[test]
+fn round_trip_with_various_buffer_sizes() {
+    for &buffer_size in &[0, 1, 64, 1000, 100000] {
+        for &len in &[100, 5000, 30000] {
+            let data = stream_data(len);
+            let mut comp =
+                CompoundFile::create(Cursor::new(Vec::new())).unwrap();
+            comp.create_stream("/foo").unwrap();
+            let options = StreamOptions::new().with_buffer_size(buffer_size);
+            let mut stream =
+                comp.open_stream_with_options("/foo", &options).unwrap();
+            // Mix small and large writes.
+            for chunk in data.chunks(777) {
+                stream.write_all(&chunk[..7]).unwrap();
+                stream.write_all(&chunk[7..]).unwrap();
+            }
+            assert_eq!(stream.len(), len as u64);
+            stream.seek(SeekFrom::Start(3)).unwrap();
+            let mut byte = [0];
+            stream.read_exact(&mut byte).unwrap();
+            assert_eq!(byte[0], data[3]);
+            stream.seek(SeekFrom::Start(0)).unwrap();
+            let mut actual = Vec::new();
+            stream.read_to_end(&mut actual).unwrap();
+            assert_eq!(actual, data, "buffer_size={}", buffer_size);
+            drop(stream);
+            assert_eq!(read_stream(&mut comp, "/foo"), data);
+        }
+    }
+}
+
+#[test]
+fn unbuffered_writes_are_immediately_visible() {
+    let mut comp = CompoundFile::create(Cursor::new(Vec::new())).unwrap();
+    comp.create_stream("/foo").unwrap();
+    let options = StreamOptions::new().with_buffer_size(0);
+    let mut stream = comp.open_stream_with_options("/foo", &options).unwrap();
+    stream.write_all(b"hello").unwrap();
+    let mut other = comp.open_stream("/foo").unwrap();
+    let mut data = Vec::new();
+    other.read_to_end(&mut data).unwrap();
+    assert_eq!(data, b"hello");
+    drop(other);
+
+    // Buffered writes, by contrast, are only visible once flushed.
+    let mut stream = comp.open_stream("/foo").unwrap();
+    stream.seek(SeekFrom::End(0)).unwrap();
+    stream.write_all(b", world").unwrap();
+    assert_eq!(read_stream(&mut comp, "/foo"), b"hello");
+    stream.flush().unwrap();
+    assert_eq!(read_stream(&mut comp, "/foo"), b"hello, world");
+}
+
+#[test]
+fn unbuffered_stream_as_buf_read() {
+    let mut comp = CompoundFile::create(Cursor::new(Vec::new())).unwrap();
+    comp.create_stream("/foo")
+        .unwrap()
+        .write_all(b"first line\nsecond line\n")
+        .unwrap();
+    let options = StreamOptions::new().with_buffer_size(0);
+    let stream = comp.open_stream_with_options("/foo", &options).unwrap();
+    let lines: Vec<String> = stream.lines().map(Result::unwrap).collect();
+    assert_eq!(lines, vec!["first line", "second line"]);
+
+    // Reading a line part of the way through doesn't leave the stream
+    // buffered: writes still go straight to the file.
+    let mut stream = comp.open_stream_with_options("/foo", &options).unwrap();
+    let mut line = String::new();
+    stream.read_line(&mut line).unwrap();
+    assert_eq!(line, "first line\n");
+    stream.write_all(b"SECOND").unwrap();
+    assert_eq!(stream.buffer_size(), 0);
+    assert_eq!(read_stream(&mut comp, "/foo"), b"first line\nSECOND line\n");
+    let mut rest = String::new();
+    stream.read_line(&mut rest).unwrap();
+    assert_eq!(rest, " line\n");
+}
+
+//===========================================================================//
@@ -0,0 +1,93 @@
+use cfb::{CompoundFile, Version};
+use std::io::{Cursor, ErrorKind, Write};
+
+//===========================================================================//
+
+type Comp = CompoundFile<Cursor<Vec<u8>>>;
+
+fn write_stream(comp: &mut Comp, path: &str, len: usize) {
+    comp.create_stream(path).unwrap().write_all(&vec![7; len]).unwrap();
+}
+
+fn sample() -> Comp {
+    let cursor = Cursor::new(Vec::new());
+    let mut comp = CompoundFile::create_with_version(Version::V3, cursor)
+        .expect("create");
+    comp.create_storage("/docs").unwrap();
+    write_stream(&mut comp, "/docs/small", 100);
+    write_stream(&mut comp, "/docs/empty", 0);
+    write_stream(&mut comp, "/docs/big", 5000);
+    comp.create_storage("/docs/sub").unwrap();
+    write_stream(&mut comp, "/docs/sub/exact", 4096);
+    write_stream(&mut comp, "/other", 64);
+    comp
+}
+
+//===========================================================================//
+
+#[test]
+fn storage_usage() {
+    let comp = sample();
+    let usage = comp.usage("/docs").unwrap();
+    assert_eq!(usage.logical_len(), 100 + 5000 + 4096);
+    assert_eq!(usage.num_mini_streams(), 2);
+    assert_eq!(usage.num_regular_streams(), 2);
+    assert_eq!(usage.num_mini_sectors(), 2);
+    assert_eq!(usage.num_sectors(), 10 + 8);
+    assert_eq!(usage.allocated_len(), 2 * 64 + 18 * 512);
+    assert_eq!(usage.slack_len(), 28 + 120);
+
+    let usage = comp.usage("/").unwrap();
+    assert_eq!(usage.logical_len(), 100 + 5000 + 4096 + 64);
+    assert_eq!(usage.num_mini_streams(), 3);
+    assert_eq!(usage.num_mini_sectors(), 3);
+    assert_eq!(usage.slack_len(), 28 + 120);
+
+    let usage = comp.usage("/docs/sub/exact").unwrap();
+    assert_eq!(usage.num_regular_streams(), 1);
+    assert_eq!(usage.slack_len(), 0);
+
+    let usage = comp.usage("/docs/sub").unwrap();
+    assert_eq!(usage.num_sectors(), 8);
+
+    let error = comp.usage("/nothing").unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::NotFound);
+}
+
+#[test]
+fn file_usage_adds_up() {
+    let mut comp = sample();
+    let usage = comp.file_usage().unwrap();
+    assert_eq!(usage.header_len(), 512);
+    assert_eq!(usage.fat_len(), 512);
+    assert_eq!(usage.difat_len(), 0);
+    assert_eq!(usage.minifat_len(), 512);
+    assert_eq!(usage.streams_len(), 18 * 512);
+    assert_eq!(usage.mini_stream_len(), 512);
+    assert_eq!(usage.unused_mini_stream_len(), 512 - 3 * 64);
+    assert_eq!(usage.orphaned_len(), 0);
+    assert_eq!(usage.trailing_len(), 0);
+    assert!(usage.directory_len() >= 512);
+
+    // Removing a big stream leaves free sectors behind.
+    comp.remove_stream("/docs/big").unwrap();
+    let usage = comp.file_usage().unwrap();
+    assert_eq!(usage.streams_len(), 8 * 512);
+    assert_eq!(usage.free_len(), 10 * 512);
+    let file_len = comp.into_inner().into_inner().len() as u64;
+    assert_eq!(usage.total_len(), file_len);
+}
+
+#[test]
+fn file_usage_with_trailing_data() {
+    let mut data = sample().into_inner().into_inner();
+    let num_sectors = data.len() as u64 / 512 - 1;
+    // Append enough sectors to run past what a single FAT sector can cover.
+    data.resize(data.len() + 200 * 512, 0xab);
+    let comp = CompoundFile::open(Cursor::new(data.clone())).unwrap();
+    let usage = comp.file_usage().unwrap();
+    assert_eq!(usage.trailing_len(), (num_sectors + 200 - 128) * 512);
+    assert_eq!(usage.total_len(), data.len() as u64);
+}
+
+//===========================================================================//
@@ -0,0 +1,159 @@
+use cfb::{CfbWriter, CompoundFile, Version};
+use std::io::{self, Cursor, ErrorKind, Read};
+
+//===========================================================================//
+
+fn stream_data(seed: usize, len: usize) -> Vec<u8> {
+    (0..len).map(|i| ((i * 13 + seed) % 251) as u8).collect()
+}
+
+fn read_stream(
+    comp: &mut CompoundFile<Cursor<Vec<u8>>>,
+    path: &str,
+) -> Vec<u8> {
+    let mut data = Vec::new();
+    comp.open_stream(path).unwrap().read_to_end(&mut data).unwrap();
+    data
+}
+
+/// A sink that only supports writing, and that records how much was written.
+struct Sink {
+    data: Vec<u8>,
+}
+
+impl io::Write for Sink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.data.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+//===========================================================================//
+
+#[test]
+fn empty_file() {
+    for &version in &[Version::V3, Version::V4] {
+        let writer = CfbWriter::with_version(version, Vec::new());
+        let data = writer.finish().unwrap();
+        assert_eq!(data.len() % version.sector_len(), 0);
+        let comp = CompoundFile::open_strict(Cursor::new(data)).unwrap();
+        assert_eq!(comp.version(), version);
+        assert_eq!(comp.walk().count(), 1);
+    }
+}
+
+#[test]
+fn write_tree_of_streams() {
+    let lengths = [0, 1, 63, 64, 100, 4095, 4096, 5000, 70000];
+    for &version in &[Version::V3, Version::V4] {
+        let contents: Vec<Vec<u8>> = lengths
+            .iter()
+            .enumerate()
+            .map(|(seed, &len)| stream_data(seed, len))
+            .collect();
+        let mut writer =
+            CfbWriter::with_version(version, Sink { data: vec![] });
+        writer.create_storage_all("/a/b").unwrap();
+        writer.create_storage("/c").unwrap();
+        for (index, data) in contents.iter().enumerate() {
+            let path = format!("/a/b/s{}", index);
+            writer
+                .add_stream(&path, data.len() as u64, data.as_slice())
+                .unwrap();
+        }
+        writer.add_stream("/c/top", 3, &b"top"[..]).unwrap();
+        let data = writer.finish().unwrap().data;
+        let mut comp = CompoundFile::open_strict(Cursor::new(data)).unwrap();
+        for (index, expected) in contents.iter().enumerate() {
+            let path = format!("/a/b/s{}", index);
+            assert_eq!(&read_stream(&mut comp, &path), expected, "{}", path);
+        }
+        assert_eq!(read_stream(&mut comp, "/c/top"), b"top");
+        assert!(comp.is_storage("/a/b"));
+        assert_eq!(comp.walk().count(), 5 + lengths.len());
+    }
+}
+
+#[test]
+fn many_siblings() {
+    for &num_children in &[2, 3, 7, 8, 9, 100, 255] {
+        let mut writer = CfbWriter::new(Vec::new());
+        for index in 0..num_children {
+            let name = format!("/Entry{}", index);
+            if index % 3 == 0 {
+                writer.create_storage(&name).unwrap();
+            } else {
+                writer.add_stream(&name, 1, io::repeat(index as u8)).unwrap();
+            }
+        }
+        let data = writer.finish().unwrap();
+        // Strict parsing checks that the red-black trees are valid.
+        let mut comp = CompoundFile::open_strict(Cursor::new(data)).unwrap();
+        assert_eq!(comp.read_root_storage().count(), num_children);
+        for index in (0..num_children).filter(|index| index % 3 != 0) {
+            let name = format!("/entry{}", index);
+            assert_eq!(read_stream(&mut comp, &name), vec![index as u8]);
+        }
+        // The file should remain modifiable afterwards.
+        comp.create_stream("/new").unwrap();
+        comp.remove_storage("/Entry0").unwrap();
+        comp.flush().unwrap();
+        let data = comp.into_inner().into_inner();
+        let comp = CompoundFile::open_strict(Cursor::new(data)).unwrap();
+        assert_eq!(comp.read_root_storage().count(), num_children);
+    }
+}
+
+#[test]
+fn large_file_needs_difat() {
+    // A V3 file this large needs more FAT sectors than fit in the header.
+    let len = 8 * 1024 * 1024;
+    let mut writer = CfbWriter::with_version(Version::V3, Vec::new());
+    writer.add_stream("/big", len, io::repeat(0x5a).take(len)).unwrap();
+    writer.add_stream("/small", 10, io::repeat(0xa5)).unwrap();
+    let data = writer.finish().unwrap();
+    let mut comp = CompoundFile::open_strict(Cursor::new(data)).unwrap();
+    assert!(!comp.difat_sector_ids().is_empty());
+    let big = read_stream(&mut comp, "/big");
+    assert_eq!(big.len() as u64, len);
+    assert!(big.iter().all(|&byte| byte == 0x5a));
+    assert_eq!(read_stream(&mut comp, "/small"), vec![0xa5; 10]);
+}
+
+#[test]
+fn declaration_errors() {
+    let mut writer = CfbWriter::new(Vec::new());
+    writer.create_storage("/foo").unwrap();
+    writer.add_stream("/bar", 0, io::empty()).unwrap();
+    let error = writer.create_storage("/FOO").unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::AlreadyExists);
+    let error = writer.add_stream("/bar", 0, io::empty()).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::AlreadyExists);
+    let error = writer.create_storage("/missing/baz").unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::NotFound);
+    let error = writer.create_storage("/bar/baz").unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::InvalidInput);
+    let error = writer.create_storage_all("/bar/baz").unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::AlreadyExists);
+    assert!(writer.create_storage("/").is_err());
+    let name = format!("/{}", "x".repeat(32));
+    assert!(writer.add_stream(&name, 0, io::empty()).is_err());
+    let mut writer = CfbWriter::with_version(Version::V3, Vec::new());
+    let len = u32::MAX as u64 + 1;
+    assert!(writer.add_stream("/huge", len, io::empty()).is_err());
+}
+
+#[test]
+fn short_reader_fails() {
+    let mut writer = CfbWriter::new(Vec::new());
+    writer.add_stream("/foo", 10000, &[1u8; 9999][..]).unwrap();
+    let error = writer.finish().unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+    assert!(error.to_string().contains("foo"));
+}
+
+//===========================================================================//
@@ -0,0 +1,89 @@
+use cfb::CompoundFile;
+use std::borrow::Cow;
+use std::io::{Cursor, ErrorKind, Write};
+
+//===========================================================================//
+
+fn stream_data(seed: usize, len: usize) -> Vec<u8> {
+    (0..len).map(|i| ((i * 3 + seed) % 251) as u8).collect()
+}
+
+fn make_cfb() -> Vec<u8> {
+    let mut comp = CompoundFile::create(Cursor::new(Vec::new())).unwrap();
+    comp.create_storage("/dir").unwrap();
+    comp.create_stream("/empty").unwrap();
+    comp.create_stream("/small")
+        .unwrap()
+        .write_all(&stream_data(1, 200))
+        .unwrap();
+    comp.create_stream("/large")
+        .unwrap()
+        .write_all(&stream_data(2, 50000))
+        .unwrap();
+    // Interleave writes to two streams, so that both are fragmented.
+    let mut frag1 = comp.create_stream("/frag1").unwrap();
+    let mut frag2 = comp.create_stream("/frag2").unwrap();
+    for chunk in 0..10 {
+        let data1 = stream_data(3, 10000);
+        let data2 = stream_data(4, 10000);
+        let range = chunk * 1000..(chunk + 1) * 1000;
+        frag1.write_all(&data1[range.clone()]).unwrap();
+        frag1.flush().unwrap();
+        frag2.write_all(&data2[range]).unwrap();
+        frag2.flush().unwrap();
+    }
+    drop(frag1);
+    drop(frag2);
+    comp.flush().unwrap();
+    comp.into_inner().into_inner()
+}
+
+//===========================================================================//
+
+#[test]
+fn contiguous_streams_are_borrowed() {
+    let data = make_cfb();
+    let comp = CompoundFile::open_slice(&data).unwrap();
+    for &(path, seed, len) in &[("/small", 1, 200), ("/large", 2, 50000)] {
+        let bytes = comp.stream_bytes(path).unwrap();
+        assert!(matches!(bytes, Cow::Borrowed(_)), "{}", path);
+        assert_eq!(bytes.as_ref(), stream_data(seed, len).as_slice());
+    }
+    let empty = comp.stream_bytes("/empty").unwrap();
+    assert!(matches!(empty, Cow::Borrowed(_)));
+    assert!(empty.is_empty());
+}
+
+#[test]
+fn fragmented_streams_are_assembled() {
+    let data = make_cfb();
+    let comp = CompoundFile::open_slice_strict(&data).unwrap();
+    for &(path, seed) in &[("/frag1", 3), ("/frag2", 4)] {
+        let bytes = comp.stream_bytes(path).unwrap();
+        assert!(matches!(bytes, Cow::Owned(_)), "{}", path);
+        assert_eq!(bytes.as_ref(), stream_data(seed, 10000).as_slice());
+    }
+}
+
+#[test]
+fn stream_bytes_outlive_compound_file() {
+    let data = make_cfb();
+    let bytes = {
+        let comp = CompoundFile::open_slice(&data).unwrap();
+        comp.stream_bytes("/large").unwrap()
+    };
+    assert_eq!(bytes.as_ref(), stream_data(2, 50000).as_slice());
+}
+
+#[test]
+fn stream_bytes_errors() {
+    let data = make_cfb();
+    let comp = CompoundFile::open_slice(&data).unwrap();
+    let error = comp.stream_bytes("/missing").unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::NotFound);
+    let error = comp.stream_bytes("/dir").unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::InvalidInput);
+    assert!(CompoundFile::open_slice(&data[..100]).is_err());
+}
+
+//===========================================================================//